@@ -1,7 +1,14 @@
 use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use echosrv::datagram::BatchConfig;
+use echosrv::fault::{FaultConfig, ResetFault, StreamFaultConfig};
+use echosrv::handler::Echo;
 use echosrv::performance::{BufferPool, global_pool};
 use echosrv::{EchoClient, EchoServerTrait, TcpConfig, TcpEchoClient, TcpEchoServer};
+use echosrv::{StreamConfig, UdpConfig, UdpEchoServer};
+use std::net::SocketAddr;
 use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tokio::runtime::Runtime;
 
 fn bench_echo_throughput(c: &mut Criterion) {
@@ -19,10 +26,16 @@ fn bench_echo_throughput(c: &mut Criterion) {
                 // Setup server
                 let config = TcpConfig {
                     bind_addr: "127.0.0.1:0".parse().unwrap(),
+                    additional_addrs: Vec::new(),
                     max_connections: 100,
                     buffer_size: 8192,
                     read_timeout: Duration::from_secs(30),
                     write_timeout: Duration::from_secs(30),
+                    transform: None,
+                    framing: None,
+                    workers: 1,
+                    steer_by_cpu: false,
+                    socket_options: Default::default(),
                 };
 
                 let server = TcpEchoServer::new(config.clone().into());
@@ -69,10 +82,16 @@ fn bench_concurrent_clients(c: &mut Criterion) {
                     // Setup server
                     let config = TcpConfig {
                         bind_addr: "127.0.0.1:0".parse().unwrap(),
+                        additional_addrs: Vec::new(),
                         max_connections: 100,
                         buffer_size: 8192,
                         read_timeout: Duration::from_secs(30),
                         write_timeout: Duration::from_secs(30),
+                        transform: None,
+                        framing: None,
+                        workers: 1,
+                        steer_by_cpu: false,
+                        socket_options: Default::default(),
                     };
 
                     let server = TcpEchoServer::new(config.clone().into());
@@ -87,7 +106,6 @@ fn bench_concurrent_clients(c: &mut Criterion) {
                     let data = vec![b'x'; 1024];
 
                     for _ in 0..count {
-                        let addr = addr;
                         let data = data.clone();
                         let handle = tokio::spawn(async move {
                             let mut client = TcpEchoClient::connect(addr).await.unwrap();
@@ -137,6 +155,15 @@ fn bench_buffer_pool(c: &mut Criterion) {
         });
     });
 
+    group.bench_function("size_classes", |b| {
+        let pool = BufferPool::new(8192, 100).with_size_classes(&[512, 2048, 65536]);
+        b.iter(|| {
+            let mut buffer = pool.get_with_capacity(black_box(1500));
+            buffer.extend_from_slice(&[b'x'; 1024]);
+            buffer
+        });
+    });
+
     group.bench_function("global_pool", |b| {
         b.iter(|| {
             let mut buffer = global_pool().get();
@@ -159,10 +186,16 @@ fn bench_protocol_overhead(c: &mut Criterion) {
         b.to_async(&rt).iter(|| async {
             let config = TcpConfig {
                 bind_addr: "127.0.0.1:0".parse().unwrap(),
+                additional_addrs: Vec::new(),
                 max_connections: 100,
                 buffer_size: 8192,
                 read_timeout: Duration::from_secs(30),
                 write_timeout: Duration::from_secs(30),
+                transform: None,
+                framing: None,
+                workers: 1,
+                steer_by_cpu: false,
+                socket_options: Default::default(),
             };
 
             let server = TcpEchoServer::new(config.clone().into());
@@ -182,15 +215,278 @@ fn bench_protocol_overhead(c: &mut Criterion) {
         });
     });
 
+    // Bursts of UDP datagrams, echoed one per system call or in batches
+    let burst = 64;
+    let size = 1024;
+    group.throughput(Throughput::Bytes((burst * size) as u64));
+    let modes = [
+        ("per_datagram", None),
+        ("batched", Some(BatchConfig::new(32))),
+        (
+            "batched_offload",
+            Some(BatchConfig::new(32).with_offload(true)),
+        ),
+    ];
+    for (name, batch) in modes {
+        let addr = rt.block_on(start_udp_server(batch));
+        group.bench_function(BenchmarkId::new("udp_burst", name), |b| {
+            b.to_async(&rt).iter(|| async move {
+                let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                socket.connect(addr).await.unwrap();
+                let data = vec![b'x'; size];
+                for _ in 0..burst {
+                    socket.send(black_box(&data)).await.unwrap();
+                }
+                // A datagram lost to a full socket buffer ends the burst early
+                let mut response = vec![0u8; size];
+                for _ in 0..burst {
+                    let recv = socket.recv(&mut response);
+                    if tokio::time::timeout(Duration::from_secs(1), recv)
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        });
+    }
+
     group.finish();
 }
 
+/// Starts a UDP echo server on a free port and returns its address
+async fn start_udp_server(batch: Option<BatchConfig>) -> SocketAddr {
+    let addr = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let config = UdpConfig {
+        bind_addr: addr,
+        buffer_size: 8192,
+        batch,
+        ..Default::default()
+    };
+    let server = UdpEchoServer::new(config.into());
+    tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    addr
+}
+
+/// Starts a TCP echo server on a free port and returns its address
+///
+/// Without `splice` the server is given an explicit [`Echo`] handler, which
+/// keeps it on the buffered path even where zero-copy echo is available.
+async fn start_tcp_server(splice: bool) -> SocketAddr {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let config = TcpConfig {
+        bind_addr: addr,
+        buffer_size: 8192,
+        ..Default::default()
+    };
+    let mut server = TcpEchoServer::new(config.into());
+    if !splice {
+        server = server.with_handler(Echo);
+    }
+    tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    addr
+}
+
+/// Starts a TCP echo server that reads and writes each connection in turn
+///
+/// Connections with stream faults are never split, so a reset fault that
+/// never fires keeps the server on the half-duplex path without changing
+/// what it echoes.
+async fn start_turn_based_tcp_server() -> SocketAddr {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let config = TcpConfig {
+        bind_addr: addr,
+        buffer_size: 8192,
+        ..Default::default()
+    };
+    let faults = FaultConfig {
+        stream: StreamFaultConfig {
+            reset: Some(ResetFault {
+                after_bytes: 0,
+                probability: 0.0,
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let config = StreamConfig {
+        faults: Some(faults),
+        ..config.into()
+    };
+    let server = TcpEchoServer::new(config).with_handler(Echo);
+    tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    addr
+}
+
+fn bench_pipelined_echo(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let addr = rt.block_on(start_tcp_server(false));
+    let spliced_addr = rt.block_on(start_tcp_server(true));
+    let turn_based_addr = rt.block_on(start_turn_based_tcp_server());
+
+    let mut group = c.benchmark_group("pipelined_echo");
+
+    // 256 messages per connection, sent back to back or one round-trip at a time
+    let messages = 256;
+    for size in [64, 1024, 8192] {
+        group.throughput(Throughput::Bytes((size * messages) as u64));
+
+        group.bench_with_input(BenchmarkId::new("lockstep", size), &size, |b, &size| {
+            b.to_async(&rt).iter(|| async move {
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                let data = vec![b'x'; size];
+                let mut response = vec![0u8; size];
+                for _ in 0..messages {
+                    stream.write_all(black_box(&data)).await.unwrap();
+                    stream.read_exact(&mut response).await.unwrap();
+                }
+            });
+        });
+
+        group.bench_with_input(BenchmarkId::new("pipelined", size), &size, |b, &size| {
+            b.to_async(&rt).iter(|| pipeline(addr, size, messages));
+        });
+
+        // The same clients against a server that reads and writes in turn,
+        // the baseline for full-duplex echo
+        group.bench_with_input(BenchmarkId::new("turn_based", size), &size, |b, &size| {
+            b.to_async(&rt)
+                .iter(|| pipeline(turn_based_addr, size, messages));
+        });
+
+        // The same clients against a plain echo, spliced where the kernel can
+        group.bench_with_input(BenchmarkId::new("spliced", size), &size, |b, &size| {
+            b.to_async(&rt)
+                .iter(|| pipeline(spliced_addr, size, messages));
+        });
+    }
+
+    // The same pipelined clients against the io_uring backend
+    #[cfg(target_os = "linux")]
+    if echosrv::uring::is_supported() {
+        let addr = rt.block_on(start_uring_tcp_server());
+        for size in [64, 1024, 8192] {
+            group.throughput(Throughput::Bytes((size * messages) as u64));
+            group.bench_with_input(
+                BenchmarkId::new("uring_pipelined", size),
+                &size,
+                |b, &size| {
+                    b.to_async(&rt).iter(|| pipeline(addr, size, messages));
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_connection_storm(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("connection_storm");
+
+    // 128 clients connect at once, echo one message and hang up, against
+    // one accept loop or one per runtime thread
+    let clients = 128;
+    group.throughput(Throughput::Elements(clients as u64));
+    for (name, workers) in [("single_acceptor", 1), ("sharded", 0)] {
+        let addr = rt.block_on(async {
+            let addr = TcpListener::bind("127.0.0.1:0")
+                .await
+                .unwrap()
+                .local_addr()
+                .unwrap();
+            let config = TcpConfig {
+                bind_addr: addr,
+                max_connections: clients * 2,
+                workers,
+                ..Default::default()
+            };
+            let server = TcpEchoServer::new(config.into());
+            tokio::spawn(async move { server.run().await });
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            addr
+        });
+
+        group.bench_function(BenchmarkId::new("accept", name), |b| {
+            b.to_async(&rt).iter(|| async move {
+                let mut handles = Vec::with_capacity(clients);
+                for _ in 0..clients {
+                    handles.push(tokio::spawn(async move {
+                        let mut stream = TcpStream::connect(addr).await.unwrap();
+                        stream.write_all(black_box(b"ping")).await.unwrap();
+                        let mut response = [0u8; 4];
+                        stream.read_exact(&mut response).await.unwrap();
+                    }));
+                }
+                for handle in handles {
+                    handle.await.unwrap();
+                }
+            });
+        });
+    }
+
+    group.finish();
+}
+
+/// Writes `messages` messages of `size` bytes while reading their echoes
+async fn pipeline(addr: SocketAddr, size: usize, messages: usize) {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut reader, mut writer) = stream.into_split();
+    let sender = tokio::spawn(async move {
+        let data = vec![b'x'; size];
+        for _ in 0..messages {
+            writer.write_all(black_box(&data)).await.unwrap();
+        }
+        writer
+    });
+    let mut response = vec![0u8; size * messages];
+    reader.read_exact(&mut response).await.unwrap();
+    sender.await.unwrap();
+}
+
+/// Starts an io_uring TCP echo server on a free port and returns its address
+#[cfg(target_os = "linux")]
+async fn start_uring_tcp_server() -> SocketAddr {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let config = TcpConfig {
+        bind_addr: addr,
+        buffer_size: 8192,
+        ..Default::default()
+    };
+    let server = echosrv::uring::UringTcpEchoServer::new(config.into());
+    tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    addr
+}
+
 criterion_group!(
     benches,
     bench_echo_throughput,
     bench_concurrent_clients,
     bench_buffer_pool,
-    bench_protocol_overhead
+    bench_protocol_overhead,
+    bench_pipelined_echo,
+    bench_connection_storm
 );
 
 criterion_main!(benches);
@@ -0,0 +1,2758 @@
+//! Command-line interface for the standalone echosrv binary
+//!
+//! Every protocol is a subcommand with flags for each field of its
+//! configuration struct. Flags fall back to `ECHOSRV_*` environment variables
+//! and then to the defaults used by the standalone server.
+
+use clap::{Args, Parser, Subcommand};
+use echosrv::Address;
+#[cfg(feature = "dtls")]
+use echosrv::datagram::DatagramConfig;
+use echosrv::datagram::{BatchConfig, MAX_DATAGRAM_SIZE, TruncationPolicy};
+#[cfg(feature = "dtls")]
+use echosrv::dtls::{DtlsConfig, Psk};
+use echosrv::fault::{
+    BandwidthLimit, ChaosController, ChaosPhase, FaultConfig, HalfCloseFault, ImpairmentConfig,
+    JitterDistribution, LatencyConfig, PartialWriteFault, ResetFault, StallDirection, StallFault,
+    StreamFaultConfig,
+};
+use echosrv::handler::{Service, Transform, Whoami};
+use echosrv::http::HttpConfig;
+use echosrv::network::{Keepalive, MulticastConfig, MulticastGroup, SocketOptions};
+use echosrv::proxy::{ProxyConfig, Toxic, Toxics};
+use echosrv::stream::{FrameCodec, Framing};
+use echosrv::tcp::TcpConfig;
+use echosrv::udp::UdpConfig;
+use echosrv::unix::{UnixDatagramConfig, UnixStreamConfig};
+use echosrv::uring::Backend;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Port used when neither `--listen` nor a positional port is given
+const DEFAULT_PORT: u16 = 8080;
+
+/// Connection limit used by the standalone server (higher than the library default)
+const DEFAULT_MAX_CONNECTIONS: usize = 1000;
+
+/// Pause between partial writes, long enough for them to leave as separate segments
+const DEFAULT_PARTIAL_WRITE_DELAY: Duration = Duration::from_millis(1);
+
+/// A high-performance echo server for TCP, UDP, HTTP and Unix domain sockets
+#[derive(Debug, Parser)]
+#[command(name = "echosrv", version)]
+pub struct Cli {
+    /// Tracing filter directive (e.g. "echosrv=debug")
+    ///
+    /// Stream payload previews (`Received data`) are logged at debug level,
+    /// datagram previews at info. While stream previews are logged, plain TCP
+    /// echo copies data through a buffer instead of splicing it in the
+    /// kernel.
+    #[arg(
+        long,
+        env = "ECHOSRV_LOG",
+        default_value = "echosrv=info",
+        global = true
+    )]
+    pub log_filter: String,
+
+    /// Protocol to serve (default: tcp)
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Protocol subcommands
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run a TCP echo server
+    Tcp(TcpArgs),
+    /// Run a UDP echo server
+    Udp(UdpArgs),
+    /// Run an HTTP echo server
+    Http(HttpArgs),
+    /// Run a Unix domain stream echo server
+    UnixStream(UnixStreamArgs),
+    /// Run a Unix domain datagram echo server
+    UnixDgram(UnixDgramArgs),
+    /// Run a DTLS echo server over UDP
+    #[cfg(feature = "dtls")]
+    Dtls(DtlsArgs),
+    /// Run a fault-injecting TCP proxy in front of another server
+    Proxy(ProxyArgs),
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Command::Tcp(TcpArgs::default())
+    }
+}
+
+/// Listening endpoints for network protocols
+#[derive(Debug, Default, Args)]
+pub struct NetworkListenArgs {
+    /// Address to listen on; repeat for multiple endpoints
+    ///
+    /// Join addresses with "+" to serve them from one endpoint with a shared
+    /// connection limit, e.g. "127.0.0.1:9000+[::1]:9000". Fault flags and
+    /// the transform can be overridden per endpoint with a query string, e.g.
+    /// "127.0.0.1:9000?latency=100ms&transform=uppercase".
+    #[arg(
+        short,
+        long = "listen",
+        value_name = "ADDR[+ADDR...][?FAULTS]",
+        env = "ECHOSRV_LISTEN",
+        value_delimiter = ','
+    )]
+    pub listen: Vec<ListenSpec>,
+
+    /// IP addresses to serve the positional port on, all from one endpoint
+    /// (default 127.0.0.1; e.g. "127.0.0.1,::1" or "::")
+    #[arg(
+        long = "host",
+        value_name = "IP",
+        env = "ECHOSRV_HOST",
+        value_delimiter = ',',
+        conflicts_with = "listen"
+    )]
+    pub hosts: Vec<IpAddr>,
+
+    /// Port to listen on (ignored when --listen is given)
+    #[arg(value_name = "PORT")]
+    pub port: Option<u16>,
+}
+
+impl NetworkListenArgs {
+    /// Resolves the configured endpoints, falling back to the hosts (or
+    /// 127.0.0.1) at <port>
+    pub fn endpoints(&self) -> Vec<ListenSpec> {
+        if !self.listen.is_empty() {
+            return self.listen.clone();
+        }
+        let port = self.port.unwrap_or(DEFAULT_PORT);
+        let mut addrs = self.hosts.iter().map(|&host| SocketAddr::new(host, port));
+        vec![ListenSpec {
+            addr: addrs
+                .next()
+                .unwrap_or(SocketAddr::from(([127, 0, 0, 1], port))),
+            additional_addrs: addrs.collect(),
+            overrides: FaultOverrides::default(),
+            transform: None,
+        }]
+    }
+}
+
+/// A `--listen` value: addresses with optional per-endpoint overrides
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenSpec {
+    /// Address to listen on
+    pub addr: SocketAddr,
+    /// Further addresses served by the same endpoint
+    pub additional_addrs: Vec<SocketAddr>,
+    /// Fault flags given in the query string
+    pub overrides: FaultOverrides,
+    /// Transform given in the query string
+    pub transform: Option<Transform>,
+}
+
+impl std::str::FromStr for ListenSpec {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (addrs, query) = input.split_once('?').unwrap_or((input, ""));
+        let mut additional_addrs = addrs
+            .split('+')
+            .map(|addr| {
+                addr.parse()
+                    .map_err(|e| format!("invalid address '{addr}': {e}"))
+            })
+            .collect::<Result<Vec<SocketAddr>, String>>()?;
+        // Splitting always yields at least one address
+        let addr = additional_addrs.remove(0);
+        let mut transform = None;
+        let mut faults = Vec::new();
+        for pair in query.split('&') {
+            match pair.strip_prefix("transform=") {
+                Some(name) => transform = Some(name.parse().map_err(|e| format!("{e}"))?),
+                None => faults.push(pair),
+            }
+        }
+        Ok(Self {
+            addr,
+            additional_addrs,
+            overrides: FaultOverrides::parse_query(&faults.join("&"))?,
+            transform,
+        })
+    }
+}
+
+/// Fault flags given in a query string, e.g. "latency=100ms&loss=5"
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultOverrides {
+    /// Latency, bandwidth and seed flags
+    pub faults: FaultArgs,
+    /// Datagram impairment flags
+    pub impairment: ImpairmentArgs,
+    /// Stream fault flags
+    pub stream: StreamFaultArgs,
+}
+
+impl FaultOverrides {
+    /// Parses `key=value` pairs separated by `&`
+    fn parse_query(query: &str) -> Result<Self, String> {
+        let mut overrides = Self::default();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{pair}'"))?;
+            if !overrides.impairment.set(key, value)? && !overrides.stream.set(key, value)? {
+                overrides.faults.set(key, value)?;
+            }
+        }
+        Ok(overrides)
+    }
+
+    /// Returns true if any flag is set
+    pub fn is_set(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// Fails if a stream endpoint asks for datagram impairments
+    fn reject_datagram_faults(&self, context: &dyn std::fmt::Display) -> Result<(), String> {
+        if self.impairment.is_set() {
+            return Err(format!(
+                "{context}: datagram impairments (loss, duplicate, reorder, corrupt, truncate) \
+                 are only supported by udp and unix-dgram"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fails if a datagram endpoint asks for stream faults
+    fn reject_stream_faults(&self, context: &dyn std::fmt::Display) -> Result<(), String> {
+        if self.stream.is_set() {
+            return Err(format!(
+                "{context}: stream faults (reset, stall, partial writes, half-close, accept limit) \
+                 are only supported by tcp, http and unix-stream"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Fault injection flags shared by every protocol that supports faults
+///
+/// Every flag can also be set per endpoint in the `--listen` query string,
+/// using the flag name without the leading dashes; `--fault-seed` becomes
+/// `seed` (e.g. `?read-bandwidth=64k&seed=7`).
+#[derive(Debug, Clone, Default, PartialEq, Args)]
+pub struct FaultArgs {
+    /// Latency added before each response (e.g. "100ms")
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_LATENCY", value_parser = parse_duration)]
+    pub latency: Option<Duration>,
+
+    /// Spread of the random jitter added to the latency
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_JITTER", value_parser = parse_duration)]
+    pub jitter: Option<Duration>,
+
+    /// Jitter distribution: uniform, normal or exponential
+    #[arg(long, value_name = "DIST", env = "ECHOSRV_JITTER_DISTRIBUTION")]
+    pub jitter_distribution: Option<JitterDistribution>,
+
+    /// Limit on bytes per second read from each client (e.g. "64k")
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_READ_BANDWIDTH", value_parser = parse_bytes)]
+    pub read_bandwidth: Option<u64>,
+
+    /// Limit on bytes per second written to each client (e.g. "64k")
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_WRITE_BANDWIDTH", value_parser = parse_bytes)]
+    pub write_bandwidth: Option<u64>,
+
+    /// Limit on bytes per second in both directions combined
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_BANDWIDTH", value_parser = parse_bytes)]
+    pub bandwidth: Option<u64>,
+
+    /// Seed for random fault decisions (random if omitted; logged at startup)
+    #[arg(long = "fault-seed", value_name = "SEED", env = "ECHOSRV_FAULT_SEED")]
+    pub seed: Option<u64>,
+}
+
+impl FaultArgs {
+    /// Sets a field from a `--listen` query string parameter
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "latency" => self.latency = Some(parse_duration(value)?),
+            "jitter" => self.jitter = Some(parse_duration(value)?),
+            "jitter-distribution" => {
+                self.jitter_distribution = Some(value.parse().map_err(|e| format!("{e}"))?)
+            }
+            "read-bandwidth" => self.read_bandwidth = Some(parse_bytes(value)?),
+            "write-bandwidth" => self.write_bandwidth = Some(parse_bytes(value)?),
+            "bandwidth" => self.bandwidth = Some(parse_bytes(value)?),
+            "seed" => {
+                self.seed = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("invalid seed '{value}': {e}"))?,
+                )
+            }
+            other => return Err(format!("unknown fault option '{other}'")),
+        }
+        Ok(())
+    }
+
+    /// Returns these flags with any field set in `overrides` replaced
+    pub fn merged_with(&self, overrides: &FaultArgs) -> FaultArgs {
+        FaultArgs {
+            latency: overrides.latency.or(self.latency),
+            jitter: overrides.jitter.or(self.jitter),
+            jitter_distribution: overrides.jitter_distribution.or(self.jitter_distribution),
+            read_bandwidth: overrides.read_bandwidth.or(self.read_bandwidth),
+            write_bandwidth: overrides.write_bandwidth.or(self.write_bandwidth),
+            bandwidth: overrides.bandwidth.or(self.bandwidth),
+            seed: overrides.seed.or(self.seed),
+        }
+    }
+
+    /// Builds the latency and bandwidth part of the fault configuration
+    pub fn to_config(&self) -> FaultConfig {
+        let latency = (self.latency.is_some() || self.jitter.is_some()).then(|| LatencyConfig {
+            base: self.latency.unwrap_or_default(),
+            jitter: self.jitter.unwrap_or_default(),
+            distribution: self.jitter_distribution.unwrap_or_default(),
+        });
+        FaultConfig {
+            seed: self.seed,
+            latency,
+            bandwidth: BandwidthLimit {
+                read: self.read_bandwidth,
+                write: self.write_bandwidth,
+                total: self.bandwidth,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Datagram impairment flags for the `udp` and `unix-dgram` subcommands
+///
+/// Probabilities are percentages (e.g. "5" or "5%"). Like [`FaultArgs`],
+/// every flag can be set per endpoint in the `--listen` query string.
+#[derive(Debug, Clone, Default, PartialEq, Args)]
+pub struct ImpairmentArgs {
+    /// Percentage of datagrams dropped instead of echoed
+    #[arg(long, value_name = "PERCENT", env = "ECHOSRV_LOSS", value_parser = parse_percent)]
+    pub loss: Option<f64>,
+
+    /// Percentage of echoes sent twice
+    #[arg(long, value_name = "PERCENT", env = "ECHOSRV_DUPLICATE", value_parser = parse_percent)]
+    pub duplicate: Option<f64>,
+
+    /// Percentage of echoes held back and sent after later ones
+    #[arg(long, value_name = "PERCENT", env = "ECHOSRV_REORDER", value_parser = parse_percent)]
+    pub reorder: Option<f64>,
+
+    /// Number of later echoes a reordered datagram waits for
+    #[arg(long, value_name = "COUNT", env = "ECHOSRV_REORDER_WINDOW")]
+    pub reorder_window: Option<usize>,
+
+    /// Longest time a reordered datagram is held when traffic stops
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_REORDER_TIMEOUT", value_parser = parse_duration)]
+    pub reorder_timeout: Option<Duration>,
+
+    /// Percentage of echoes with randomly flipped bits
+    #[arg(long, value_name = "PERCENT", env = "ECHOSRV_CORRUPT", value_parser = parse_percent)]
+    pub corrupt: Option<f64>,
+
+    /// Percentage of echoes cut to a random shorter length
+    #[arg(long, value_name = "PERCENT", env = "ECHOSRV_TRUNCATE", value_parser = parse_percent)]
+    pub truncate: Option<f64>,
+}
+
+impl ImpairmentArgs {
+    /// Sets a field from a `--listen` query string parameter
+    ///
+    /// Returns `Ok(false)` if `key` is not an impairment option.
+    fn set(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        match key {
+            "loss" => self.loss = Some(parse_percent(value)?),
+            "duplicate" => self.duplicate = Some(parse_percent(value)?),
+            "reorder" => self.reorder = Some(parse_percent(value)?),
+            "reorder-window" => {
+                self.reorder_window = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("invalid reorder window '{value}': {e}"))?,
+                )
+            }
+            "reorder-timeout" => self.reorder_timeout = Some(parse_duration(value)?),
+            "corrupt" => self.corrupt = Some(parse_percent(value)?),
+            "truncate" => self.truncate = Some(parse_percent(value)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Returns true if any flag is set
+    pub fn is_set(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// Returns these flags with any field set in `overrides` replaced
+    pub fn merged_with(&self, overrides: &ImpairmentArgs) -> ImpairmentArgs {
+        ImpairmentArgs {
+            loss: overrides.loss.or(self.loss),
+            duplicate: overrides.duplicate.or(self.duplicate),
+            reorder: overrides.reorder.or(self.reorder),
+            reorder_window: overrides.reorder_window.or(self.reorder_window),
+            reorder_timeout: overrides.reorder_timeout.or(self.reorder_timeout),
+            corrupt: overrides.corrupt.or(self.corrupt),
+            truncate: overrides.truncate.or(self.truncate),
+        }
+    }
+
+    /// Builds the impairment configuration
+    pub fn to_config(&self) -> ImpairmentConfig {
+        let defaults = ImpairmentConfig::default();
+        ImpairmentConfig {
+            loss: self.loss.unwrap_or(defaults.loss),
+            duplicate: self.duplicate.unwrap_or(defaults.duplicate),
+            reorder: self.reorder.unwrap_or(defaults.reorder),
+            reorder_window: self.reorder_window.unwrap_or(defaults.reorder_window),
+            reorder_timeout: self.reorder_timeout.unwrap_or(defaults.reorder_timeout),
+            corrupt: self.corrupt.unwrap_or(defaults.corrupt),
+            truncate: self.truncate.unwrap_or(defaults.truncate),
+        }
+    }
+}
+
+/// Stream fault flags for the `tcp`, `http` and `unix-stream` subcommands
+///
+/// Each connection-level mode applies to the given percentage of accepted
+/// connections (100% if omitted). Like [`FaultArgs`], every flag can be set
+/// per endpoint in the `--listen` query string.
+#[derive(Debug, Clone, Default, PartialEq, Args)]
+pub struct StreamFaultArgs {
+    /// Reset connections with a TCP RST after echoing this many bytes
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_RESET_AFTER", value_parser = parse_bytes)]
+    pub reset_after: Option<u64>,
+
+    /// Percentage of connections that are reset
+    #[arg(long, value_name = "PERCENT", env = "ECHOSRV_RESET_PERCENT", value_parser = parse_percent)]
+    pub reset_percent: Option<f64>,
+
+    /// Stop reading or echoing until the connection idles out for
+    /// `--read-timeout`: read or write
+    #[arg(long, value_name = "DIRECTION", env = "ECHOSRV_STALL")]
+    pub stall: Option<StallDirection>,
+
+    /// Bytes echoed before a connection stalls (default: 0)
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_STALL_AFTER", value_parser = parse_bytes)]
+    pub stall_after: Option<u64>,
+
+    /// Percentage of connections that stall
+    #[arg(long, value_name = "PERCENT", env = "ECHOSRV_STALL_PERCENT", value_parser = parse_percent)]
+    pub stall_percent: Option<f64>,
+
+    /// Split echoes into writes of 1 to this many bytes
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_PARTIAL_WRITES", value_parser = parse_bytes)]
+    pub partial_writes: Option<u64>,
+
+    /// Pause between partial writes (default: 1ms)
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_PARTIAL_WRITE_DELAY", value_parser = parse_duration)]
+    pub partial_write_delay: Option<Duration>,
+
+    /// Percentage of connections that use partial writes
+    #[arg(long, value_name = "PERCENT", env = "ECHOSRV_PARTIAL_WRITES_PERCENT", value_parser = parse_percent)]
+    pub partial_writes_percent: Option<f64>,
+
+    /// Close the write half after echoing this many bytes
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_HALF_CLOSE_AFTER", value_parser = parse_bytes)]
+    pub half_close_after: Option<u64>,
+
+    /// Percentage of connections that are half-closed
+    #[arg(long, value_name = "PERCENT", env = "ECHOSRV_HALF_CLOSE_PERCENT", value_parser = parse_percent)]
+    pub half_close_percent: Option<f64>,
+
+    /// Stop accepting after this many connections (0 never accepts)
+    #[arg(long, value_name = "COUNT", env = "ECHOSRV_ACCEPT_LIMIT")]
+    pub accept_limit: Option<usize>,
+}
+
+impl StreamFaultArgs {
+    /// Sets a field from a `--listen` query string parameter
+    ///
+    /// Returns `Ok(false)` if `key` is not a stream fault option.
+    fn set(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        match key {
+            "reset-after" => self.reset_after = Some(parse_bytes(value)?),
+            "reset-percent" => self.reset_percent = Some(parse_percent(value)?),
+            "stall" => self.stall = Some(value.parse().map_err(|e| format!("{e}"))?),
+            "stall-after" => self.stall_after = Some(parse_bytes(value)?),
+            "stall-percent" => self.stall_percent = Some(parse_percent(value)?),
+            "partial-writes" => self.partial_writes = Some(parse_bytes(value)?),
+            "partial-write-delay" => self.partial_write_delay = Some(parse_duration(value)?),
+            "partial-writes-percent" => self.partial_writes_percent = Some(parse_percent(value)?),
+            "half-close-after" => self.half_close_after = Some(parse_bytes(value)?),
+            "half-close-percent" => self.half_close_percent = Some(parse_percent(value)?),
+            "accept-limit" => {
+                self.accept_limit = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("invalid accept limit '{value}': {e}"))?,
+                )
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Returns true if any flag is set
+    pub fn is_set(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// Returns these flags with any field set in `overrides` replaced
+    pub fn merged_with(&self, overrides: &StreamFaultArgs) -> StreamFaultArgs {
+        StreamFaultArgs {
+            reset_after: overrides.reset_after.or(self.reset_after),
+            reset_percent: overrides.reset_percent.or(self.reset_percent),
+            stall: overrides.stall.or(self.stall),
+            stall_after: overrides.stall_after.or(self.stall_after),
+            stall_percent: overrides.stall_percent.or(self.stall_percent),
+            partial_writes: overrides.partial_writes.or(self.partial_writes),
+            partial_write_delay: overrides.partial_write_delay.or(self.partial_write_delay),
+            partial_writes_percent: overrides
+                .partial_writes_percent
+                .or(self.partial_writes_percent),
+            half_close_after: overrides.half_close_after.or(self.half_close_after),
+            half_close_percent: overrides.half_close_percent.or(self.half_close_percent),
+            accept_limit: overrides.accept_limit.or(self.accept_limit),
+        }
+    }
+
+    /// Builds the stream fault configuration
+    ///
+    /// A mode is enabled by its main flag; percentages alone do nothing.
+    pub fn to_config(&self) -> StreamFaultConfig {
+        StreamFaultConfig {
+            reset: self.reset_after.map(|after_bytes| ResetFault {
+                after_bytes,
+                probability: self.reset_percent.unwrap_or(1.0),
+            }),
+            stall: self.stall.map(|direction| StallFault {
+                direction,
+                after_bytes: self.stall_after.unwrap_or(0),
+                probability: self.stall_percent.unwrap_or(1.0),
+            }),
+            partial_writes: self.partial_writes.map(|max_chunk| PartialWriteFault {
+                max_chunk: usize::try_from(max_chunk).unwrap_or(usize::MAX),
+                delay: self
+                    .partial_write_delay
+                    .unwrap_or(DEFAULT_PARTIAL_WRITE_DELAY),
+                probability: self.partial_writes_percent.unwrap_or(1.0),
+            }),
+            half_close: self.half_close_after.map(|after_bytes| HalfCloseFault {
+                after_bytes,
+                probability: self.half_close_percent.unwrap_or(1.0),
+            }),
+            accept_limit: self.accept_limit,
+        }
+    }
+}
+
+/// Chaos timeline flags for every protocol that supports faults
+#[derive(Debug, Clone, Default, Args)]
+pub struct ChaosArgs {
+    /// Timeline phase; repeat to build a timeline that loops forever
+    ///
+    /// Each phase starts from the fault flags and applies its own query
+    /// string, e.g. "healthy=5m" or "lossy=1m?loss=30&latency=200ms".
+    #[arg(
+        long = "chaos-phase",
+        value_name = "NAME=DURATION[?FAULTS]",
+        env = "ECHOSRV_CHAOS_PHASE",
+        value_delimiter = ','
+    )]
+    pub phases: Vec<PhaseSpec>,
+
+    /// Address for the chaos admin endpoint (GET /chaos, POST /chaos/advance, GET /metrics)
+    #[arg(
+        long,
+        value_name = "ADDR",
+        env = "ECHOSRV_CHAOS_ADMIN",
+        requires = "phases"
+    )]
+    pub chaos_admin: Option<SocketAddr>,
+}
+
+impl ChaosArgs {
+    /// Returns true if a timeline is configured
+    pub fn is_set(&self) -> bool {
+        !self.phases.is_empty()
+    }
+
+    /// Builds the timeline, turning each phase into faults with `faults_for`
+    fn controller(
+        &self,
+        faults_for: impl Fn(&PhaseSpec) -> Result<FaultConfig, String>,
+    ) -> Result<Option<ChaosController>, String> {
+        if !self.is_set() {
+            return Ok(None);
+        }
+        let phases = self
+            .phases
+            .iter()
+            .map(|phase| {
+                Ok(ChaosPhase::new(
+                    phase.name.clone(),
+                    phase.duration,
+                    faults_for(phase)?,
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        ChaosController::new(phases)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Fails if an endpoint overrides faults that the timeline would replace
+    fn reject_overrides(&self, spec: &ListenSpec) -> Result<(), String> {
+        if self.is_set() && spec.overrides.is_set() {
+            return Err(format!(
+                "{}: per-endpoint fault overrides cannot be combined with --chaos-phase",
+                spec.addr
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A `--chaos-phase` value: a named duration with optional fault overrides
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseSpec {
+    /// Name shown in logs and metrics
+    pub name: String,
+    /// How long the phase lasts
+    pub duration: Duration,
+    /// Fault flags given in the query string
+    pub overrides: FaultOverrides,
+}
+
+impl std::str::FromStr for PhaseSpec {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (head, query) = input.split_once('?').unwrap_or((input, ""));
+        let (name, duration) = head
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=DURATION, got '{head}'"))?;
+        if name.is_empty() {
+            return Err(format!("missing phase name in '{input}'"));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            duration: parse_duration(duration)?,
+            overrides: FaultOverrides::parse_query(query)?,
+        })
+    }
+}
+
+/// Attaches `chaos` to an endpoint's faults, keeping its seed
+pub fn attach_chaos(faults: &mut Option<FaultConfig>, chaos: Option<&ChaosController>) {
+    if let Some(chaos) = chaos {
+        faults.get_or_insert_with(FaultConfig::default).chaos = Some(chaos.clone());
+    }
+}
+
+/// A listener configuration paired with its fault settings
+#[derive(Debug, Clone)]
+pub struct Endpoint<C> {
+    /// Protocol configuration for the listener
+    pub config: C,
+    /// Faults to inject, or `None` to serve without the fault layer
+    pub faults: Option<FaultConfig>,
+}
+
+impl<C> Endpoint<C> {
+    /// Pairs `config` with `faults`, dropping them if nothing is enabled
+    fn new(config: C, faults: FaultConfig) -> Self {
+        Self {
+            config,
+            faults: faults.is_active().then_some(faults),
+        }
+    }
+}
+
+/// Listening endpoints for Unix domain socket protocols
+#[derive(Debug, Default, Args)]
+pub struct UnixListenArgs {
+    /// Socket path to listen on; repeat for multiple endpoints
+    #[arg(
+        short,
+        long = "listen",
+        value_name = "PATH",
+        env = "ECHOSRV_LISTEN",
+        value_delimiter = ','
+    )]
+    pub listen: Vec<PathBuf>,
+
+    /// Socket path to listen on (ignored when --listen is given)
+    #[arg(value_name = "SOCKET_PATH")]
+    pub socket_path: Option<PathBuf>,
+
+    /// Service name used to look up an inherited file descriptor (systemd LISTEN_FDNAMES)
+    #[arg(long, value_name = "NAME", env = "ECHOSRV_SERVICE_NAME")]
+    pub service_name: Option<String>,
+}
+
+impl UnixListenArgs {
+    /// Resolves the configured socket paths, falling back to `default`
+    pub fn paths(&self, default: &str) -> Vec<PathBuf> {
+        if !self.listen.is_empty() {
+            return self.listen.clone();
+        }
+        vec![
+            self.socket_path
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(default)),
+        ]
+    }
+}
+
+/// Buffer and timeout settings shared by every protocol
+#[derive(Debug, Default, Args)]
+pub struct IoArgs {
+    /// Buffer size in bytes for reading and writing data (e.g. "4096" or
+    /// "64k"); "auto" fits the largest datagram (65535 bytes)
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_BUFFER_SIZE", value_parser = parse_buffer_size)]
+    pub buffer_size: Option<usize>,
+
+    /// Read timeout (e.g. "30s", "500ms", "2m")
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_READ_TIMEOUT", value_parser = parse_duration)]
+    pub read_timeout: Option<Duration>,
+
+    /// Write timeout (e.g. "30s", "500ms", "2m")
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_WRITE_TIMEOUT", value_parser = parse_duration)]
+    pub write_timeout: Option<Duration>,
+}
+
+/// Handling of datagrams too large for the buffer, shared by the datagram
+/// protocols
+#[derive(Debug, Default, Args)]
+pub struct TruncationArgs {
+    /// What to do with datagrams larger than the buffer: echo the part that
+    /// fit, drop them, or reply "ERR TRUNCATED <size>" (echo, drop or error)
+    #[arg(long = "truncation", value_name = "POLICY", env = "ECHOSRV_TRUNCATION")]
+    pub policy: Option<TruncationPolicy>,
+}
+
+/// Payload transform shared by the network and Unix socket echo protocols
+#[derive(Debug, Default, Args)]
+pub struct TransformArgs {
+    /// Transform echoed data: uppercase, reverse, rot13, sha256, crc32,
+    /// base64, base64-decode or hexdump
+    #[arg(long, value_name = "NAME", env = "ECHOSRV_TRANSFORM")]
+    pub transform: Option<Transform>,
+}
+
+impl TransformArgs {
+    /// Returns the transform for `spec`, preferring its query string
+    pub fn for_endpoint(&self, spec: &ListenSpec) -> Option<Transform> {
+        spec.transform.or(self.transform)
+    }
+}
+
+/// Message framing for the `tcp` and `unix-stream` subcommands
+#[derive(Debug, Default, Args)]
+pub struct FramingArgs {
+    /// Exchange whole messages instead of raw chunks: lines, u16, u32
+    /// (big-endian length prefix) or netstring
+    #[arg(long, value_name = "NAME", env = "ECHOSRV_FRAMING")]
+    pub framing: Option<Framing>,
+
+    /// Largest message payload accepted or sent when framing is enabled
+    #[arg(
+        long,
+        value_name = "BYTES",
+        env = "ECHOSRV_MAX_FRAME_SIZE",
+        requires = "framing"
+    )]
+    pub max_frame_size: Option<usize>,
+}
+
+impl FramingArgs {
+    /// Builds the codec for the selected framing, if any
+    pub fn codec(&self) -> Option<FrameCodec> {
+        let codec = FrameCodec::new(self.framing?);
+        Some(match self.max_frame_size {
+            Some(size) => codec.with_max_frame_size(size),
+            None => codec,
+        })
+    }
+}
+
+/// Transport backend selection for the `tcp` and `udp` subcommands
+#[derive(Debug, Default, Args)]
+pub struct BackendArgs {
+    /// Transport running the echo loops: tokio, or io-uring on Linux 6.0
+    /// and later (falls back to tokio where the kernel lacks it)
+    #[arg(long, value_name = "NAME", env = "ECHOSRV_BACKEND")]
+    pub backend: Option<Backend>,
+}
+
+/// Simple service and address reply selection for the `tcp` and `udp`
+/// subcommands
+#[derive(Debug, Default, Args)]
+pub struct ServiceArgs {
+    /// Serve a classic simple service instead of echo: discard, chargen,
+    /// daytime, time or qotd
+    #[arg(
+        long,
+        value_name = "NAME",
+        env = "ECHOSRV_SERVICE",
+        conflicts_with = "transform"
+    )]
+    pub service: Option<Service>,
+
+    /// Answer each client with the address and port it was seen from, as
+    /// plain text or as a STUN Binding response (RFC 5389)
+    #[arg(
+        long,
+        value_name = "MODE",
+        env = "ECHOSRV_WHOAMI",
+        conflicts_with_all = ["transform", "service"]
+    )]
+    pub whoami: Option<Whoami>,
+}
+
+impl ServiceArgs {
+    /// Fails if `spec` asks for a transform while a service is selected
+    fn reject_transform(&self, spec: &ListenSpec) -> Result<(), String> {
+        let flag = if self.service.is_some() {
+            "--service"
+        } else if self.whoami.is_some() {
+            "--whoami"
+        } else {
+            return Ok(());
+        };
+        if spec.transform.is_some() {
+            return Err(format!(
+                "{}: transforms cannot be combined with {flag}",
+                spec.addr
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Connection limit shared by stream protocols
+#[derive(Debug, Default, Args)]
+pub struct ConnectionArgs {
+    /// Maximum number of concurrent connections
+    #[arg(long, value_name = "COUNT", env = "ECHOSRV_MAX_CONNECTIONS")]
+    pub max_connections: Option<usize>,
+}
+
+/// Accept loop sharding shared by the TCP-based protocols
+#[derive(Debug, Default, Args)]
+pub struct AcceptArgs {
+    /// Accept loops per endpoint, each on its own SO_REUSEPORT listener;
+    /// 0 runs one per runtime worker thread
+    #[arg(long, value_name = "N", env = "ECHOSRV_WORKERS")]
+    pub workers: Option<usize>,
+
+    /// Hand each connection to the accept loop of the CPU that received it
+    /// (Linux)
+    #[arg(long, env = "ECHOSRV_STEER_BY_CPU", requires = "workers")]
+    pub steer_by_cpu: bool,
+}
+
+/// Socket options shared by the network protocols
+#[derive(Debug, Default, Args)]
+pub struct SocketArgs {
+    /// Kernel receive buffer size (SO_RCVBUF, e.g. "256k")
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_RECV_BUFFER_SIZE", value_parser = parse_bytes)]
+    pub recv_buffer_size: Option<u64>,
+
+    /// Kernel send buffer size (SO_SNDBUF, e.g. "256k")
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_SEND_BUFFER_SIZE", value_parser = parse_bytes)]
+    pub send_buffer_size: Option<u64>,
+
+    /// Type of service byte of sent packets (IP_TOS, or the IPv6 traffic
+    /// class)
+    #[arg(
+        long,
+        value_name = "BYTE",
+        env = "ECHOSRV_TOS",
+        value_parser = clap::value_parser!(u32).range(0..=255)
+    )]
+    pub tos: Option<u32>,
+
+    /// DSCP code point of sent packets, 0 to 63 (sets the type of service)
+    #[arg(
+        long,
+        value_name = "CODE",
+        env = "ECHOSRV_DSCP",
+        conflicts_with = "tos",
+        value_parser = clap::value_parser!(u8).range(0..=63)
+    )]
+    pub dscp: Option<u8>,
+
+    /// Time to live of sent packets (IP_TTL, or the IPv6 hop limit)
+    #[arg(
+        long,
+        value_name = "HOPS",
+        env = "ECHOSRV_TTL",
+        value_parser = clap::value_parser!(u32).range(1..=255)
+    )]
+    pub ttl: Option<u32>,
+
+    /// Whether IPv6 sockets serve IPv6 only (IPV6_V6ONLY); "false" lets
+    /// "[::]" serve IPv4 clients too
+    #[arg(long = "ipv6-only", value_name = "BOOL", env = "ECHOSRV_IPV6_ONLY")]
+    pub only_v6: Option<bool>,
+}
+
+impl SocketArgs {
+    /// Builds the socket options these flags select
+    pub fn options(&self) -> SocketOptions {
+        let options = SocketOptions {
+            recv_buffer_size: self.recv_buffer_size.map(|size| size as usize),
+            send_buffer_size: self.send_buffer_size.map(|size| size as usize),
+            tos: self.tos,
+            ttl: self.ttl,
+            only_v6: self.only_v6,
+            ..Default::default()
+        };
+        match self.dscp {
+            Some(dscp) => options.with_dscp(dscp),
+            None => options,
+        }
+    }
+}
+
+/// Socket options of the TCP-based protocols
+#[derive(Debug, Default, Args)]
+pub struct StreamSocketArgs {
+    /// Send small writes immediately instead of coalescing them (TCP_NODELAY)
+    #[arg(long, env = "ECHOSRV_NODELAY")]
+    pub nodelay: bool,
+
+    /// Probe connections idle this long (e.g. "60s")
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_KEEPALIVE", value_parser = parse_duration)]
+    pub keepalive: Option<Duration>,
+
+    /// Time between unanswered keepalive probes
+    #[arg(
+        long,
+        value_name = "DURATION",
+        env = "ECHOSRV_KEEPALIVE_INTERVAL",
+        requires = "keepalive",
+        value_parser = parse_duration
+    )]
+    pub keepalive_interval: Option<Duration>,
+
+    /// Unanswered keepalive probes before the connection is dropped
+    #[arg(
+        long,
+        value_name = "COUNT",
+        env = "ECHOSRV_KEEPALIVE_RETRIES",
+        requires = "keepalive"
+    )]
+    pub keepalive_retries: Option<u32>,
+
+    /// How long closing a connection waits to send queued data (SO_LINGER);
+    /// "0" resets it instead
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_LINGER", value_parser = parse_duration)]
+    pub linger: Option<Duration>,
+
+    /// Drop connections whose sent data stays unacknowledged this long
+    /// (TCP_USER_TIMEOUT, Linux)
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_USER_TIMEOUT", value_parser = parse_duration)]
+    pub user_timeout: Option<Duration>,
+
+    /// Accept TCP Fast Open, queueing up to N pending connections (Linux)
+    #[arg(long, value_name = "N", env = "ECHOSRV_FASTOPEN")]
+    pub fastopen: Option<u32>,
+
+    /// Connections queued before they are accepted
+    #[arg(long, value_name = "N", env = "ECHOSRV_BACKLOG")]
+    pub backlog: Option<u32>,
+}
+
+impl StreamSocketArgs {
+    /// Builds the socket options these flags select on top of `socket`
+    pub fn options(&self, socket: &SocketArgs) -> SocketOptions {
+        let keepalive = self.keepalive.map(|idle| Keepalive {
+            idle,
+            interval: self.keepalive_interval,
+            retries: self.keepalive_retries,
+        });
+        SocketOptions {
+            nodelay: self.nodelay.then_some(true),
+            keepalive,
+            linger: self.linger,
+            user_timeout: self.user_timeout,
+            fastopen: self.fastopen,
+            backlog: self.backlog,
+            ..socket.options()
+        }
+    }
+}
+
+/// Broadcast and multicast options of UDP sockets
+#[derive(Debug, Default, Args)]
+pub struct DatagramSocketArgs {
+    /// Allow sending and receiving broadcasts (SO_BROADCAST)
+    #[arg(long, env = "ECHOSRV_BROADCAST")]
+    pub broadcast: bool,
+
+    /// Join a multicast group, on an interface given by name or index if
+    /// set (e.g. "239.1.2.3%eth0" or "ff02::1%2"); repeatable or
+    /// comma-separated
+    #[arg(
+        long = "multicast-group",
+        value_name = "GROUP[%IFACE]",
+        env = "ECHOSRV_MULTICAST_GROUP",
+        value_delimiter = ','
+    )]
+    pub multicast_groups: Vec<MulticastGroup>,
+
+    /// Time to live of datagrams sent to multicast groups (the IPv6 hop
+    /// limit)
+    #[arg(
+        long,
+        value_name = "HOPS",
+        env = "ECHOSRV_MULTICAST_TTL",
+        requires = "multicast_groups",
+        value_parser = clap::value_parser!(u32).range(0..=255)
+    )]
+    pub multicast_ttl: Option<u32>,
+
+    /// Whether datagrams sent to multicast groups loop back to local members
+    #[arg(
+        long,
+        value_name = "BOOL",
+        env = "ECHOSRV_MULTICAST_LOOP",
+        requires = "multicast_groups"
+    )]
+    pub multicast_loop: Option<bool>,
+}
+
+impl DatagramSocketArgs {
+    /// Builds the socket options these flags select on top of `socket`
+    pub fn options(&self, socket: &SocketArgs) -> SocketOptions {
+        SocketOptions {
+            broadcast: self.broadcast.then_some(true),
+            ..socket.options()
+        }
+    }
+
+    /// Builds the multicast configuration, if any group is given
+    pub fn multicast(&self) -> Option<MulticastConfig> {
+        if self.multicast_groups.is_empty() {
+            return None;
+        }
+        Some(MulticastConfig {
+            groups: self.multicast_groups.clone(),
+            ttl: self.multicast_ttl,
+            loopback: self.multicast_loop,
+        })
+    }
+}
+
+/// Flags for the `tcp` subcommand
+#[derive(Debug, Default, Args)]
+#[command(mut_arg("framing", |arg| arg.conflicts_with("service")))]
+pub struct TcpArgs {
+    #[command(flatten)]
+    pub listen: NetworkListenArgs,
+    #[command(flatten)]
+    pub connections: ConnectionArgs,
+    #[command(flatten)]
+    pub accept: AcceptArgs,
+    #[command(flatten)]
+    pub socket: SocketArgs,
+    #[command(flatten)]
+    pub stream_socket: StreamSocketArgs,
+    #[command(flatten)]
+    pub io: IoArgs,
+    #[command(flatten)]
+    pub transform: TransformArgs,
+    #[command(flatten)]
+    pub framing: FramingArgs,
+    #[command(flatten)]
+    pub service: ServiceArgs,
+    #[command(flatten)]
+    pub backend: BackendArgs,
+    #[command(flatten)]
+    pub faults: FaultArgs,
+    #[command(flatten)]
+    pub stream: StreamFaultArgs,
+    #[command(flatten)]
+    pub chaos: ChaosArgs,
+}
+
+impl TcpArgs {
+    /// Builds the chaos timeline, if any `--chaos-phase` is given
+    pub fn chaos(&self) -> Result<Option<ChaosController>, String> {
+        self.chaos.controller(|phase| {
+            phase.overrides.reject_datagram_faults(&phase.name)?;
+            Ok(FaultConfig {
+                stream: self.stream.merged_with(&phase.overrides.stream).to_config(),
+                ..self.faults.merged_with(&phase.overrides.faults).to_config()
+            })
+        })
+    }
+
+    /// Builds one configuration per listening endpoint
+    ///
+    /// Fails if an endpoint asks for datagram impairments, or for a
+    /// transform while a service is selected.
+    pub fn configs(&self) -> Result<Vec<Endpoint<TcpConfig>>, String> {
+        self.listen
+            .endpoints()
+            .into_iter()
+            .map(|spec| {
+                spec.overrides.reject_datagram_faults(&spec.addr)?;
+                self.chaos.reject_overrides(&spec)?;
+                self.service.reject_transform(&spec)?;
+                let defaults = TcpConfig::default();
+                let config = TcpConfig {
+                    bind_addr: spec.addr,
+                    additional_addrs: spec.additional_addrs.clone(),
+                    max_connections: self
+                        .connections
+                        .max_connections
+                        .unwrap_or(DEFAULT_MAX_CONNECTIONS),
+                    buffer_size: self.io.buffer_size.unwrap_or(defaults.buffer_size),
+                    read_timeout: self.io.read_timeout.unwrap_or(defaults.read_timeout),
+                    write_timeout: self.io.write_timeout.unwrap_or(defaults.write_timeout),
+                    transform: self.transform.for_endpoint(&spec),
+                    framing: self.framing.codec(),
+                    workers: self.accept.workers.unwrap_or(defaults.workers),
+                    steer_by_cpu: self.accept.steer_by_cpu,
+                    socket_options: self.stream_socket.options(&self.socket),
+                };
+                let faults = FaultConfig {
+                    stream: self.stream.merged_with(&spec.overrides.stream).to_config(),
+                    ..self.faults.merged_with(&spec.overrides.faults).to_config()
+                };
+                Ok(Endpoint::new(config, faults))
+            })
+            .collect()
+    }
+}
+
+/// Flags for the `udp` subcommand
+#[derive(Debug, Default, Args)]
+pub struct UdpArgs {
+    #[command(flatten)]
+    pub listen: NetworkListenArgs,
+    #[command(flatten)]
+    pub socket: SocketArgs,
+    #[command(flatten)]
+    pub datagram_socket: DatagramSocketArgs,
+    #[command(flatten)]
+    pub io: IoArgs,
+    #[command(flatten)]
+    pub truncation: TruncationArgs,
+    #[command(flatten)]
+    pub transform: TransformArgs,
+    #[command(flatten)]
+    pub service: ServiceArgs,
+    #[command(flatten)]
+    pub backend: BackendArgs,
+    #[command(flatten)]
+    pub faults: FaultArgs,
+    #[command(flatten)]
+    pub impairment: ImpairmentArgs,
+    #[command(flatten)]
+    pub chaos: ChaosArgs,
+
+    /// Receive loops per endpoint, each on its own SO_REUSEPORT socket;
+    /// 0 runs one per runtime worker thread
+    #[arg(long, value_name = "N", env = "ECHOSRV_WORKERS")]
+    pub workers: Option<usize>,
+
+    /// Receive and send up to N datagrams per system call (recvmmsg and
+    /// sendmmsg on Linux)
+    #[arg(long, value_name = "N", env = "ECHOSRV_BATCH")]
+    pub batch: Option<usize>,
+
+    /// Let the kernel coalesce received datagrams and segment sent ones
+    /// (UDP GRO and GSO), where supported
+    #[arg(long, env = "ECHOSRV_OFFLOAD", requires = "batch")]
+    pub offload: bool,
+
+    /// Answer TWAMP-light test packets (RFC 5357) with receive and transmit
+    /// timestamps instead of echoing, taking receive times from the kernel
+    /// where supported
+    #[arg(
+        long,
+        env = "ECHOSRV_TWAMP",
+        conflicts_with_all = ["transform", "service", "whoami"]
+    )]
+    pub twamp: bool,
+}
+
+impl UdpArgs {
+    /// Builds the chaos timeline, if any `--chaos-phase` is given
+    pub fn chaos(&self) -> Result<Option<ChaosController>, String> {
+        self.chaos.controller(|phase| {
+            phase.overrides.reject_stream_faults(&phase.name)?;
+            Ok(FaultConfig {
+                impairment: self
+                    .impairment
+                    .merged_with(&phase.overrides.impairment)
+                    .to_config(),
+                ..self.faults.merged_with(&phase.overrides.faults).to_config()
+            })
+        })
+    }
+
+    /// Builds one configuration per listening endpoint
+    ///
+    /// Fails if an endpoint asks for stream faults, or for a transform while
+    /// a service is selected.
+    pub fn configs(&self) -> Result<Vec<Endpoint<UdpConfig>>, String> {
+        self.listen
+            .endpoints()
+            .into_iter()
+            .map(|spec| {
+                spec.overrides.reject_stream_faults(&spec.addr)?;
+                self.chaos.reject_overrides(&spec)?;
+                self.service.reject_transform(&spec)?;
+                if self.twamp && spec.transform.is_some() {
+                    return Err(format!(
+                        "{}: transforms cannot be combined with --twamp",
+                        spec.addr
+                    ));
+                }
+                let defaults = UdpConfig::default();
+                let mut socket_options = self.datagram_socket.options(&self.socket);
+                if self.twamp {
+                    socket_options.timestamping = cfg!(target_os = "linux").then_some(true);
+                    socket_options.recv_ttl = Some(true);
+                }
+                let config = UdpConfig {
+                    bind_addr: spec.addr,
+                    additional_addrs: spec.additional_addrs.clone(),
+                    buffer_size: self.io.buffer_size.unwrap_or(defaults.buffer_size),
+                    read_timeout: self.io.read_timeout.unwrap_or(defaults.read_timeout),
+                    write_timeout: self.io.write_timeout.unwrap_or(defaults.write_timeout),
+                    transform: self.transform.for_endpoint(&spec),
+                    workers: self.workers.unwrap_or(defaults.workers),
+                    batch: self
+                        .batch
+                        .map(|size| BatchConfig::new(size).with_offload(self.offload)),
+                    socket_options,
+                    multicast: self.datagram_socket.multicast(),
+                    truncation: self.truncation.policy.unwrap_or(defaults.truncation),
+                };
+                let faults = FaultConfig {
+                    impairment: self
+                        .impairment
+                        .merged_with(&spec.overrides.impairment)
+                        .to_config(),
+                    ..self.faults.merged_with(&spec.overrides.faults).to_config()
+                };
+                Ok(Endpoint::new(config, faults))
+            })
+            .collect()
+    }
+}
+
+/// Flags for the `dtls` subcommand
+///
+/// The server authenticates with `--psk`, or with `--cert` and `--key`;
+/// without either it generates a self-signed certificate for localhost.
+#[cfg(feature = "dtls")]
+#[derive(Debug, Default, Args)]
+pub struct DtlsArgs {
+    #[command(flatten)]
+    pub listen: NetworkListenArgs,
+    #[command(flatten)]
+    pub socket: SocketArgs,
+    #[command(flatten)]
+    pub io: IoArgs,
+    #[command(flatten)]
+    pub truncation: TruncationArgs,
+    #[command(flatten)]
+    pub transform: TransformArgs,
+
+    /// Pre-shared key clients must know, as IDENTITY:HEXKEY
+    #[arg(
+        long,
+        value_name = "IDENTITY:HEXKEY",
+        env = "ECHOSRV_DTLS_PSK",
+        conflicts_with = "cert"
+    )]
+    pub psk: Option<Psk>,
+
+    /// PEM certificate chain to present, leaf first
+    #[arg(long, value_name = "PATH", env = "ECHOSRV_DTLS_CERT", requires = "key")]
+    pub cert: Option<PathBuf>,
+
+    /// PEM private key for --cert
+    #[arg(long, value_name = "PATH", env = "ECHOSRV_DTLS_KEY", requires = "cert")]
+    pub key: Option<PathBuf>,
+
+    /// Skip the stateless cookie exchange (HelloVerifyRequest) that new
+    /// peers otherwise complete before the server sends its larger flights
+    #[arg(long, env = "ECHOSRV_DTLS_NO_COOKIE_EXCHANGE")]
+    pub no_cookie_exchange: bool,
+
+    /// How long a peer may take to complete its handshake (e.g. "10s")
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_DTLS_HANDSHAKE_TIMEOUT", value_parser = parse_duration)]
+    pub handshake_timeout: Option<Duration>,
+
+    /// How long a session may stay silent before it is dropped (e.g. "5m")
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_DTLS_IDLE_TIMEOUT", value_parser = parse_duration)]
+    pub idle_timeout: Option<Duration>,
+
+    /// Most peer sessions kept at once
+    #[arg(long, value_name = "N", env = "ECHOSRV_DTLS_MAX_SESSIONS")]
+    pub max_sessions: Option<usize>,
+
+    /// Largest datagram handshake messages are split to fit
+    #[arg(long, value_name = "BYTES", env = "ECHOSRV_DTLS_MTU")]
+    pub mtu: Option<u16>,
+}
+
+#[cfg(feature = "dtls")]
+impl DtlsArgs {
+    /// Builds the DTLS settings, reading the certificate and key files
+    pub fn dtls(&self) -> Result<DtlsConfig, String> {
+        let defaults = match (&self.psk, &self.cert, &self.key) {
+            (Some(psk), _, _) => DtlsConfig::psk(psk.clone()),
+            (None, Some(cert), Some(key)) => {
+                let read = |path: &PathBuf| {
+                    std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))
+                };
+                DtlsConfig::certificate(read(cert)?, read(key)?)
+            }
+            _ => DtlsConfig::default(),
+        };
+        Ok(DtlsConfig {
+            cookie_exchange: !self.no_cookie_exchange,
+            handshake_timeout: self.handshake_timeout.unwrap_or(defaults.handshake_timeout),
+            idle_timeout: self.idle_timeout.unwrap_or(defaults.idle_timeout),
+            max_sessions: self.max_sessions.unwrap_or(defaults.max_sessions),
+            mtu: self.mtu.unwrap_or(defaults.mtu),
+            ..defaults
+        })
+    }
+
+    /// Builds one configuration per listening endpoint, each served with
+    /// the settings from [`dtls`](Self::dtls)
+    ///
+    /// Fails if an endpoint asks for faults, which DTLS does not support.
+    pub fn configs(&self) -> Result<Vec<DatagramConfig>, String> {
+        self.listen
+            .endpoints()
+            .into_iter()
+            .map(|spec| {
+                if spec.overrides.is_set() {
+                    return Err(format!(
+                        "{}: dtls does not support fault injection",
+                        spec.addr
+                    ));
+                }
+                let defaults = DatagramConfig::default();
+                Ok(DatagramConfig {
+                    bind_addr: spec.addr,
+                    additional_addrs: spec.additional_addrs.clone(),
+                    buffer_size: self.io.buffer_size.unwrap_or(defaults.buffer_size),
+                    read_timeout: self.io.read_timeout.unwrap_or(defaults.read_timeout),
+                    write_timeout: self.io.write_timeout.unwrap_or(defaults.write_timeout),
+                    transform: self.transform.for_endpoint(&spec),
+                    socket_options: self.socket.options(),
+                    truncation: self.truncation.policy.unwrap_or(defaults.truncation),
+                    ..defaults
+                })
+            })
+            .collect()
+    }
+}
+
+/// Flags for the `http` subcommand
+#[derive(Debug, Default, Args)]
+pub struct HttpArgs {
+    #[command(flatten)]
+    pub listen: NetworkListenArgs,
+    #[command(flatten)]
+    pub connections: ConnectionArgs,
+    #[command(flatten)]
+    pub accept: AcceptArgs,
+    #[command(flatten)]
+    pub socket: SocketArgs,
+    #[command(flatten)]
+    pub stream_socket: StreamSocketArgs,
+    #[command(flatten)]
+    pub io: IoArgs,
+    #[command(flatten)]
+    pub transform: TransformArgs,
+    #[command(flatten)]
+    pub faults: FaultArgs,
+    #[command(flatten)]
+    pub stream: StreamFaultArgs,
+    #[command(flatten)]
+    pub chaos: ChaosArgs,
+
+}
+
+impl HttpArgs {
+    /// Builds the chaos timeline, if any `--chaos-phase` is given
+    pub fn chaos(&self) -> Result<Option<ChaosController>, String> {
+        self.chaos.controller(|phase| {
+            phase.overrides.reject_datagram_faults(&phase.name)?;
+            Ok(FaultConfig {
+                stream: self.stream.merged_with(&phase.overrides.stream).to_config(),
+                ..self.faults.merged_with(&phase.overrides.faults).to_config()
+            })
+        })
+    }
+
+    /// Builds one configuration per listening endpoint
+    ///
+    /// Fails if an endpoint asks for datagram impairments.
+    pub fn configs(&self) -> Result<Vec<Endpoint<HttpConfig>>, String> {
+        self.listen
+            .endpoints()
+            .into_iter()
+            .map(|spec| {
+                spec.overrides.reject_datagram_faults(&spec.addr)?;
+                self.chaos.reject_overrides(&spec)?;
+                let defaults = HttpConfig::default();
+                let config = HttpConfig {
+                    bind_addr: spec.addr,
+                    additional_addrs: spec.additional_addrs.clone(),
+                    max_connections: self
+                        .connections
+                        .max_connections
+                        .unwrap_or(DEFAULT_MAX_CONNECTIONS),
+                    buffer_size: self.io.buffer_size.unwrap_or(defaults.buffer_size),
+                    read_timeout: self.io.read_timeout.unwrap_or(defaults.read_timeout),
+                    write_timeout: self.io.write_timeout.unwrap_or(defaults.write_timeout),
+                    transform: self.transform.for_endpoint(&spec),
+                    workers: self.accept.workers.unwrap_or(defaults.workers),
+                    steer_by_cpu: self.accept.steer_by_cpu,
+                    socket_options: self.stream_socket.options(&self.socket),
+                };
+                let faults = FaultConfig {
+                    stream: self.stream.merged_with(&spec.overrides.stream).to_config(),
+                    ..self.faults.merged_with(&spec.overrides.faults).to_config()
+                };
+                Ok(Endpoint::new(config, faults))
+            })
+            .collect()
+    }
+}
+
+/// Flags for the `unix-stream` subcommand
+#[derive(Debug, Default, Args)]
+pub struct UnixStreamArgs {
+    #[command(flatten)]
+    pub listen: UnixListenArgs,
+    #[command(flatten)]
+    pub connections: ConnectionArgs,
+    #[command(flatten)]
+    pub io: IoArgs,
+    #[command(flatten)]
+    pub transform: TransformArgs,
+    #[command(flatten)]
+    pub framing: FramingArgs,
+    #[command(flatten)]
+    pub faults: FaultArgs,
+    #[command(flatten)]
+    pub stream: StreamFaultArgs,
+    #[command(flatten)]
+    pub chaos: ChaosArgs,
+}
+
+impl UnixStreamArgs {
+    /// Builds the chaos timeline, if any `--chaos-phase` is given
+    pub fn chaos(&self) -> Result<Option<ChaosController>, String> {
+        self.chaos.controller(|phase| {
+            phase.overrides.reject_datagram_faults(&phase.name)?;
+            Ok(FaultConfig {
+                stream: self.stream.merged_with(&phase.overrides.stream).to_config(),
+                ..self.faults.merged_with(&phase.overrides.faults).to_config()
+            })
+        })
+    }
+
+    /// Builds one configuration per socket path
+    pub fn configs(&self) -> Vec<UnixStreamConfig> {
+        self.listen
+            .paths("/tmp/echosrv_stream.sock")
+            .into_iter()
+            .map(|path| {
+                let defaults = UnixStreamConfig::default();
+                let config = match &self.listen.service_name {
+                    Some(name) => defaults.with_fd_inheritance(name.clone(), path),
+                    None => defaults.with_socket_path(path),
+                };
+                UnixStreamConfig {
+                    max_connections: self
+                        .connections
+                        .max_connections
+                        .unwrap_or(config.max_connections),
+                    buffer_size: self.io.buffer_size.unwrap_or(config.buffer_size),
+                    read_timeout: self.io.read_timeout.unwrap_or(config.read_timeout),
+                    write_timeout: self.io.write_timeout.unwrap_or(config.write_timeout),
+                    transform: self.transform.transform,
+                    framing: self.framing.codec(),
+                    faults: Some(FaultConfig {
+                        stream: self.stream.to_config(),
+                        ..self.faults.to_config()
+                    })
+                    .filter(FaultConfig::is_active),
+                    ..config
+                }
+            })
+            .collect()
+    }
+}
+
+/// Flags for the `unix-dgram` subcommand
+#[derive(Debug, Default, Args)]
+pub struct UnixDgramArgs {
+    #[command(flatten)]
+    pub listen: UnixListenArgs,
+    #[command(flatten)]
+    pub io: IoArgs,
+    #[command(flatten)]
+    pub truncation: TruncationArgs,
+    #[command(flatten)]
+    pub transform: TransformArgs,
+    #[command(flatten)]
+    pub faults: FaultArgs,
+    #[command(flatten)]
+    pub impairment: ImpairmentArgs,
+    #[command(flatten)]
+    pub chaos: ChaosArgs,
+}
+
+impl UnixDgramArgs {
+    /// Builds the chaos timeline, if any `--chaos-phase` is given
+    pub fn chaos(&self) -> Result<Option<ChaosController>, String> {
+        self.chaos.controller(|phase| {
+            phase.overrides.reject_stream_faults(&phase.name)?;
+            Ok(FaultConfig {
+                impairment: self
+                    .impairment
+                    .merged_with(&phase.overrides.impairment)
+                    .to_config(),
+                ..self.faults.merged_with(&phase.overrides.faults).to_config()
+            })
+        })
+    }
+
+    /// Builds one configuration per socket path
+    pub fn configs(&self) -> Vec<UnixDatagramConfig> {
+        self.listen
+            .paths("/tmp/echosrv_datagram.sock")
+            .into_iter()
+            .map(|path| {
+                let defaults = UnixDatagramConfig::default();
+                let config = match &self.listen.service_name {
+                    Some(name) => defaults.with_fd_inheritance(name.clone(), path),
+                    None => defaults.with_socket_path(path),
+                };
+                UnixDatagramConfig {
+                    buffer_size: self.io.buffer_size.unwrap_or(config.buffer_size),
+                    read_timeout: self.io.read_timeout.unwrap_or(config.read_timeout),
+                    write_timeout: self.io.write_timeout.unwrap_or(config.write_timeout),
+                    truncation: self.truncation.policy.unwrap_or(config.truncation),
+                    transform: self.transform.transform,
+                    faults: Some(FaultConfig {
+                        impairment: self.impairment.to_config(),
+                        ..self.faults.to_config()
+                    })
+                    .filter(FaultConfig::is_active),
+                    ..config
+                }
+            })
+            .collect()
+    }
+}
+
+/// Flags for the `proxy` subcommand
+///
+/// `--read-timeout` is the idle timeout: connections close after that long
+/// without data in either direction.
+#[derive(Debug, Default, Args)]
+pub struct ProxyArgs {
+    #[command(flatten)]
+    pub listen: NetworkListenArgs,
+
+    /// Server to relay to ("HOST:PORT" or "unix:PATH")
+    #[arg(
+        long,
+        value_name = "ADDR",
+        env = "ECHOSRV_UPSTREAM",
+        required = true,
+        value_parser = parse_address
+    )]
+    pub upstream: Option<Address>,
+
+    /// How long to wait for the upstream connection
+    #[arg(long, value_name = "DURATION", env = "ECHOSRV_CONNECT_TIMEOUT", value_parser = parse_duration)]
+    pub connect_timeout: Option<Duration>,
+
+    /// Toxic to start with; repeat for several
+    ///
+    /// Toxics use toxiproxy-style specs, e.g. "slow=latency?latency=200&jitter=20"
+    /// or "cut=reset_peer?bytes=4096&stream=upstream".
+    #[arg(
+        long = "toxic",
+        value_name = "NAME=TYPE[?ATTRS]",
+        env = "ECHOSRV_TOXIC",
+        value_delimiter = ','
+    )]
+    pub toxics: Vec<Toxic>,
+
+    /// Seed for toxicity rolls and jitter (random if omitted; logged at startup)
+    #[arg(long = "fault-seed", value_name = "SEED", env = "ECHOSRV_FAULT_SEED")]
+    pub seed: Option<u64>,
+
+    /// Address for the toxic admin endpoint (GET/POST /toxics, DELETE /toxics/NAME)
+    #[arg(long, value_name = "ADDR", env = "ECHOSRV_PROXY_ADMIN")]
+    pub proxy_admin: Option<SocketAddr>,
+
+    #[command(flatten)]
+    pub connections: ConnectionArgs,
+    #[command(flatten)]
+    pub io: IoArgs,
+}
+
+impl ProxyArgs {
+    /// Builds one configuration per listening endpoint
+    ///
+    /// All endpoints share one toxic set. Fails if an endpoint carries fault
+    /// overrides or two toxics share a name.
+    pub fn configs(&self) -> Result<Vec<ProxyConfig>, String> {
+        let toxics = Toxics::default();
+        for toxic in &self.toxics {
+            toxics.add(toxic.clone()).map_err(|e| e.to_string())?;
+        }
+        let upstream = self
+            .upstream
+            .clone()
+            .ok_or_else(|| "an upstream address is required".to_string())?;
+
+        self.listen
+            .endpoints()
+            .into_iter()
+            .map(|spec| {
+                if spec.overrides.is_set() {
+                    return Err(format!(
+                        "endpoint {} has fault overrides; the proxy takes --toxic instead",
+                        spec.addr
+                    ));
+                }
+                if spec.transform.is_some() {
+                    return Err(format!(
+                        "endpoint {}: the proxy relays data unchanged and has no transforms",
+                        spec.addr
+                    ));
+                }
+                if !spec.additional_addrs.is_empty() {
+                    return Err(format!(
+                        "endpoint {}: the proxy listens on one address per endpoint; repeat --listen instead",
+                        spec.addr
+                    ));
+                }
+                let defaults = ProxyConfig::default();
+                Ok(ProxyConfig {
+                    bind_addr: spec.addr,
+                    upstream: upstream.clone(),
+                    max_connections: self
+                        .connections
+                        .max_connections
+                        .unwrap_or(DEFAULT_MAX_CONNECTIONS),
+                    buffer_size: self.io.buffer_size.unwrap_or(defaults.buffer_size),
+                    connect_timeout: self.connect_timeout.unwrap_or(defaults.connect_timeout),
+                    idle_timeout: self.io.read_timeout.unwrap_or(defaults.idle_timeout),
+                    write_timeout: self.io.write_timeout.unwrap_or(defaults.write_timeout),
+                    seed: self.seed,
+                    toxics: toxics.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Parses a network address or a "unix:"-prefixed socket path
+pub fn parse_address(input: &str) -> Result<Address, String> {
+    input
+        .parse()
+        .map_err(|_| format!("invalid address '{input}', expected HOST:PORT or unix:PATH"))
+}
+
+/// Parses a human-readable duration such as "30s", "500ms", "2m" or "1h"
+///
+/// A bare number is interpreted as seconds.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (value, unit) = input.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration '{input}'"))?;
+
+    let seconds = match unit.trim() {
+        "" | "s" | "sec" | "secs" => value,
+        "ms" => value / 1_000.0,
+        "us" => value / 1_000_000.0,
+        "m" | "min" | "mins" => value * 60.0,
+        "h" => value * 3_600.0,
+        other => return Err(format!("unknown duration unit '{other}' in '{input}'")),
+    };
+
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("invalid duration '{input}': {e}"))
+}
+
+/// Parses a percentage such as "5", "5%" or "0.5%" into a probability
+pub fn parse_percent(input: &str) -> Result<f64, String> {
+    let value: f64 = input
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("invalid percentage '{input}'"))?;
+    if !(0.0..=100.0).contains(&value) {
+        return Err(format!("percentage '{input}' is not between 0 and 100"));
+    }
+    Ok(value / 100.0)
+}
+
+/// Parses a buffer size as a byte count, or "auto" for the largest datagram
+pub fn parse_buffer_size(input: &str) -> Result<usize, String> {
+    if input.trim().eq_ignore_ascii_case("auto") {
+        return Ok(MAX_DATAGRAM_SIZE);
+    }
+    parse_bytes(input).map(|size| size as usize)
+}
+
+/// Parses a byte count such as "512", "64k", "1.5m" or "1g" (binary multiples)
+pub fn parse_bytes(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (value, unit) = input.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid byte count '{input}'"))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        other => return Err(format!("unknown byte unit '{other}' in '{input}'")),
+    };
+
+    Ok((value * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("1.5h").unwrap(), Duration::from_secs(5400));
+        assert!(parse_duration("fast").is_err());
+        assert!(parse_duration("10 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_legacy_positional_port() {
+        let cli = Cli::try_parse_from(["echosrv", "udp", "9090"]).unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        let configs = args.configs().unwrap();
+        assert_eq!(configs.len(), 1);
+        assert_eq!(
+            configs[0].config.bind_addr,
+            "127.0.0.1:9090".parse().unwrap()
+        );
+        assert!(configs[0].faults.is_none());
+        assert_eq!(configs[0].config.workers, 1);
+        assert_eq!(configs[0].config.batch, None);
+    }
+
+    #[test]
+    fn test_backend() {
+        let cli = Cli::try_parse_from(["echosrv", "tcp", "--backend", "io-uring"]).unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        assert_eq!(args.backend.backend, Some(Backend::IoUring));
+
+        let cli = Cli::try_parse_from(["echosrv", "udp", "--backend", "tokio", "9090"]).unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        assert_eq!(args.backend.backend, Some(Backend::Tokio));
+
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--backend", "epoll"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "http", "--backend", "io-uring"]).is_err());
+    }
+
+    #[test]
+    fn test_udp_workers() {
+        let cli = Cli::try_parse_from(["echosrv", "udp", "--workers", "4", "9090"]).unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        assert_eq!(args.configs().unwrap()[0].config.workers, 4);
+    }
+
+    #[test]
+    fn test_accept_workers() {
+        let cli =
+            Cli::try_parse_from(["echosrv", "tcp", "--workers", "0", "--steer-by-cpu"]).unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        let config = &args.configs().unwrap()[0].config;
+        assert_eq!(config.workers, 0);
+        assert!(config.steer_by_cpu);
+
+        let cli = Cli::try_parse_from(["echosrv", "http", "--workers", "4"]).unwrap();
+        let Some(Command::Http(args)) = cli.command else {
+            panic!("expected http subcommand");
+        };
+        let config = &args.configs().unwrap()[0].config;
+        assert_eq!(config.workers, 4);
+        assert!(!config.steer_by_cpu);
+
+        // Steering needs several loops to steer between
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--steer-by-cpu"]).is_err());
+    }
+
+    #[test]
+    fn test_socket_options() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "tcp",
+            "--nodelay",
+            "--keepalive",
+            "60s",
+            "--keepalive-retries",
+            "3",
+            "--recv-buffer-size",
+            "256k",
+            "--dscp",
+            "46",
+            "--linger",
+            "0",
+            "--backlog",
+            "4096",
+        ])
+        .unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        let options = &args.configs().unwrap()[0].config.socket_options;
+        assert_eq!(options.nodelay, Some(true));
+        assert_eq!(
+            options.keepalive,
+            Some(Keepalive::new(Duration::from_secs(60)).with_retries(3))
+        );
+        assert_eq!(options.recv_buffer_size, Some(256 * 1024));
+        assert_eq!(options.tos, Some(184));
+        assert_eq!(options.linger, Some(Duration::ZERO));
+        assert_eq!(options.backlog, Some(4096));
+        assert_eq!(options.fastopen, None);
+
+        let cli = Cli::try_parse_from(["echosrv", "udp", "--ttl", "8", "9090"]).unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        let options = &args.configs().unwrap()[0].config.socket_options;
+        assert_eq!(options.ttl, Some(8));
+        assert_eq!(options.nodelay, None);
+
+        // Stream options are not offered for datagrams
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--nodelay"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--tos", "16", "--dscp", "4"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--dscp", "64"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "http", "--keepalive-interval", "5s"]).is_err());
+    }
+
+    #[test]
+    fn test_multicast() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "udp",
+            "--host",
+            "0.0.0.0",
+            "--multicast-group",
+            "239.1.2.3%1,ff02::1",
+            "--multicast-ttl",
+            "4",
+            "--multicast-loop",
+            "false",
+            "--broadcast",
+            "5353",
+        ])
+        .unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        let config = &args.configs().unwrap()[0].config;
+        let multicast = config.multicast.as_ref().unwrap();
+        assert_eq!(
+            multicast.groups,
+            vec![
+                MulticastGroup::new("239.1.2.3".parse().unwrap()).on_interface(1),
+                MulticastGroup::new("ff02::1".parse().unwrap()),
+            ]
+        );
+        assert_eq!(multicast.ttl, Some(4));
+        assert_eq!(multicast.loopback, Some(false));
+        assert_eq!(config.socket_options.broadcast, Some(true));
+
+        let cli = Cli::try_parse_from(["echosrv", "udp", "5353"]).unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        assert!(args.configs().unwrap()[0].config.multicast.is_none());
+
+        // Unicast groups and settings without a group are rejected
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--multicast-group", "10.0.0.1"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--multicast-ttl", "2"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--broadcast"]).is_err());
+    }
+
+    #[test]
+    fn test_udp_batch() {
+        let cli =
+            Cli::try_parse_from(["echosrv", "udp", "--batch", "64", "--offload", "9090"]).unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        assert_eq!(
+            args.configs().unwrap()[0].config.batch,
+            Some(BatchConfig::new(64).with_offload(true))
+        );
+
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--offload", "9090"]).is_err());
+    }
+
+    #[test]
+    fn test_truncation() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "udp",
+            "--buffer-size",
+            "auto",
+            "--truncation",
+            "error",
+        ])
+        .unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        let config = &args.configs().unwrap()[0].config;
+        assert_eq!(config.buffer_size, MAX_DATAGRAM_SIZE);
+        assert_eq!(config.truncation, TruncationPolicy::Error);
+
+        let cli = Cli::try_parse_from(["echosrv", "unix-dgram", "--truncation", "drop"]).unwrap();
+        let Some(Command::UnixDgram(args)) = cli.command else {
+            panic!("expected unix-dgram subcommand");
+        };
+        assert_eq!(args.configs()[0].truncation, TruncationPolicy::Drop);
+        assert_eq!(args.configs()[0].buffer_size, 1024);
+
+        assert_eq!(parse_buffer_size("4k").unwrap(), 4096);
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--truncation", "clip"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--truncation", "drop"]).is_err());
+    }
+
+    #[test]
+    fn test_whoami() {
+        let cli = Cli::try_parse_from(["echosrv", "udp", "--whoami", "stun", "3478"]).unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        assert_eq!(args.service.whoami, Some(Whoami::Stun));
+
+        let cli = Cli::try_parse_from(["echosrv", "tcp", "--whoami", "text"]).unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        assert_eq!(args.service.whoami, Some(Whoami::Text));
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "tcp",
+            "--whoami",
+            "text",
+            "--listen",
+            "127.0.0.1:8080?transform=upper",
+        ])
+        .unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        assert!(args.configs().is_err());
+
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--whoami", "dns"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "unix-dgram", "--whoami", "text"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--whoami", "text", "--twamp"]).is_err());
+        assert!(
+            Cli::try_parse_from(["echosrv", "tcp", "--whoami", "text", "--service", "qotd"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_twamp() {
+        let cli = Cli::try_parse_from(["echosrv", "udp", "--twamp"]).unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        assert!(args.twamp);
+        let options = &args.configs().unwrap()[0].config.socket_options;
+        assert_eq!(options.recv_ttl, Some(true));
+        assert_eq!(options.timestamping.is_some(), cfg!(target_os = "linux"));
+
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--twamp", "--service", "qotd"]).is_err());
+        assert!(
+            Cli::try_parse_from(["echosrv", "udp", "--twamp", "--transform", "upper"]).is_err()
+        );
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--twamp"]).is_err());
+    }
+
+    #[cfg(feature = "dtls")]
+    #[test]
+    fn test_dtls() {
+        use echosrv::dtls::DtlsCredentials;
+
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "dtls",
+            "--psk",
+            "client:00ff",
+            "--no-cookie-exchange",
+            "--idle-timeout",
+            "1m",
+            "4433",
+        ])
+        .unwrap();
+        let Some(Command::Dtls(args)) = cli.command else {
+            panic!("expected dtls subcommand");
+        };
+        let configs = args.configs().unwrap();
+        assert_eq!(configs[0].bind_addr, "127.0.0.1:4433".parse().unwrap());
+        let dtls = args.dtls().unwrap();
+        assert_eq!(
+            dtls.credentials,
+            DtlsCredentials::Psk(Psk::new("client", vec![0x00, 0xff]))
+        );
+        assert!(!dtls.cookie_exchange);
+        assert_eq!(dtls.idle_timeout, Duration::from_secs(60));
+
+        let cli = Cli::try_parse_from(["echosrv", "dtls"]).unwrap();
+        let Some(Command::Dtls(args)) = cli.command else {
+            panic!("expected dtls subcommand");
+        };
+        let dtls = args.dtls().unwrap();
+        assert_eq!(dtls.credentials, DtlsCredentials::SelfSigned);
+        assert!(dtls.cookie_exchange);
+
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "dtls",
+            "--cert",
+            "/nonexistent/cert.pem",
+            "--key",
+            "/nonexistent/key.pem",
+        ])
+        .unwrap();
+        let Some(Command::Dtls(args)) = cli.command else {
+            panic!("expected dtls subcommand");
+        };
+        assert!(args.dtls().is_err());
+
+        assert!(Cli::try_parse_from(["echosrv", "dtls", "--psk", "nokey"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "dtls", "--cert", "cert.pem"]).is_err());
+        assert!(
+            Cli::try_parse_from([
+                "echosrv", "dtls", "--psk", "a:00", "--cert", "c", "--key", "k"
+            ])
+            .is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["echosrv", "dtls", "--listen", "127.0.0.1:4433?loss=0.5"])
+                .unwrap()
+                .command
+                .is_some_and(|command| match command {
+                    Command::Dtls(args) => args.configs().is_err(),
+                    _ => false,
+                })
+        );
+    }
+
+    #[test]
+    fn test_repeated_listen_and_overrides() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "tcp",
+            "--listen",
+            "127.0.0.1:7000",
+            "--listen",
+            "[::1]:7000",
+            "--max-connections",
+            "5",
+            "--buffer-size",
+            "4096",
+            "--read-timeout",
+            "5s",
+        ])
+        .unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        let configs: Vec<_> = args
+            .configs()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.config)
+            .collect();
+        assert_eq!(configs.len(), 2);
+        assert_eq!(configs[1].bind_addr, "[::1]:7000".parse().unwrap());
+        assert!(configs.iter().all(|c| c.max_connections == 5));
+        assert!(configs.iter().all(|c| c.buffer_size == 4096));
+        assert!(
+            configs
+                .iter()
+                .all(|c| c.read_timeout == Duration::from_secs(5))
+        );
+        assert!(
+            configs
+                .iter()
+                .all(|c| c.write_timeout == Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn test_multi_address_listen() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "tcp",
+            "--listen",
+            "127.0.0.1:7000+[::1]:7000?transform=reverse",
+        ])
+        .unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        let configs = args.configs().unwrap();
+        assert_eq!(configs.len(), 1);
+        let config = &configs[0].config;
+        assert_eq!(config.bind_addr, "127.0.0.1:7000".parse().unwrap());
+        assert_eq!(config.additional_addrs, ["[::1]:7000".parse().unwrap()]);
+        assert_eq!(config.transform, Some(Transform::Reverse));
+
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "udp",
+            "--host",
+            "::",
+            "--ipv6-only",
+            "false",
+            "9090",
+        ])
+        .unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        let config = &args.configs().unwrap()[0].config;
+        assert_eq!(config.bind_addr, "[::]:9090".parse().unwrap());
+        assert!(config.additional_addrs.is_empty());
+        assert_eq!(config.socket_options.only_v6, Some(false));
+
+        let cli = Cli::try_parse_from(["echosrv", "http", "--host", "127.0.0.1,::1"]).unwrap();
+        let Some(Command::Http(args)) = cli.command else {
+            panic!("expected http subcommand");
+        };
+        let config = &args.configs().unwrap()[0].config;
+        assert_eq!(config.bind_addr, "127.0.0.1:8080".parse().unwrap());
+        assert_eq!(config.additional_addrs, ["[::1]:8080".parse().unwrap()]);
+
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--listen", "127.0.0.1:7000+"]).is_err());
+        assert!(
+            Cli::try_parse_from(["echosrv", "tcp", "--host", "::1", "--listen", "[::1]:1"])
+                .is_err()
+        );
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "proxy",
+            "--upstream",
+            "127.0.0.1:9000",
+            "--listen",
+            "127.0.0.1:7000+[::1]:7000",
+        ])
+        .unwrap();
+        let Some(Command::Proxy(args)) = cli.command else {
+            panic!("expected proxy subcommand");
+        };
+        assert!(args.configs().is_err());
+    }
+
+    #[test]
+    fn test_http_defaults() {
+        let cli = Cli::try_parse_from(["echosrv", "http"]).unwrap();
+        let Some(Command::Http(args)) = cli.command else {
+            panic!("expected http subcommand");
+        };
+        let config = args.configs().unwrap().remove(0).config;
+        assert_eq!(config.bind_addr, "127.0.0.1:8080".parse().unwrap());
+        assert_eq!(config.buffer_size, 8192);
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        assert_eq!(parse_bytes("512").unwrap(), 512);
+        assert_eq!(parse_bytes("64k").unwrap(), 64 * 1024);
+        assert_eq!(parse_bytes("1.5M").unwrap(), 1536 * 1024);
+        assert_eq!(parse_bytes("1g").unwrap(), 1 << 30);
+        assert!(parse_bytes("lots").is_err());
+        assert!(parse_bytes("3 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_per_listener_faults() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "tcp",
+            "--latency",
+            "50ms",
+            "--fault-seed",
+            "7",
+            "--listen",
+            "127.0.0.1:7000",
+            "--listen",
+            "127.0.0.1:7001?latency=200ms&jitter=20ms&jitter-distribution=normal&write-bandwidth=64k",
+        ])
+        .unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        let endpoints = args.configs().unwrap();
+
+        let global = endpoints[0].faults.as_ref().unwrap();
+        assert_eq!(global.seed, Some(7));
+        assert_eq!(
+            global.latency,
+            Some(LatencyConfig::fixed(Duration::from_millis(50)))
+        );
+        assert!(!global.bandwidth.is_limited());
+
+        let custom = endpoints[1].faults.as_ref().unwrap();
+        assert_eq!(custom.seed, Some(7));
+        let latency = custom.latency.as_ref().unwrap();
+        assert_eq!(latency.base, Duration::from_millis(200));
+        assert_eq!(latency.jitter, Duration::from_millis(20));
+        assert_eq!(latency.distribution, JitterDistribution::Normal);
+        assert_eq!(custom.bandwidth.write, Some(64 * 1024));
+    }
+
+    #[test]
+    fn test_transforms() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "http",
+            "--transform",
+            "sha256",
+            "--listen",
+            "127.0.0.1:7000",
+            "--listen",
+            "127.0.0.1:7001?latency=5ms&transform=hexdump",
+        ])
+        .unwrap();
+        let Some(Command::Http(args)) = cli.command else {
+            panic!("expected http subcommand");
+        };
+        let endpoints = args.configs().unwrap();
+        assert_eq!(endpoints[0].config.transform, Some(Transform::Sha256));
+        assert!(endpoints[0].faults.is_none());
+        assert_eq!(endpoints[1].config.transform, Some(Transform::HexDump));
+        assert!(endpoints[1].faults.is_some());
+
+        let cli = Cli::try_parse_from(["echosrv", "udp", "9090"]).unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        assert_eq!(args.configs().unwrap()[0].config.transform, None);
+
+        let cli = Cli::try_parse_from(["echosrv", "unix-stream", "--transform", "rot13"]).unwrap();
+        let Some(Command::UnixStream(args)) = cli.command else {
+            panic!("expected unix-stream subcommand");
+        };
+        assert_eq!(args.configs()[0].transform, Some(Transform::Rot13));
+        let cli = Cli::try_parse_from(["echosrv", "unix-dgram", "--transform", "crc32"]).unwrap();
+        let Some(Command::UnixDgram(args)) = cli.command else {
+            panic!("expected unix-dgram subcommand");
+        };
+        assert_eq!(args.configs()[0].transform, Some(Transform::Crc32));
+
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--transform", "md5"]).is_err());
+        assert!(
+            Cli::try_parse_from(["echosrv", "tcp", "--listen", "127.0.0.1:7000?transform=md5"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_services() {
+        let cli = Cli::try_parse_from(["echosrv", "udp", "--service", "chargen", "1919"]).unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        assert_eq!(args.service.service, Some(Service::Chargen));
+        assert!(args.configs().is_ok());
+
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--service", "finger"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "http", "--service", "discard"]).is_err());
+        assert!(
+            Cli::try_parse_from([
+                "echosrv",
+                "tcp",
+                "--service",
+                "discard",
+                "--transform",
+                "uppercase"
+            ])
+            .is_err()
+        );
+
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "tcp",
+            "--service",
+            "daytime",
+            "--listen",
+            "127.0.0.1:1313?transform=reverse",
+        ])
+        .unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        assert!(args.configs().is_err());
+    }
+
+    #[test]
+    fn test_framing() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "tcp",
+            "--framing",
+            "netstring",
+            "--max-frame-size",
+            "512",
+        ])
+        .unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        let codec = args.configs().unwrap()[0].config.framing.clone().unwrap();
+        assert_eq!(codec.framing(), Framing::Netstring);
+        assert_eq!(codec.max_frame_size(), 512);
+
+        let cli = Cli::try_parse_from(["echosrv", "tcp", "--framing", "lines"]).unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        assert_eq!(
+            args.configs().unwrap()[0].config.framing,
+            Some(FrameCodec::new(Framing::Lines))
+        );
+
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--framing", "json"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--max-frame-size", "512"]).is_err());
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--framing", "lines"]).is_err());
+
+        let cli = Cli::try_parse_from(["echosrv", "unix-stream", "--framing", "u16"]).unwrap();
+        let Some(Command::UnixStream(args)) = cli.command else {
+            panic!("expected unix-stream subcommand");
+        };
+        assert_eq!(
+            args.configs()[0].framing,
+            Some(FrameCodec::new(Framing::LengthU16))
+        );
+        assert!(Cli::try_parse_from(["echosrv", "unix-dgram", "--framing", "lines"]).is_err());
+        assert!(
+            Cli::try_parse_from([
+                "echosrv",
+                "tcp",
+                "--framing",
+                "lines",
+                "--service",
+                "daytime"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_invalid_listen_spec() {
+        for spec in [
+            "127.0.0.1:7000?latency",
+            "127.0.0.1:7000?speed=fast",
+            "127.0.0.1:7000?jitter-distribution=pareto",
+        ] {
+            assert!(Cli::try_parse_from(["echosrv", "udp", "--listen", spec]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_datagram_impairment_flags() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "udp",
+            "--loss",
+            "5%",
+            "--fault-seed",
+            "3",
+            "--listen",
+            "127.0.0.1:7000",
+            "--listen",
+            "127.0.0.1:7001?loss=0&duplicate=1.5&reorder=10&reorder-window=5",
+        ])
+        .unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        let endpoints = args.configs().unwrap();
+
+        let global = endpoints[0].faults.as_ref().unwrap();
+        assert_eq!(global.seed, Some(3));
+        assert_eq!(global.impairment.loss, 0.05);
+        assert!(global.latency.is_none());
+
+        let custom = &endpoints[1].faults.as_ref().unwrap().impairment;
+        assert_eq!(custom.loss, 0.0);
+        assert_eq!(custom.duplicate, 0.015);
+        assert_eq!(custom.reorder, 0.1);
+        assert_eq!(custom.reorder_window, 5);
+
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--corrupt", "101"]).is_err());
+    }
+
+    #[test]
+    fn test_impairment_rejected_for_stream_endpoints() {
+        assert!(Cli::try_parse_from(["echosrv", "tcp", "--loss", "5"]).is_err());
+
+        let cli =
+            Cli::try_parse_from(["echosrv", "tcp", "--listen", "127.0.0.1:7000?loss=5"]).unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        assert!(args.configs().is_err());
+    }
+
+    #[test]
+    fn test_stream_fault_flags_and_overrides() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "tcp",
+            "--reset-after",
+            "1KiB",
+            "--reset-percent",
+            "25",
+            "--accept-limit",
+            "10",
+            "--listen",
+            "127.0.0.1:7000",
+            "--listen",
+            "127.0.0.1:7001?stall=write&stall-after=64&partial-writes=8&half-close-after=512",
+        ])
+        .unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        let endpoints = args.configs().unwrap();
+
+        let global = &endpoints[0].faults.as_ref().unwrap().stream;
+        let reset = global.reset.as_ref().unwrap();
+        assert_eq!(reset.after_bytes, 1024);
+        assert_eq!(reset.probability, 0.25);
+        assert_eq!(global.accept_limit, Some(10));
+        assert!(global.stall.is_none());
+
+        let custom = &endpoints[1].faults.as_ref().unwrap().stream;
+        let stall = custom.stall.as_ref().unwrap();
+        assert_eq!(stall.direction, StallDirection::Write);
+        assert_eq!(stall.after_bytes, 64);
+        assert_eq!(stall.probability, 1.0);
+        let partial = custom.partial_writes.as_ref().unwrap();
+        assert_eq!(partial.max_chunk, 8);
+        assert_eq!(partial.delay, DEFAULT_PARTIAL_WRITE_DELAY);
+        assert_eq!(custom.half_close.as_ref().unwrap().after_bytes, 512);
+        assert!(custom.reset.is_some());
+
+        // A percentage without its mode enables nothing
+        let cli = Cli::try_parse_from(["echosrv", "tcp", "--stall-percent", "50"]).unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        assert!(args.configs().unwrap()[0].faults.is_none());
+    }
+
+    #[test]
+    fn test_unix_stream_faults_and_chaos() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "unix-stream",
+            "--half-close-after",
+            "64",
+            "--latency",
+            "5ms",
+            "--chaos-phase",
+            "resets=10s?reset-after=0",
+            "/tmp/echo.sock",
+        ])
+        .unwrap();
+        let Some(Command::UnixStream(args)) = cli.command else {
+            panic!("expected unix-stream subcommand");
+        };
+        let faults = args.configs()[0].faults.clone().unwrap();
+        assert_eq!(faults.stream.half_close.as_ref().unwrap().after_bytes, 64);
+        assert_eq!(
+            faults.latency,
+            Some(LatencyConfig::fixed(Duration::from_millis(5)))
+        );
+        let chaos = args.chaos().unwrap().unwrap();
+        assert_eq!(
+            chaos.phases()[0]
+                .faults
+                .stream
+                .reset
+                .as_ref()
+                .unwrap()
+                .after_bytes,
+            0
+        );
+
+        let cli = Cli::try_parse_from(["echosrv", "unix-stream"]).unwrap();
+        let Some(Command::UnixStream(args)) = cli.command else {
+            panic!("expected unix-stream subcommand");
+        };
+        assert!(args.configs()[0].faults.is_none());
+
+        assert!(Cli::try_parse_from(["echosrv", "unix-stream", "--loss", "5"]).is_err());
+        let cli =
+            Cli::try_parse_from(["echosrv", "unix-stream", "--chaos-phase", "lossy=1m?loss=5"])
+                .unwrap();
+        let Some(Command::UnixStream(args)) = cli.command else {
+            panic!("expected unix-stream subcommand");
+        };
+        assert!(args.chaos().is_err());
+    }
+
+    #[test]
+    fn test_stream_faults_rejected_for_datagram_endpoints() {
+        assert!(Cli::try_parse_from(["echosrv", "udp", "--reset-after", "10"]).is_err());
+
+        let cli = Cli::try_parse_from(["echosrv", "udp", "--listen", "127.0.0.1:7000?stall=read"])
+            .unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        assert!(args.configs().is_err());
+    }
+
+    #[test]
+    fn test_chaos_phases() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "udp",
+            "--latency",
+            "10ms",
+            "--fault-seed",
+            "4",
+            "--chaos-phase",
+            "healthy=5m",
+            "--chaos-phase",
+            "lossy=1m?loss=30&latency=2s",
+            "--chaos-admin",
+            "127.0.0.1:9900",
+        ])
+        .unwrap();
+        let Some(Command::Udp(args)) = cli.command else {
+            panic!("expected udp subcommand");
+        };
+        assert_eq!(
+            args.chaos.chaos_admin,
+            Some("127.0.0.1:9900".parse().unwrap())
+        );
+
+        let chaos = args.chaos().unwrap().unwrap();
+        let phases = chaos.phases();
+        assert_eq!(phases.len(), 2);
+        assert_eq!(phases[0].name, "healthy");
+        assert_eq!(phases[0].duration, Duration::from_secs(300));
+        assert_eq!(
+            phases[0].faults.latency,
+            Some(LatencyConfig::fixed(Duration::from_millis(10)))
+        );
+        assert_eq!(phases[1].faults.impairment.loss, 0.3);
+        assert_eq!(
+            phases[1].faults.latency,
+            Some(LatencyConfig::fixed(Duration::from_secs(2)))
+        );
+
+        let mut faults = args.configs().unwrap().remove(0).faults;
+        attach_chaos(&mut faults, Some(&chaos));
+        let faults = faults.unwrap();
+        assert_eq!(faults.seed, Some(4));
+        assert_eq!(faults.chaos, Some(chaos));
+    }
+
+    #[test]
+    fn test_invalid_chaos_phases() {
+        assert!("healthy".parse::<PhaseSpec>().is_err());
+        assert!("=5m".parse::<PhaseSpec>().is_err());
+        assert!("slow=soon".parse::<PhaseSpec>().is_err());
+        // The admin endpoint needs a timeline to control
+        assert!(
+            Cli::try_parse_from(["echosrv", "tcp", "--chaos-admin", "127.0.0.1:9900"]).is_err()
+        );
+
+        let cli =
+            Cli::try_parse_from(["echosrv", "tcp", "--chaos-phase", "lossy=1m?loss=5"]).unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        assert!(args.chaos().is_err());
+
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "tcp",
+            "--chaos-phase",
+            "calm=1m",
+            "--listen",
+            "127.0.0.1:7000?latency=5ms",
+        ])
+        .unwrap();
+        let Some(Command::Tcp(args)) = cli.command else {
+            panic!("expected tcp subcommand");
+        };
+        assert!(args.configs().is_err());
+    }
+
+    #[test]
+    fn test_proxy_args() {
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "proxy",
+            "--listen",
+            "127.0.0.1:9000",
+            "--listen",
+            "127.0.0.1:9001",
+            "--upstream",
+            "unix:/tmp/upstream.sock",
+            "--toxic",
+            "slow=latency?latency=200",
+            "--toxic",
+            "cut=reset_peer?bytes=10&stream=upstream",
+            "--read-timeout",
+            "1m",
+            "--fault-seed",
+            "3",
+            "--proxy-admin",
+            "127.0.0.1:9901",
+        ])
+        .unwrap();
+        let Some(Command::Proxy(args)) = cli.command else {
+            panic!("expected proxy subcommand");
+        };
+        assert_eq!(args.proxy_admin, Some("127.0.0.1:9901".parse().unwrap()));
+
+        let configs = args.configs().unwrap();
+        assert_eq!(configs.len(), 2);
+        assert_eq!(
+            configs[0].upstream,
+            "unix:/tmp/upstream.sock".parse().unwrap()
+        );
+        assert_eq!(configs[0].idle_timeout, Duration::from_secs(60));
+        assert_eq!(configs[0].seed, Some(3));
+        assert_eq!(configs[0].toxics.list().len(), 2);
+
+        // Endpoints share one toxic set
+        configs[0].toxics.remove("slow").unwrap();
+        assert_eq!(configs[1].toxics.list().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_proxy_args() {
+        assert!(Cli::try_parse_from(["echosrv", "proxy", "9000"]).is_err());
+        assert!(
+            Cli::try_parse_from(["echosrv", "proxy", "--upstream", "x", "--toxic", "a=fast"])
+                .is_err()
+        );
+
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "proxy",
+            "--upstream",
+            "127.0.0.1:8080",
+            "--toxic",
+            "a=latency",
+            "--toxic",
+            "a=bandwidth?rate=5",
+        ])
+        .unwrap();
+        let Some(Command::Proxy(args)) = cli.command else {
+            panic!("expected proxy subcommand");
+        };
+        assert!(args.configs().is_err());
+
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "proxy",
+            "--upstream",
+            "127.0.0.1:8080",
+            "--listen",
+            "127.0.0.1:9000?latency=5ms",
+        ])
+        .unwrap();
+        let Some(Command::Proxy(args)) = cli.command else {
+            panic!("expected proxy subcommand");
+        };
+        assert!(args.configs().is_err());
+
+        let cli = Cli::try_parse_from([
+            "echosrv",
+            "proxy",
+            "--upstream",
+            "127.0.0.1:8080",
+            "--listen",
+            "127.0.0.1:9000?transform=uppercase",
+        ])
+        .unwrap();
+        let Some(Command::Proxy(args)) = cli.command else {
+            panic!("expected proxy subcommand");
+        };
+        assert!(args.configs().is_err());
+    }
+}
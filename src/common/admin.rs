@@ -0,0 +1,172 @@
+//! Minimal HTTP/1.1 plumbing shared by the admin endpoints
+//!
+//! Admin endpoints answer one request per connection and close it, which
+//! keeps them usable from `curl` without pulling in an HTTP server.
+
+use crate::{EchoError, Result};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{Instrument, debug, error, warn};
+
+/// Largest request (head plus body) an admin endpoint reads
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// First wait after a failed accept
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(100);
+
+/// Longest wait between accept retries
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// A parsed admin request
+#[derive(Debug)]
+pub(crate) struct AdminRequest {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) body: Vec<u8>,
+}
+
+/// Response to an admin request
+#[derive(Debug)]
+pub(crate) struct AdminResponse {
+    pub(crate) status: u16,
+    pub(crate) content_type: &'static str,
+    pub(crate) body: String,
+}
+
+impl AdminResponse {
+    /// A plain text response
+    pub(crate) fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: body.into(),
+        }
+    }
+
+    /// The response for a known path requested with the wrong method
+    pub(crate) fn method_not_allowed() -> Self {
+        Self::text(405, "Method not allowed\n")
+    }
+
+    /// The response for an unknown path
+    pub(crate) fn not_found() -> Self {
+        Self::text(404, "Not found\n")
+    }
+}
+
+/// Serves admin requests on `listener` with `route` until the task is dropped
+pub(crate) async fn serve<F>(listener: TcpListener, endpoint: &'static str, route: F) -> Result<()>
+where
+    F: Fn(AdminRequest) -> AdminResponse + Clone + Send + 'static,
+{
+    let mut backoff = ACCEPT_BACKOFF_MIN;
+    loop {
+        // A failed accept (e.g. out of file descriptors) must not stop the
+        // endpoint for good, nor spin while the condition lasts
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => {
+                backoff = ACCEPT_BACKOFF_MIN;
+                accepted
+            }
+            Err(e) => {
+                error!(endpoint, error = %e, retry_in = ?backoff, "Failed to accept admin connection");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                continue;
+            }
+        };
+        let route = route.clone();
+        let span = tracing::info_span!("admin", endpoint, %addr);
+        tokio::spawn(
+            async move {
+                if let Err(e) = handle(stream, route).await {
+                    warn!(error = %e, "Admin request failed");
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+async fn handle<F>(mut stream: TcpStream, route: F) -> Result<()>
+where
+    F: Fn(AdminRequest) -> AdminResponse,
+{
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream))
+        .await
+        .map_err(|_| EchoError::Timeout("Admin request".to_string()))??;
+
+    let response = match request {
+        Some(request) => {
+            debug!(method = %request.method, path = %request.path, "Admin request");
+            route(request)
+        }
+        None => AdminResponse::text(400, "Malformed request\n"),
+    };
+
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    };
+    let reply = format!(
+        "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Reads one request, returning `None` if it cannot be parsed
+async fn read_request(stream: &mut TcpStream) -> Result<Option<AdminRequest>> {
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 1024];
+    loop {
+        let mut headers = [httparse::EMPTY_HEADER; 32];
+        let mut request = httparse::Request::new(&mut headers);
+        match request.parse(&data) {
+            Ok(httparse::Status::Complete(head_len)) => {
+                let body_len = request
+                    .headers
+                    .iter()
+                    .find(|h| h.name.eq_ignore_ascii_case("content-length"))
+                    .and_then(|h| std::str::from_utf8(h.value).ok())
+                    .and_then(|v| v.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                if head_len + body_len > MAX_REQUEST {
+                    return Err(EchoError::Config("Admin request too large".to_string()));
+                }
+                if data.len() >= head_len + body_len {
+                    return Ok(Some(AdminRequest {
+                        method: request.method.unwrap_or_default().to_string(),
+                        path: request.path.unwrap_or_default().to_string(),
+                        body: data[head_len..head_len + body_len].to_vec(),
+                    }));
+                }
+            }
+            Ok(httparse::Status::Partial) => {}
+            Err(_) => return Ok(None),
+        }
+
+        if data.len() > MAX_REQUEST {
+            return Err(EchoError::Config("Admin request too large".to_string()));
+        }
+        let n = stream.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        data.extend_from_slice(&buffer[..n]);
+    }
+}
@@ -3,6 +3,7 @@
 //! This module contains the core traits that define the interface
 //! for echo servers and clients.
 
+pub(crate) mod admin;
 pub mod test_utils;
 pub mod traits;
 
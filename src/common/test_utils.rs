@@ -25,10 +25,16 @@ pub async fn create_controlled_test_server_with_limit(
 
     let config = TcpConfig {
         bind_addr: addr,
+        additional_addrs: Vec::new(),
         max_connections,
         buffer_size: 1024,
         read_timeout: Duration::from_secs(30),
         write_timeout: Duration::from_secs(30),
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let server = TcpEchoServer::new(config.into());
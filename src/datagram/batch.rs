@@ -0,0 +1,194 @@
+use super::Received;
+use std::ops::Range;
+
+/// Default number of datagrams received or sent per call
+pub const DEFAULT_BATCH_SIZE: usize = 32;
+
+/// Largest datagram the kernel hands over when it coalesces received ones
+pub const MAX_OFFLOAD_SIZE: usize = u16::MAX as usize;
+
+/// Settings for receiving and sending several datagrams per system call
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::datagram::BatchConfig;
+///
+/// let batch = BatchConfig::new(64).with_offload(true);
+/// assert_eq!(batch.size, 64);
+/// // Coalesced datagrams need room for a full 64 KiB receive
+/// assert_eq!(batch.slot_size(1500), 65535);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchConfig {
+    /// Most datagrams received or sent per call
+    pub size: usize,
+    /// Let the kernel coalesce received datagrams and split sent ones
+    /// (UDP GRO and GSO on Linux)
+    pub offload: bool,
+}
+
+impl BatchConfig {
+    /// Creates a batch of `size` datagrams without segmentation offload
+    pub fn new(size: usize) -> Self {
+        Self {
+            size: size.max(1),
+            offload: false,
+        }
+    }
+
+    /// Enables or disables segmentation offload
+    pub fn with_offload(mut self, offload: bool) -> Self {
+        self.offload = offload;
+        self
+    }
+
+    /// Returns the receive buffer size of each datagram slot
+    ///
+    /// With offload, one slot may receive several coalesced datagrams, so it
+    /// is at least [`MAX_OFFLOAD_SIZE`].
+    pub fn slot_size(&self, buffer_size: usize) -> usize {
+        if self.offload {
+            buffer_size.max(MAX_OFFLOAD_SIZE)
+        } else {
+            buffer_size
+        }
+    }
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_BATCH_SIZE)
+    }
+}
+
+/// Datagrams received by one call, stored in equal slots of a single buffer
+///
+/// Each receive fills some of the slots. A slot holding several coalesced
+/// datagrams from one sender is listed as one entry per datagram. Each entry
+/// keeps what the protocol reported about its datagram, such as the local
+/// address it was sent to.
+#[derive(Debug)]
+pub struct RecvBatch {
+    buffer: Vec<u8>,
+    slot_size: usize,
+    datagrams: Vec<(Range<usize>, Received)>,
+}
+
+impl RecvBatch {
+    /// Creates a batch of `slots` slots of `slot_size` bytes each
+    pub fn new(slots: usize, slot_size: usize) -> Self {
+        let slots = slots.max(1);
+        let slot_size = slot_size.max(1);
+        Self {
+            buffer: vec![0; slots * slot_size],
+            slot_size,
+            datagrams: Vec::with_capacity(slots),
+        }
+    }
+
+    /// Returns the number of slots
+    pub fn slots(&self) -> usize {
+        self.buffer.len() / self.slot_size
+    }
+
+    /// Returns the size of each slot in bytes
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    /// Returns the buffer of `slot`
+    pub fn slot_mut(&mut self, slot: usize) -> &mut [u8] {
+        let start = slot * self.slot_size;
+        &mut self.buffer[start..start + self.slot_size]
+    }
+
+    /// Returns the buffers of every slot, in order
+    pub fn slots_mut(&mut self) -> std::slice::ChunksExactMut<'_, u8> {
+        self.buffer.chunks_exact_mut(self.slot_size)
+    }
+
+    /// Records the `received.len` bytes at `offset` in `slot` as a datagram
+    ///
+    /// # Panics
+    ///
+    /// Panics if the datagram does not fit within the slot.
+    pub fn push(&mut self, slot: usize, offset: usize, received: Received) {
+        assert!(
+            slot < self.slots() && offset + received.len <= self.slot_size,
+            "datagram outside its slot"
+        );
+        let start = slot * self.slot_size + offset;
+        self.datagrams.push((start..start + received.len, received));
+    }
+
+    /// Forgets every received datagram
+    pub fn clear(&mut self) {
+        self.datagrams.clear();
+    }
+
+    /// Returns the number of received datagrams
+    pub fn len(&self) -> usize {
+        self.datagrams.len()
+    }
+
+    /// Returns true if no datagram has been received
+    pub fn is_empty(&self) -> bool {
+        self.datagrams.is_empty()
+    }
+
+    /// Returns each received datagram with what was reported about it, in
+    /// arrival order
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], Received)> {
+        self.datagrams
+            .iter()
+            .map(|(range, received)| (&self.buffer[range.clone()], *received))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_config() {
+        assert_eq!(BatchConfig::default().size, DEFAULT_BATCH_SIZE);
+        assert_eq!(BatchConfig::new(0).size, 1);
+        assert_eq!(BatchConfig::new(8).slot_size(1500), 1500);
+        assert_eq!(
+            BatchConfig::new(8).with_offload(true).slot_size(100_000),
+            100_000
+        );
+    }
+
+    #[test]
+    fn test_recv_batch_slots() {
+        let addr: std::net::SocketAddr = "127.0.0.1:7".parse().unwrap();
+        let mut batch = RecvBatch::new(3, 8);
+        assert_eq!(batch.slots(), 3);
+        assert_eq!(batch.slots_mut().count(), 3);
+
+        batch.slot_mut(0)[..5].copy_from_slice(b"hello");
+        batch.slot_mut(2).copy_from_slice(b"abcdefgh");
+        batch.push(0, 0, Received::new(5, addr).with_truncated(9));
+        // One coalesced slot holds two datagrams
+        let local = Some(addr.ip());
+        batch.push(2, 0, Received::new(4, addr).with_local(local));
+        batch.push(2, 4, Received::new(4, addr).with_local(local));
+
+        let datagrams: Vec<_> = batch.iter().map(|(data, _)| data).collect();
+        assert_eq!(datagrams, vec![&b"hello"[..], b"abcd", b"efgh"]);
+        assert_eq!(batch.iter().next().unwrap().1.truncated, Some(9));
+        assert_eq!(batch.iter().last().unwrap().1.local, local);
+
+        batch.clear();
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "datagram outside its slot")]
+    fn test_recv_batch_rejects_overflow() {
+        let mut batch = RecvBatch::new(1, 4);
+        batch.push(0, 2, Received::new(4, "127.0.0.1:7".parse().unwrap()));
+    }
+}
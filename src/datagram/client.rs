@@ -1,5 +1,6 @@
 use super::{DatagramConfig, DatagramProtocol};
 use crate::common::EchoClient;
+use crate::performance::global_pool;
 use crate::{EchoError, Result};
 use async_trait::async_trait;
 use std::net::SocketAddr;
@@ -39,11 +40,24 @@ where
     /// Connects to a datagram echo server at the given address
     pub async fn connect(server_addr: SocketAddr) -> Result<Self> {
         // For datagram protocols, we need to bind to any available address
+        // of the server's family
+        let any: std::net::IpAddr = match server_addr {
+            SocketAddr::V4(_) => std::net::Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => std::net::Ipv6Addr::UNSPECIFIED.into(),
+        };
         let config = DatagramConfig {
-            bind_addr: "0.0.0.0:0".parse().unwrap(),
+            bind_addr: SocketAddr::new(any, 0),
+            additional_addrs: Vec::new(),
             buffer_size: 1024,
             read_timeout: std::time::Duration::from_secs(30),
             write_timeout: std::time::Duration::from_secs(30),
+            faults: None,
+            transform: None,
+            workers: 1,
+            batch: None,
+            socket_options: Default::default(),
+            multicast: None,
+            truncation: Default::default(),
         };
 
         let socket = P::bind(&config).await.map_err(|e| e.into())?;
@@ -68,7 +82,8 @@ where
             .map_err(|e| e.into())?;
 
         // Receive response with timeout
-        let mut buffer = vec![0; 1024];
+        let mut buffer = global_pool().get_with_capacity(1024);
+        buffer.resize(1024, 0);
         let (n, _) = timeout(
             Duration::from_millis(500),
             P::recv_from(&self.socket, &mut buffer),
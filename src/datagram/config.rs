@@ -1,6 +1,78 @@
+use super::BatchConfig;
+use crate::fault::FaultConfig;
+use crate::handler::Transform;
+use crate::network::{MulticastConfig, SocketOptions};
+use std::fmt;
 use std::net::SocketAddr;
 use std::time::Duration;
 
+/// Largest datagram a UDP socket can receive; a `buffer_size` of this never
+/// truncates one
+pub const MAX_DATAGRAM_SIZE: usize = 65535;
+
+/// What a datagram server does with a datagram too large for its buffer
+///
+/// Such a datagram arrives cut to the buffer's size. Where the protocol can
+/// tell (UDP and Unix datagrams on Linux), servers count and log each one,
+/// then answer it as this policy says.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::datagram::TruncationPolicy;
+///
+/// let policy: TruncationPolicy = "error".parse().unwrap();
+/// assert_eq!(policy, TruncationPolicy::Error);
+/// assert_eq!(TruncationPolicy::marker(3000), b"ERR TRUNCATED 3000");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TruncationPolicy {
+    /// Answer the part that fit as if it were the whole datagram
+    #[default]
+    Echo,
+    /// Drop the datagram without a reply
+    Drop,
+    /// Reply with [`marker`](Self::marker) instead of an echo
+    Error,
+}
+
+impl TruncationPolicy {
+    /// Every policy, in the order they are listed to users
+    pub const ALL: [TruncationPolicy; 3] = [Self::Echo, Self::Drop, Self::Error];
+
+    /// Returns the name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Echo => "echo",
+            Self::Drop => "drop",
+            Self::Error => "error",
+        }
+    }
+
+    /// Returns the reply [`Error`](Self::Error) sends for a datagram of
+    /// `size` bytes
+    pub fn marker(size: usize) -> Vec<u8> {
+        format!("ERR TRUNCATED {size}").into_bytes()
+    }
+}
+
+impl std::str::FromStr for TruncationPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown truncation policy '{s}', expected echo, drop or error"))
+    }
+}
+
+impl fmt::Display for TruncationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 /// Configuration for datagram-based echo servers
 ///
 /// This struct contains all the configuration options needed for
@@ -14,30 +86,75 @@ use std::time::Duration;
 ///
 /// let config = DatagramConfig {
 ///     bind_addr: "127.0.0.1:8080".parse().unwrap(),
+///     additional_addrs: Vec::new(),
 ///     buffer_size: 1024,
 ///     read_timeout: Duration::from_secs(30),
 ///     write_timeout: Duration::from_secs(30),
+///     faults: None,
+///     transform: None,
+///     workers: 1,
+///     batch: None,
+///     socket_options: Default::default(),
+///     multicast: None,
+///     truncation: Default::default(),
 /// };
 /// ```
 #[derive(Debug, Clone)]
 pub struct DatagramConfig {
     /// Address to bind the server to
     pub bind_addr: SocketAddr,
+    /// Further addresses served alongside `bind_addr`, sharing its
+    /// connection limit and statistics
+    pub additional_addrs: Vec<SocketAddr>,
     /// Buffer size for reading/writing data
     pub buffer_size: usize,
     /// Read timeout for datagrams
     pub read_timeout: Duration,
     /// Write timeout for datagrams
     pub write_timeout: Duration,
+    /// Fault injection settings, applied when served through `fault::Faulty`
+    pub faults: Option<FaultConfig>,
+    /// Transform applied to echoed datagrams; plain echo if `None`
+    pub transform: Option<Transform>,
+    /// Receive loops, each on its own `SO_REUSEPORT` socket; 0 runs one per
+    /// runtime worker thread
+    pub workers: usize,
+    /// Receive and send several datagrams per system call where the
+    /// protocol supports it; one at a time if `None`
+    pub batch: Option<BatchConfig>,
+    /// Socket options of the server's sockets
+    pub socket_options: SocketOptions,
+    /// Multicast groups to join, for protocols that support them; none if
+    /// `None`
+    pub multicast: Option<MulticastConfig>,
+    /// What to do with datagrams larger than `buffer_size`
+    pub truncation: TruncationPolicy,
+}
+
+impl DatagramConfig {
+    /// Returns every address the server binds, `bind_addr` first
+    pub fn addresses(&self) -> Vec<SocketAddr> {
+        std::iter::once(self.bind_addr)
+            .chain(self.additional_addrs.iter().copied())
+            .collect()
+    }
 }
 
 impl Default for DatagramConfig {
     fn default() -> Self {
         Self {
             bind_addr: "127.0.0.1:0".parse().unwrap(),
+            additional_addrs: Vec::new(),
             buffer_size: 1024,
             read_timeout: Duration::from_secs(30),
             write_timeout: Duration::from_secs(30),
+            faults: None,
+            transform: None,
+            workers: 1,
+            batch: None,
+            socket_options: SocketOptions::default(),
+            multicast: None,
+            truncation: TruncationPolicy::Echo,
         }
     }
 }
@@ -3,12 +3,16 @@
 //! This module provides generic datagram-based echo servers and clients
 //! that can work with any datagram protocol (UDP, Unix datagrams, etc.).
 
+pub mod batch;
 pub mod client;
 pub mod config;
 pub mod protocol;
 pub mod server;
+pub mod twamp;
 
+pub use batch::{BatchConfig, RecvBatch};
 pub use client::DatagramEchoClient;
-pub use config::DatagramConfig;
-pub use protocol::DatagramProtocol;
+pub use config::{DatagramConfig, MAX_DATAGRAM_SIZE, TruncationPolicy};
+pub use protocol::{DatagramProtocol, Received};
 pub use server::DatagramEchoServer;
+pub use twamp::{TwampClient, TwampReflector, TwampSample, TwampStats};
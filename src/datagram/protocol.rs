@@ -1,7 +1,72 @@
+use super::batch::{BatchConfig, RecvBatch};
 use super::config::DatagramConfig;
 use crate::network::fd_inheritance::FdInheritanceConfig;
 use async_trait::async_trait;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::time::SystemTime;
+
+/// A datagram received into a buffer, as reported by
+/// [`DatagramProtocol::recv_msg`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Received {
+    /// Bytes of the datagram written to the buffer
+    pub len: usize,
+    /// Sender of the datagram
+    pub peer: SocketAddr,
+    /// Local address the datagram was sent to, if the protocol can tell
+    pub local: Option<IpAddr>,
+    /// Size of the datagram as sent, if it did not fit in the buffer and
+    /// only its first `len` bytes were received
+    pub truncated: Option<usize>,
+    /// When the kernel received the datagram, if the socket reports
+    /// timestamps (see [`SocketOptions::timestamping`])
+    ///
+    /// [`SocketOptions::timestamping`]: crate::network::SocketOptions::timestamping
+    pub timestamp: Option<SystemTime>,
+    /// Time to live (or IPv6 hop limit) the datagram arrived with, if the
+    /// socket reports it (see [`SocketOptions::recv_ttl`])
+    ///
+    /// [`SocketOptions::recv_ttl`]: crate::network::SocketOptions::recv_ttl
+    pub ttl: Option<u8>,
+}
+
+impl Received {
+    /// Creates a whole datagram of `len` bytes from `peer`
+    pub fn new(len: usize, peer: SocketAddr) -> Self {
+        Self {
+            len,
+            peer,
+            local: None,
+            truncated: None,
+            timestamp: None,
+            ttl: None,
+        }
+    }
+
+    /// Sets the local address the datagram was sent to
+    pub fn with_local(mut self, local: Option<IpAddr>) -> Self {
+        self.local = local;
+        self
+    }
+
+    /// Marks the datagram as cut from `size` bytes to the `len` received
+    pub fn with_truncated(mut self, size: usize) -> Self {
+        self.truncated = Some(size);
+        self
+    }
+
+    /// Sets when the kernel received the datagram
+    pub fn with_timestamp(mut self, timestamp: Option<SystemTime>) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Sets the time to live the datagram arrived with
+    pub fn with_ttl(mut self, ttl: Option<u8>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
 
 /// Trait for datagram-based protocols (UDP, Unix datagrams, etc.)
 ///
@@ -14,8 +79,8 @@ use std::net::SocketAddr;
 pub trait DatagramProtocol {
     /// Error type for this protocol
     type Error: Send + Into<crate::EchoError>;
-    /// Socket type for this protocol
-    type Socket: Send;
+    /// Socket type for this protocol, shared by a worker's receive and send tasks
+    type Socket: Send + Sync;
 
     /// Binds a socket to the given configuration
     /// 
@@ -40,6 +105,47 @@ pub trait DatagramProtocol {
         Self::bind(config).await
     }
 
+    /// Binds `count` sockets that share the configured address
+    ///
+    /// The kernel spreads incoming datagrams across the sockets, so each can
+    /// be served by its own receive loop. The default implementation only
+    /// supports a single socket; protocols that can share an address (such as
+    /// UDP with `SO_REUSEPORT`) override it.
+    async fn bind_workers(
+        config: &DatagramConfig,
+        count: usize,
+    ) -> std::result::Result<Vec<Self::Socket>, Self::Error> {
+        if count > 1 {
+            return Err(Self::map_io_error(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "this protocol cannot share an address between worker sockets",
+            )));
+        }
+        Ok(vec![Self::bind(config).await?])
+    }
+
+    /// Binds `count` sockets on each configured address, as
+    /// [`bind_workers`](Self::bind_workers) would for that address alone
+    ///
+    /// The sockets of every address are returned together, each paired with
+    /// its address and `bind_addr`'s first, so one server can serve them
+    /// all.
+    async fn bind_all(
+        config: &DatagramConfig,
+        count: usize,
+    ) -> std::result::Result<Vec<(SocketAddr, Self::Socket)>, Self::Error> {
+        let mut sockets = Vec::new();
+        for addr in config.addresses() {
+            let config = DatagramConfig {
+                bind_addr: addr,
+                ..config.clone()
+            };
+            let bound = Self::bind_workers(&config, count).await?;
+            sockets.extend(bound.into_iter().map(|bound| (addr, bound)));
+        }
+        Ok(sockets)
+    }
+
     /// Receives data from a socket
     async fn recv_from(
         socket: &Self::Socket,
@@ -53,6 +159,71 @@ pub trait DatagramProtocol {
         addr: SocketAddr,
     ) -> std::result::Result<usize, Self::Error>;
 
+    /// Receives data from a socket along with the local address it was sent
+    /// to and whether it was truncated, as far as the protocol can tell
+    ///
+    /// The default receives with [`recv_from`](Self::recv_from) and reports
+    /// neither; protocols that can (such as UDP with `IP_PKTINFO` and
+    /// `MSG_TRUNC` on Linux) override it, so that servers bound to a
+    /// wildcard address can reply from the address each datagram was sent
+    /// to, and servers notice datagrams larger than their buffer.
+    async fn recv_msg(
+        socket: &Self::Socket,
+        buffer: &mut [u8],
+    ) -> std::result::Result<Received, Self::Error> {
+        let (n, addr) = Self::recv_from(socket, buffer).await?;
+        Ok(Received::new(n, addr))
+    }
+
+    /// Sends data to a specific address, from the local address `local` if
+    /// given
+    ///
+    /// The default sends with [`send_to`](Self::send_to), leaving the source
+    /// address to the system.
+    async fn send_msg(
+        socket: &Self::Socket,
+        data: &[u8],
+        addr: SocketAddr,
+        _local: Option<IpAddr>,
+    ) -> std::result::Result<usize, Self::Error> {
+        Self::send_to(socket, data, addr).await
+    }
+
+    /// Receives one or more datagrams into the empty `batch`, returning how
+    /// many arrived
+    ///
+    /// The default receives a single datagram with
+    /// [`recv_msg`](Self::recv_msg); protocols with batched receives (such
+    /// as UDP with `recvmmsg` on Linux) override it.
+    async fn recv_batch(
+        socket: &Self::Socket,
+        batch: &mut RecvBatch,
+    ) -> std::result::Result<usize, Self::Error> {
+        let received = Self::recv_msg(socket, batch.slot_mut(0)).await?;
+        batch.push(0, 0, received);
+        Ok(1)
+    }
+
+    /// Sends datagrams from the front of `datagrams`, returning how many
+    /// were sent
+    ///
+    /// Each datagram goes to its peer, from its local address if given.
+    /// Unless `datagrams` is empty, at least one is sent or the error applies
+    /// to the first. The default sends a single datagram with
+    /// [`send_msg`](Self::send_msg); protocols with batched sends (such as
+    /// UDP with `sendmmsg` on Linux) override it.
+    async fn send_batch(
+        socket: &Self::Socket,
+        datagrams: &[(&[u8], SocketAddr, Option<IpAddr>)],
+        _config: &BatchConfig,
+    ) -> std::result::Result<usize, Self::Error> {
+        let Some(&(data, addr, local)) = datagrams.first() else {
+            return Ok(0);
+        };
+        Self::send_msg(socket, data, addr, local).await?;
+        Ok(1)
+    }
+
     /// Maps a standard IO error to this protocol's error type
     fn map_io_error(err: std::io::Error) -> Self::Error;
 }
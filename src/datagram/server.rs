@@ -1,15 +1,40 @@
-use super::{DatagramConfig, DatagramProtocol};
+use super::{BatchConfig, DatagramConfig, DatagramProtocol, Received, RecvBatch, TruncationPolicy};
 use crate::common::EchoServerTrait;
+use crate::fault::chaos::FaultSource;
+use crate::fault::impairment::sleep_until_release;
+use crate::fault::{FaultRng, Impairer, ImpairmentCounters, ImpairmentStats};
+use crate::handler::{DatagramContext, DatagramHandler, Echo};
+use crate::performance::buffer_pool::Payload;
+use crate::performance::{PooledBuffer, global_pool};
 use crate::{EchoError, Result};
 use async_trait::async_trait;
+use bytes::Bytes;
+use std::borrow::Cow;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
 use tokio::{signal, time::timeout};
-use tracing::{error, info, warn};
+use tracing::{Instrument, debug, error, info, warn};
+
+/// Replies each worker holds for its send task
+///
+/// Sending runs apart from receiving, so a slow send never delays the next
+/// receive; once this many replies wait, new ones are dropped as a congested
+/// network would drop them.
+const SEND_QUEUE_DEPTH: usize = 1024;
 
 /// Generic datagram-based echo server that works with any datagram protocol
 ///
 /// This server can work with any protocol that implements `DatagramProtocol`,
-/// such as UDP, Unix datagrams, etc.
+/// such as UDP, Unix datagrams, etc. Each datagram is answered by its
+/// [`DatagramHandler`]: the config's [`Transform`](crate::handler::Transform)
+/// if one is set, otherwise a plain echo. Either can be replaced with
+/// [`with_handler`](Self::with_handler).
 ///
 /// # Examples
 ///
@@ -25,9 +50,17 @@ use tracing::{error, info, warn};
 /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
 ///     let config = DatagramConfig {
 ///         bind_addr: "127.0.0.1:8080".parse()?,
+///         additional_addrs: Vec::new(),
 ///         buffer_size: 1024,
 ///         read_timeout: Duration::from_secs(30),
 ///         write_timeout: Duration::from_secs(30),
+///         faults: None,
+///         transform: None,
+///         workers: 1,
+///         batch: None,
+///         socket_options: Default::default(),
+///         multicast: None,
+///         truncation: Default::default(),
 ///     };
 ///
 ///     let server: DatagramEchoServer<UdpProtocol> = DatagramEchoServer::new(config);
@@ -35,10 +68,37 @@ use tracing::{error, info, warn};
 ///     Ok(())
 /// }
 /// ```
+///
+/// When `config.faults` enables datagram impairments (loss, duplication,
+/// reordering, corruption or truncation) they are applied to every echo and
+/// counted in [`impairment_stats`](Self::impairment_stats).
+///
+/// With `config.workers` above 1, the server binds that many sockets to the
+/// same address through [`DatagramProtocol::bind_workers`] and runs a receive
+/// loop on each, so the load spreads across runtime threads. Each worker
+/// impairs its own datagrams.
+///
+/// With `config.batch`, each worker receives and sends through
+/// [`DatagramProtocol::recv_batch`] and [`DatagramProtocol::send_batch`],
+/// moving up to `batch.size` datagrams per call where the protocol can.
+///
+/// Where the protocol reports the local address each datagram was sent to
+/// (see [`DatagramProtocol::recv_msg`]), it is logged, passed to the handler
+/// as the context's local address, and used as the source of the reply, so a
+/// server bound to a wildcard address answers from the address the client
+/// targeted.
+///
+/// Datagrams larger than `config.buffer_size` that the protocol reports as
+/// truncated are logged, counted in
+/// [`truncated_datagrams`](Self::truncated_datagrams), and answered as
+/// `config.truncation` says.
 pub struct DatagramEchoServer<P: DatagramProtocol> {
     config: DatagramConfig,
     protocol: std::marker::PhantomData<P>,
     shutdown_signal: Arc<tokio::sync::broadcast::Sender<()>>,
+    impairment: Arc<ImpairmentCounters>,
+    truncated: Arc<AtomicU64>,
+    handler: Arc<dyn DatagramHandler>,
 }
 
 impl<P: DatagramProtocol> DatagramEchoServer<P>
@@ -48,42 +108,226 @@ where
     /// Creates a new datagram-based echo server with the given configuration
     pub fn new(config: DatagramConfig) -> Self {
         let (shutdown_signal, _) = tokio::sync::broadcast::channel(1);
+        let handler: Arc<dyn DatagramHandler> = match config.transform {
+            Some(transform) => Arc::new(transform),
+            None => Arc::new(Echo),
+        };
         Self {
             config,
             protocol: std::marker::PhantomData,
             shutdown_signal: Arc::new(shutdown_signal),
+            impairment: Arc::new(ImpairmentCounters::default()),
+            truncated: Arc::new(AtomicU64::new(0)),
+            handler,
+        }
+    }
+
+    /// Replaces the echo with `handler` as the source of every reply
+    pub fn with_handler(mut self, handler: impl DatagramHandler + 'static) -> Self {
+        self.handler = Arc::new(handler);
+        self
+    }
+
+    /// Returns how many datagrams each impairment has affected so far
+    pub fn impairment_stats(&self) -> ImpairmentStats {
+        self.impairment.stats()
+    }
+
+    /// Returns how many received datagrams were larger than the buffer
+    pub fn truncated_datagrams(&self) -> u64 {
+        self.truncated.load(Ordering::Relaxed)
+    }
+
+    /// Returns the generator for datagram impairments if `config.faults`
+    /// enables them or attaches a chaos timeline
+    fn impairment_rng(&self, source: &FaultSource) -> Option<FaultRng> {
+        let faults = self
+            .config
+            .faults
+            .as_ref()
+            .filter(|f| f.impairment.is_active() || f.chaos.is_some())?;
+        match source.phase() {
+            Some(phase) => info!(%phase, "Datagram impairment follows chaos timeline"),
+            None => info!(impairment = ?faults.impairment, "Datagram impairment configured"),
+        }
+        let seed = faults.resolve_seed("datagram impairment", &self.config.bind_addr);
+        Some(FaultRng::new(seed))
+    }
+
+    /// Returns the server's configuration
+    pub fn config(&self) -> &DatagramConfig {
+        &self.config
+    }
+
+    /// Returns how many receive loops to run
+    pub(crate) fn worker_count(&self) -> usize {
+        match self.config.workers {
+            0 => tokio::runtime::Handle::current().metrics().num_workers(),
+            workers => workers,
+        }
+    }
+}
+
+impl<P> DatagramEchoServer<P>
+where
+    P: DatagramProtocol + Send + Sync + 'static,
+    P::Socket: Sync + 'static,
+    P::Error: Into<EchoError> + std::fmt::Display,
+{
+    /// Serves datagrams on `sockets`, each paired with the address it is
+    /// bound to, until shutdown
+    ///
+    /// Lets servers whose protocol needs more than the configuration to bind
+    /// (such as DTLS) bind the sockets themselves.
+    pub(crate) async fn serve(&self, sockets: Vec<(SocketAddr, P::Socket)>) -> Result<()> {
+        let workers = self.worker_count();
+        for address in self.config.addresses() {
+            info!(%address, workers, "Datagram echo server listening");
         }
+
+        let faults = FaultSource::new(self.config.faults.as_ref());
+        let mut rng = self.impairment_rng(&faults);
+        let mut tasks = JoinSet::new();
+        for (id, (address, socket)) in sockets.into_iter().enumerate() {
+            // The first worker keeps the configured seed, so a single worker
+            // makes the same impairment decisions regardless of worker count
+            let impairer = rng.as_mut().map(|rng| {
+                let rng = if id == 0 { rng.clone() } else { rng.fork() };
+                Impairer::new(
+                    faults.current().impairment.clone(),
+                    rng,
+                    Arc::clone(&self.impairment),
+                )
+            });
+            let worker = Worker::<P> {
+                socket: Arc::new(socket),
+                config: DatagramConfig {
+                    bind_addr: address,
+                    ..self.config.clone()
+                },
+                handler: Arc::clone(&self.handler),
+                faults: faults.clone(),
+                impairer,
+                truncated: Arc::clone(&self.truncated),
+                shutdown_rx: self.shutdown_signal.subscribe(),
+            };
+            tasks.spawn(worker.run().instrument(tracing::info_span!("worker", id)));
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            if let Err(e) = result {
+                error!(error = %e, "Datagram worker failed");
+            }
+        }
+
+        if rng.is_some() {
+            info!(stats = ?self.impairment.stats(), "Datagram impairment summary");
+        }
+        let truncated = self.truncated_datagrams();
+        if truncated > 0 {
+            warn!(
+                count = truncated,
+                buffer_size = self.config.buffer_size,
+                "Datagrams were truncated"
+            );
+        }
+
+        info!("Datagram echo server stopped");
+        Ok(())
     }
 }
 
 #[async_trait]
-impl<P: DatagramProtocol + Sync> EchoServerTrait for DatagramEchoServer<P>
+impl<P> EchoServerTrait for DatagramEchoServer<P>
 where
+    P: DatagramProtocol + Send + Sync + 'static,
+    P::Socket: Sync + 'static,
     P::Error: Into<EchoError> + std::fmt::Display,
 {
     /// Starts the datagram-based echo server and listens for datagrams
     async fn run(&self) -> Result<()> {
-        let socket = P::bind(&self.config).await.map_err(|e| e.into())?;
+        let sockets = P::bind_all(&self.config, self.worker_count())
+            .await
+            .map_err(|e| e.into())?;
+        self.serve(sockets).await
+    }
+
+    /// Returns a shutdown signal sender that can be used to gracefully shutdown the server
+    fn shutdown_signal(&self) -> tokio::sync::broadcast::Sender<()> {
+        self.shutdown_signal.as_ref().clone()
+    }
+}
+
+/// Peer and local address of a datagram, and so of its reply
+type Route = (SocketAddr, Option<IpAddr>);
+
+/// A reply waiting for the sending task of a worker
+type Reply = (Payload, SocketAddr, Option<IpAddr>);
+
+/// Where a worker receives its datagrams
+enum Inbox {
+    /// One datagram at a time into a pooled buffer, echoed without copying
+    Single {
+        buffer: PooledBuffer,
+        received: Option<Received>,
+    },
+    /// Several datagrams per call
+    Batch(RecvBatch),
+}
 
-        info!(address = %self.config.bind_addr, "Datagram echo server listening");
+impl Inbox {
+    fn new(config: &DatagramConfig) -> Self {
+        match config.batch {
+            Some(batch) => Self::Batch(RecvBatch::new(
+                batch.size,
+                batch.slot_size(config.buffer_size),
+            )),
+            None => Self::Single {
+                buffer: global_pool().get_with_capacity(config.buffer_size),
+                received: None,
+            },
+        }
+    }
+}
+
+/// One receive loop of a datagram server, serving its own socket
+struct Worker<P: DatagramProtocol> {
+    socket: Arc<P::Socket>,
+    config: DatagramConfig,
+    handler: Arc<dyn DatagramHandler>,
+    faults: FaultSource,
+    impairer: Option<Impairer<Route>>,
+    truncated: Arc<AtomicU64>,
+    shutdown_rx: broadcast::Receiver<()>,
+}
 
-        let mut buffer = vec![0; self.config.buffer_size];
-        let mut shutdown_rx = self.shutdown_signal.subscribe();
+impl<P> Worker<P>
+where
+    P: DatagramProtocol + Send + Sync + 'static,
+    P::Socket: Sync + 'static,
+    P::Error: Into<EchoError> + std::fmt::Display,
+{
+    /// Receives datagrams until shutdown, leaving replies to a send task
+    async fn run(mut self) {
+        let (replies, queue) = mpsc::channel(SEND_QUEUE_DEPTH);
+        let socket = Arc::clone(&self.socket);
+        let write_timeout = self.config.write_timeout;
+        let sender = match self.config.batch {
+            Some(batch) => tokio::spawn(
+                Self::send_batches(socket, queue, write_timeout, batch).in_current_span(),
+            ),
+            None => {
+                tokio::spawn(Self::send_replies(socket, queue, write_timeout).in_current_span())
+            }
+        };
 
+        let mut inbox = Inbox::new(&self.config);
         loop {
+            let release_deadline = self.impairer.as_ref().and_then(Impairer::next_deadline);
             tokio::select! {
-                recv_result = timeout(self.config.read_timeout, P::recv_from(&socket, &mut buffer)) => {
+                recv_result = timeout(self.config.read_timeout, Self::receive(&self.socket, &mut inbox, self.config.buffer_size)) => {
                     match recv_result {
-                        Ok(Ok((n, addr))) => {
-                            let preview = String::from_utf8_lossy(&buffer[..n]);
-                            info!(%addr, size = n, preview = %preview, "Received datagram");
-
-                            if let Err(e) = P::send_to(&socket, &buffer[..n], addr).await {
-                                error!(%addr, error = %e, "Failed to send echo response");
-                            } else {
-                                info!(%addr, size = n, "Echoed datagram");
-                            }
-                        }
+                        Ok(Ok(())) => self.answer_inbox(&mut inbox, &replies),
                         Ok(Err(e)) => {
                             error!(error = %e, "Failed to receive datagram");
                         }
@@ -92,23 +336,218 @@ where
                         }
                     }
                 }
+                _ = sleep_until_release(release_deadline) => {
+                    if let Some(impairer) = self.impairer.as_mut() {
+                        for (data, route) in impairer.release_expired(Instant::now()) {
+                            queue_reply(&replies, Payload::Shared(data.into()), route);
+                        }
+                    }
+                }
                 _ = signal::ctrl_c() => {
                     info!("Received shutdown signal, stopping server");
                     break;
                 }
-                _ = shutdown_rx.recv() => {
+                _ = self.shutdown_rx.recv() => {
                     info!("Received internal shutdown signal, stopping server");
                     break;
                 }
             }
         }
 
-        info!("Datagram echo server stopped");
+        // Echoes still held by the impairment pipeline go out before stopping
+        if let Some(mut impairer) = self.impairer.take() {
+            for (data, (addr, local)) in impairer.drain() {
+                if replies
+                    .send((Payload::Shared(data.into()), addr, local))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }
+        drop(replies);
+        let _ = sender.await;
+    }
+
+    /// Receives the next datagram, or batch of datagrams, into `inbox`
+    async fn receive(
+        socket: &P::Socket,
+        inbox: &mut Inbox,
+        buffer_size: usize,
+    ) -> std::result::Result<(), P::Error> {
+        match inbox {
+            Inbox::Single { buffer, received } => {
+                buffer.resize(buffer_size, 0);
+                let datagram = P::recv_msg(socket, buffer).await?;
+                buffer.truncate(datagram.len);
+                *received = Some(datagram);
+            }
+            Inbox::Batch(batch) => {
+                batch.clear();
+                P::recv_batch(socket, batch).await?;
+            }
+        }
         Ok(())
     }
 
-    /// Returns a shutdown signal sender that can be used to gracefully shutdown the server
-    fn shutdown_signal(&self) -> tokio::sync::broadcast::Sender<()> {
-        self.shutdown_signal.as_ref().clone()
+    /// Answers every datagram received into `inbox`
+    fn answer_inbox(&mut self, inbox: &mut Inbox, replies: &mpsc::Sender<Reply>) {
+        match inbox {
+            Inbox::Single { buffer, received } => {
+                if let Some(received) = received.take() {
+                    self.answer(buffer, received, replies);
+                }
+            }
+            Inbox::Batch(batch) => {
+                for (data, received) in batch.iter() {
+                    if let Some(response) = self.respond(data, received, replies) {
+                        queue_reply(
+                            replies,
+                            Payload::Shared(Bytes::from(response.into_owned())),
+                            (received.peer, received.local),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Answers the datagram in `buffer`, queueing the handler's reply
+    fn answer(
+        &mut self,
+        buffer: &mut PooledBuffer,
+        received: Received,
+        replies: &mpsc::Sender<Reply>,
+    ) {
+        let Some(response) = self.respond(&buffer[..], received, replies) else {
+            return;
+        };
+
+        // A plain echo queues the pooled datagram itself rather than a copy
+        let payload = match response {
+            Cow::Borrowed(data) => match buffer.range_of(data) {
+                Some(range) => {
+                    let next = global_pool().get_with_capacity(self.config.buffer_size);
+                    Payload::Pooled(std::mem::replace(buffer, next), range)
+                }
+                None => Payload::Shared(Bytes::copy_from_slice(data)),
+            },
+            Cow::Owned(data) => Payload::Shared(data.into()),
+        };
+        queue_reply(replies, payload, (received.peer, received.local));
+    }
+
+    /// Runs the handler on one datagram, returning the reply left to queue
+    ///
+    /// Impaired replies are queued here, so `None` also covers them. A
+    /// truncated datagram is answered as the truncation policy says.
+    fn respond<'a>(
+        &mut self,
+        data: &'a [u8],
+        received: Received,
+        replies: &mpsc::Sender<Reply>,
+    ) -> Option<Cow<'a, [u8]>> {
+        let route = (received.peer, received.local);
+        let addr = received.peer;
+        let local = received.local.map_or(self.config.bind_addr, |ip| {
+            SocketAddr::new(ip, self.config.bind_addr.port())
+        });
+        let n = data.len();
+        let preview = String::from_utf8_lossy(data);
+        info!(%addr, %local, size = n, preview = %preview, "Received datagram");
+
+        if let Some(size) = received.truncated {
+            self.truncated.fetch_add(1, Ordering::Relaxed);
+            let policy = self.config.truncation;
+            warn!(%addr, size, received = n, %policy, "Datagram truncated to fit the buffer");
+            match policy {
+                TruncationPolicy::Echo => {}
+                TruncationPolicy::Drop => return None,
+                TruncationPolicy::Error => return Some(TruncationPolicy::marker(size).into()),
+            }
+        }
+
+        let context =
+            DatagramContext::new(addr, local).with_arrival(received.timestamp, received.ttl);
+        let Some(response) = self.handler.handle(data, &context) else {
+            debug!(%addr, size = n, "Handler sent no reply");
+            return None;
+        };
+
+        if let Some(impairer) = self.impairer.as_mut() {
+            if self.faults.refresh() {
+                info!(phase = ?self.faults.phase(), "Chaos phase applied to datagram impairment");
+                impairer.set_config(self.faults.current().impairment.clone());
+            }
+            for (data, route) in impairer.process(&response, route) {
+                queue_reply(replies, Payload::Shared(data.into()), route);
+            }
+            return None;
+        }
+        Some(response)
+    }
+
+    /// Sends queued replies in order until the receive loop stops
+    async fn send_replies(
+        socket: Arc<P::Socket>,
+        mut queue: mpsc::Receiver<Reply>,
+        write_timeout: Duration,
+    ) {
+        while let Some((payload, addr, local)) = queue.recv().await {
+            let data = payload.as_bytes();
+            match timeout(write_timeout, P::send_msg(&socket, data, addr, local)).await {
+                Ok(Ok(_)) => info!(%addr, size = data.len(), "Echoed datagram"),
+                Ok(Err(e)) => error!(%addr, error = %e, "Failed to send echo response"),
+                Err(_) => warn!(%addr, "Send timeout"),
+            }
+        }
+    }
+
+    /// Sends queued replies in order, up to `batch.size` per call, until the
+    /// receive loop stops
+    async fn send_batches(
+        socket: Arc<P::Socket>,
+        mut queue: mpsc::Receiver<Reply>,
+        write_timeout: Duration,
+        batch: BatchConfig,
+    ) {
+        let mut pending = Vec::with_capacity(batch.size);
+        while queue.recv_many(&mut pending, batch.size).await > 0 {
+            let datagrams: Vec<(&[u8], SocketAddr, Option<IpAddr>)> = pending
+                .iter()
+                .map(|(payload, addr, local)| (payload.as_bytes(), *addr, *local))
+                .collect();
+            let mut sent = 0;
+            while sent < datagrams.len() {
+                let rest = &datagrams[sent..];
+                let addr = rest[0].1;
+                match timeout(write_timeout, P::send_batch(&socket, rest, &batch)).await {
+                    Ok(Ok(0)) => break,
+                    Ok(Ok(n)) => {
+                        info!(count = n, "Echoed datagrams");
+                        sent += n;
+                    }
+                    // The failed datagram is skipped so the rest still go out
+                    Ok(Err(e)) => {
+                        error!(%addr, error = %e, "Failed to send echo response");
+                        sent += 1;
+                    }
+                    Err(_) => {
+                        warn!(%addr, "Send timeout");
+                        sent += 1;
+                    }
+                }
+            }
+            drop(datagrams);
+            pending.clear();
+        }
+    }
+}
+
+/// Queues a reply without waiting, dropping it if the send queue is full
+fn queue_reply(replies: &mpsc::Sender<Reply>, payload: Payload, (addr, local): Route) {
+    if let Err(TrySendError::Full((payload, addr, _))) = replies.try_send((payload, addr, local)) {
+        warn!(%addr, size = payload.as_bytes().len(), "Send queue full, dropping reply");
     }
 }
@@ -0,0 +1,576 @@
+//! TWAMP-light test packets, reflector and client
+//!
+//! TWAMP-light (RFC 5357, appendix I) measures a path with unauthenticated
+//! UDP test packets and no control session. A session-sender stamps each
+//! packet with a sequence number and its transmit time; the
+//! session-reflector answers with its own sequence number, when it received
+//! the packet and when it sent the answer, followed by the sender's fields.
+//! The four timestamps give the round-trip time without the reflector's
+//! processing, and the one-way delays when both clocks are synchronized.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use echosrv::EchoServerTrait;
+//! use echosrv::datagram::TwampReflector;
+//! use echosrv::network::SocketOptions;
+//! use echosrv::udp::{UdpConfig, UdpEchoServer, UdpTwampClient};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let config = UdpConfig {
+//!         bind_addr: "127.0.0.1:8862".parse()?,
+//!         socket_options: SocketOptions {
+//!             timestamping: Some(true),
+//!             recv_ttl: Some(true),
+//!             ..Default::default()
+//!         },
+//!         ..Default::default()
+//!     };
+//!     let server = UdpEchoServer::new(config.into()).with_handler(TwampReflector::new());
+//!     tokio::spawn(async move { server.run().await });
+//!
+//!     let mut client = UdpTwampClient::connect("127.0.0.1:8862".parse()?).await?;
+//!     let stats = client.run(100, Duration::from_millis(10)).await?;
+//!     println!("{stats:?}");
+//!     Ok(())
+//! }
+//! ```
+
+use super::{DatagramConfig, DatagramProtocol, MAX_DATAGRAM_SIZE};
+use crate::handler::{DatagramContext, DatagramHandler};
+use crate::network::SocketOptions;
+use crate::{EchoError, Result};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{Instant, timeout_at};
+use tracing::debug;
+
+/// Size of a sender's test packet without padding
+pub const SENDER_PACKET_LEN: usize = 14;
+
+/// Size of a reflector's answer without padding
+pub const REFLECTOR_PACKET_LEN: usize = 41;
+
+/// Seconds from the NTP epoch (1900) to the Unix epoch (1970)
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Error estimate of this host's timestamps (RFC 4656): not synchronized
+/// to UTC, and accurate to about a millisecond (multiplier 1, scale 22)
+const ERROR_ESTIMATE: u16 = (22 << 8) | 1;
+
+/// Sender TTL reported when the socket does not tell the real one, as
+/// TWAMP senders send with a TTL of 255
+const DEFAULT_TTL: u8 = 255;
+
+/// Peers whose reflector sequence numbers are kept before starting over
+const MAX_SESSIONS: usize = 4096;
+
+/// A test packet as a session-sender sends it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SenderPacket {
+    /// Position of the packet in the sender's stream, from zero
+    pub sequence: u32,
+    /// When the sender sent the packet
+    pub timestamp: SystemTime,
+    /// Error estimate of the timestamp (RFC 4656)
+    pub error_estimate: u16,
+}
+
+impl SenderPacket {
+    /// Creates packet `sequence` sent at `timestamp`
+    pub fn new(sequence: u32, timestamp: SystemTime) -> Self {
+        Self {
+            sequence,
+            timestamp,
+            error_estimate: ERROR_ESTIMATE,
+        }
+    }
+
+    /// Reads a test packet, or `None` if `data` is too short to be one
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < SENDER_PACKET_LEN {
+            return None;
+        }
+        Some(Self {
+            sequence: read_u32(&data[0..4]),
+            timestamp: from_ntp(read_u64(&data[4..12])),
+            error_estimate: read_u16(&data[12..14]),
+        })
+    }
+
+    /// Encodes the packet, padded with zeros to `len` bytes if longer
+    pub fn encode(&self, len: usize) -> Vec<u8> {
+        let mut packet = vec![0; len.max(SENDER_PACKET_LEN)];
+        self.write(&mut packet);
+        packet
+    }
+
+    fn write(&self, out: &mut [u8]) {
+        out[0..4].copy_from_slice(&self.sequence.to_be_bytes());
+        out[4..12].copy_from_slice(&to_ntp(self.timestamp).to_be_bytes());
+        out[12..14].copy_from_slice(&self.error_estimate.to_be_bytes());
+    }
+}
+
+/// A session-reflector's answer to a test packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReflectorPacket {
+    /// Position of the answer in the reflector's stream to this sender,
+    /// from zero
+    pub sequence: u32,
+    /// When the reflector sent the answer
+    pub timestamp: SystemTime,
+    /// Error estimate of the reflector's timestamps (RFC 4656)
+    pub error_estimate: u16,
+    /// When the reflector received the test packet
+    pub receive_timestamp: SystemTime,
+    /// The test packet answered
+    pub sender: SenderPacket,
+    /// Time to live the test packet arrived with
+    pub sender_ttl: u8,
+}
+
+impl ReflectorPacket {
+    /// Reads an answer, or `None` if `data` is too short to be one
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < REFLECTOR_PACKET_LEN {
+            return None;
+        }
+        Some(Self {
+            sequence: read_u32(&data[0..4]),
+            timestamp: from_ntp(read_u64(&data[4..12])),
+            error_estimate: read_u16(&data[12..14]),
+            receive_timestamp: from_ntp(read_u64(&data[16..24])),
+            sender: SenderPacket::parse(&data[24..38])?,
+            sender_ttl: data[40],
+        })
+    }
+
+    /// Encodes the answer, padded with zeros to `len` bytes if longer
+    pub fn encode(&self, len: usize) -> Vec<u8> {
+        let mut packet = vec![0; len.max(REFLECTOR_PACKET_LEN)];
+        packet[0..4].copy_from_slice(&self.sequence.to_be_bytes());
+        packet[4..12].copy_from_slice(&to_ntp(self.timestamp).to_be_bytes());
+        packet[12..14].copy_from_slice(&self.error_estimate.to_be_bytes());
+        packet[16..24].copy_from_slice(&to_ntp(self.receive_timestamp).to_be_bytes());
+        self.sender.write(&mut packet[24..38]);
+        packet[40] = self.sender_ttl;
+        packet
+    }
+}
+
+/// Answers TWAMP-light test packets as a session-reflector
+///
+/// Each sender, told apart by address, gets answers numbered from zero.
+/// The receive timestamp is the kernel's when the socket reports timestamps
+/// (see [`SocketOptions::timestamping`]), and otherwise taken when the
+/// handler runs; the transmit timestamp is taken as the answer is built.
+/// Answers are as long as the test packet, or [`REFLECTOR_PACKET_LEN`] if
+/// that is longer. Datagrams too short to be test packets get no answer.
+#[derive(Debug, Default)]
+pub struct TwampReflector {
+    /// Next reflector sequence number of each sender
+    sessions: Mutex<HashMap<SocketAddr, u32>>,
+}
+
+impl TwampReflector {
+    /// Creates a reflector with no sessions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the next sequence number of the answers to `peer`
+    fn next_sequence(&self, peer: SocketAddr) -> u32 {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if sessions.len() >= MAX_SESSIONS && !sessions.contains_key(&peer) {
+            debug!(
+                sessions = sessions.len(),
+                "Too many TWAMP senders, starting over"
+            );
+            sessions.clear();
+        }
+        let next = sessions.entry(peer).or_insert(0);
+        let sequence = *next;
+        *next = next.wrapping_add(1);
+        sequence
+    }
+}
+
+impl DatagramHandler for TwampReflector {
+    fn handle<'a>(&self, data: &'a [u8], context: &DatagramContext) -> Option<Cow<'a, [u8]>> {
+        let sender = SenderPacket::parse(data)?;
+        let receive_timestamp = context.timestamp.unwrap_or_else(SystemTime::now);
+        let answer = ReflectorPacket {
+            sequence: self.next_sequence(context.peer),
+            timestamp: SystemTime::now(),
+            error_estimate: ERROR_ESTIMATE,
+            receive_timestamp,
+            sender,
+            sender_ttl: context.ttl.unwrap_or(DEFAULT_TTL),
+        };
+        Some(answer.encode(data.len()).into())
+    }
+}
+
+/// Timings of one answered test packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TwampSample {
+    /// Sequence number of the test packet
+    pub sequence: u32,
+    /// Sequence number of the reflector's answer
+    pub reflector_sequence: u32,
+    /// Round-trip time, less the time the reflector held the packet
+    pub rtt: Duration,
+    /// Delay from the sender to the reflector; `None` if the reflector's
+    /// clock makes it negative, as only synchronized clocks give one-way
+    /// delays
+    pub forward: Option<Duration>,
+    /// Delay from the reflector back to the sender, under the same caveat
+    pub reverse: Option<Duration>,
+    /// Time to live the test packet reached the reflector with
+    pub sender_ttl: u8,
+}
+
+impl TwampSample {
+    /// Measures `answer`, which arrived back at `arrival`
+    pub fn new(answer: &ReflectorPacket, arrival: SystemTime) -> Self {
+        let sent = answer.sender.timestamp;
+        let total = arrival.duration_since(sent).unwrap_or_default();
+        let held = answer
+            .timestamp
+            .duration_since(answer.receive_timestamp)
+            .unwrap_or_default();
+        Self {
+            sequence: answer.sender.sequence,
+            reflector_sequence: answer.sequence,
+            rtt: total.saturating_sub(held),
+            forward: answer.receive_timestamp.duration_since(sent).ok(),
+            reverse: arrival.duration_since(answer.timestamp).ok(),
+            sender_ttl: answer.sender_ttl,
+        }
+    }
+}
+
+/// Statistics of a run of test packets
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TwampStats {
+    /// Test packets sent
+    pub sent: u32,
+    /// Test packets answered, each counted once
+    pub received: u32,
+    /// Answers to a test packet that was already answered
+    pub duplicates: u32,
+    /// Answers that arrived after the answer to a later test packet
+    pub reordered: u32,
+    /// Shortest round-trip time
+    pub rtt_min: Option<Duration>,
+    /// Mean round-trip time
+    pub rtt_avg: Option<Duration>,
+    /// Longest round-trip time
+    pub rtt_max: Option<Duration>,
+    /// Mean difference between the round-trip times of consecutive answers
+    pub jitter: Duration,
+    /// Each answer, in the order it arrived, duplicates excluded
+    pub samples: Vec<TwampSample>,
+}
+
+impl TwampStats {
+    /// Summarizes the answers to `sent` test packets, in arrival order
+    pub fn new(sent: u32, answers: impl IntoIterator<Item = TwampSample>) -> Self {
+        let mut stats = Self {
+            sent,
+            ..Default::default()
+        };
+        let mut seen = std::collections::HashSet::new();
+        let mut highest = None;
+        let mut total = Duration::ZERO;
+        let mut variation = Duration::ZERO;
+        for sample in answers {
+            if !seen.insert(sample.sequence) {
+                stats.duplicates += 1;
+                continue;
+            }
+            if highest.is_some_and(|highest| sample.sequence < highest) {
+                stats.reordered += 1;
+            }
+            highest = highest.max(Some(sample.sequence));
+            if let Some(last) = stats.samples.last() {
+                variation += sample.rtt.abs_diff(last.rtt);
+            }
+            total += sample.rtt;
+            stats.rtt_min = Some(stats.rtt_min.map_or(sample.rtt, |min| min.min(sample.rtt)));
+            stats.rtt_max = stats.rtt_max.max(Some(sample.rtt));
+            stats.samples.push(sample);
+        }
+        stats.received = stats.samples.len() as u32;
+        if stats.received > 0 {
+            stats.rtt_avg = Some(total / stats.received);
+        }
+        if stats.received > 1 {
+            stats.jitter = variation / (stats.received - 1);
+        }
+        stats
+    }
+
+    /// Returns how many test packets went unanswered
+    pub fn lost(&self) -> u32 {
+        self.sent.saturating_sub(self.received)
+    }
+
+    /// Returns the share of test packets that went unanswered, from 0 to 1
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        f64::from(self.lost()) / f64::from(self.sent)
+    }
+}
+
+/// TWAMP-light session-sender for any datagram protocol
+///
+/// Answers arrive timestamped by the kernel where the socket supports it,
+/// so the round-trip time leaves out the client's scheduling delays.
+pub struct TwampClient<P: DatagramProtocol> {
+    socket: P::Socket,
+    server_addr: SocketAddr,
+    sequence: u32,
+    packet_size: usize,
+    timeout: Duration,
+}
+
+impl<P> TwampClient<P>
+where
+    P: DatagramProtocol + Send + Sync,
+    P::Error: Into<EchoError> + std::fmt::Display,
+{
+    /// Binds a socket of the reflector's family to send test packets from
+    pub async fn connect(server_addr: SocketAddr) -> Result<Self> {
+        let any: IpAddr = match server_addr {
+            SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+        let config = DatagramConfig {
+            bind_addr: SocketAddr::new(any, 0),
+            buffer_size: MAX_DATAGRAM_SIZE,
+            socket_options: SocketOptions {
+                timestamping: cfg!(target_os = "linux").then_some(true),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let socket = P::bind(&config).await.map_err(Into::into)?;
+        Ok(Self {
+            socket,
+            server_addr,
+            sequence: 0,
+            packet_size: SENDER_PACKET_LEN,
+            timeout: Duration::from_secs(1),
+        })
+    }
+
+    /// Pads test packets to `size` bytes; padding to
+    /// [`REFLECTOR_PACKET_LEN`] makes both directions carry the same size
+    pub fn with_packet_size(mut self, size: usize) -> Self {
+        self.packet_size = size.max(SENDER_PACKET_LEN);
+        self
+    }
+
+    /// Sets how long answers are awaited after the last test packet
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends `count` test packets `interval` apart and summarizes the
+    /// answers
+    ///
+    /// Sequence numbers carry on from the previous run, and late answers to
+    /// earlier runs are ignored.
+    pub async fn run(&mut self, count: u32, interval: Duration) -> Result<TwampStats> {
+        let first = self.sequence;
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        let mut answers = Vec::new();
+        let mut sent = 0;
+        let mut deadline = Instant::now();
+        loop {
+            let received = match timeout_at(deadline, P::recv_msg(&self.socket, &mut buffer)).await
+            {
+                Ok(received) => received.map_err(Into::into)?,
+                Err(_) if sent == count => break,
+                Err(_) => {
+                    let packet = SenderPacket::new(self.sequence, SystemTime::now());
+                    P::send_to(
+                        &self.socket,
+                        &packet.encode(self.packet_size),
+                        self.server_addr,
+                    )
+                    .await
+                    .map_err(Into::into)?;
+                    self.sequence = self.sequence.wrapping_add(1);
+                    sent += 1;
+                    deadline += if sent == count {
+                        self.timeout
+                    } else {
+                        interval
+                    };
+                    continue;
+                }
+            };
+            let arrival = received.timestamp.unwrap_or_else(SystemTime::now);
+            if received.peer != self.server_addr {
+                continue;
+            }
+            let Some(answer) = ReflectorPacket::parse(&buffer[..received.len]) else {
+                debug!(
+                    size = received.len,
+                    "Ignoring a datagram that is not a TWAMP answer"
+                );
+                continue;
+            };
+            if answer.sender.sequence.wrapping_sub(first) < sent {
+                answers.push(TwampSample::new(&answer, arrival));
+            }
+        }
+        Ok(TwampStats::new(count, answers))
+    }
+}
+
+/// Converts a time to the NTP format: seconds since 1900 in the high 32
+/// bits, and the fraction of a second in the low 32 bits
+fn to_ntp(time: SystemTime) -> u64 {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = (since.as_secs() + NTP_UNIX_OFFSET) & 0xffff_ffff;
+    let fraction = (u64::from(since.subsec_nanos()) << 32) / 1_000_000_000;
+    (seconds << 32) | fraction
+}
+
+/// Converts an NTP timestamp, taking it to be within the era from 1900
+fn from_ntp(ntp: u64) -> SystemTime {
+    let seconds = (ntp >> 32).saturating_sub(NTP_UNIX_OFFSET);
+    let nanos = ((ntp & 0xffff_ffff) * 1_000_000_000) >> 32;
+    UNIX_EPOCH + Duration::new(seconds, nanos as u32)
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    u16::from_be_bytes([data[0], data[1]])
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes(data.try_into().expect("four bytes"))
+}
+
+fn read_u64(data: &[u8]) -> u64 {
+    u64::from_be_bytes(data.try_into().expect("eight bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: SystemTime, b: SystemTime) -> bool {
+        a.duration_since(b).unwrap_or_else(|e| e.duration()) < Duration::from_micros(1)
+    }
+
+    #[test]
+    fn test_ntp_round_trip() {
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        assert!(close(from_ntp(to_ntp(time)), time));
+        assert_eq!(to_ntp(UNIX_EPOCH) >> 32, NTP_UNIX_OFFSET);
+        assert_eq!(
+            to_ntp(UNIX_EPOCH + Duration::from_millis(500)) as u32,
+            1 << 31
+        );
+    }
+
+    #[test]
+    fn test_packet_layout() {
+        let sent = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let packet = SenderPacket::new(7, sent);
+        let encoded = packet.encode(0);
+        assert_eq!(encoded.len(), SENDER_PACKET_LEN);
+        assert_eq!(&encoded[0..4], &[0, 0, 0, 7]);
+        assert_eq!(SenderPacket::parse(&encoded), Some(packet));
+        assert_eq!(SenderPacket::parse(&encoded[..13]), None);
+
+        let answer = ReflectorPacket {
+            sequence: 3,
+            timestamp: sent + Duration::from_millis(2),
+            error_estimate: ERROR_ESTIMATE,
+            receive_timestamp: sent + Duration::from_millis(1),
+            sender: packet,
+            sender_ttl: 64,
+        };
+        let encoded = answer.encode(100);
+        assert_eq!(encoded.len(), 100);
+        assert_eq!(&encoded[24..28], &[0, 0, 0, 7]);
+        assert_eq!(encoded[40], 64);
+        let parsed = ReflectorPacket::parse(&encoded).unwrap();
+        assert_eq!(parsed.sequence, 3);
+        assert!(close(parsed.receive_timestamp, answer.receive_timestamp));
+        assert_eq!(parsed.sender, packet);
+    }
+
+    #[test]
+    fn test_reflector() {
+        let reflector = TwampReflector::new();
+        let peer = "127.0.0.1:4000".parse().unwrap();
+        let arrival = SystemTime::now();
+        let context = DatagramContext::new(peer, "127.0.0.1:862".parse().unwrap())
+            .with_arrival(Some(arrival), Some(61));
+        let request = SenderPacket::new(42, arrival).encode(SENDER_PACKET_LEN);
+        for expected in 0..2 {
+            let answer = reflector.handle(&request, &context).unwrap();
+            assert_eq!(answer.len(), REFLECTOR_PACKET_LEN);
+            let answer = ReflectorPacket::parse(&answer).unwrap();
+            assert_eq!(answer.sequence, expected);
+            assert_eq!(answer.sender.sequence, 42);
+            assert_eq!(answer.sender_ttl, 61);
+            assert!(close(answer.receive_timestamp, arrival));
+            assert!(answer.timestamp >= answer.receive_timestamp);
+        }
+        assert!(reflector.handle(b"short", &context).is_none());
+    }
+
+    #[test]
+    fn test_stats() {
+        let sample = |sequence, rtt_ms| TwampSample {
+            sequence,
+            reflector_sequence: sequence,
+            rtt: Duration::from_millis(rtt_ms),
+            forward: None,
+            reverse: None,
+            sender_ttl: 64,
+        };
+        let stats = TwampStats::new(
+            5,
+            [sample(0, 10), sample(2, 14), sample(1, 12), sample(2, 14)],
+        );
+        assert_eq!(stats.received, 3);
+        assert_eq!(stats.lost(), 2);
+        assert_eq!(stats.loss(), 0.4);
+        assert_eq!(stats.duplicates, 1);
+        assert_eq!(stats.reordered, 1);
+        assert_eq!(stats.rtt_min, Some(Duration::from_millis(10)));
+        assert_eq!(stats.rtt_avg, Some(Duration::from_millis(12)));
+        assert_eq!(stats.rtt_max, Some(Duration::from_millis(14)));
+        assert_eq!(stats.jitter, Duration::from_millis(3));
+
+        let answer = ReflectorPacket {
+            sequence: 0,
+            timestamp: UNIX_EPOCH + Duration::from_millis(1005),
+            error_estimate: ERROR_ESTIMATE,
+            receive_timestamp: UNIX_EPOCH + Duration::from_millis(1003),
+            sender: SenderPacket::new(0, UNIX_EPOCH + Duration::from_secs(1)),
+            sender_ttl: 255,
+        };
+        let sample = TwampSample::new(&answer, UNIX_EPOCH + Duration::from_millis(1010));
+        assert_eq!(sample.rtt, Duration::from_millis(8));
+        assert_eq!(sample.forward, Some(Duration::from_millis(3)));
+        assert_eq!(sample.reverse, Some(Duration::from_millis(5)));
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+/// In-memory transport between an OpenSSL session and a UDP socket
+///
+/// Each read hands OpenSSL one received datagram, or `WouldBlock` when none
+/// is waiting, and each write is one datagram to send. The socket is driven
+/// by the caller, so a session never blocks or awaits.
+#[derive(Debug, Default)]
+pub(crate) struct Channel {
+    /// Datagrams received from the peer, not yet read by OpenSSL
+    pub(crate) inbound: VecDeque<Vec<u8>>,
+    /// Datagrams written by OpenSSL, not yet sent to the peer
+    outbound: Vec<Vec<u8>>,
+}
+
+impl Channel {
+    /// Takes the datagrams OpenSSL has written since the last call
+    pub(crate) fn take_outbound(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.outbound)
+    }
+}
+
+impl Read for Channel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let datagram = self.inbound.pop_front().ok_or(io::ErrorKind::WouldBlock)?;
+        // Like a UDP socket, the rest of a datagram too large for `buf` is lost
+        let len = datagram.len().min(buf.len());
+        buf[..len].copy_from_slice(&datagram[..len]);
+        Ok(len)
+    }
+}
+
+impl Write for Channel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.outbound.push(buf.to_vec());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datagram_boundaries() {
+        let mut channel = Channel::default();
+        channel
+            .inbound
+            .extend([b"first".to_vec(), b"second".to_vec()]);
+
+        let mut buf = [0u8; 3];
+        assert_eq!(channel.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"fir");
+        let mut buf = [0u8; 16];
+        assert_eq!(channel.read(&mut buf).unwrap(), 6);
+        assert_eq!(&buf[..6], b"second");
+        assert_eq!(
+            channel.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        channel.write_all(b"one").unwrap();
+        channel.write_all(b"two").unwrap();
+        assert_eq!(channel.take_outbound(), [b"one".to_vec(), b"two".to_vec()]);
+        assert!(channel.take_outbound().is_empty());
+    }
+}
@@ -0,0 +1,203 @@
+use super::channel::Channel;
+use super::config::{DtlsClientConfig, DtlsTrust};
+use super::protocol::MAX_RECORD_SIZE;
+use crate::common::EchoClient;
+use crate::datagram::MAX_DATAGRAM_SIZE;
+use crate::{EchoError, Result};
+use async_trait::async_trait;
+use openssl::ssl::{
+    ErrorCode, Ssl, SslContext, SslMethod, SslOptions, SslStream, SslVerifyMode, SslVersion,
+};
+use openssl::x509::X509;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+/// How long to wait for a datagram before letting OpenSSL check whether a
+/// flight needs retransmitting
+const RETRANSMIT_CHECK: Duration = Duration::from_millis(100);
+
+/// DTLS echo client
+///
+/// Completes a DTLS 1.2 handshake with the server on
+/// [`connect`](Self::connect), retransmitting lost flights, then exchanges
+/// application data records.
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::EchoClient;
+/// use echosrv::dtls::{DtlsClientConfig, DtlsEchoClient, DtlsTrust, Psk};
+///
+/// # async fn example() -> echosrv::Result<()> {
+/// let psk: Psk = "client:73656372657421".parse()?;
+/// let config = DtlsClientConfig::new(DtlsTrust::Psk(psk));
+/// let mut client = DtlsEchoClient::connect("127.0.0.1:4433".parse().unwrap(), config).await?;
+/// let response = client.echo_string("Hello, DTLS!").await?;
+/// assert_eq!(response, "Hello, DTLS!");
+/// client.close().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct DtlsEchoClient {
+    socket: UdpSocket,
+    stream: SslStream<Channel>,
+    read_timeout: Duration,
+}
+
+impl DtlsEchoClient {
+    /// Connects to `server_addr` and completes the handshake
+    pub async fn connect(server_addr: SocketAddr, config: DtlsClientConfig) -> Result<Self> {
+        let local = match server_addr {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(local).await.map_err(EchoError::Udp)?;
+        socket.connect(server_addr).await.map_err(EchoError::Udp)?;
+
+        let mut client = Self {
+            socket,
+            stream: SslStream::new(session(&config)?, Channel::default())?,
+            read_timeout: config.read_timeout,
+        };
+        timeout(config.handshake_timeout, client.handshake())
+            .await
+            .map_err(|_| {
+                EchoError::Timeout(format!("DTLS handshake with {server_addr} timed out"))
+            })??;
+        Ok(client)
+    }
+
+    /// Sends `data` as one application data record
+    pub async fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.stream
+            .ssl_write(data)
+            .map_err(|e| EchoError::Dtls(format!("failed to encrypt: {e}")))?;
+        self.flush().await
+    }
+
+    /// Receives the plaintext of the next application data record
+    pub async fn recv(&mut self) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + self.read_timeout;
+        let mut record = vec![0; MAX_RECORD_SIZE];
+        loop {
+            match self.stream.ssl_read(&mut record) {
+                Ok(n) => {
+                    record.truncate(n);
+                    return Ok(record);
+                }
+                Err(e) if e.code() == ErrorCode::WANT_READ => {}
+                Err(e) if e.code() == ErrorCode::ZERO_RETURN => {
+                    return Err(EchoError::Dtls("the server closed the session".to_string()));
+                }
+                Err(e) => return Err(EchoError::Dtls(format!("failed to decrypt: {e}"))),
+            }
+            // Reading may have queued a retransmission of our last flight
+            self.flush().await?;
+            let wait = deadline.saturating_duration_since(Instant::now());
+            if wait.is_zero() {
+                return Err(EchoError::Timeout("no DTLS record received".to_string()));
+            }
+            self.receive(wait.min(RETRANSMIT_CHECK)).await?;
+        }
+    }
+
+    /// Sends a close_notify alert, ending the session
+    pub async fn close(mut self) -> Result<()> {
+        // The server's answering close_notify is not waited for
+        let _ = self.stream.shutdown();
+        self.flush().await
+    }
+
+    /// Drives the handshake until it completes or fails
+    async fn handshake(&mut self) -> Result<()> {
+        loop {
+            let result = self.stream.do_handshake();
+            self.flush().await?;
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) if e.code() == ErrorCode::WANT_READ => {
+                    self.receive(RETRANSMIT_CHECK).await?;
+                }
+                Err(e) => return Err(EchoError::Dtls(format!("handshake failed: {e}"))),
+            }
+        }
+    }
+
+    /// Waits up to `wait` for a datagram from the server and queues it for
+    /// the session
+    async fn receive(&mut self, wait: Duration) -> Result<()> {
+        let mut datagram = vec![0; MAX_DATAGRAM_SIZE];
+        match timeout(wait, self.socket.recv(&mut datagram)).await {
+            Ok(Ok(n)) => {
+                datagram.truncate(n);
+                self.stream.get_mut().inbound.push_back(datagram);
+                Ok(())
+            }
+            Ok(Err(e)) => Err(EchoError::Udp(e)),
+            // Nothing yet; the caller retries, which retransmits if due
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Sends the datagrams the session has written
+    async fn flush(&mut self) -> Result<()> {
+        for datagram in self.stream.get_mut().take_outbound() {
+            self.socket.send(&datagram).await.map_err(EchoError::Udp)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EchoClient for DtlsEchoClient {
+    async fn echo(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.send(data).await?;
+        self.recv().await
+    }
+}
+
+/// Creates the client session for `config`
+pub(super) fn session(config: &DtlsClientConfig) -> Result<Ssl> {
+    let mut builder = SslContext::builder(SslMethod::dtls_client())?;
+    builder.set_min_proto_version(Some(SslVersion::DTLS1_2))?;
+    builder.set_options(SslOptions::NO_QUERY_MTU);
+    match &config.trust {
+        DtlsTrust::Psk(psk) => {
+            builder.set_cipher_list("PSK")?;
+            let psk = psk.clone();
+            builder.set_psk_client_callback(move |_, _, identity, key| {
+                // The identity is written NUL-terminated
+                if identity.len() <= psk.identity.len() || key.len() < psk.key.len() {
+                    return Ok(0);
+                }
+                identity[..psk.identity.len()].copy_from_slice(psk.identity.as_bytes());
+                identity[psk.identity.len()] = 0;
+                key[..psk.key.len()].copy_from_slice(&psk.key);
+                Ok(psk.key.len())
+            });
+        }
+        DtlsTrust::Certificate { ca, .. } => {
+            for certificate in X509::stack_from_pem(ca)? {
+                builder.cert_store_mut().add_cert(certificate)?;
+            }
+            builder.set_verify(SslVerifyMode::PEER);
+        }
+        DtlsTrust::Insecure => builder.set_verify(SslVerifyMode::NONE),
+    }
+
+    let mut ssl = Ssl::new(&builder.build())?;
+    if let DtlsTrust::Certificate { server_name, .. } = &config.trust {
+        match server_name.parse::<IpAddr>() {
+            Ok(ip) => ssl.param_mut().set_ip(ip)?,
+            Err(_) => {
+                ssl.set_hostname(server_name)?;
+                ssl.param_mut().set_host(server_name)?;
+            }
+        }
+    }
+    ssl.set_mtu(u32::from(config.mtu))?;
+    ssl.set_connect_state();
+    Ok(ssl)
+}
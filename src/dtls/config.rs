@@ -0,0 +1,318 @@
+use crate::EchoError;
+use std::fmt;
+use std::time::Duration;
+
+/// Largest datagram DTLS records are sized for by default, small enough to
+/// cross most paths without IP fragmentation
+pub const DEFAULT_MTU: u16 = 1200;
+
+/// A pre-shared key and the identity that names it
+///
+/// Parsed from `IDENTITY:HEXKEY`, as given on the command line. The key is
+/// never printed.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::dtls::Psk;
+///
+/// let psk: Psk = "sensor-7:00112233".parse().unwrap();
+/// assert_eq!(psk.identity, "sensor-7");
+/// assert_eq!(psk.key, [0x00, 0x11, 0x22, 0x33]);
+/// assert_eq!(format!("{psk:?}"), r#"Psk { identity: "sensor-7", key: "<4 bytes>" }"#);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Psk {
+    /// Identity the client presents
+    pub identity: String,
+    /// Shared secret
+    pub key: Vec<u8>,
+}
+
+impl Psk {
+    /// Creates a key named `identity`
+    pub fn new(identity: impl Into<String>, key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            identity: identity.into(),
+            key: key.into(),
+        }
+    }
+}
+
+impl fmt::Debug for Psk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Psk")
+            .field("identity", &self.identity)
+            .field("key", &format_args!("\"<{} bytes>\"", self.key.len()))
+            .finish()
+    }
+}
+
+impl std::str::FromStr for Psk {
+    type Err = EchoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Never echo the input: it holds the key
+        let invalid = |reason: &str| EchoError::Config(format!("invalid PSK: {reason}"));
+        let (identity, hex) = s
+            .rsplit_once(':')
+            .ok_or_else(|| invalid("expected IDENTITY:HEXKEY"))?;
+        if identity.is_empty() {
+            return Err(invalid("empty identity"));
+        }
+        if hex.is_empty() || hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid(&format!(
+                "the key for '{identity}' must be a non-empty, even number of hex digits"
+            )));
+        }
+        let key: Vec<u8> = hex
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| {
+                let digit = |b: u8| (b as char).to_digit(16).unwrap() as u8;
+                digit(pair[0]) << 4 | digit(pair[1])
+            })
+            .collect();
+        Ok(Self::new(identity, key))
+    }
+}
+
+/// How a DTLS server proves itself to its clients
+#[derive(Clone, PartialEq, Eq, Default)]
+pub enum DtlsCredentials {
+    /// Only clients that know this key can connect (`PSK` cipher suites)
+    Psk(Psk),
+    /// A PEM certificate chain, leaf first, and its PEM private key
+    Certificate {
+        /// Certificate chain
+        chain: Vec<u8>,
+        /// Private key
+        key: Vec<u8>,
+    },
+    /// A certificate for `localhost` generated when the server binds; its
+    /// fingerprint is logged
+    #[default]
+    SelfSigned,
+}
+
+impl fmt::Debug for DtlsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Psk(psk) => f.debug_tuple("Psk").field(psk).finish(),
+            Self::Certificate { chain, .. } => f
+                .debug_struct("Certificate")
+                .field("chain", &format_args!("\"<{} bytes>\"", chain.len()))
+                .field("key", &"<redacted>")
+                .finish(),
+            Self::SelfSigned => f.write_str("SelfSigned"),
+        }
+    }
+}
+
+/// Configuration for DTLS servers
+///
+/// Each peer gets its own session, from its first `ClientHello` (with cookie
+/// exchange, the first that returns its cookie) until it closes, errs, or
+/// goes quiet for longer than the timeouts.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::dtls::{DtlsConfig, Psk};
+/// use std::time::Duration;
+///
+/// let config = DtlsConfig::psk(Psk::new("client", b"secret".to_vec()))
+///     .with_idle_timeout(Duration::from_secs(60))
+///     .with_max_sessions(64);
+/// assert!(config.cookie_exchange);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DtlsConfig {
+    /// How the server authenticates
+    pub credentials: DtlsCredentials,
+    /// Whether new peers must return a stateless cookie
+    /// (`HelloVerifyRequest`) before the server sends its certificate or key
+    /// exchange, so spoofed addresses cannot be flooded with large replies;
+    /// until then no session is kept for them
+    pub cookie_exchange: bool,
+    /// How long a peer may take to complete its handshake
+    pub handshake_timeout: Duration,
+    /// How long an established session may stay silent before it is dropped
+    pub idle_timeout: Duration,
+    /// Most sessions, handshaking or established, kept at once; datagrams
+    /// from further peers are dropped
+    pub max_sessions: usize,
+    /// Largest datagram the server's handshake messages are split to fit
+    pub mtu: u16,
+}
+
+impl DtlsConfig {
+    /// Creates a configuration for clients that know `psk`
+    pub fn psk(psk: Psk) -> Self {
+        Self {
+            credentials: DtlsCredentials::Psk(psk),
+            ..Self::default()
+        }
+    }
+
+    /// Creates a configuration presenting the PEM certificate `chain`,
+    /// signed by the PEM private `key`
+    pub fn certificate(chain: impl Into<Vec<u8>>, key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            credentials: DtlsCredentials::Certificate {
+                chain: chain.into(),
+                key: key.into(),
+            },
+            ..Self::default()
+        }
+    }
+
+    /// Sets whether new peers must complete a cookie exchange
+    pub fn with_cookie_exchange(mut self, enabled: bool) -> Self {
+        self.cookie_exchange = enabled;
+        self
+    }
+
+    /// Sets how long a peer may take to complete its handshake
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Sets how long an established session may stay silent
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Sets the most sessions kept at once
+    pub fn with_max_sessions(mut self, max: usize) -> Self {
+        self.max_sessions = max;
+        self
+    }
+
+    /// Sets the largest datagram handshake messages are split to fit
+    pub fn with_mtu(mut self, mtu: u16) -> Self {
+        self.mtu = mtu;
+        self
+    }
+}
+
+impl Default for DtlsConfig {
+    fn default() -> Self {
+        Self {
+            credentials: DtlsCredentials::SelfSigned,
+            cookie_exchange: true,
+            handshake_timeout: Duration::from_secs(10),
+            idle_timeout: Duration::from_secs(300),
+            max_sessions: 1024,
+            mtu: DEFAULT_MTU,
+        }
+    }
+}
+
+/// How a DTLS client authenticates the server
+#[derive(Clone, PartialEq, Eq)]
+pub enum DtlsTrust {
+    /// Both sides prove they know this key
+    Psk(Psk),
+    /// The server's certificate must chain to one of these PEM certificates
+    /// and name `server_name` (a DNS name or IP address)
+    Certificate {
+        /// Trusted certificates
+        ca: Vec<u8>,
+        /// Name the certificate must carry
+        server_name: String,
+    },
+    /// Any certificate is accepted; for testing against self-signed servers
+    Insecure,
+}
+
+impl fmt::Debug for DtlsTrust {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Psk(psk) => f.debug_tuple("Psk").field(psk).finish(),
+            Self::Certificate { ca, server_name } => f
+                .debug_struct("Certificate")
+                .field("ca", &format_args!("\"<{} bytes>\"", ca.len()))
+                .field("server_name", server_name)
+                .finish(),
+            Self::Insecure => f.write_str("Insecure"),
+        }
+    }
+}
+
+/// Configuration for DTLS clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DtlsClientConfig {
+    /// How the server is authenticated
+    pub trust: DtlsTrust,
+    /// How long the handshake may take, retransmissions included
+    pub handshake_timeout: Duration,
+    /// How long to wait for each echoed record
+    pub read_timeout: Duration,
+    /// Largest datagram handshake messages are split to fit
+    pub mtu: u16,
+}
+
+impl DtlsClientConfig {
+    /// Creates a configuration that authenticates the server with `trust`
+    pub fn new(trust: DtlsTrust) -> Self {
+        Self {
+            trust,
+            handshake_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(5),
+            mtu: DEFAULT_MTU,
+        }
+    }
+
+    /// Sets how long the handshake may take
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Sets how long to wait for each echoed record
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_psk_parsing() {
+        let psk: Psk = "a:b:0aFF".parse().unwrap();
+        assert_eq!(psk, Psk::new("a:b", vec![0x0a, 0xff]));
+
+        for invalid in ["nokey", ":00", "id:", "id:abc", "id:zz", "id:aé01", "id:+1"] {
+            assert!(
+                matches!(invalid.parse::<Psk>(), Err(EchoError::Config(_))),
+                "{invalid} should be rejected"
+            );
+        }
+
+        // Errors name the identity but never the key
+        let error = "client:c0ffeX".parse::<Psk>().unwrap_err().to_string();
+        assert!(error.contains("client") && !error.contains("c0ffe"));
+    }
+
+    #[test]
+    fn test_debug_redacts_keys() {
+        let credentials = DtlsCredentials::Certificate {
+            chain: b"chain".to_vec(),
+            key: b"PRIVATE".to_vec(),
+        };
+        assert!(!format!("{credentials:?}").contains("PRIVATE"));
+
+        let trust = DtlsTrust::Psk(Psk::new("id", b"SECRET".to_vec()));
+        assert!(!format!("{trust:?}").contains("SECRET"));
+        assert!(
+            !format!("{:?}", DtlsConfig::psk(Psk::new("id", b"SECRET".to_vec())))
+                .contains("SECRET")
+        );
+    }
+}
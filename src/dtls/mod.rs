@@ -0,0 +1,30 @@
+//! DTLS echo over UDP
+//!
+//! [`DtlsProtocol`] implements [`DatagramProtocol`](crate::datagram::DatagramProtocol)
+//! on top of OpenSSL, so the generic datagram echo server serves DTLS 1.2
+//! peers: each gets its own session, authenticated by a pre-shared key or a
+//! certificate, with cookie exchange protecting against spoofed handshakes.
+//! [`DtlsEchoClient`] is the matching client.
+//!
+//! The settings in [`config`] are always available; the protocol and client
+//! need the `dtls` feature (enabled by default).
+
+pub mod config;
+
+#[cfg(feature = "dtls")]
+mod channel;
+#[cfg(feature = "dtls")]
+pub mod client;
+#[cfg(feature = "dtls")]
+pub mod protocol;
+#[cfg(feature = "dtls")]
+pub mod server;
+
+pub use config::{DtlsClientConfig, DtlsConfig, DtlsCredentials, DtlsTrust, Psk};
+
+#[cfg(feature = "dtls")]
+pub use client::DtlsEchoClient;
+#[cfg(feature = "dtls")]
+pub use protocol::{DtlsProtocol, DtlsSocket};
+#[cfg(feature = "dtls")]
+pub use server::DtlsEchoServer;
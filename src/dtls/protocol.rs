@@ -0,0 +1,729 @@
+use super::channel::Channel;
+use super::config::{DtlsConfig, DtlsCredentials};
+use crate::datagram::{DatagramConfig, DatagramProtocol, MAX_DATAGRAM_SIZE, Received};
+use crate::network::fd_inheritance::FdInheritanceConfig;
+use crate::udp::UdpProtocol;
+use crate::{EchoError, Result};
+use async_trait::async_trait;
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::error::ErrorStack;
+use openssl::ex_data::Index;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::ssl::{
+    ErrorCode, Ssl, SslContext, SslContextBuilder, SslMethod, SslOptions, SslRef, SslStream,
+    SslVersion,
+};
+use openssl::x509::extension::SubjectAlternativeName;
+use openssl::x509::{X509, X509NameBuilder};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::ops::Range;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tracing::{debug, info, warn};
+
+/// Largest plaintext a DTLS record carries
+pub(crate) const MAX_RECORD_SIZE: usize = 16384;
+
+/// How often sessions are checked against their timeouts
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// DTLS 1.2 over UDP
+///
+/// Every peer gets its own session, created by its first `ClientHello`, or
+/// with cookie exchange by the first that returns the peer's cookie: until
+/// then a peer is answered with a `HelloVerifyRequest` and nothing is stored.
+/// Handshake messages, retransmissions and alerts are answered inside
+/// [`recv_from`](DatagramProtocol::recv_from), which only returns the
+/// plaintext of application data records; [`send_to`](DatagramProtocol::send_to)
+/// sends its data as a record of the peer's session. A peer that sends a new
+/// `ClientHello` after its handshake completed (a restarted client) starts a
+/// new session.
+///
+/// Binding through [`DatagramProtocol`] secures the socket with
+/// [`DtlsConfig::default`], a self-signed certificate for localhost;
+/// [`DtlsEchoServer`](super::DtlsEchoServer) and [`DtlsSocket::bind`] take
+/// other settings. Sessions live on the socket that accepted them, so only
+/// one worker socket is supported.
+pub struct DtlsProtocol;
+
+/// A UDP socket and the DTLS sessions of its peers
+pub struct DtlsSocket {
+    socket: UdpSocket,
+    context: SslContext,
+    /// Key of the cookies new peers must return
+    cookie_secret: [u8; 32],
+    config: DtlsConfig,
+    /// Receive buffer for raw datagrams, used by one receive at a time
+    buffer: tokio::sync::Mutex<Vec<u8>>,
+    state: Mutex<State>,
+}
+
+struct State {
+    sessions: HashMap<SocketAddr, Session>,
+    /// Decrypted application data, oldest first
+    ready: VecDeque<(Vec<u8>, SocketAddr)>,
+    last_sweep: Instant,
+}
+
+struct Session {
+    stream: SslStream<Channel>,
+    established: bool,
+    started: Instant,
+    last_seen: Instant,
+}
+
+impl DtlsSocket {
+    fn new(
+        socket: UdpSocket,
+        context: SslContext,
+        cookie_secret: [u8; 32],
+        config: DtlsConfig,
+    ) -> Self {
+        Self {
+            socket,
+            context,
+            cookie_secret,
+            config,
+            buffer: tokio::sync::Mutex::new(vec![0; MAX_DATAGRAM_SIZE]),
+            state: Mutex::new(State {
+                sessions: HashMap::new(),
+                ready: VecDeque::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+
+    /// Binds a UDP socket for `config` whose peers get sessions with the
+    /// settings in `dtls`
+    pub async fn bind(config: &DatagramConfig, dtls: &DtlsConfig) -> Result<Self> {
+        let (context, cookie_secret) = server_context(dtls)?;
+        let socket = UdpProtocol::bind(config).await?;
+        Ok(Self::new(socket, context, cookie_secret, dtls.clone()))
+    }
+
+    /// Binds like [`bind`](Self::bind), with explicit file descriptor
+    /// inheritance
+    pub async fn bind_with_inheritance(
+        config: &DatagramConfig,
+        fd_config: &FdInheritanceConfig,
+        dtls: &DtlsConfig,
+    ) -> Result<Self> {
+        let (context, cookie_secret) = server_context(dtls)?;
+        let socket = UdpProtocol::bind_with_inheritance(config, fd_config).await?;
+        Ok(Self::new(socket, context, cookie_secret, dtls.clone()))
+    }
+
+    /// Returns the address the socket is bound to
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Returns how many sessions are open, handshaking or established
+    pub fn sessions(&self) -> usize {
+        self.state.lock().unwrap().sessions.len()
+    }
+
+    /// Feeds a datagram from `peer` to its session, queueing any application
+    /// data, and returns the datagrams to send back
+    fn handle(&self, datagram: &[u8], peer: SocketAddr) -> Vec<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if now.duration_since(state.last_sweep) >= SWEEP_INTERVAL {
+            state.sweep(now, &self.config);
+        }
+        let State {
+            sessions, ready, ..
+        } = &mut *state;
+
+        if is_client_hello(datagram) && sessions.get(&peer).is_some_and(|s| s.established) {
+            debug!(%peer, "Peer started a new DTLS handshake; replacing its session");
+            sessions.remove(&peer);
+        }
+        let count = sessions.len();
+        let session = match sessions.entry(peer) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                // Anything else from an unknown peer is a leftover or noise
+                if !is_client_hello(datagram) {
+                    debug!(%peer, "Dropping a datagram outside any DTLS session");
+                    return Vec::new();
+                }
+                let mut replay = None;
+                if self.config.cookie_exchange {
+                    match self.check_cookie(datagram, peer) {
+                        Ok(first) => replay = Some(first),
+                        Err(request) => return request.into_iter().collect(),
+                    }
+                }
+                if count >= self.config.max_sessions {
+                    warn!(%peer, max_sessions = self.config.max_sessions, "Too many DTLS sessions; dropping a new peer");
+                    return Vec::new();
+                }
+                let session = match self.accept(peer) {
+                    Ok(session) => entry.insert(session),
+                    Err(e) => {
+                        warn!(%peer, error = %e, "Failed to create a DTLS session");
+                        return Vec::new();
+                    }
+                };
+                // Take the new session through the exchange answered without
+                // it, so it expects the ClientHello that returned the cookie
+                if let Some(first) = replay {
+                    session.stream.get_mut().inbound.push_back(first);
+                    let open = session.advance(peer, ready);
+                    // The peer already has its HelloVerifyRequest
+                    session.stream.get_mut().take_outbound();
+                    if !open {
+                        sessions.remove(&peer);
+                        return Vec::new();
+                    }
+                }
+                session
+            }
+        };
+        session.last_seen = now;
+        session
+            .stream
+            .get_mut()
+            .inbound
+            .push_back(datagram.to_vec());
+
+        let open = session.advance(peer, ready);
+        let replies = session.stream.get_mut().take_outbound();
+        if !open {
+            sessions.remove(&peer);
+        }
+        replies
+    }
+
+    /// Checks the cookie of a `ClientHello` from a peer without a session
+    ///
+    /// Returns the cookieless `ClientHello` the peer sent first when the
+    /// cookie is valid, or else the `HelloVerifyRequest` to answer with, if
+    /// the `ClientHello` could be parsed.
+    fn check_cookie(
+        &self,
+        hello: &[u8],
+        peer: SocketAddr,
+    ) -> std::result::Result<Vec<u8>, Option<Vec<u8>>> {
+        let Some(received) = hello_cookie(hello) else {
+            debug!(%peer, "Dropping a malformed or fragmented ClientHello");
+            return Err(None);
+        };
+        let expected = match cookie(&self.cookie_secret, Some(&peer)) {
+            Ok(cookie) => cookie,
+            Err(e) => {
+                warn!(%peer, error = %e, "Failed to compute a DTLS cookie");
+                return Err(None);
+            }
+        };
+        let cookie = &hello[received.clone()];
+        if cookie.len() == expected.len() && openssl::memcmp::eq(cookie, &expected) {
+            Ok(cookieless_hello(hello, received))
+        } else {
+            debug!(%peer, "Asking a new DTLS peer to return a cookie");
+            Err(Some(hello_verify_request(hello, &expected)))
+        }
+    }
+
+    /// Creates the session for a new peer
+    fn accept(&self, peer: SocketAddr) -> std::result::Result<Session, ErrorStack> {
+        let mut ssl = Ssl::new(&self.context)?;
+        ssl.set_ex_data(peer_index()?, peer);
+        ssl.set_mtu(u32::from(self.config.mtu))?;
+        ssl.set_accept_state();
+        let now = Instant::now();
+        Ok(Session {
+            stream: SslStream::new(ssl, Channel::default())?,
+            established: false,
+            started: now,
+            last_seen: now,
+        })
+    }
+
+    /// Encrypts `data` for `peer`, returning the datagrams to send
+    fn seal(&self, data: &[u8], peer: SocketAddr) -> Result<Vec<Vec<u8>>> {
+        let mut state = self.state.lock().unwrap();
+        let session = state
+            .sessions
+            .get_mut(&peer)
+            .filter(|session| session.established)
+            .ok_or_else(|| EchoError::Dtls(format!("no DTLS session with {peer}")))?;
+        if let Err(e) = session.stream.ssl_write(data) {
+            state.sessions.remove(&peer);
+            return Err(EchoError::Dtls(format!(
+                "failed to encrypt for {peer}: {e}"
+            )));
+        }
+        Ok(session.stream.get_mut().take_outbound())
+    }
+
+    /// Takes the oldest decrypted application data
+    fn next_ready(&self) -> Option<(Vec<u8>, SocketAddr)> {
+        self.state.lock().unwrap().ready.pop_front()
+    }
+}
+
+impl State {
+    /// Drops sessions that outlived their handshake or idle timeout
+    fn sweep(&mut self, now: Instant, config: &DtlsConfig) {
+        self.sessions.retain(|peer, session| {
+            let expired = if session.established {
+                now.duration_since(session.last_seen) > config.idle_timeout
+            } else {
+                now.duration_since(session.started) > config.handshake_timeout
+            };
+            if expired {
+                debug!(%peer, established = session.established, "Dropping an expired DTLS session");
+            }
+            !expired
+        });
+        self.last_sweep = now;
+    }
+}
+
+impl Session {
+    /// Processes the session's inbound datagrams, queueing application data
+    /// for `peer` into `ready`; returns false once the session is over
+    fn advance(&mut self, peer: SocketAddr, ready: &mut VecDeque<(Vec<u8>, SocketAddr)>) -> bool {
+        if !self.established {
+            match self.stream.do_handshake() {
+                Ok(()) => {
+                    self.established = true;
+                    info!(%peer, version = self.stream.ssl().version_str(), cipher = ?self.stream.ssl().current_cipher().map(|c| c.name()), "DTLS handshake complete");
+                }
+                Err(e) if e.code() == ErrorCode::WANT_READ => return true,
+                Err(e) => {
+                    warn!(%peer, error = %e, "DTLS handshake failed");
+                    return false;
+                }
+            }
+        }
+
+        let mut record = [0u8; MAX_RECORD_SIZE];
+        loop {
+            match self.stream.ssl_read(&mut record) {
+                Ok(n) => ready.push_back((record[..n].to_vec(), peer)),
+                Err(e) if e.code() == ErrorCode::WANT_READ => return true,
+                Err(e) if e.code() == ErrorCode::ZERO_RETURN => {
+                    debug!(%peer, "Peer closed its DTLS session");
+                    // Answer with our own close_notify
+                    let _ = self.stream.shutdown();
+                    return false;
+                }
+                Err(e) => {
+                    warn!(%peer, error = %e, "DTLS session failed");
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+/// Whether `datagram` starts with a `ClientHello` record of epoch 0
+fn is_client_hello(datagram: &[u8]) -> bool {
+    // Record header: type (22 = handshake), version, epoch, sequence number,
+    // length; then the handshake header: type (1 = ClientHello), ...
+    datagram.len() > 13 && datagram[0] == 22 && datagram[3..5] == [0, 0] && datagram[13] == 1
+}
+
+/// Returns where the cookie of an unfragmented `ClientHello` record lies
+fn hello_cookie(hello: &[u8]) -> Option<Range<usize>> {
+    // Record header (13 bytes), handshake header (12 bytes: type, length,
+    // message sequence, fragment offset and length), then the client
+    // version (2), random (32), session ID and cookie
+    let header = hello.get(..25)?;
+    let record_end = 13 + usize::from(u16::from_be_bytes([header[11], header[12]]));
+    if record_end > hello.len()
+        || header[19..22] != [0, 0, 0]
+        || header[22..25] != header[14..17]
+    {
+        return None;
+    }
+    let session_id = 25 + 34;
+    let cookie_len = session_id + 1 + usize::from(*hello.get(session_id)?);
+    let start = cookie_len + 1;
+    let end = start + usize::from(*hello.get(cookie_len)?);
+    (end <= record_end).then_some(start..end)
+}
+
+/// Rebuilds the `ClientHello` a peer sent before `hello`, which returned the
+/// cookie at `cookie`: the same record without a cookie, numbered first
+fn cookieless_hello(hello: &[u8], cookie: Range<usize>) -> Vec<u8> {
+    let record_end = 13 + usize::from(u16::from_be_bytes([hello[11], hello[12]]));
+    let mut first = hello[..cookie.start - 1].to_vec();
+    first.push(0);
+    first.extend_from_slice(&hello[cookie.end..record_end]);
+
+    let record_len = (first.len() - 13) as u16;
+    let body_len = (first.len() - 25) as u32;
+    // Record sequence number, then the lengths and message sequence number
+    first[5..11].fill(0);
+    first[11..13].copy_from_slice(&record_len.to_be_bytes());
+    first[14..17].copy_from_slice(&body_len.to_be_bytes()[1..]);
+    first[17..19].fill(0);
+    first[22..25].copy_from_slice(&body_len.to_be_bytes()[1..]);
+    first
+}
+
+/// Builds the `HelloVerifyRequest` asking the sender of `hello` to return
+/// `cookie`
+fn hello_verify_request(hello: &[u8], cookie: &[u8]) -> Vec<u8> {
+    let body_len = (3 + cookie.len()) as u32;
+    let mut request = Vec::with_capacity(25 + 3 + cookie.len());
+    // Record header: handshake, DTLS 1.0 (which RFC 6347 asks for in this
+    // message whatever the version), epoch 0, the ClientHello's sequence
+    // number, length
+    request.extend_from_slice(&[22, 0xfe, 0xff, 0, 0]);
+    request.extend_from_slice(&hello[5..11]);
+    request.extend_from_slice(&(12 + body_len as u16).to_be_bytes());
+    // Handshake header: HelloVerifyRequest, length, the ClientHello's
+    // message sequence number, one fragment
+    request.push(3);
+    request.extend_from_slice(&body_len.to_be_bytes()[1..]);
+    request.extend_from_slice(&hello[17..19]);
+    request.extend_from_slice(&[0, 0, 0]);
+    request.extend_from_slice(&body_len.to_be_bytes()[1..]);
+    // Server version and cookie
+    request.extend_from_slice(&[0xfe, 0xff, cookie.len() as u8]);
+    request.extend_from_slice(cookie);
+    request
+}
+
+/// Index of the peer address stored with each server session, for the
+/// cookie callbacks
+fn peer_index() -> std::result::Result<Index<Ssl, SocketAddr>, ErrorStack> {
+    static INDEX: OnceLock<Index<Ssl, SocketAddr>> = OnceLock::new();
+    if let Some(index) = INDEX.get() {
+        return Ok(*index);
+    }
+    let index = Ssl::new_ex_index()?;
+    Ok(*INDEX.get_or_init(|| index))
+}
+
+/// Computes the cookie for the session's peer
+fn session_cookie(secret: &[u8], ssl: &SslRef) -> std::result::Result<Vec<u8>, ErrorStack> {
+    let peer = peer_index().map(|index| ssl.ex_data(index))?;
+    cookie(secret, peer)
+}
+
+/// Computes the cookie for `peer`: an HMAC of its address
+fn cookie(secret: &[u8], peer: Option<&SocketAddr>) -> std::result::Result<Vec<u8>, ErrorStack> {
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(peer.map(ToString::to_string).unwrap_or_default().as_bytes())?;
+    signer.sign_to_vec()
+}
+
+/// Builds the server context for `config` with a fresh cookie secret
+fn server_context(config: &DtlsConfig) -> Result<(SslContext, [u8; 32])> {
+    let mut cookie_secret = [0u8; 32];
+    openssl::rand::rand_bytes(&mut cookie_secret)?;
+    Ok((context(config, cookie_secret)?, cookie_secret))
+}
+
+/// Builds the server context for `config`, whose cookies are keyed with
+/// `cookie_secret`
+fn context(config: &DtlsConfig, cookie_secret: [u8; 32]) -> Result<SslContext> {
+    let mut builder = SslContext::builder(SslMethod::dtls_server())?;
+    builder.set_min_proto_version(Some(SslVersion::DTLS1_2))?;
+    let mut options = SslOptions::NO_QUERY_MTU;
+
+    match &config.credentials {
+        DtlsCredentials::Psk(psk) => {
+            builder.set_cipher_list("PSK")?;
+            let psk = psk.clone();
+            builder.set_psk_server_callback(move |_, identity, key| {
+                if identity != Some(psk.identity.as_bytes()) || key.len() < psk.key.len() {
+                    warn!(identity = ?identity.map(String::from_utf8_lossy), "Unknown DTLS PSK identity");
+                    return Ok(0);
+                }
+                key[..psk.key.len()].copy_from_slice(&psk.key);
+                Ok(psk.key.len())
+            });
+        }
+        DtlsCredentials::Certificate { chain, key } => {
+            load_certificate(&mut builder, chain, key)?;
+        }
+        DtlsCredentials::SelfSigned => {
+            let DtlsCredentials::Certificate { chain, key } =
+                DtlsCredentials::generate("localhost")?
+            else {
+                unreachable!("generate returns a certificate");
+            };
+            let leaf = load_certificate(&mut builder, &chain, &key)?;
+            let fingerprint = leaf.digest(MessageDigest::sha256())?;
+            let fingerprint: Vec<String> = fingerprint.iter().map(|b| format!("{b:02X}")).collect();
+            info!(
+                fingerprint = fingerprint.join(":"),
+                "Generated a self-signed DTLS certificate for localhost"
+            );
+        }
+    }
+
+    if config.cookie_exchange {
+        options |= SslOptions::COOKIE_EXCHANGE;
+        builder.set_cookie_generate_cb(move |ssl, out| {
+            let cookie = session_cookie(&cookie_secret, ssl)?;
+            out[..cookie.len()].copy_from_slice(&cookie);
+            Ok(cookie.len())
+        });
+        builder.set_cookie_verify_cb(move |ssl, received| {
+            session_cookie(&cookie_secret, ssl).is_ok_and(|cookie| {
+                cookie.len() == received.len() && openssl::memcmp::eq(&cookie, received)
+            })
+        });
+    }
+    builder.set_options(options);
+    Ok(builder.build())
+}
+
+/// Loads a PEM chain and key into `builder`, returning the leaf certificate
+fn load_certificate(builder: &mut SslContextBuilder, chain: &[u8], key: &[u8]) -> Result<X509> {
+    let mut chain = X509::stack_from_pem(chain)?.into_iter();
+    let leaf = chain
+        .next()
+        .ok_or_else(|| EchoError::Config("the DTLS certificate chain is empty".to_string()))?;
+    builder.set_certificate(&leaf)?;
+    for certificate in chain {
+        builder.add_extra_chain_cert(certificate)?;
+    }
+    let key = PKey::private_key_from_pem(key)?;
+    builder.set_private_key(&key)?;
+    builder.check_private_key()?;
+    Ok(leaf)
+}
+
+impl DtlsCredentials {
+    /// Generates a self-signed certificate for `name` (a DNS name or IP
+    /// address), valid for a year, with a P-256 key
+    ///
+    /// The certificate is both the server's credentials and, for clients,
+    /// the only certificate to trust.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use echosrv::dtls::{DtlsCredentials, DtlsTrust};
+    ///
+    /// let credentials = DtlsCredentials::generate("localhost").unwrap();
+    /// let DtlsCredentials::Certificate { chain, .. } = &credentials else {
+    ///     unreachable!();
+    /// };
+    /// let trust = DtlsTrust::Certificate {
+    ///     ca: chain.clone(),
+    ///     server_name: "localhost".to_string(),
+    /// };
+    /// ```
+    pub fn generate(name: &str) -> Result<Self> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+
+        let mut subject = X509NameBuilder::new()?;
+        subject.append_entry_by_nid(Nid::COMMONNAME, name)?;
+        let subject = subject.build();
+
+        let mut serial = BigNum::new()?;
+        serial.rand(64, MsbOption::MAYBE_ZERO, false)?;
+
+        let mut builder = X509::builder()?;
+        builder.set_version(2)?;
+        let serial = serial.to_asn1_integer()?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&subject)?;
+        builder.set_issuer_name(&subject)?;
+        builder.set_pubkey(&key)?;
+        let not_before = Asn1Time::days_from_now(0)?;
+        let not_after = Asn1Time::days_from_now(365)?;
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+        let mut names = SubjectAlternativeName::new();
+        match name.parse::<IpAddr>() {
+            Ok(_) => names.ip(name),
+            Err(_) => names.dns(name),
+        };
+        let names = names.build(&builder.x509v3_context(None, None))?;
+        builder.append_extension(names)?;
+        builder.sign(&key, MessageDigest::sha256())?;
+
+        Ok(Self::Certificate {
+            chain: builder.build().to_pem()?,
+            key: key.private_key_to_pem_pkcs8()?,
+        })
+    }
+}
+
+#[async_trait]
+impl DatagramProtocol for DtlsProtocol {
+    type Error = EchoError;
+    type Socket = DtlsSocket;
+
+    async fn bind(config: &DatagramConfig) -> Result<DtlsSocket> {
+        DtlsSocket::bind(config, &DtlsConfig::default()).await
+    }
+
+    async fn bind_with_inheritance(
+        config: &DatagramConfig,
+        fd_config: &FdInheritanceConfig,
+    ) -> Result<DtlsSocket> {
+        DtlsSocket::bind_with_inheritance(config, fd_config, &DtlsConfig::default()).await
+    }
+
+    async fn recv_from(socket: &DtlsSocket, buffer: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        let received = Self::recv_msg(socket, buffer).await?;
+        Ok((received.len, received.peer))
+    }
+
+    async fn send_to(socket: &DtlsSocket, data: &[u8], addr: SocketAddr) -> Result<usize> {
+        for datagram in socket.seal(data, addr)? {
+            socket
+                .socket
+                .send_to(&datagram, addr)
+                .await
+                .map_err(EchoError::Udp)?;
+        }
+        Ok(data.len())
+    }
+
+    async fn recv_msg(socket: &DtlsSocket, buffer: &mut [u8]) -> Result<Received> {
+        loop {
+            if let Some((data, peer)) = socket.next_ready() {
+                let len = data.len().min(buffer.len());
+                buffer[..len].copy_from_slice(&data[..len]);
+                let received = Received::new(len, peer);
+                return Ok(if len < data.len() {
+                    received.with_truncated(data.len())
+                } else {
+                    received
+                });
+            }
+
+            let mut datagram = socket.buffer.lock().await;
+            let (n, peer) = socket
+                .socket
+                .recv_from(&mut datagram)
+                .await
+                .map_err(EchoError::Udp)?;
+            for reply in socket.handle(&datagram[..n], peer) {
+                if let Err(e) = socket.socket.send_to(&reply, peer).await {
+                    debug!(%peer, error = %e, "Failed to send a DTLS handshake message");
+                }
+            }
+        }
+    }
+
+    fn map_io_error(err: std::io::Error) -> EchoError {
+        EchoError::Udp(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_hello_detection() {
+        let mut hello = vec![0u8; 25];
+        hello[0] = 22;
+        hello[13] = 1;
+        assert!(is_client_hello(&hello));
+
+        // Encrypted handshake (epoch 1), application data and short datagrams
+        let mut later = hello.clone();
+        later[4] = 1;
+        assert!(!is_client_hello(&later));
+        let mut data = hello.clone();
+        data[0] = 23;
+        assert!(!is_client_hello(&data));
+        assert!(!is_client_hello(&hello[..13]));
+    }
+
+    #[test]
+    fn test_generated_certificate_loads() {
+        let config = DtlsConfig::default();
+        assert!(context(&config, [0; 32]).is_ok());
+
+        let config = DtlsConfig::certificate(b"no certificates here".to_vec(), Vec::new());
+        assert!(context(&config, [0; 32]).is_err());
+    }
+
+    /// Creates a server socket and a client session for the same PSK
+    async fn pair(cookie_exchange: bool) -> (DtlsSocket, SslStream<Channel>) {
+        use super::super::client::session;
+        use super::super::config::{DtlsClientConfig, DtlsTrust, Psk};
+
+        let psk = Psk::new("client", b"key".to_vec());
+        let config = DtlsConfig::psk(psk.clone()).with_cookie_exchange(cookie_exchange);
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let secret = [7; 32];
+        let socket = DtlsSocket::new(udp, context(&config, secret).unwrap(), secret, config);
+
+        let client = session(&DtlsClientConfig::new(DtlsTrust::Psk(psk))).unwrap();
+        let client = SslStream::new(client, Channel::default()).unwrap();
+        (socket, client)
+    }
+
+    /// Feeds `replies` to the client, returning the next datagram it sends
+    fn next_hello(client: &mut SslStream<Channel>, replies: Vec<Vec<u8>>) -> Vec<u8> {
+        client.get_mut().inbound.extend(replies);
+        assert!(client.do_handshake().is_err());
+        client.get_mut().take_outbound().remove(0)
+    }
+
+    #[tokio::test]
+    async fn test_cookie_exchange() {
+        let peer: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+
+        // HelloVerifyRequest, without a session
+        let (socket, mut client) = pair(true).await;
+        let hello = next_hello(&mut client, Vec::new());
+        let replies = socket.handle(&hello, peer);
+        assert_eq!(replies[0][13], 3);
+        assert_eq!(socket.sessions(), 0);
+
+        // Retransmissions and other peers are not stored either
+        assert_eq!(socket.handle(&hello, peer)[0][13], 3);
+        assert_eq!(
+            socket.handle(&hello, "127.0.0.1:5001".parse().unwrap())[0][13],
+            3
+        );
+        assert_eq!(socket.sessions(), 0);
+
+        // Returning the cookie gets a ServerHello and a session
+        let hello = next_hello(&mut client, replies);
+
+        // A record length past the end of the datagram, or a datagram cut
+        // short, is dropped rather than trusted
+        let mut oversized = hello.clone();
+        let record_len = u16::from_be_bytes([hello[11], hello[12]]) + 16;
+        oversized[11..13].copy_from_slice(&record_len.to_be_bytes());
+        assert!(socket.handle(&oversized, peer).is_empty());
+        assert!(socket.handle(&hello[..hello.len() - 1], peer).is_empty());
+        assert_eq!(socket.sessions(), 0);
+
+        assert_eq!(socket.handle(&hello, peer)[0][13], 2);
+        assert_eq!(socket.sessions(), 1);
+
+        // The cookie is bound to the peer's address
+        let (socket, _) = pair(true).await;
+        assert_eq!(
+            socket.handle(&hello, "127.0.0.1:5001".parse().unwrap())[0][13],
+            3
+        );
+        assert_eq!(socket.sessions(), 0);
+
+        // Without cookie exchange, the first ClientHello gets a ServerHello
+        let (socket, mut client) = pair(false).await;
+        let hello = next_hello(&mut client, Vec::new());
+        assert_eq!(socket.handle(&hello, peer)[0][13], 2);
+        assert_eq!(socket.sessions(), 1);
+    }
+}
@@ -0,0 +1,88 @@
+use super::config::DtlsConfig;
+use super::protocol::{DtlsProtocol, DtlsSocket};
+use crate::common::EchoServerTrait;
+use crate::datagram::{DatagramConfig, DatagramEchoServer};
+use crate::handler::DatagramHandler;
+use crate::{EchoError, Result};
+use async_trait::async_trait;
+
+/// DTLS echo server
+///
+/// Serves like a [`DatagramEchoServer`] over [`DtlsProtocol`], with sockets
+/// secured by the DTLS settings it was created with. Sessions live on the
+/// socket that accepted them, so each address gets a single worker socket.
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::common::EchoServerTrait;
+/// use echosrv::datagram::DatagramConfig;
+/// use echosrv::dtls::{DtlsConfig, DtlsEchoServer, Psk};
+///
+/// #[tokio::main]
+/// async fn main() -> echosrv::Result<()> {
+///     let config = DatagramConfig {
+///         bind_addr: "127.0.0.1:4433".parse().unwrap(),
+///         ..Default::default()
+///     };
+///     let dtls = DtlsConfig::psk(Psk::new("client", b"secret".to_vec()));
+///     DtlsEchoServer::new(config, dtls).run().await
+/// }
+/// ```
+pub struct DtlsEchoServer {
+    server: DatagramEchoServer<DtlsProtocol>,
+    dtls: DtlsConfig,
+}
+
+impl DtlsEchoServer {
+    /// Creates a server for `config` whose peers get sessions with the
+    /// settings in `dtls`
+    pub fn new(config: DatagramConfig, dtls: DtlsConfig) -> Self {
+        Self {
+            server: DatagramEchoServer::new(config),
+            dtls,
+        }
+    }
+
+    /// Replaces the echo with `handler` as the source of every reply
+    pub fn with_handler(mut self, handler: impl DatagramHandler + 'static) -> Self {
+        self.server = self.server.with_handler(handler);
+        self
+    }
+
+    /// Returns the DTLS settings of the server
+    pub fn dtls(&self) -> &DtlsConfig {
+        &self.dtls
+    }
+
+    /// Returns how many received datagrams were larger than the buffer
+    pub fn truncated_datagrams(&self) -> u64 {
+        self.server.truncated_datagrams()
+    }
+}
+
+#[async_trait]
+impl EchoServerTrait for DtlsEchoServer {
+    /// Binds a DTLS socket on every configured address and serves them
+    async fn run(&self) -> Result<()> {
+        if self.server.worker_count() > 1 {
+            return Err(EchoError::Unsupported(
+                "DTLS sessions cannot be shared between worker sockets".to_string(),
+            ));
+        }
+        let config = self.server.config();
+        let mut sockets = Vec::new();
+        for addr in config.addresses() {
+            let config = DatagramConfig {
+                bind_addr: addr,
+                ..config.clone()
+            };
+            sockets.push((addr, DtlsSocket::bind(&config, &self.dtls).await?));
+        }
+        self.server.serve(sockets).await
+    }
+
+    fn shutdown_signal(&self) -> tokio::sync::broadcast::Sender<()> {
+        self.server.shutdown_signal()
+    }
+}
@@ -0,0 +1,155 @@
+use super::chaos::{ChaosController, ChaosStats};
+use crate::Result;
+use crate::common::admin::{self, AdminRequest, AdminResponse};
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tracing::info;
+
+/// Plain HTTP endpoint for inspecting and steering a chaos timeline
+///
+/// Routes:
+///
+/// - `GET /chaos` returns the current phase as `key=value` lines
+/// - `POST /chaos/advance` ends the current phase and returns the next one
+/// - `GET /metrics` returns the timeline state in Prometheus text format
+///
+/// There is no authentication, so bind it to a loopback or otherwise
+/// trusted address.
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::fault::{ChaosAdmin, ChaosController, ChaosPhase, FaultConfig};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let chaos = ChaosController::new(vec![ChaosPhase::new(
+///         "healthy",
+///         Duration::from_secs(60),
+///         FaultConfig::default(),
+///     )])?;
+///     let admin = ChaosAdmin::bind("127.0.0.1:9900".parse()?, chaos).await?;
+///     admin.run().await?;
+///     Ok(())
+/// }
+/// ```
+pub struct ChaosAdmin {
+    listener: TcpListener,
+    chaos: ChaosController,
+}
+
+impl ChaosAdmin {
+    /// Binds the admin endpoint for `chaos` to `addr`
+    pub async fn bind(addr: SocketAddr, chaos: ChaosController) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Self { listener, chaos })
+    }
+
+    /// Returns the address the endpoint is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serves admin requests until the task is dropped
+    pub async fn run(self) -> Result<()> {
+        info!(address = %self.local_addr()?, "Chaos admin endpoint listening");
+        let chaos = self.chaos;
+        admin::serve(self.listener, "chaos", move |request| {
+            route(&request, &chaos)
+        })
+        .await
+    }
+}
+
+fn route(request: &AdminRequest, chaos: &ChaosController) -> AdminResponse {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/chaos") => AdminResponse::text(200, status_text(&chaos.stats())),
+        ("POST", "/chaos/advance") => {
+            let phase = chaos.advance();
+            info!(phase = %phase.name, "Chaos phase advanced through admin endpoint");
+            AdminResponse::text(200, status_text(&chaos.stats()))
+        }
+        ("GET", "/metrics") => AdminResponse {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: metrics_text(chaos, &chaos.stats()),
+        },
+        (_, "/chaos" | "/chaos/advance" | "/metrics") => AdminResponse::method_not_allowed(),
+        _ => AdminResponse::not_found(),
+    }
+}
+
+fn status_text(stats: &ChaosStats) -> String {
+    format!(
+        "phase={}\nindex={}\ncycle={}\ntransitions={}\nmanual_advances={}\n\
+         time_in_phase_ms={}\nphase_duration_ms={}\n",
+        stats.phase_name,
+        stats.phase_index,
+        stats.cycle,
+        stats.transitions,
+        stats.manual_advances,
+        stats.time_in_phase.as_millis(),
+        stats.phase_duration.as_millis(),
+    )
+}
+
+fn metrics_text(chaos: &ChaosController, stats: &ChaosStats) -> String {
+    let mut out = String::new();
+    out.push_str("# HELP echosrv_chaos_phase Current chaos phase (1 for the active phase)\n");
+    out.push_str("# TYPE echosrv_chaos_phase gauge\n");
+    for (index, phase) in chaos.phases().iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "echosrv_chaos_phase{{index=\"{index}\",name=\"{}\"}} {}",
+            escape_label(&phase.name),
+            u8::from(index == stats.phase_index)
+        );
+    }
+    let metrics = [
+        (
+            "cycles_total",
+            "counter",
+            "Completed passes through the timeline",
+            stats.cycle as f64,
+        ),
+        (
+            "transitions_total",
+            "counter",
+            "Phase changes",
+            stats.transitions as f64,
+        ),
+        (
+            "manual_advances_total",
+            "counter",
+            "Phase changes requested manually",
+            stats.manual_advances as f64,
+        ),
+        (
+            "phase_elapsed_seconds",
+            "gauge",
+            "Time spent in the current phase",
+            stats.time_in_phase.as_secs_f64(),
+        ),
+        (
+            "phase_duration_seconds",
+            "gauge",
+            "Scheduled length of the current phase",
+            stats.phase_duration.as_secs_f64(),
+        ),
+    ];
+    for (name, kind, help, value) in metrics {
+        let _ = writeln!(out, "# HELP echosrv_chaos_{name} {help}");
+        let _ = writeln!(out, "# TYPE echosrv_chaos_{name} {kind}");
+        let _ = writeln!(out, "echosrv_chaos_{name} {value}");
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,490 @@
+use super::config::FaultConfig;
+use super::rng::FaultRng;
+use super::shaper::TrafficShaper;
+use crate::{EchoError, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, watch};
+use tokio::time::Instant;
+use tracing::info;
+
+/// One step of a chaos timeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosPhase {
+    /// Name shown in logs and metrics
+    pub name: String,
+    /// How long the phase lasts before the next one starts
+    pub duration: Duration,
+    /// Faults in effect during the phase
+    ///
+    /// The seed is ignored; listeners keep the generator seeded from their
+    /// own `FaultConfig` for the whole run.
+    pub faults: FaultConfig,
+}
+
+impl ChaosPhase {
+    /// Creates a phase
+    pub fn new(name: impl Into<String>, duration: Duration, faults: FaultConfig) -> Self {
+        Self {
+            name: name.into(),
+            duration,
+            faults,
+        }
+    }
+}
+
+/// The phase a timeline is currently in
+#[derive(Debug, Clone)]
+pub struct ActivePhase {
+    /// Position of the phase in the timeline
+    pub index: usize,
+    /// Name of the phase
+    pub name: String,
+    /// Number of times the timeline has wrapped around
+    pub cycle: u64,
+    /// Faults in effect
+    pub faults: Arc<FaultConfig>,
+}
+
+/// Snapshot of a timeline's progress
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChaosStats {
+    /// Position of the current phase
+    pub phase_index: usize,
+    /// Name of the current phase
+    pub phase_name: String,
+    /// Number of completed passes through the timeline
+    pub cycle: u64,
+    /// Phase changes so far, scheduled or manual
+    pub transitions: u64,
+    /// Phase changes requested through [`ChaosController::advance`]
+    pub manual_advances: u64,
+    /// Time spent in the current phase
+    pub time_in_phase: Duration,
+    /// Scheduled length of the current phase
+    pub phase_duration: Duration,
+}
+
+/// Position in the timeline, guarded by the controller's lock
+#[derive(Debug)]
+struct Position {
+    index: usize,
+    cycle: u64,
+    started: Instant,
+}
+
+#[derive(Debug)]
+struct Timeline {
+    phases: Vec<ChaosPhase>,
+    faults: Vec<Arc<FaultConfig>>,
+    position: Mutex<Position>,
+    current: watch::Sender<ActivePhase>,
+    advanced: Notify,
+    transitions: AtomicU64,
+    manual_advances: AtomicU64,
+}
+
+/// Walks listeners through a looping timeline of fault phases
+///
+/// Attach a clone to `FaultConfig::chaos` of every listener that should
+/// follow the timeline, then drive it with [`run`](Self::run). While a
+/// timeline is attached, its current phase replaces the listener's other
+/// fault settings. New connections and datagrams pick up a phase change
+/// right away; open stream connections roll their stream faults again, with
+/// byte thresholds counted from the start of the phase.
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::common::EchoServerTrait;
+/// use echosrv::datagram::DatagramConfig;
+/// use echosrv::fault::{ChaosController, ChaosPhase, FaultConfig, ImpairmentConfig};
+/// use echosrv::UdpEchoServer;
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let lossy = FaultConfig {
+///         impairment: ImpairmentConfig { loss: 0.3, ..Default::default() },
+///         ..Default::default()
+///     };
+///     let chaos = ChaosController::new(vec![
+///         ChaosPhase::new("healthy", Duration::from_secs(300), FaultConfig::default()),
+///         ChaosPhase::new("lossy", Duration::from_secs(60), lossy),
+///     ])?;
+///     tokio::spawn({
+///         let chaos = chaos.clone();
+///         async move { chaos.run().await }
+///     });
+///
+///     let config = DatagramConfig {
+///         bind_addr: "127.0.0.1:8080".parse()?,
+///         faults: Some(FaultConfig { chaos: Some(chaos), ..Default::default() }),
+///         ..Default::default()
+///     };
+///     UdpEchoServer::new(config).run().await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ChaosController {
+    timeline: Arc<Timeline>,
+}
+
+impl ChaosController {
+    /// Creates a controller positioned at the first phase
+    ///
+    /// Fails if there are no phases, a phase lasts zero time, or a phase
+    /// attaches a timeline of its own.
+    pub fn new(phases: Vec<ChaosPhase>) -> Result<Self> {
+        if phases.is_empty() {
+            return Err(EchoError::Config(
+                "A chaos timeline needs at least one phase".to_string(),
+            ));
+        }
+        for phase in &phases {
+            if phase.duration.is_zero() {
+                return Err(EchoError::Config(format!(
+                    "Chaos phase '{}' must last longer than zero",
+                    phase.name
+                )));
+            }
+            if phase.faults.chaos.is_some() {
+                return Err(EchoError::Config(format!(
+                    "Chaos phase '{}' cannot contain another timeline",
+                    phase.name
+                )));
+            }
+        }
+
+        let faults: Vec<_> = phases.iter().map(|p| Arc::new(p.faults.clone())).collect();
+        let (current, _) = watch::channel(ActivePhase {
+            index: 0,
+            name: phases[0].name.clone(),
+            cycle: 0,
+            faults: Arc::clone(&faults[0]),
+        });
+        Ok(Self {
+            timeline: Arc::new(Timeline {
+                phases,
+                faults,
+                position: Mutex::new(Position {
+                    index: 0,
+                    cycle: 0,
+                    started: Instant::now(),
+                }),
+                current,
+                advanced: Notify::new(),
+                transitions: AtomicU64::new(0),
+                manual_advances: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    /// Returns the phases of the timeline
+    pub fn phases(&self) -> &[ChaosPhase] {
+        &self.timeline.phases
+    }
+
+    /// Returns the current phase
+    pub fn current(&self) -> ActivePhase {
+        self.timeline.current.borrow().clone()
+    }
+
+    /// Subscribes to phase changes
+    pub fn subscribe(&self) -> watch::Receiver<ActivePhase> {
+        self.timeline.current.subscribe()
+    }
+
+    /// Takes a snapshot of the timeline's progress
+    pub fn stats(&self) -> ChaosStats {
+        let position = self.timeline.position.lock().unwrap();
+        let phase = &self.timeline.phases[position.index];
+        ChaosStats {
+            phase_index: position.index,
+            phase_name: phase.name.clone(),
+            cycle: position.cycle,
+            transitions: self.timeline.transitions.load(Ordering::Relaxed),
+            manual_advances: self.timeline.manual_advances.load(Ordering::Relaxed),
+            time_in_phase: position.started.elapsed(),
+            phase_duration: phase.duration,
+        }
+    }
+
+    /// Ends the current phase early and starts the next one
+    ///
+    /// The next phase gets its full duration.
+    pub fn advance(&self) -> ActivePhase {
+        let phase = self.step(true);
+        self.timeline.advanced.notify_one();
+        phase
+    }
+
+    /// Moves through the timeline forever, starting each phase on schedule
+    ///
+    /// Run this on its own task; dropping the future freezes the timeline in
+    /// its current phase.
+    pub async fn run(&self) {
+        let phase = self.current();
+        info!(phase = %phase.name, index = phase.index, "Chaos timeline started");
+        loop {
+            let remaining = {
+                let position = self.timeline.position.lock().unwrap();
+                let duration = self.timeline.phases[position.index].duration;
+                duration.saturating_sub(position.started.elapsed())
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(remaining) => {
+                    self.step(false);
+                }
+                // A manual advance restarted the clock; wait for the new phase
+                _ = self.timeline.advanced.notified() => {}
+            }
+        }
+    }
+
+    fn step(&self, manual: bool) -> ActivePhase {
+        let mut position = self.timeline.position.lock().unwrap();
+        position.index = (position.index + 1) % self.timeline.phases.len();
+        if position.index == 0 {
+            position.cycle += 1;
+        }
+        position.started = Instant::now();
+        self.timeline.transitions.fetch_add(1, Ordering::Relaxed);
+        if manual {
+            self.timeline
+                .manual_advances
+                .fetch_add(1, Ordering::Relaxed);
+        }
+
+        let phase = &self.timeline.phases[position.index];
+        info!(
+            phase = %phase.name,
+            index = position.index,
+            cycle = position.cycle,
+            duration = ?phase.duration,
+            trigger = if manual { "manual" } else { "scheduled" },
+            "Chaos phase started"
+        );
+        let active = ActivePhase {
+            index: position.index,
+            name: phase.name.clone(),
+            cycle: position.cycle,
+            faults: Arc::clone(&self.timeline.faults[position.index]),
+        };
+        // Publish while holding the lock so subscribers see phases in order
+        self.timeline.current.send_replace(active.clone());
+        active
+    }
+}
+
+impl std::fmt::Debug for ChaosController {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<_> = self.phases().iter().map(|p| p.name.as_str()).collect();
+        f.debug_struct("ChaosController")
+            .field("phases", &names)
+            .field("current", &self.current().name)
+            .finish()
+    }
+}
+
+impl PartialEq for ChaosController {
+    /// Controllers are equal when they drive the same timeline
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.timeline, &other.timeline)
+    }
+}
+
+/// The faults a listener applies right now
+///
+/// Static configurations never change. With a timeline attached, the
+/// source follows its phases; callers poll [`refresh`](Self::refresh) at
+/// convenient points or await [`changed`](Self::changed).
+#[derive(Debug, Clone)]
+pub(crate) struct FaultSource {
+    current: Arc<FaultConfig>,
+    phases: Option<watch::Receiver<ActivePhase>>,
+}
+
+impl FaultSource {
+    pub(crate) fn new(config: Option<&FaultConfig>) -> Self {
+        match config.and_then(|c| c.chaos.as_ref()) {
+            Some(chaos) => {
+                let mut phases = chaos.subscribe();
+                let current = Arc::clone(&phases.borrow_and_update().faults);
+                Self {
+                    current,
+                    phases: Some(phases),
+                }
+            }
+            None => Self {
+                current: Arc::new(config.cloned().unwrap_or_default()),
+                phases: None,
+            },
+        }
+    }
+
+    /// Faults in effect
+    pub(crate) fn current(&self) -> &FaultConfig {
+        &self.current
+    }
+
+    /// Identifies the faults in effect: consecutive phases never share an
+    /// id, while clones of a source agree on it
+    pub(crate) fn phase_id(&self) -> usize {
+        Arc::as_ptr(&self.current) as usize
+    }
+
+    /// Name of the current phase, if a timeline is attached
+    pub(crate) fn phase(&self) -> Option<String> {
+        self.phases.as_ref().map(|p| p.borrow().name.clone())
+    }
+
+    /// Picks up a phase change; returns true if the faults changed
+    pub(crate) fn refresh(&mut self) -> bool {
+        let Some(phases) = self.phases.as_mut() else {
+            return false;
+        };
+        if !phases.has_changed().unwrap_or(false) {
+            return false;
+        }
+        self.current = Arc::clone(&phases.borrow_and_update().faults);
+        true
+    }
+
+    /// Waits for the next phase change; never returns for static faults
+    pub(crate) async fn changed(&mut self) {
+        let Some(phases) = self.phases.as_mut() else {
+            return std::future::pending().await;
+        };
+        if phases.changed().await.is_err() {
+            // Every controller is gone, so the current phase is final
+            return std::future::pending().await;
+        }
+        self.current = Arc::clone(&phases.borrow_and_update().faults);
+    }
+}
+
+/// Traffic shaper that is rebuilt whenever a new phase starts
+#[derive(Debug)]
+pub(crate) struct PhasedShaper {
+    source: FaultSource,
+    shaper: Option<TrafficShaper>,
+    rng: FaultRng,
+}
+
+impl PhasedShaper {
+    pub(crate) fn new(source: FaultSource, mut rng: FaultRng) -> Self {
+        let shaper = Self::build(source.current(), &mut rng);
+        Self {
+            source,
+            shaper,
+            rng,
+        }
+    }
+
+    /// The shaper for the current phase, or `None` if it does not shape
+    pub(crate) fn get(&mut self) -> Option<&mut TrafficShaper> {
+        if self.source.refresh() {
+            self.shaper = Self::build(self.source.current(), &mut self.rng);
+        }
+        self.shaper.as_mut()
+    }
+
+    fn build(config: &FaultConfig, rng: &mut FaultRng) -> Option<TrafficShaper> {
+        // Fork even when inactive so later phases see the same sequence
+        let rng = rng.fork();
+        config
+            .shapes_traffic()
+            .then(|| TrafficShaper::new(config, rng))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fault::LatencyConfig;
+
+    fn timeline() -> ChaosController {
+        let slow = FaultConfig {
+            latency: Some(LatencyConfig::fixed(Duration::from_millis(50))),
+            ..Default::default()
+        };
+        ChaosController::new(vec![
+            ChaosPhase::new("healthy", Duration::from_secs(10), FaultConfig::default()),
+            ChaosPhase::new("slow", Duration::from_secs(5), slow),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rejects_invalid_timelines() {
+        assert!(ChaosController::new(Vec::new()).is_err());
+        let instant = ChaosPhase::new("instant", Duration::ZERO, FaultConfig::default());
+        assert!(ChaosController::new(vec![instant]).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_phases_loop_on_schedule() {
+        let chaos = timeline();
+        let mut source = FaultSource::new(Some(&FaultConfig {
+            chaos: Some(chaos.clone()),
+            ..Default::default()
+        }));
+        assert_eq!(source.phase().as_deref(), Some("healthy"));
+        assert!(!source.current().shapes_traffic());
+
+        let driver = tokio::spawn({
+            let chaos = chaos.clone();
+            async move { chaos.run().await }
+        });
+
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert!(source.refresh());
+        assert_eq!(source.phase().as_deref(), Some("slow"));
+        assert!(source.current().shapes_traffic());
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        let stats = chaos.stats();
+        assert_eq!(stats.phase_name, "healthy");
+        assert_eq!(stats.cycle, 1);
+        assert_eq!(stats.transitions, 2);
+        driver.abort();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_manual_advance_restarts_clock() {
+        let chaos = timeline();
+        let driver = tokio::spawn({
+            let chaos = chaos.clone();
+            async move { chaos.run().await }
+        });
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(chaos.advance().name, "slow");
+        // The manual phase gets its full five seconds
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(chaos.current().name, "slow");
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(chaos.current().name, "healthy");
+
+        let stats = chaos.stats();
+        assert_eq!(stats.transitions, 2);
+        assert_eq!(stats.manual_advances, 1);
+        driver.abort();
+    }
+
+    #[test]
+    fn test_phased_shaper_follows_phase() {
+        let chaos = timeline();
+        let source = FaultSource::new(Some(&FaultConfig {
+            chaos: Some(chaos.clone()),
+            ..Default::default()
+        }));
+        let mut shaper = PhasedShaper::new(source, FaultRng::new(1));
+        assert!(shaper.get().is_none());
+        chaos.advance();
+        assert_eq!(shaper.get().unwrap().latency(), Duration::from_millis(50));
+    }
+}
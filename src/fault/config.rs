@@ -0,0 +1,155 @@
+use super::chaos::ChaosController;
+use super::impairment::ImpairmentConfig;
+use super::stream::StreamFaultConfig;
+use std::time::Duration;
+
+/// Fault injection settings for a single listener
+///
+/// Attach this to `StreamConfig::faults` or `DatagramConfig::faults` and run
+/// the server with a [`Faulty`](super::Faulty) protocol to slow down echoes.
+/// Datagram servers apply `impairment` and stream servers apply `stream`
+/// themselves, with or without `Faulty`. Setting `chaos` makes the listener
+/// follow a timeline of phases instead of the other fields.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::fault::{BandwidthLimit, FaultConfig, JitterDistribution, LatencyConfig};
+/// use std::time::Duration;
+///
+/// let faults = FaultConfig {
+///     seed: Some(42),
+///     latency: Some(LatencyConfig {
+///         base: Duration::from_millis(100),
+///         jitter: Duration::from_millis(20),
+///         distribution: JitterDistribution::Normal,
+///     }),
+///     bandwidth: BandwidthLimit {
+///         write: Some(64 * 1024), // 64 KiB/s towards the client
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// assert!(faults.is_active());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FaultConfig {
+    /// Seed for all random fault decisions; `None` picks one from the clock
+    pub seed: Option<u64>,
+    /// Delay added before each response is written
+    pub latency: Option<LatencyConfig>,
+    /// Bandwidth limits, in bytes per second
+    pub bandwidth: BandwidthLimit,
+    /// Loss, duplication, reordering and corruption of datagrams
+    pub impairment: ImpairmentConfig,
+    /// Resets, stalls, partial writes and half-closes of stream connections
+    pub stream: StreamFaultConfig,
+    /// Timeline whose current phase replaces the fields above
+    pub chaos: Option<ChaosController>,
+}
+
+impl FaultConfig {
+    /// Returns true if any fault is configured
+    pub fn is_active(&self) -> bool {
+        self.shapes_traffic()
+            || self.impairment.is_active()
+            || self.stream.is_active()
+            || self.chaos.is_some()
+    }
+
+    /// Returns true if latency or a bandwidth limit is configured now or in
+    /// any phase of the attached timeline
+    pub fn may_shape_traffic(&self) -> bool {
+        self.shapes_traffic()
+            || self
+                .chaos
+                .as_ref()
+                .is_some_and(|c| c.phases().iter().any(|p| p.faults.shapes_traffic()))
+    }
+
+    /// Returns true if latency or a bandwidth limit is configured
+    pub fn shapes_traffic(&self) -> bool {
+        self.latency.is_some() || self.bandwidth.is_limited()
+    }
+
+    /// Picks the seed for a fault stage and logs it so a run can be reproduced
+    pub(crate) fn resolve_seed(&self, stage: &str, listener: &dyn std::fmt::Display) -> u64 {
+        let seed = self.seed.unwrap_or_else(super::rng::entropy_seed);
+        tracing::info!(%listener, seed, stage, "Fault injection enabled");
+        seed
+    }
+}
+
+/// Latency added to responses
+///
+/// Each delay is `base` plus a jitter sample drawn from `distribution`,
+/// never going below zero. A zero `jitter` gives a fixed delay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyConfig {
+    /// Base delay applied to every response
+    pub base: Duration,
+    /// Spread of the jitter distribution
+    pub jitter: Duration,
+    /// Shape of the jitter distribution
+    pub distribution: JitterDistribution,
+}
+
+impl LatencyConfig {
+    /// Creates a fixed latency without jitter
+    pub fn fixed(base: Duration) -> Self {
+        Self {
+            base,
+            jitter: Duration::ZERO,
+            distribution: JitterDistribution::Uniform,
+        }
+    }
+}
+
+/// Distribution used to sample latency jitter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JitterDistribution {
+    /// Uniform in `[-jitter, +jitter]`
+    #[default]
+    Uniform,
+    /// Normal with standard deviation `jitter`
+    Normal,
+    /// Exponential with mean `jitter` (long positive tail)
+    Exponential,
+}
+
+impl std::str::FromStr for JitterDistribution {
+    type Err = crate::EchoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "uniform" => Ok(Self::Uniform),
+            "normal" => Ok(Self::Normal),
+            "exponential" => Ok(Self::Exponential),
+            other => Err(crate::EchoError::Config(format!(
+                "Unknown jitter distribution '{other}', expected uniform, normal or exponential"
+            ))),
+        }
+    }
+}
+
+/// Bandwidth limits in bytes per second
+///
+/// Limits apply per connection for stream protocols and per socket for
+/// datagram protocols. `total` is shared by both directions and applies on
+/// top of the per-direction limits.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BandwidthLimit {
+    /// Limit on data read from the client
+    pub read: Option<u64>,
+    /// Limit on data written to the client
+    pub write: Option<u64>,
+    /// Limit on both directions combined
+    pub total: Option<u64>,
+}
+
+impl BandwidthLimit {
+    /// Returns true if any limit is set
+    pub fn is_limited(&self) -> bool {
+        self.read.is_some() || self.write.is_some() || self.total.is_some()
+    }
+}
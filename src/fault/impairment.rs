@@ -0,0 +1,355 @@
+use super::rng::FaultRng;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Datagram impairments applied before echoing
+///
+/// Every probability is in `[0, 1]` and is rolled independently for each
+/// datagram, in this order: loss, truncation, corruption, reordering and
+/// duplication. A lost datagram is not subject to the later impairments.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::fault::{FaultConfig, ImpairmentConfig};
+///
+/// let faults = FaultConfig {
+///     seed: Some(7),
+///     impairment: ImpairmentConfig {
+///         loss: 0.05,
+///         duplicate: 0.01,
+///         reorder: 0.1,
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// assert!(faults.is_active());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImpairmentConfig {
+    /// Probability that a datagram is dropped instead of echoed
+    pub loss: f64,
+    /// Probability that an echo is sent twice
+    pub duplicate: f64,
+    /// Probability that an echo is held back and sent after later ones
+    pub reorder: f64,
+    /// Number of later echoes a held datagram waits for
+    pub reorder_window: usize,
+    /// Longest time a held datagram waits when traffic stops
+    pub reorder_timeout: Duration,
+    /// Probability that random bits of the echo are flipped
+    pub corrupt: f64,
+    /// Probability that the echo is cut to a random shorter length
+    pub truncate: f64,
+}
+
+impl Default for ImpairmentConfig {
+    fn default() -> Self {
+        Self {
+            loss: 0.0,
+            duplicate: 0.0,
+            reorder: 0.0,
+            reorder_window: 3,
+            reorder_timeout: Duration::from_millis(100),
+            corrupt: 0.0,
+            truncate: 0.0,
+        }
+    }
+}
+
+impl ImpairmentConfig {
+    /// Returns true if any impairment has a non-zero probability
+    pub fn is_active(&self) -> bool {
+        [
+            self.loss,
+            self.duplicate,
+            self.reorder,
+            self.corrupt,
+            self.truncate,
+        ]
+        .iter()
+        .any(|p| *p > 0.0)
+    }
+}
+
+/// Live counters of applied impairments, shared with the server
+#[derive(Debug, Default)]
+pub struct ImpairmentCounters {
+    received: AtomicU64,
+    dropped: AtomicU64,
+    duplicated: AtomicU64,
+    reordered: AtomicU64,
+    corrupted: AtomicU64,
+    truncated: AtomicU64,
+}
+
+impl ImpairmentCounters {
+    /// Takes a snapshot of the counters
+    pub fn stats(&self) -> ImpairmentStats {
+        ImpairmentStats {
+            received: self.received.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            duplicated: self.duplicated.load(Ordering::Relaxed),
+            reordered: self.reordered.load(Ordering::Relaxed),
+            corrupted: self.corrupted.load(Ordering::Relaxed),
+            truncated: self.truncated.load(Ordering::Relaxed),
+        }
+    }
+
+    fn bump(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Snapshot of impairment counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImpairmentStats {
+    /// Datagrams that entered the pipeline
+    pub received: u64,
+    /// Datagrams dropped
+    pub dropped: u64,
+    /// Extra copies sent
+    pub duplicated: u64,
+    /// Datagrams held back and sent out of order
+    pub reordered: u64,
+    /// Datagrams with flipped bits
+    pub corrupted: u64,
+    /// Datagrams cut short
+    pub truncated: u64,
+}
+
+/// A datagram waiting to be released out of order
+#[derive(Debug)]
+struct Held<A> {
+    data: Vec<u8>,
+    addr: A,
+    remaining: usize,
+    deadline: Instant,
+}
+
+/// Applies [`ImpairmentConfig`] to a stream of datagrams
+///
+/// Generic over the peer address so it serves both IP and Unix datagram
+/// servers. `process` returns the datagrams to send right away, in order;
+/// held datagrams come back from later calls or from `release_expired`.
+#[derive(Debug)]
+pub struct Impairer<A> {
+    config: ImpairmentConfig,
+    rng: FaultRng,
+    counters: Arc<ImpairmentCounters>,
+    held: VecDeque<Held<A>>,
+}
+
+impl<A: Clone + std::fmt::Debug> Impairer<A> {
+    /// Creates a pipeline that records what it applies in `counters`
+    pub fn new(config: ImpairmentConfig, rng: FaultRng, counters: Arc<ImpairmentCounters>) -> Self {
+        Self {
+            config,
+            rng,
+            counters,
+            held: VecDeque::new(),
+        }
+    }
+
+    /// Switches to a new configuration, keeping datagrams already held
+    pub fn set_config(&mut self, config: ImpairmentConfig) {
+        self.config = config;
+    }
+
+    /// Runs one received datagram through the pipeline
+    pub fn process(&mut self, data: &[u8], addr: A) -> Vec<(Vec<u8>, A)> {
+        ImpairmentCounters::bump(&self.counters.received);
+
+        if self.rng.chance(self.config.loss) {
+            ImpairmentCounters::bump(&self.counters.dropped);
+            debug!(?addr, size = data.len(), "Dropped datagram");
+            return Vec::new();
+        }
+
+        let mut data = data.to_vec();
+
+        if data.len() > 1 && self.rng.chance(self.config.truncate) {
+            let len = self.rng.range_inclusive(0, data.len() as u64 - 1) as usize;
+            debug!(?addr, from = data.len(), to = len, "Truncated datagram");
+            data.truncate(len);
+            ImpairmentCounters::bump(&self.counters.truncated);
+        }
+
+        if !data.is_empty() && self.rng.chance(self.config.corrupt) {
+            let flips = self.rng.range_inclusive(1, 8);
+            for _ in 0..flips {
+                let bit = self.rng.range_inclusive(0, data.len() as u64 * 8 - 1);
+                data[(bit / 8) as usize] ^= 1 << (bit % 8);
+            }
+            debug!(?addr, flips, "Corrupted datagram");
+            ImpairmentCounters::bump(&self.counters.corrupted);
+        }
+
+        if self.config.reorder_window > 0 && self.rng.chance(self.config.reorder) {
+            debug!(?addr, size = data.len(), "Holding datagram for reordering");
+            ImpairmentCounters::bump(&self.counters.reordered);
+            self.held.push_back(Held {
+                data,
+                addr,
+                remaining: self.config.reorder_window,
+                deadline: Instant::now() + self.config.reorder_timeout,
+            });
+            return Vec::new();
+        }
+
+        let mut out = Vec::with_capacity(2);
+        if self.rng.chance(self.config.duplicate) {
+            debug!(?addr, size = data.len(), "Duplicating datagram");
+            ImpairmentCounters::bump(&self.counters.duplicated);
+            out.push((data.clone(), addr.clone()));
+        }
+        out.push((data, addr));
+
+        // Held datagrams fall behind this one
+        for held in &mut self.held {
+            held.remaining = held.remaining.saturating_sub(1);
+        }
+        self.release(|held| held.remaining == 0, &mut out);
+        out
+    }
+
+    /// Earliest instant a held datagram must be released, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.held.iter().map(|held| held.deadline).min()
+    }
+
+    /// Releases held datagrams whose timeout has passed
+    pub fn release_expired(&mut self, now: Instant) -> Vec<(Vec<u8>, A)> {
+        let mut out = Vec::new();
+        self.release(|held| held.deadline <= now, &mut out);
+        out
+    }
+
+    /// Releases every held datagram, e.g. on shutdown
+    pub fn drain(&mut self) -> Vec<(Vec<u8>, A)> {
+        self.held
+            .drain(..)
+            .map(|held| (held.data, held.addr))
+            .collect()
+    }
+
+    fn release(&mut self, due: impl Fn(&Held<A>) -> bool, out: &mut Vec<(Vec<u8>, A)>) {
+        let mut index = 0;
+        while index < self.held.len() {
+            if due(&self.held[index]) {
+                let held = self.held.remove(index).expect("index is in bounds");
+                out.push((held.data, held.addr));
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+/// Sleeps until `deadline`, or forever if there is none
+///
+/// Used as a `select!` branch so servers release held datagrams on time.
+pub(crate) async fn sleep_until_release(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn impairer(config: ImpairmentConfig) -> (Impairer<u8>, Arc<ImpairmentCounters>) {
+        let counters = Arc::new(ImpairmentCounters::default());
+        let impairer = Impairer::new(config, FaultRng::new(3), Arc::clone(&counters));
+        (impairer, counters)
+    }
+
+    #[test]
+    fn test_inactive_passes_through() {
+        let (mut impairer, counters) = impairer(ImpairmentConfig::default());
+        assert_eq!(impairer.process(b"hello", 1), vec![(b"hello".to_vec(), 1)]);
+        assert_eq!(
+            counters.stats(),
+            ImpairmentStats {
+                received: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_loss_and_duplicate_always() {
+        let (mut lossy, counters) = impairer(ImpairmentConfig {
+            loss: 1.0,
+            ..Default::default()
+        });
+        assert!(lossy.process(b"x", 1).is_empty());
+        assert_eq!(counters.stats().dropped, 1);
+
+        let (mut dup, counters) = impairer(ImpairmentConfig {
+            duplicate: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(dup.process(b"x", 1).len(), 2);
+        assert_eq!(counters.stats().duplicated, 1);
+    }
+
+    #[test]
+    fn test_corrupt_and_truncate_change_payload() {
+        let payload = [0u8; 64];
+        let (mut impairer, counters) = impairer(ImpairmentConfig {
+            corrupt: 1.0,
+            truncate: 1.0,
+            ..Default::default()
+        });
+        let out = impairer.process(&payload, 1);
+        assert_eq!(out.len(), 1);
+        assert!(out[0].0.len() < payload.len());
+        let stats = counters.stats();
+        assert_eq!(stats.truncated, 1);
+        assert_eq!(stats.corrupted, u64::from(!out[0].0.is_empty()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reorder_within_window_and_timeout() {
+        let (mut impairer, counters) = impairer(ImpairmentConfig {
+            reorder: 1.0,
+            reorder_window: 2,
+            ..Default::default()
+        });
+        assert!(impairer.process(b"first", 1).is_empty());
+        impairer.config.reorder = 0.0;
+        assert_eq!(impairer.process(b"second", 2).len(), 1);
+        let out = impairer.process(b"third", 3);
+        assert_eq!(out, vec![(b"third".to_vec(), 3), (b"first".to_vec(), 1)]);
+        assert_eq!(counters.stats().reordered, 1);
+
+        impairer.config.reorder = 1.0;
+        assert!(impairer.process(b"late", 4).is_empty());
+        let deadline = impairer.next_deadline().unwrap();
+        assert!(impairer.release_expired(Instant::now()).is_empty());
+        assert_eq!(impairer.release_expired(deadline).len(), 1);
+        assert!(impairer.next_deadline().is_none());
+    }
+
+    #[test]
+    fn test_same_seed_same_decisions() {
+        let config = ImpairmentConfig {
+            loss: 0.3,
+            duplicate: 0.3,
+            corrupt: 0.3,
+            ..Default::default()
+        };
+        let (mut a, _) = impairer(config.clone());
+        let (mut b, _) = impairer(config);
+        for i in 0..200u8 {
+            assert_eq!(a.process(&[i; 16], i), b.process(&[i; 16], i));
+        }
+    }
+}
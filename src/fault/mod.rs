@@ -0,0 +1,35 @@
+//! Fault injection for echo servers
+//!
+//! This module provides a protocol wrapper, [`Faulty`], that degrades the
+//! echo path of any stream or datagram protocol. Faults are configured per
+//! listener through [`FaultConfig`] and all random decisions come from a
+//! seeded [`FaultRng`], so a run can be reproduced from its seed.
+//!
+//! Datagram impairments ([`ImpairmentConfig`]) and stream fault modes
+//! ([`StreamFaultConfig`]) change what is echoed rather than when, so the
+//! servers apply them directly and `Faulty` is only needed for shaping.
+//!
+//! A [`ChaosController`] changes all of these over time by walking listeners
+//! through a looping timeline of phases, optionally steered at runtime
+//! through a [`ChaosAdmin`] endpoint.
+
+pub mod admin;
+pub mod chaos;
+pub mod config;
+pub mod impairment;
+pub mod protocol;
+pub mod rng;
+pub mod shaper;
+pub mod stream;
+
+pub use admin::ChaosAdmin;
+pub use chaos::{ActivePhase, ChaosController, ChaosPhase, ChaosStats};
+pub use config::{BandwidthLimit, FaultConfig, JitterDistribution, LatencyConfig};
+pub use impairment::{Impairer, ImpairmentConfig, ImpairmentCounters, ImpairmentStats};
+pub use protocol::{Faulty, FaultyListener, FaultySocket, FaultyStream};
+pub use rng::FaultRng;
+pub use shaper::TrafficShaper;
+pub use stream::{
+    HalfCloseFault, PartialWriteFault, ResetFault, StallDirection, StallFault, StreamEnding,
+    StreamFaultConfig, StreamFaults,
+};
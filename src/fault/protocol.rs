@@ -0,0 +1,555 @@
+use super::chaos::{FaultSource, PhasedShaper};
+use super::config::FaultConfig;
+use super::rng::FaultRng;
+use crate::datagram::{DatagramConfig, DatagramProtocol, Received};
+use crate::network::fd_inheritance::FdInheritanceConfig;
+use crate::network::{Address, SocketOptions};
+use crate::stream::{ReadHalf, StreamConfig, StreamProtocol, WriteHalf};
+use async_trait::async_trait;
+use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+/// Delayed datagrams each socket holds for its release task; once this many
+/// wait, new ones are dropped as a congested link would drop them
+const RELEASE_QUEUE_DEPTH: usize = 1024;
+
+/// A shaped datagram waiting for its release instant
+type Delayed = (Instant, Vec<u8>, SocketAddr, Option<IpAddr>);
+
+/// Protocol wrapper that injects latency and bandwidth faults
+///
+/// `Faulty<P>` implements `StreamProtocol` for any stream protocol `P` and
+/// `DatagramProtocol` for any datagram protocol `P`. Faults are read from
+/// the `faults` field of the config passed to `bind`, so every listener can
+/// be configured independently, and follow the phases of an attached chaos
+/// timeline. Client-side connections are not impaired.
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::common::EchoServerTrait;
+/// use echosrv::fault::{FaultConfig, Faulty, LatencyConfig};
+/// use echosrv::stream::{StreamConfig, StreamEchoServer};
+/// use echosrv::tcp::TcpProtocol;
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let config = StreamConfig {
+///         bind_addr: "127.0.0.1:8080".parse()?,
+///         faults: Some(FaultConfig {
+///             seed: Some(1),
+///             latency: Some(LatencyConfig::fixed(Duration::from_millis(200))),
+///             ..Default::default()
+///         }),
+///         ..Default::default()
+///     };
+///
+///     let server: StreamEchoServer<Faulty<TcpProtocol>> = StreamEchoServer::new(config);
+///     server.run().await?;
+///     Ok(())
+/// }
+/// ```
+pub struct Faulty<P> {
+    protocol: PhantomData<fn() -> P>,
+}
+
+/// Listener wrapper that hands each accepted stream its own fault state
+pub struct FaultyListener<L> {
+    inner: L,
+    source: FaultSource,
+    shapes: bool,
+    rng: FaultRng,
+}
+
+impl<L> FaultyListener<L> {
+    pub(crate) fn new(inner: L, config: Option<&FaultConfig>, addr: &SocketAddr) -> Self {
+        let shapes = config.is_some_and(FaultConfig::may_shape_traffic);
+        let seed = match config {
+            Some(config) if shapes => {
+                log_shaping(config);
+                config.resolve_seed("traffic shaping", addr)
+            }
+            _ => 0,
+        };
+        Self {
+            inner,
+            source: FaultSource::new(config),
+            shapes,
+            rng: FaultRng::new(seed),
+        }
+    }
+
+    /// Returns the wrapped listener
+    pub fn get_ref(&self) -> &L {
+        &self.inner
+    }
+
+    /// Returns the fault configuration applied to newly accepted streams
+    pub fn config(&self) -> &FaultConfig {
+        self.source.current()
+    }
+}
+
+/// Stream wrapper that delays and paces reads and writes
+pub struct FaultyStream<S> {
+    inner: S,
+    shaper: Option<PhasedShaper>,
+    /// Earliest instant the next read may start, to pace inbound traffic
+    read_ready: Option<Instant>,
+}
+
+impl<S> FaultyStream<S> {
+    /// Returns the wrapped stream
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the wrapped stream mutably
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Unwraps the stream, discarding its fault state
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<P> StreamProtocol for Faulty<P>
+where
+    P: StreamProtocol + Send + Sync,
+    P::Listener: Send,
+    P::Stream: Send,
+{
+    type Error = P::Error;
+    type Listener = FaultyListener<P::Listener>;
+    type Stream = FaultyStream<P::Stream>;
+
+    async fn bind(config: &StreamConfig) -> std::result::Result<Self::Listener, Self::Error> {
+        let listener = P::bind(config).await?;
+        Ok(FaultyListener::new(
+            listener,
+            config.faults.as_ref(),
+            &config.bind_addr,
+        ))
+    }
+
+    async fn bind_with_inheritance(
+        config: &StreamConfig,
+        fd_config: &FdInheritanceConfig,
+    ) -> std::result::Result<Self::Listener, Self::Error> {
+        let listener = P::bind_with_inheritance(config, fd_config).await?;
+        Ok(FaultyListener::new(
+            listener,
+            config.faults.as_ref(),
+            &config.bind_addr,
+        ))
+    }
+
+    /// Later listeners fork the first one's generator, so each shapes its
+    /// connections differently
+    async fn bind_workers(
+        config: &StreamConfig,
+        count: usize,
+    ) -> std::result::Result<Vec<Self::Listener>, Self::Error> {
+        let mut listeners = P::bind_workers(config, count).await?.into_iter();
+        let Some(first) = listeners.next() else {
+            return Ok(Vec::new());
+        };
+        let mut faulty = vec![FaultyListener::new(
+            first,
+            config.faults.as_ref(),
+            &config.bind_addr,
+        )];
+        for inner in listeners {
+            let first = &mut faulty[0];
+            let listener = FaultyListener {
+                inner,
+                source: first.source.clone(),
+                shapes: first.shapes,
+                rng: first.rng.fork(),
+            };
+            faulty.push(listener);
+        }
+        Ok(faulty)
+    }
+
+    async fn accept(
+        listener: &mut Self::Listener,
+    ) -> std::result::Result<(Self::Stream, SocketAddr), Self::Error> {
+        let (stream, addr) = P::accept(&mut listener.inner).await?;
+        listener.source.refresh();
+        // Fork even when inactive so connection N always sees the same sequence
+        let rng = listener.rng.fork();
+        let shaper = listener
+            .shapes
+            .then(|| PhasedShaper::new(listener.source.clone(), rng));
+        Ok((
+            FaultyStream {
+                inner: stream,
+                shaper,
+                read_ready: None,
+            },
+            addr,
+        ))
+    }
+
+    fn configure_stream(
+        stream: &Self::Stream,
+        options: &SocketOptions,
+    ) -> std::result::Result<(), Self::Error> {
+        P::configure_stream(&stream.inner, options)
+    }
+
+    async fn connect(addr: SocketAddr) -> std::result::Result<Self::Stream, Self::Error> {
+        let stream = P::connect(addr).await?;
+        Ok(FaultyStream {
+            inner: stream,
+            shaper: None,
+            read_ready: None,
+        })
+    }
+
+    async fn connect_address(addr: &Address) -> std::result::Result<Self::Stream, Self::Error> {
+        let stream = P::connect_address(addr).await?;
+        Ok(FaultyStream {
+            inner: stream,
+            shaper: None,
+            read_ready: None,
+        })
+    }
+
+    async fn read(
+        stream: &mut Self::Stream,
+        buffer: &mut [u8],
+    ) -> std::result::Result<usize, Self::Error> {
+        let Some(shaper) = stream.shaper.as_mut().and_then(PhasedShaper::get) else {
+            return P::read(&mut stream.inner, buffer).await;
+        };
+
+        // Pay for the previous read before this one, so that cancelling a
+        // read (e.g. when a chaos phase starts) never discards received data
+        if let Some(ready) = stream.read_ready {
+            tokio::time::sleep_until(ready).await;
+            stream.read_ready = None;
+        }
+
+        let limit = shaper
+            .read_chunk()
+            .unwrap_or(buffer.len())
+            .min(buffer.len());
+        let n = P::read(&mut stream.inner, &mut buffer[..limit]).await?;
+        let wait = shaper.reserve_read(n);
+        if !wait.is_zero() {
+            stream.read_ready = Some(Instant::now() + wait);
+        }
+        Ok(n)
+    }
+
+    async fn write(stream: &mut Self::Stream, data: &[u8]) -> std::result::Result<(), Self::Error> {
+        let Some(shaper) = stream.shaper.as_mut().and_then(PhasedShaper::get) else {
+            return P::write(&mut stream.inner, data).await;
+        };
+
+        let latency = shaper.latency();
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+
+        let chunk_size = shaper.write_chunk().unwrap_or(data.len()).max(1);
+        for chunk in data.chunks(chunk_size) {
+            let wait = shaper.reserve_write(chunk.len());
+            if !wait.is_zero() {
+                P::flush(&mut stream.inner).await?;
+                tokio::time::sleep(wait).await;
+            }
+            P::write(&mut stream.inner, chunk).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(stream: &mut Self::Stream) -> std::result::Result<(), Self::Error> {
+        P::flush(&mut stream.inner).await
+    }
+
+    /// Splits streams that are not shaped
+    ///
+    /// Shaped streams stay whole so that latency and bandwidth limits apply
+    /// to reads and writes in the order they happen.
+    fn into_split(
+        stream: Self::Stream,
+    ) -> std::result::Result<(ReadHalf, WriteHalf), Self::Stream> {
+        if stream.shaper.is_some() {
+            return Err(stream);
+        }
+        P::into_split(stream.inner).map_err(|inner| FaultyStream {
+            inner,
+            shaper: None,
+            read_ready: None,
+        })
+    }
+
+    async fn read_half(
+        half: &mut ReadHalf,
+        buffer: &mut [u8],
+    ) -> std::result::Result<usize, Self::Error> {
+        P::read_half(half, buffer).await
+    }
+
+    async fn read_half_buf(
+        half: &mut ReadHalf,
+        buffer: &mut bytes::BytesMut,
+        max: usize,
+    ) -> std::result::Result<usize, Self::Error> {
+        P::read_half_buf(half, buffer, max).await
+    }
+
+    async fn write_half(half: &mut WriteHalf, data: &[u8]) -> std::result::Result<(), Self::Error> {
+        P::write_half(half, data).await
+    }
+
+    async fn shutdown_write(stream: &mut Self::Stream) -> std::result::Result<(), Self::Error> {
+        P::shutdown_write(&mut stream.inner).await
+    }
+
+    async fn abort(stream: Self::Stream) -> std::result::Result<(), Self::Error> {
+        P::abort(stream.inner).await
+    }
+
+    async fn shutdown_write_half(half: &mut WriteHalf) -> std::result::Result<(), Self::Error> {
+        P::shutdown_write_half(half).await
+    }
+
+    async fn abort_split(read: ReadHalf, write: WriteHalf) -> std::result::Result<(), Self::Error> {
+        P::abort_split(read, write).await
+    }
+
+    fn map_io_error(err: std::io::Error) -> Self::Error {
+        P::map_io_error(err)
+    }
+}
+
+/// Datagram socket wrapper that delays and paces datagrams
+///
+/// Fault state is shared by all peers of the socket, and by the other worker
+/// sockets bound with it. Each shaped datagram is given its own release
+/// instant and handed to a release task, so latency overlaps between
+/// datagrams and only the bandwidth limit spaces them out.
+pub struct FaultySocket<S> {
+    inner: Arc<S>,
+    shaper: Option<Arc<Mutex<PhasedShaper>>>,
+    /// When the read bandwidth spent on the last datagram is paid off
+    read_ready: Mutex<Option<Instant>>,
+    /// Release instant of the last delayed datagram, so jitter never
+    /// reorders the queue
+    last_release: Mutex<Option<Instant>>,
+    /// Queue of the release task, started by the first delayed datagram
+    delayed: OnceLock<mpsc::Sender<Delayed>>,
+}
+
+impl<S> FaultySocket<S> {
+    pub(crate) fn new(inner: S, config: Option<&FaultConfig>, addr: &SocketAddr) -> Self {
+        let shaper = config.filter(|c| c.may_shape_traffic()).map(|c| {
+            log_shaping(c);
+            let rng = FaultRng::new(c.resolve_seed("traffic shaping", addr));
+            Arc::new(Mutex::new(PhasedShaper::new(
+                FaultSource::new(Some(c)),
+                rng,
+            )))
+        });
+        Self::with_shaper(inner, shaper)
+    }
+
+    fn with_shaper(inner: S, shaper: Option<Arc<Mutex<PhasedShaper>>>) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            shaper,
+            read_ready: Mutex::new(None),
+            last_release: Mutex::new(None),
+            delayed: OnceLock::new(),
+        }
+    }
+
+    /// Returns the wrapped socket
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the instant `len` more bytes may leave, or `None` to send now
+    fn release(&self, len: usize) -> Option<Instant> {
+        let shaper = self.shaper.as_ref()?;
+        let wait = shaper
+            .lock()
+            .unwrap()
+            .get()
+            .map_or(Duration::ZERO, |s| s.latency() + s.reserve_write(len));
+        let now = Instant::now();
+        let mut last_release = self.last_release.lock().unwrap();
+        let release = last_release.map_or(now + wait, |last| (now + wait).max(last));
+        if release <= now {
+            return None;
+        }
+        *last_release = Some(release);
+        Some(release)
+    }
+}
+
+#[async_trait]
+impl<P> DatagramProtocol for Faulty<P>
+where
+    P: DatagramProtocol + Send + Sync + 'static,
+    P::Socket: Send + Sync + 'static,
+{
+    type Error = P::Error;
+    type Socket = FaultySocket<P::Socket>;
+
+    async fn bind(config: &DatagramConfig) -> std::result::Result<Self::Socket, Self::Error> {
+        let socket = P::bind(config).await?;
+        Ok(FaultySocket::new(
+            socket,
+            config.faults.as_ref(),
+            &config.bind_addr,
+        ))
+    }
+
+    async fn bind_with_inheritance(
+        config: &DatagramConfig,
+        fd_config: &FdInheritanceConfig,
+    ) -> std::result::Result<Self::Socket, Self::Error> {
+        let socket = P::bind_with_inheritance(config, fd_config).await?;
+        Ok(FaultySocket::new(
+            socket,
+            config.faults.as_ref(),
+            &config.bind_addr,
+        ))
+    }
+
+    /// Worker sockets share one shaper, so limits apply to the whole server
+    async fn bind_workers(
+        config: &DatagramConfig,
+        count: usize,
+    ) -> std::result::Result<Vec<Self::Socket>, Self::Error> {
+        let mut sockets = P::bind_workers(config, count).await?.into_iter();
+        let Some(first) = sockets.next() else {
+            return Ok(Vec::new());
+        };
+        let first = FaultySocket::new(first, config.faults.as_ref(), &config.bind_addr);
+        let shaper = first.shaper.clone();
+        let mut faulty = vec![first];
+        faulty.extend(sockets.map(|inner| FaultySocket::with_shaper(inner, shaper.clone())));
+        Ok(faulty)
+    }
+
+    async fn recv_from(
+        socket: &Self::Socket,
+        buffer: &mut [u8],
+    ) -> std::result::Result<(usize, SocketAddr), Self::Error> {
+        let received = Self::recv_msg(socket, buffer).await?;
+        Ok((received.len, received.peer))
+    }
+
+    async fn send_to(
+        socket: &Self::Socket,
+        data: &[u8],
+        addr: SocketAddr,
+    ) -> std::result::Result<usize, Self::Error> {
+        Self::send_msg(socket, data, addr, None).await
+    }
+
+    async fn recv_msg(
+        socket: &Self::Socket,
+        buffer: &mut [u8],
+    ) -> std::result::Result<Received, Self::Error> {
+        let Some(shaper) = &socket.shaper else {
+            return P::recv_msg(&socket.inner, buffer).await;
+        };
+
+        // Pay for the previous datagram before taking the next one, so that
+        // cancelling a receive (e.g. on a read timeout or a reorder release)
+        // never discards a datagram already taken from the socket
+        let ready = *socket.read_ready.lock().unwrap();
+        if let Some(ready) = ready {
+            tokio::time::sleep_until(ready).await;
+            *socket.read_ready.lock().unwrap() = None;
+        }
+
+        let received = P::recv_msg(&socket.inner, buffer).await?;
+        let wait = shaper
+            .lock()
+            .unwrap()
+            .get()
+            .map_or(Duration::ZERO, |s| s.reserve_read(received.len));
+        if !wait.is_zero() {
+            *socket.read_ready.lock().unwrap() = Some(Instant::now() + wait);
+        }
+        Ok(received)
+    }
+
+    async fn send_msg(
+        socket: &Self::Socket,
+        data: &[u8],
+        addr: SocketAddr,
+        local: Option<IpAddr>,
+    ) -> std::result::Result<usize, Self::Error> {
+        let Some(release) = socket.release(data.len()) else {
+            return P::send_msg(&socket.inner, data, addr, local).await;
+        };
+        let delayed = socket
+            .delayed
+            .get_or_init(|| release_task::<P>(Arc::clone(&socket.inner)));
+        if let Err(TrySendError::Full(_)) = delayed.try_send((release, data.to_vec(), addr, local))
+        {
+            warn!(%addr, size = data.len(), "Release queue full, dropping datagram");
+        }
+        Ok(data.len())
+    }
+
+    fn map_io_error(err: std::io::Error) -> Self::Error {
+        P::map_io_error(err)
+    }
+}
+
+/// Starts the task that sends each delayed datagram at its release instant
+///
+/// Release instants never decrease, so waiting on them in turn adds no delay
+/// of its own. The task ends once the socket is dropped and the queue drains.
+fn release_task<P>(socket: Arc<P::Socket>) -> mpsc::Sender<Delayed>
+where
+    P: DatagramProtocol + Send + Sync + 'static,
+    P::Socket: Send + Sync + 'static,
+{
+    let (delayed, mut queue) = mpsc::channel::<Delayed>(RELEASE_QUEUE_DEPTH);
+    tokio::spawn(async move {
+        while let Some((release, data, addr, local)) = queue.recv().await {
+            tokio::time::sleep_until(release).await;
+            if let Err(e) = P::send_msg(&socket, &data, addr, local).await {
+                let e: crate::EchoError = e.into();
+                error!(%addr, error = %e, "Failed to send delayed datagram");
+            }
+        }
+    });
+    delayed
+}
+
+fn log_shaping(config: &FaultConfig) {
+    if let Some(chaos) = &config.chaos {
+        info!(
+            phases = chaos.phases().len(),
+            "Traffic shaping follows chaos timeline"
+        );
+        return;
+    }
+    info!(
+        latency = ?config.latency,
+        bandwidth = ?config.bandwidth,
+        "Traffic shaping configured"
+    );
+}
@@ -0,0 +1,201 @@
+use super::config::{FaultConfig, JitterDistribution, LatencyConfig};
+use super::rng::FaultRng;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Smallest chunk a rate-limited transfer is split into
+const MIN_CHUNK: usize = 512;
+
+/// Paces transfers to a fixed rate
+///
+/// Tracks the earliest instant the next byte may move. Reserving `n` bytes
+/// returns how long to wait before moving them and pushes that instant
+/// forward by `n / rate` seconds.
+#[derive(Debug, Clone)]
+struct Pacer {
+    bytes_per_second: u64,
+    next: Option<Instant>,
+}
+
+impl Pacer {
+    fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            next: None,
+        }
+    }
+
+    fn reserve(&mut self, bytes: usize, now: Instant) -> Duration {
+        let start = match self.next {
+            Some(next) if next > now => next,
+            _ => now,
+        };
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+        self.next = Some(start + cost);
+        start - now
+    }
+
+    fn chunk_size(&self) -> usize {
+        // Roughly 50 chunks per second keeps pacing smooth without tiny writes
+        ((self.bytes_per_second / 50) as usize).max(MIN_CHUNK)
+    }
+}
+
+/// Per-connection (or per-socket) fault state
+///
+/// Owns the seeded generator and the bandwidth pacers. The shaper only
+/// computes delays; callers sleep for them around the real I/O.
+#[derive(Debug, Clone)]
+pub struct TrafficShaper {
+    latency: Option<LatencyConfig>,
+    read: Option<Pacer>,
+    write: Option<Pacer>,
+    total: Option<Pacer>,
+    rng: FaultRng,
+}
+
+impl TrafficShaper {
+    /// Creates shaper state for `config`, drawing randomness from `rng`
+    pub fn new(config: &FaultConfig, rng: FaultRng) -> Self {
+        Self {
+            latency: config.latency.clone(),
+            read: config.bandwidth.read.map(Pacer::new),
+            write: config.bandwidth.write.map(Pacer::new),
+            total: config.bandwidth.total.map(Pacer::new),
+            rng,
+        }
+    }
+
+    /// Samples the delay to add before the next response
+    pub fn latency(&mut self) -> Duration {
+        let Some(latency) = &self.latency else {
+            return Duration::ZERO;
+        };
+        let base = latency.base.as_secs_f64();
+        let jitter = latency.jitter.as_secs_f64();
+        let offset = match latency.distribution {
+            JitterDistribution::Uniform => (self.rng.next_f64() * 2.0 - 1.0) * jitter,
+            JitterDistribution::Normal => self.rng.standard_normal() * jitter,
+            JitterDistribution::Exponential => self.rng.standard_exponential() * jitter,
+        };
+        Duration::from_secs_f64((base + offset).max(0.0))
+    }
+
+    /// Largest read that keeps inbound pacing smooth, or `None` if unlimited
+    pub fn read_chunk(&self) -> Option<usize> {
+        Self::chunk(&self.read, &self.total)
+    }
+
+    /// Largest write that keeps outbound pacing smooth, or `None` if unlimited
+    pub fn write_chunk(&self) -> Option<usize> {
+        Self::chunk(&self.write, &self.total)
+    }
+
+    /// Reserves inbound bandwidth for `bytes` and returns the wait required
+    pub fn reserve_read(&mut self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        Self::reserve(&mut self.read, &mut self.total, bytes, now)
+    }
+
+    /// Reserves outbound bandwidth for `bytes` and returns the wait required
+    pub fn reserve_write(&mut self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        Self::reserve(&mut self.write, &mut self.total, bytes, now)
+    }
+
+    /// Mutable access to the underlying generator for other fault decisions
+    pub fn rng(&mut self) -> &mut FaultRng {
+        &mut self.rng
+    }
+
+    fn chunk(direction: &Option<Pacer>, total: &Option<Pacer>) -> Option<usize> {
+        [direction, total]
+            .into_iter()
+            .flatten()
+            .map(Pacer::chunk_size)
+            .min()
+    }
+
+    fn reserve(
+        direction: &mut Option<Pacer>,
+        total: &mut Option<Pacer>,
+        bytes: usize,
+        now: Instant,
+    ) -> Duration {
+        let direction_wait = direction
+            .as_mut()
+            .map_or(Duration::ZERO, |p| p.reserve(bytes, now));
+        let total_wait = total
+            .as_mut()
+            .map_or(Duration::ZERO, |p| p.reserve(bytes, now));
+        direction_wait.max(total_wait)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fault::BandwidthLimit;
+
+    #[test]
+    fn test_fixed_latency() {
+        let config = FaultConfig {
+            latency: Some(LatencyConfig::fixed(Duration::from_millis(50))),
+            ..Default::default()
+        };
+        let mut shaper = TrafficShaper::new(&config, FaultRng::new(1));
+        assert_eq!(shaper.latency(), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_jitter_is_deterministic_and_bounded() {
+        let config = FaultConfig {
+            latency: Some(LatencyConfig {
+                base: Duration::from_millis(100),
+                jitter: Duration::from_millis(20),
+                distribution: JitterDistribution::Uniform,
+            }),
+            ..Default::default()
+        };
+        let mut a = TrafficShaper::new(&config, FaultRng::new(9));
+        let mut b = TrafficShaper::new(&config, FaultRng::new(9));
+        for _ in 0..100 {
+            let delay = a.latency();
+            assert_eq!(delay, b.latency());
+            assert!(delay >= Duration::from_millis(80) && delay <= Duration::from_millis(120));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_write_pacing() {
+        let config = FaultConfig {
+            bandwidth: BandwidthLimit {
+                write: Some(1000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut shaper = TrafficShaper::new(&config, FaultRng::new(1));
+        assert_eq!(shaper.reserve_write(500), Duration::ZERO);
+        assert_eq!(shaper.reserve_write(500), Duration::from_millis(500));
+        assert_eq!(shaper.reserve_write(1), Duration::from_secs(1));
+        // Reads are not limited by a write-only limit
+        assert_eq!(shaper.reserve_read(10_000), Duration::ZERO);
+        assert_eq!(shaper.read_chunk(), None);
+        assert_eq!(shaper.write_chunk(), Some(MIN_CHUNK));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_total_limit_shared_between_directions() {
+        let config = FaultConfig {
+            bandwidth: BandwidthLimit {
+                total: Some(1000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut shaper = TrafficShaper::new(&config, FaultRng::new(1));
+        assert_eq!(shaper.reserve_read(1000), Duration::ZERO);
+        assert_eq!(shaper.reserve_write(1000), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,338 @@
+use super::chaos::FaultSource;
+use super::rng::FaultRng;
+use std::time::Duration;
+
+/// Misbehaviours of stream connections
+///
+/// Every connection-level mode has a `probability` that is rolled once per
+/// accepted connection, so a listener can misbehave on, say, 10% of its
+/// connections. `accept_limit` applies to the whole listener.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::fault::{FaultConfig, ResetFault, StreamFaultConfig};
+///
+/// // Reset a quarter of all connections after 1 KiB has been echoed
+/// let faults = FaultConfig {
+///     stream: StreamFaultConfig {
+///         reset: Some(ResetFault {
+///             after_bytes: 1024,
+///             probability: 0.25,
+///         }),
+///         ..Default::default()
+///     },
+///     ..Default::default()
+/// };
+/// assert!(faults.is_active());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamFaultConfig {
+    /// Abort the connection with a TCP RST (SO_LINGER 0)
+    pub reset: Option<ResetFault>,
+    /// Stop reading or writing without closing the connection
+    pub stall: Option<StallFault>,
+    /// Split each echo into small writes of random size
+    pub partial_writes: Option<PartialWriteFault>,
+    /// Shut down the write half while still reading
+    pub half_close: Option<HalfCloseFault>,
+    /// Stop accepting after this many connections, leaving the rest in the backlog
+    pub accept_limit: Option<usize>,
+}
+
+impl StreamFaultConfig {
+    /// Returns true if any stream fault is configured
+    pub fn is_active(&self) -> bool {
+        self.reset.is_some()
+            || self.stall.is_some()
+            || self.partial_writes.is_some()
+            || self.half_close.is_some()
+            || self.accept_limit.is_some()
+    }
+
+    /// Rolls which modes apply to a newly accepted connection
+    ///
+    /// Returns `None` when the connection should behave normally.
+    pub fn sample(&self, rng: &mut FaultRng) -> Option<StreamFaults> {
+        // Roll every mode, even unconfigured ones, so adding a mode to the
+        // configuration does not shift the decisions for the others
+        let reset = rng.chance(self.reset.as_ref().map_or(0.0, |f| f.probability));
+        let stall = rng.chance(self.stall.as_ref().map_or(0.0, |f| f.probability));
+        let partial = rng.chance(self.partial_writes.as_ref().map_or(0.0, |f| f.probability));
+        let half_close = rng.chance(self.half_close.as_ref().map_or(0.0, |f| f.probability));
+
+        let faults = StreamFaults {
+            reset_after: self.reset.as_ref().filter(|_| reset).map(|f| f.after_bytes),
+            stall: self.stall.clone().filter(|_| stall),
+            partial_writes: self.partial_writes.clone().filter(|_| partial),
+            half_close_after: self
+                .half_close
+                .as_ref()
+                .filter(|_| half_close)
+                .map(|f| f.after_bytes),
+            echoed: 0,
+            rng: rng.fork(),
+        };
+        faults.is_active().then_some(faults)
+    }
+}
+
+/// Reset the connection once `after_bytes` have been echoed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResetFault {
+    /// Bytes echoed before the reset
+    pub after_bytes: u64,
+    /// Fraction of connections affected, in `[0, 1]`
+    pub probability: f64,
+}
+
+/// Stop serving one direction once `after_bytes` have been echoed
+#[derive(Debug, Clone, PartialEq)]
+pub struct StallFault {
+    /// Direction that stops making progress
+    pub direction: StallDirection,
+    /// Bytes echoed before the stall
+    pub after_bytes: u64,
+    /// Fraction of connections affected, in `[0, 1]`
+    pub probability: f64,
+}
+
+/// Direction a stalled connection stops serving
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StallDirection {
+    /// Stop reading until the read timeout; the client's writes eventually
+    /// block
+    #[default]
+    Read,
+    /// Keep reading but never echo; the client's reads block
+    Write,
+}
+
+impl std::str::FromStr for StallDirection {
+    type Err = crate::EchoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "read" | "reads" => Ok(Self::Read),
+            "write" | "writes" => Ok(Self::Write),
+            other => Err(crate::EchoError::Config(format!(
+                "Unknown stall direction '{other}', expected read or write"
+            ))),
+        }
+    }
+}
+
+/// Echo in writes of `1..=max_chunk` bytes instead of one write
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialWriteFault {
+    /// Largest write, in bytes
+    pub max_chunk: usize,
+    /// Pause between writes so they leave as separate segments
+    pub delay: Duration,
+    /// Fraction of connections affected, in `[0, 1]`
+    pub probability: f64,
+}
+
+/// Shut down the write half once `after_bytes` have been echoed
+#[derive(Debug, Clone, PartialEq)]
+pub struct HalfCloseFault {
+    /// Bytes echoed before the half-close
+    pub after_bytes: u64,
+    /// Fraction of connections affected, in `[0, 1]`
+    pub probability: f64,
+}
+
+/// How a faulty connection ends once its byte budget is spent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEnding {
+    /// Abort with a reset
+    Reset,
+    /// Shut down the write half and drain reads
+    HalfClose,
+    /// Stop reading without closing until the read timeout
+    StallReads,
+    /// Keep reading but never echo again
+    StallWrites,
+}
+
+/// Fault modes chosen for one connection, plus its progress
+#[derive(Debug, Clone)]
+pub struct StreamFaults {
+    reset_after: Option<u64>,
+    stall: Option<StallFault>,
+    partial_writes: Option<PartialWriteFault>,
+    half_close_after: Option<u64>,
+    echoed: u64,
+    rng: FaultRng,
+}
+
+impl StreamFaults {
+    fn is_active(&self) -> bool {
+        self.reset_after.is_some()
+            || self.stall.is_some()
+            || self.partial_writes.is_some()
+            || self.half_close_after.is_some()
+    }
+
+    /// The ending that triggers first, with the echoed byte count it needs
+    fn next_ending(&self) -> Option<(u64, StreamEnding)> {
+        let stall = self.stall.as_ref().map(|s| {
+            let ending = match s.direction {
+                StallDirection::Read => StreamEnding::StallReads,
+                StallDirection::Write => StreamEnding::StallWrites,
+            };
+            (s.after_bytes, ending)
+        });
+        [
+            self.reset_after.map(|n| (n, StreamEnding::Reset)),
+            self.half_close_after.map(|n| (n, StreamEnding::HalfClose)),
+            stall,
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(|(after, _)| *after)
+    }
+
+    /// Returns the ending that is due before any more data is echoed
+    pub fn due(&self) -> Option<StreamEnding> {
+        self.next_ending()
+            .filter(|(after, _)| self.echoed >= *after)
+            .map(|(_, ending)| ending)
+    }
+
+    /// Returns how many of `received` bytes may be echoed before an ending
+    pub fn echo_len(&self, received: usize) -> usize {
+        match self.next_ending() {
+            Some((after, _)) => {
+                let budget = after.saturating_sub(self.echoed);
+                received.min(usize::try_from(budget).unwrap_or(usize::MAX))
+            }
+            None => received,
+        }
+    }
+
+    /// Records that `len` bytes were echoed
+    pub fn record_echo(&mut self, len: usize) {
+        self.echoed += len as u64;
+    }
+
+    /// Size of the next partial write and the pause after it, if enabled
+    pub fn next_chunk(&mut self, remaining: usize) -> Option<(usize, Duration)> {
+        let partial = self.partial_writes.as_ref()?;
+        let max = partial.max_chunk.clamp(1, remaining.max(1));
+        let size = self.rng.range_inclusive(1, max as u64) as usize;
+        Some((size, partial.delay))
+    }
+
+    /// Returns true if echoes are split into partial writes
+    pub fn splits_writes(&self) -> bool {
+        self.partial_writes.is_some()
+    }
+}
+
+/// Stream faults of one connection, rolled again whenever a chaos phase starts
+#[derive(Debug)]
+pub(crate) struct ConnectionFaults {
+    source: FaultSource,
+    rng: FaultRng,
+    active: Option<StreamFaults>,
+}
+
+impl ConnectionFaults {
+    pub(crate) fn new(source: FaultSource, mut rng: FaultRng) -> Self {
+        let active = source.current().stream.sample(&mut rng);
+        Self {
+            source,
+            rng,
+            active,
+        }
+    }
+
+    /// Modes that apply to the connection right now
+    pub(crate) fn active(&self) -> Option<&StreamFaults> {
+        self.active.as_ref()
+    }
+
+    /// Modes that apply to the connection right now, mutably
+    pub(crate) fn active_mut(&mut self) -> Option<&mut StreamFaults> {
+        self.active.as_mut()
+    }
+
+    /// Waits for the next phase and rolls the connection's modes for it
+    pub(crate) async fn next_phase(&mut self) -> Option<String> {
+        self.source.changed().await;
+        self.active = self.source.current().stream.sample(&mut self.rng);
+        self.source.phase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_respects_probability() {
+        let config = StreamFaultConfig {
+            reset: Some(ResetFault {
+                after_bytes: 10,
+                probability: 1.0,
+            }),
+            half_close: Some(HalfCloseFault {
+                after_bytes: 5,
+                probability: 0.0,
+            }),
+            ..Default::default()
+        };
+        let mut rng = FaultRng::new(1);
+        let faults = config.sample(&mut rng).unwrap();
+        assert_eq!(faults.reset_after, Some(10));
+        assert_eq!(faults.half_close_after, None);
+
+        let never = StreamFaultConfig {
+            accept_limit: Some(1),
+            ..Default::default()
+        };
+        assert!(never.sample(&mut rng).is_none());
+    }
+
+    #[test]
+    fn test_budget_and_earliest_ending() {
+        let config = StreamFaultConfig {
+            reset: Some(ResetFault {
+                after_bytes: 10,
+                probability: 1.0,
+            }),
+            stall: Some(StallFault {
+                direction: StallDirection::Write,
+                after_bytes: 4,
+                probability: 1.0,
+            }),
+            ..Default::default()
+        };
+        let mut faults = config.sample(&mut FaultRng::new(2)).unwrap();
+        assert_eq!(faults.due(), None);
+        assert_eq!(faults.echo_len(100), 4);
+        faults.record_echo(4);
+        assert_eq!(faults.due(), Some(StreamEnding::StallWrites));
+        assert_eq!(faults.echo_len(100), 0);
+    }
+
+    #[test]
+    fn test_partial_write_chunks() {
+        let config = StreamFaultConfig {
+            partial_writes: Some(PartialWriteFault {
+                max_chunk: 3,
+                delay: Duration::ZERO,
+                probability: 1.0,
+            }),
+            ..Default::default()
+        };
+        let mut faults = config.sample(&mut FaultRng::new(3)).unwrap();
+        assert!(faults.splits_writes());
+        for _ in 0..100 {
+            let (size, _) = faults.next_chunk(10).unwrap();
+            assert!((1..=3).contains(&size));
+        }
+        assert_eq!(faults.next_chunk(1).unwrap().0, 1);
+    }
+}
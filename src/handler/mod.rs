@@ -0,0 +1,188 @@
+//! Pluggable response logic for the generic servers
+//!
+//! [`StreamEchoServer`](crate::stream::StreamEchoServer) and
+//! [`DatagramEchoServer`](crate::datagram::DatagramEchoServer) hand every
+//! chunk or datagram they receive to a handler and send back whatever it
+//! returns. The default handler, [`Echo`], returns the data unchanged.
+//!
+//! Custom handlers keep the servers' connection limits, timeouts, fault
+//! injection and shutdown handling. Closures taking the data and context and
+//! returning `Option<Vec<u8>>` are handlers too.
+//!
+//! Three families of handlers are built in: [`Transform`]s answer with a
+//! processed copy of the data, [`Service`]s implement the classic simple
+//! services (discard, chargen, daytime, time and quote of the day), and
+//! [`Whoami`] tells clients the address they were seen from, as text or
+//! as a STUN Binding response.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use echosrv::common::EchoServerTrait;
+//! use echosrv::handler::StreamContext;
+//! use echosrv::tcp::{TcpConfig, TcpEchoServer};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let config = TcpConfig {
+//!         bind_addr: "127.0.0.1:8080".parse()?,
+//!         ..Default::default()
+//!     };
+//!
+//!     // Answer every chunk with its length, and ignore empty lines
+//!     let server = TcpEchoServer::new(config.into()).with_handler(
+//!         |data: &[u8], _: &StreamContext| {
+//!             (data != b"\n").then(|| format!("{}\n", data.len()).into_bytes())
+//!         },
+//!     );
+//!     server.run().await?;
+//!     Ok(())
+//! }
+//! ```
+
+mod service;
+mod transform;
+mod whoami;
+
+pub use service::Service;
+pub use transform::Transform;
+pub use whoami::Whoami;
+
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+/// What a stream handler knows about the chunk it is answering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StreamContext {
+    /// Address of the client
+    pub peer: SocketAddr,
+    /// Address the server is listening on
+    pub local: SocketAddr,
+    /// Bytes received on this connection before this chunk
+    pub received: u64,
+    /// Chunks received on this connection before this one
+    pub chunks: u64,
+}
+
+impl StreamContext {
+    pub(crate) fn new(peer: SocketAddr, local: SocketAddr) -> Self {
+        Self {
+            peer,
+            local,
+            received: 0,
+            chunks: 0,
+        }
+    }
+
+    /// Records a chunk of `len` bytes once it has been handled
+    pub(crate) fn advance(&mut self, len: usize) {
+        self.received += len as u64;
+        self.chunks += 1;
+    }
+}
+
+/// What a datagram handler knows about the datagram it is answering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DatagramContext {
+    /// Address the datagram came from
+    pub peer: SocketAddr,
+    /// Address the server is listening on
+    pub local: SocketAddr,
+    /// When the kernel received the datagram, if the socket reports
+    /// timestamps
+    pub timestamp: Option<SystemTime>,
+    /// Time to live the datagram arrived with, if the socket reports it
+    pub ttl: Option<u8>,
+}
+
+impl DatagramContext {
+    pub(crate) fn new(peer: SocketAddr, local: SocketAddr) -> Self {
+        Self {
+            peer,
+            local,
+            timestamp: None,
+            ttl: None,
+        }
+    }
+
+    /// Adds what the kernel reported about the datagram
+    pub(crate) fn with_arrival(mut self, timestamp: Option<SystemTime>, ttl: Option<u8>) -> Self {
+        self.timestamp = timestamp;
+        self.ttl = ttl;
+        self
+    }
+}
+
+/// How a stream handler starts a new connection
+pub enum Opening {
+    /// Wait for the client and answer each chunk it sends
+    Wait,
+    /// Send this data as soon as the connection opens, then close it
+    Reply(Vec<u8>),
+    /// Write each chunk in turn until the client goes away or the iterator
+    /// ends, discarding anything the client sends
+    Generate(Box<dyn Iterator<Item = Vec<u8>> + Send>),
+}
+
+/// Produces the response to each chunk read from a stream connection
+///
+/// Chunks are whatever a single read returned, so a message may arrive split
+/// across several calls. Returning `None` sends nothing for that chunk and
+/// keeps the connection open.
+pub trait EchoHandler: Send + Sync {
+    /// Returns the bytes to write back for `data`, or `None` for no response
+    fn handle<'a>(&self, data: &'a [u8], context: &StreamContext) -> Option<Cow<'a, [u8]>>;
+
+    /// Decides what happens when a connection opens
+    ///
+    /// The default waits for the client, so [`handle`](Self::handle) answers
+    /// everything.
+    fn open(&self, _context: &StreamContext) -> Opening {
+        Opening::Wait
+    }
+}
+
+/// Produces the response to each datagram
+///
+/// Returning `None` sends no reply to that datagram.
+pub trait DatagramHandler: Send + Sync {
+    /// Returns the datagram to send back for `data`, or `None` for no reply
+    fn handle<'a>(&self, data: &'a [u8], context: &DatagramContext) -> Option<Cow<'a, [u8]>>;
+}
+
+/// The default handler: responds with exactly what was received
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Echo;
+
+impl EchoHandler for Echo {
+    fn handle<'a>(&self, data: &'a [u8], _context: &StreamContext) -> Option<Cow<'a, [u8]>> {
+        Some(Cow::Borrowed(data))
+    }
+}
+
+impl DatagramHandler for Echo {
+    fn handle<'a>(&self, data: &'a [u8], _context: &DatagramContext) -> Option<Cow<'a, [u8]>> {
+        Some(Cow::Borrowed(data))
+    }
+}
+
+impl<F> EchoHandler for F
+where
+    F: Fn(&[u8], &StreamContext) -> Option<Vec<u8>> + Send + Sync,
+{
+    fn handle<'a>(&self, data: &'a [u8], context: &StreamContext) -> Option<Cow<'a, [u8]>> {
+        self(data, context).map(Cow::Owned)
+    }
+}
+
+impl<F> DatagramHandler for F
+where
+    F: Fn(&[u8], &DatagramContext) -> Option<Vec<u8>> + Send + Sync,
+{
+    fn handle<'a>(&self, data: &'a [u8], context: &DatagramContext) -> Option<Cow<'a, [u8]>> {
+        self(data, context).map(Cow::Owned)
+    }
+}
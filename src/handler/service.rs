@@ -0,0 +1,315 @@
+use super::{DatagramContext, DatagramHandler, EchoHandler, Opening, StreamContext};
+use crate::EchoError;
+use std::borrow::Cow;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Characters of the chargen pattern: printable ASCII from space to tilde
+const CHARGEN_CHARS: &[u8] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// Characters per chargen line, not counting the CRLF
+const CHARGEN_LINE: usize = 72;
+
+/// Lines per chargen datagram; 6 lines of 74 bytes stay under the RFC 864 limit of 512
+const CHARGEN_DATAGRAM_LINES: usize = 6;
+
+/// Seconds from the RFC 868 epoch (1900-01-01) to the Unix epoch
+const RFC868_OFFSET: u64 = 2_208_988_800;
+
+/// Quotes served by QOTD, one per day
+const QUOTES: &[&str] = &[
+    "The best way to predict the future is to invent it. -- Alan Kay",
+    "Simplicity is prerequisite for reliability. -- Edsger W. Dijkstra",
+    "Be conservative in what you do, be liberal in what you accept from others. -- Jon Postel",
+    "If it hurts, do it more often. -- Martin Fowler",
+    "There is no place like 127.0.0.1. -- Anonymous",
+];
+
+/// The classic simple services that sit next to RFC 862 echo
+///
+/// Each service works as both a stream and a datagram handler, so it can be
+/// served by [`StreamEchoServer`](crate::stream::StreamEchoServer) and
+/// [`DatagramEchoServer`](crate::datagram::DatagramEchoServer) with their
+/// usual limits, timeouts and shutdown handling.
+///
+/// | Service | Stream | Datagram |
+/// |---------|--------|----------|
+/// | discard (RFC 863) | reads and drops everything | never replies |
+/// | chargen (RFC 864) | writes the rotating pattern until the client leaves | replies with six pattern lines |
+/// | daytime (RFC 867) | sends the UTC date and time, then closes | replies with the date and time |
+/// | time (RFC 868) | sends 32-bit seconds since 1900, then closes | replies with the seconds |
+/// | qotd (RFC 865) | sends a quote, then closes | replies with a quote |
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::common::EchoServerTrait;
+/// use echosrv::handler::Service;
+/// use echosrv::tcp::{TcpConfig, TcpEchoServer};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let config = TcpConfig {
+///         bind_addr: "127.0.0.1:1919".parse()?,
+///         ..Default::default()
+///     };
+///
+///     let server = TcpEchoServer::new(config.into()).with_handler(Service::Chargen);
+///     server.run().await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    /// Throws away everything it receives
+    Discard,
+    /// Generates the rotating printable-character pattern
+    Chargen,
+    /// Tells the current date and time in human-readable form
+    Daytime,
+    /// Tells the current time as a binary count of seconds since 1900
+    Time,
+    /// Tells a quote of the day
+    Qotd,
+}
+
+impl Service {
+    /// Every service, in the order they are listed in help texts
+    pub const ALL: [Service; 5] = [
+        Service::Discard,
+        Service::Chargen,
+        Service::Daytime,
+        Service::Time,
+        Service::Qotd,
+    ];
+
+    /// The name used to select the service
+    pub fn name(self) -> &'static str {
+        match self {
+            Service::Discard => "discard",
+            Service::Chargen => "chargen",
+            Service::Daytime => "daytime",
+            Service::Time => "time",
+            Service::Qotd => "qotd",
+        }
+    }
+
+    /// The well-known port the service is assigned
+    pub fn port(self) -> u16 {
+        match self {
+            Service::Discard => 9,
+            Service::Chargen => 19,
+            Service::Daytime => 13,
+            Service::Time => 37,
+            Service::Qotd => 17,
+        }
+    }
+
+    /// Returns the reply a client gets at `now`, or `None` for discard
+    ///
+    /// Chargen replies with the first lines of the pattern.
+    pub fn reply(self, now: SystemTime) -> Option<Vec<u8>> {
+        let seconds = now
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        match self {
+            Service::Discard => None,
+            Service::Chargen => Some((0..CHARGEN_DATAGRAM_LINES).flat_map(chargen_line).collect()),
+            Service::Daytime => Some(daytime(seconds).into_bytes()),
+            // RFC 868 time is 32 bits wide and wraps in 2036
+            Service::Time => Some(((seconds + RFC868_OFFSET) as u32).to_be_bytes().to_vec()),
+            Service::Qotd => {
+                let quote = QUOTES[(seconds / 86_400) as usize % QUOTES.len()];
+                Some(format!("{quote}\r\n").into_bytes())
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Service {
+    type Err = EchoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        Service::ALL
+            .into_iter()
+            .find(|service| service.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Service::ALL.iter().map(|s| s.name()).collect();
+                EchoError::Config(format!(
+                    "Unknown service '{s}', expected one of {}",
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl EchoHandler for Service {
+    fn handle<'a>(&self, _data: &'a [u8], _context: &StreamContext) -> Option<Cow<'a, [u8]>> {
+        None
+    }
+
+    fn open(&self, _context: &StreamContext) -> Opening {
+        match self {
+            Service::Discard => Opening::Wait,
+            // One full rotation of the pattern per write
+            Service::Chargen => {
+                let rotation = (0..CHARGEN_CHARS.len()).flat_map(chargen_line).collect();
+                Opening::Generate(Box::new(std::iter::repeat(rotation)))
+            }
+            service => Opening::Reply(service.reply(SystemTime::now()).unwrap_or_default()),
+        }
+    }
+}
+
+impl DatagramHandler for Service {
+    fn handle<'a>(&self, _data: &'a [u8], _context: &DatagramContext) -> Option<Cow<'a, [u8]>> {
+        self.reply(SystemTime::now()).map(Cow::Owned)
+    }
+}
+
+/// Returns line `index` of the chargen pattern, CRLF included
+fn chargen_line(index: usize) -> Vec<u8> {
+    let mut line: Vec<u8> = (0..CHARGEN_LINE)
+        .map(|column| CHARGEN_CHARS[(index + column) % CHARGEN_CHARS.len()])
+        .collect();
+    line.extend_from_slice(b"\r\n");
+    line
+}
+
+/// Formats `seconds` since the Unix epoch the way RFC 867 suggests,
+/// e.g. "Sunday, October 18, 2026 09:05:00-UTC"
+fn daytime(seconds: u64) -> String {
+    const WEEKDAYS: [&str; 7] = [
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+    ];
+    const MONTHS: [&str; 12] = [
+        "January",
+        "February",
+        "March",
+        "April",
+        "May",
+        "June",
+        "July",
+        "August",
+        "September",
+        "October",
+        "November",
+        "December",
+    ];
+
+    let days = seconds / 86_400;
+    let time = seconds % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {} {day}, {year} {:02}:{:02}:{:02}-UTC\r\n",
+        WEEKDAYS[(days % 7) as usize],
+        MONTHS[month as usize - 1],
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) date
+///
+/// This is Howard Hinnant's `civil_from_days`, limited to dates after 1970.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// 2026-10-18 09:05:00 UTC
+    const SUNDAY: u64 = 1_792_314_300;
+
+    #[test]
+    fn test_chargen_pattern() {
+        assert_eq!(
+            chargen_line(0),
+            b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefg\r\n"
+        );
+        assert!(chargen_line(1).starts_with(b"!\"#"));
+        // Lines wrap around the end of the character set
+        assert!(chargen_line(94).starts_with(b"~ !"));
+
+        let Opening::Generate(mut chunks) = Service::Chargen.open(&context()) else {
+            panic!("chargen should generate");
+        };
+        let first = chunks.next().unwrap();
+        assert_eq!(first.len(), 95 * 74);
+        assert_eq!(chunks.next().unwrap(), first);
+    }
+
+    #[test]
+    fn test_time_services() {
+        let now = UNIX_EPOCH + Duration::from_secs(SUNDAY);
+        assert_eq!(
+            Service::Daytime.reply(now).unwrap(),
+            b"Sunday, October 18, 2026 09:05:00-UTC\r\n"
+        );
+        assert_eq!(
+            Service::Daytime.reply(UNIX_EPOCH).unwrap(),
+            b"Thursday, January 1, 1970 00:00:00-UTC\r\n"
+        );
+        assert_eq!(
+            Service::Time.reply(UNIX_EPOCH).unwrap(),
+            2_208_988_800u32.to_be_bytes()
+        );
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    }
+
+    #[test]
+    fn test_replies() {
+        let now = SystemTime::now();
+        assert_eq!(Service::Discard.reply(now), None);
+        assert_eq!(Service::Chargen.reply(now).unwrap().len(), 444);
+        let quote = Service::Qotd.reply(now).unwrap();
+        assert!(quote.len() < 512 && quote.ends_with(b"\r\n"));
+
+        assert!(matches!(Service::Discard.open(&context()), Opening::Wait));
+        assert!(matches!(Service::Time.open(&context()), Opening::Reply(r) if r.len() == 4));
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for service in Service::ALL {
+            assert_eq!(service.to_string().parse::<Service>().unwrap(), service);
+        }
+        assert!("finger".parse::<Service>().is_err());
+    }
+
+    fn context() -> StreamContext {
+        let addr = "127.0.0.1:0".parse().unwrap();
+        StreamContext::new(addr, addr)
+    }
+}
@@ -0,0 +1,258 @@
+use super::{DatagramContext, DatagramHandler, EchoHandler, StreamContext};
+use crate::EchoError;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::fmt::{self, Write as _};
+
+/// Built-in payload transforms that make an echo visibly processed
+///
+/// A transform is applied to each chunk a stream server reads and to each
+/// datagram. Outputs that are text of their own (digests, base64, hex dumps)
+/// end with a newline, so they read well from `nc` or `curl`.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::handler::Transform;
+///
+/// let transform: Transform = "reverse".parse().unwrap();
+/// assert_eq!(transform.apply(b"hello\n"), b"olleh\n");
+/// assert_eq!(Transform::Crc32.apply(b"hello\n"), b"363a3020\n");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    /// ASCII letters in upper case
+    Uppercase,
+    /// Bytes in reverse order, keeping a trailing line ending in place
+    Reverse,
+    /// ASCII letters rotated by 13 places
+    Rot13,
+    /// Hex SHA-256 digest of the payload
+    Sha256,
+    /// Hex CRC-32 (IEEE) checksum of the payload
+    Crc32,
+    /// Standard base64 encoding of the payload
+    Base64Encode,
+    /// Payload decoded from base64, ignoring surrounding whitespace
+    Base64Decode,
+    /// `hexdump -C` style dump of the payload
+    HexDump,
+}
+
+impl Transform {
+    /// Every transform, in the order they are listed in help texts
+    pub const ALL: [Transform; 8] = [
+        Transform::Uppercase,
+        Transform::Reverse,
+        Transform::Rot13,
+        Transform::Sha256,
+        Transform::Crc32,
+        Transform::Base64Encode,
+        Transform::Base64Decode,
+        Transform::HexDump,
+    ];
+
+    /// The name used to select the transform
+    pub fn name(self) -> &'static str {
+        match self {
+            Transform::Uppercase => "uppercase",
+            Transform::Reverse => "reverse",
+            Transform::Rot13 => "rot13",
+            Transform::Sha256 => "sha256",
+            Transform::Crc32 => "crc32",
+            Transform::Base64Encode => "base64",
+            Transform::Base64Decode => "base64-decode",
+            Transform::HexDump => "hexdump",
+        }
+    }
+
+    /// Returns the transformed payload
+    ///
+    /// Input that is not valid base64 is answered with an error message
+    /// rather than dropped, so the client always sees a response.
+    pub fn apply(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Transform::Uppercase => data.to_ascii_uppercase(),
+            Transform::Reverse => {
+                let (body, ending) = split_line_ending(data);
+                let mut reversed: Vec<u8> = body.iter().rev().copied().collect();
+                reversed.extend_from_slice(ending);
+                reversed
+            }
+            Transform::Rot13 => data.iter().map(|&b| rot13(b)).collect(),
+            Transform::Sha256 => line(&hex(&Sha256::digest(data))),
+            Transform::Crc32 => line(&format!("{:08x}", crc32fast::hash(data))),
+            Transform::Base64Encode => line(&BASE64.encode(data)),
+            Transform::Base64Decode => {
+                let encoded: Vec<u8> = data
+                    .iter()
+                    .copied()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .collect();
+                BASE64
+                    .decode(encoded)
+                    .unwrap_or_else(|e| line(&format!("invalid base64: {e}")))
+            }
+            Transform::HexDump => hex_dump(data).into_bytes(),
+        }
+    }
+}
+
+impl std::str::FromStr for Transform {
+    type Err = EchoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        let alias = match name.as_str() {
+            "upper" => "uppercase",
+            "base64-encode" => "base64",
+            "unbase64" => "base64-decode",
+            "hex" => "hexdump",
+            other => other,
+        };
+        Transform::ALL
+            .into_iter()
+            .find(|t| t.name() == alias)
+            .ok_or_else(|| {
+                let names: Vec<_> = Transform::ALL.iter().map(|t| t.name()).collect();
+                EchoError::Config(format!(
+                    "Unknown transform '{s}', expected one of {}",
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl EchoHandler for Transform {
+    fn handle<'a>(&self, data: &'a [u8], _context: &StreamContext) -> Option<Cow<'a, [u8]>> {
+        Some(Cow::Owned(self.apply(data)))
+    }
+}
+
+impl DatagramHandler for Transform {
+    fn handle<'a>(&self, data: &'a [u8], _context: &DatagramContext) -> Option<Cow<'a, [u8]>> {
+        Some(Cow::Owned(self.apply(data)))
+    }
+}
+
+fn split_line_ending(data: &[u8]) -> (&[u8], &[u8]) {
+    let body = data
+        .strip_suffix(b"\r\n")
+        .or_else(|| data.strip_suffix(b"\n"))
+        .unwrap_or(data);
+    data.split_at(body.len())
+}
+
+fn rot13(byte: u8) -> u8 {
+    match byte {
+        b'a'..=b'z' => (byte - b'a' + 13) % 26 + b'a',
+        b'A'..=b'Z' => (byte - b'A' + 13) % 26 + b'A',
+        _ => byte,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{b:02x}");
+        out
+    })
+}
+
+fn line(text: &str) -> Vec<u8> {
+    format!("{text}\n").into_bytes()
+}
+
+fn hex_dump(data: &[u8]) -> String {
+    let mut out = String::new();
+    for (index, row) in data.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x} ", index * 16);
+        for column in 0..16 {
+            if column == 8 {
+                out.push(' ');
+            }
+            match row.get(column) {
+                Some(b) => {
+                    let _ = write!(out, " {b:02x}");
+                }
+                None => out.push_str("   "),
+            }
+        }
+        let text: String = row
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let _ = writeln!(out, "  |{text}|");
+    }
+    let _ = writeln!(out, "{:08x}", data.len());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_transforms() {
+        assert_eq!(Transform::Uppercase.apply(b"Hi there\n"), b"HI THERE\n");
+        assert_eq!(Transform::Reverse.apply(b"abc\r\n"), b"cba\r\n");
+        assert_eq!(Transform::Reverse.apply(b"\x01\x02"), b"\x02\x01");
+        assert_eq!(Transform::Rot13.apply(b"Hello, World!"), b"Uryyb, Jbeyq!");
+    }
+
+    #[test]
+    fn test_digests_match_coreutils() {
+        // echo hello | sha256sum
+        assert_eq!(
+            Transform::Sha256.apply(b"hello\n"),
+            b"5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03\n"
+        );
+        assert_eq!(Transform::Crc32.apply(b""), b"00000000\n");
+    }
+
+    #[test]
+    fn test_base64_round_trip() {
+        let encoded = Transform::Base64Encode.apply(b"hello\n");
+        assert_eq!(encoded, b"aGVsbG8K\n");
+        assert_eq!(Transform::Base64Decode.apply(&encoded), b"hello\n");
+        assert!(
+            Transform::Base64Decode
+                .apply(b"not base64!")
+                .starts_with(b"invalid base64")
+        );
+    }
+
+    #[test]
+    fn test_hex_dump_layout() {
+        let dump = Transform::HexDump.apply(b"0123456789abcdef\x00hi");
+        let expected = "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n\
+                        00000010  00 68 69                                          |.hi|\n\
+                        00000013\n";
+        assert_eq!(String::from_utf8(dump).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for transform in Transform::ALL {
+            assert_eq!(
+                transform.to_string().parse::<Transform>().unwrap(),
+                transform
+            );
+        }
+        assert_eq!("HEX".parse::<Transform>().unwrap(), Transform::HexDump);
+        assert!("md5".parse::<Transform>().is_err());
+    }
+}
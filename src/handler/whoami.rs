@@ -0,0 +1,378 @@
+use super::{DatagramContext, DatagramHandler, EchoHandler, Opening, StreamContext};
+use crate::EchoError;
+use std::borrow::Cow;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Size of a STUN message header
+const HEADER_LEN: usize = 20;
+
+/// Fixed value in every RFC 5389 message, which RFC 3489 messages lack
+const MAGIC_COOKIE: u32 = 0x2112_a442;
+
+/// Binding request message type
+const BINDING_REQUEST: u16 = 0x0001;
+
+/// Binding success response message type
+const BINDING_SUCCESS: u16 = 0x0101;
+
+/// Attribute types used in responses
+const MAPPED_ADDRESS: u16 = 0x0001;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const SOFTWARE: u16 = 0x8022;
+const FINGERPRINT: u16 = 0x8028;
+
+/// Value the CRC32 of a message is XORed with in FINGERPRINT
+const FINGERPRINT_XOR: u32 = 0x5354_554e;
+
+/// Description sent in the SOFTWARE attribute
+const SOFTWARE_NAME: &str = concat!("echosrv ", env!("CARGO_PKG_VERSION"));
+
+/// Answers each client with the address and port the server saw it come
+/// from, for NAT and container networking tests
+///
+/// The address is the one the kernel reported for the connection or
+/// datagram, so a client behind NAT sees its translated address. IPv4
+/// clients of dual-stack sockets are reported as IPv4.
+///
+/// | Mode | Stream | Datagram |
+/// |------|--------|----------|
+/// | text | sends `ADDRESS:PORT` and a newline, then closes | replies with `ADDRESS:PORT` and a newline |
+/// | stun | answers each read holding a Binding request | answers each Binding request |
+///
+/// STUN answers are Binding success responses (RFC 5389) carrying the
+/// address in XOR-MAPPED-ADDRESS, or in MAPPED-ADDRESS for RFC 3489 clients,
+/// with a FINGERPRINT if the request had one. Anything other than a Binding
+/// request is silently ignored, and no authentication is done.
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::common::EchoServerTrait;
+/// use echosrv::handler::Whoami;
+/// use echosrv::udp::{UdpConfig, UdpEchoServer};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let config = UdpConfig {
+///         bind_addr: "0.0.0.0:3478".parse()?,
+///         ..Default::default()
+///     };
+///
+///     let server = UdpEchoServer::new(config.into()).with_handler(Whoami::Stun);
+///     server.run().await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Whoami {
+    /// Plain text `ADDRESS:PORT`, with IPv6 addresses in brackets
+    Text,
+    /// STUN Binding success responses
+    Stun,
+}
+
+impl Whoami {
+    /// Every mode, in the order they are listed in help texts
+    pub const ALL: [Whoami; 2] = [Whoami::Text, Whoami::Stun];
+
+    /// The name used to select the mode
+    pub fn name(self) -> &'static str {
+        match self {
+            Whoami::Text => "text",
+            Whoami::Stun => "stun",
+        }
+    }
+
+    /// Returns the answer to `request` from `peer`, or `None` if a STUN
+    /// request is not a Binding request
+    pub fn reply(self, request: &[u8], peer: SocketAddr) -> Option<Vec<u8>> {
+        let peer = SocketAddr::new(peer.ip().to_canonical(), peer.port());
+        match self {
+            Whoami::Text => Some(format!("{peer}\n").into_bytes()),
+            Whoami::Stun => binding_response(request, peer),
+        }
+    }
+
+    /// Builds an RFC 5389 Binding request with the given transaction ID
+    pub fn binding_request(transaction_id: [u8; 12]) -> Vec<u8> {
+        let mut request = Vec::with_capacity(HEADER_LEN);
+        request.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+        request.extend_from_slice(&0u16.to_be_bytes());
+        request.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        request.extend_from_slice(&transaction_id);
+        request
+    }
+
+    /// Reads the address a Binding success response reports, from
+    /// XOR-MAPPED-ADDRESS or else MAPPED-ADDRESS
+    pub fn mapped_address(response: &[u8]) -> Option<SocketAddr> {
+        let (kind, attributes) = message(response)?;
+        if kind != BINDING_SUCCESS {
+            return None;
+        }
+        let mut mapped = None;
+        for (kind, value) in attributes {
+            match kind {
+                XOR_MAPPED_ADDRESS => return decode_address(value, Some(&response[4..20])),
+                MAPPED_ADDRESS => mapped = decode_address(value, None),
+                _ => {}
+            }
+        }
+        mapped
+    }
+}
+
+impl std::str::FromStr for Whoami {
+    type Err = EchoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        Whoami::ALL
+            .into_iter()
+            .find(|mode| mode.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = Whoami::ALL.iter().map(|m| m.name()).collect();
+                EchoError::Config(format!(
+                    "Unknown whoami mode '{s}', expected one of {}",
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+impl fmt::Display for Whoami {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl EchoHandler for Whoami {
+    fn handle<'a>(&self, data: &'a [u8], context: &StreamContext) -> Option<Cow<'a, [u8]>> {
+        self.reply(data, context.peer).map(Cow::Owned)
+    }
+
+    fn open(&self, context: &StreamContext) -> Opening {
+        match self {
+            Whoami::Text => Opening::Reply(self.reply(&[], context.peer).unwrap_or_default()),
+            Whoami::Stun => Opening::Wait,
+        }
+    }
+}
+
+impl DatagramHandler for Whoami {
+    fn handle<'a>(&self, data: &'a [u8], context: &DatagramContext) -> Option<Cow<'a, [u8]>> {
+        self.reply(data, context.peer).map(Cow::Owned)
+    }
+}
+
+/// Type and value of a STUN attribute
+type Attribute<'a> = (u16, &'a [u8]);
+
+/// Splits a STUN message into its type and attributes, or `None` if it is
+/// malformed
+///
+/// Bytes after the message are ignored, as a stream read may hold more.
+fn message(data: &[u8]) -> Option<(u16, Vec<Attribute<'_>>)> {
+    if data.len() < HEADER_LEN || data[0] & 0xc0 != 0 {
+        return None;
+    }
+    let kind = u16::from_be_bytes([data[0], data[1]]);
+    let len = usize::from(u16::from_be_bytes([data[2], data[3]]));
+    if len % 4 != 0 || data.len() < HEADER_LEN + len {
+        return None;
+    }
+    let mut body = &data[HEADER_LEN..HEADER_LEN + len];
+    let mut attributes = Vec::new();
+    while !body.is_empty() {
+        if body.len() < 4 {
+            return None;
+        }
+        let kind = u16::from_be_bytes([body[0], body[1]]);
+        let len = usize::from(u16::from_be_bytes([body[2], body[3]]));
+        let padded = len.div_ceil(4) * 4;
+        if body.len() < 4 + padded {
+            return None;
+        }
+        attributes.push((kind, &body[4..4 + len]));
+        body = &body[4 + padded..];
+    }
+    Some((kind, attributes))
+}
+
+/// Answers a Binding request from `peer`, or returns `None` for anything
+/// else
+fn binding_response(request: &[u8], peer: SocketAddr) -> Option<Vec<u8>> {
+    let (kind, attributes) = message(request)?;
+    if kind != BINDING_REQUEST {
+        return None;
+    }
+    // RFC 3489 requests carry a 16-byte transaction ID where the cookie is
+    let id = &request[4..20];
+    let modern = id[..4] == MAGIC_COOKIE.to_be_bytes();
+
+    let mut response = Vec::with_capacity(64);
+    response.extend_from_slice(&BINDING_SUCCESS.to_be_bytes());
+    response.extend_from_slice(&0u16.to_be_bytes());
+    response.extend_from_slice(id);
+    if modern {
+        append_attribute(
+            &mut response,
+            XOR_MAPPED_ADDRESS,
+            &encode_address(peer, Some(id)),
+        );
+    } else {
+        append_attribute(&mut response, MAPPED_ADDRESS, &encode_address(peer, None));
+    }
+    append_attribute(&mut response, SOFTWARE, SOFTWARE_NAME.as_bytes());
+    if modern && attributes.iter().any(|&(kind, _)| kind == FINGERPRINT) {
+        // The CRC covers the header with the fingerprint's length included
+        let len = response.len() - HEADER_LEN + 8;
+        set_length(&mut response, len);
+        let crc = crc32fast::hash(&response) ^ FINGERPRINT_XOR;
+        append_attribute(&mut response, FINGERPRINT, &crc.to_be_bytes());
+    }
+    Some(response)
+}
+
+/// Appends an attribute padded to four bytes, and updates the message length
+fn append_attribute(message: &mut Vec<u8>, kind: u16, value: &[u8]) {
+    message.extend_from_slice(&kind.to_be_bytes());
+    message.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    message.extend_from_slice(value);
+    message.resize(message.len().div_ceil(4) * 4, 0);
+    let len = message.len() - HEADER_LEN;
+    set_length(message, len);
+}
+
+fn set_length(message: &mut [u8], len: usize) {
+    message[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+}
+
+/// Encodes an address attribute value, XORed with the cookie and
+/// transaction ID in `id` for XOR-MAPPED-ADDRESS
+fn encode_address(addr: SocketAddr, id: Option<&[u8]>) -> Vec<u8> {
+    let (family, mut ip) = match addr.ip() {
+        IpAddr::V4(ip) => (1, ip.octets().to_vec()),
+        IpAddr::V6(ip) => (2, ip.octets().to_vec()),
+    };
+    let mut port = addr.port().to_be_bytes();
+    if let Some(id) = id {
+        xor(&mut port, id);
+        xor(&mut ip, id);
+    }
+    let mut value = vec![0, family];
+    value.extend_from_slice(&port);
+    value.extend_from_slice(&ip);
+    value
+}
+
+/// Decodes an address attribute value, undoing the XOR if `id` is given
+fn decode_address(value: &[u8], id: Option<&[u8]>) -> Option<SocketAddr> {
+    let len = match value.get(1)? {
+        1 => 4,
+        2 => 16,
+        _ => return None,
+    };
+    let mut port = [*value.get(2)?, *value.get(3)?];
+    let mut ip = value.get(4..4 + len)?.to_vec();
+    if let Some(id) = id {
+        xor(&mut port, id);
+        xor(&mut ip, id);
+    }
+    let ip: IpAddr = match <[u8; 4]>::try_from(ip.as_slice()) {
+        Ok(octets) => Ipv4Addr::from(octets).into(),
+        Err(_) => Ipv6Addr::from(<[u8; 16]>::try_from(ip.as_slice()).ok()?).into(),
+    };
+    Some(SocketAddr::new(ip, u16::from_be_bytes(port)))
+}
+
+fn xor(data: &mut [u8], key: &[u8]) {
+    for (byte, key) in data.iter_mut().zip(key) {
+        *byte ^= key;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: [u8; 12] = *b"0123456789ab";
+
+    #[test]
+    fn test_text() {
+        let peer: SocketAddr = "[::ffff:192.0.2.1]:4000".parse().unwrap();
+        assert_eq!(Whoami::Text.reply(b"", peer).unwrap(), b"192.0.2.1:4000\n");
+        let peer: SocketAddr = "[2001:db8::1]:53".parse().unwrap();
+        assert_eq!(
+            Whoami::Text.reply(b"anything", peer).unwrap(),
+            b"[2001:db8::1]:53\n"
+        );
+        assert_eq!("STUN".parse::<Whoami>().unwrap(), Whoami::Stun);
+        assert!("dns".parse::<Whoami>().is_err());
+    }
+
+    #[test]
+    fn test_binding_response() {
+        for peer in ["192.0.2.1:32853", "[2001:db8:1234::1]:32853"] {
+            let peer: SocketAddr = peer.parse().unwrap();
+            let response = Whoami::Stun
+                .reply(&Whoami::binding_request(ID), peer)
+                .unwrap();
+            assert_eq!(&response[0..2], &[0x01, 0x01]);
+            assert_eq!(&response[8..20], &ID);
+            assert_eq!(response.len() % 4, 0);
+            assert_eq!(
+                usize::from(u16::from_be_bytes([response[2], response[3]])),
+                response.len() - HEADER_LEN
+            );
+            assert_eq!(Whoami::mapped_address(&response), Some(peer));
+        }
+
+        // RFC 5389 section 15.2: port 32853 XORed with the cookie is 0xa147
+        let peer: SocketAddr = "192.0.2.1:32853".parse().unwrap();
+        let response = binding_response(&Whoami::binding_request(ID), peer).unwrap();
+        assert_eq!(&response[20..24], &[0x00, 0x20, 0x00, 0x08]);
+        assert_eq!(&response[26..28], &[0xa1, 0x47]);
+        assert_eq!(&response[28..32], &[0xe1, 0x12, 0xa6, 0x43]);
+
+        // Responses, other methods and garbage get no answer
+        assert!(Whoami::Stun.reply(&response, peer).is_none());
+        let mut allocate = Whoami::binding_request(ID);
+        allocate[1] = 0x03;
+        assert!(Whoami::Stun.reply(&allocate, peer).is_none());
+        assert!(
+            Whoami::Stun
+                .reply(b"GET / HTTP/1.1\r\n\r\n", peer)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_legacy_and_fingerprint() {
+        let peer: SocketAddr = "192.0.2.1:4000".parse().unwrap();
+
+        // RFC 3489 requests have no cookie, and get a plain MAPPED-ADDRESS
+        let mut legacy = Whoami::binding_request(ID);
+        legacy[4..8].copy_from_slice(b"abcd");
+        let response = Whoami::Stun.reply(&legacy, peer).unwrap();
+        assert_eq!(&response[4..20], &legacy[4..20]);
+        assert_eq!(&response[20..22], &[0x00, 0x01]);
+        assert_eq!(Whoami::mapped_address(&response), Some(peer));
+
+        // A request with a fingerprint gets one back, covering the message
+        let mut request = Whoami::binding_request(ID);
+        set_length(&mut request, 8);
+        let crc = crc32fast::hash(&request) ^ FINGERPRINT_XOR;
+        append_attribute(&mut request, FINGERPRINT, &crc.to_be_bytes());
+        let response = Whoami::Stun.reply(&request, peer).unwrap();
+        let (_, attributes) = message(&response).unwrap();
+        let &(kind, value) = attributes.last().unwrap();
+        assert_eq!(kind, FINGERPRINT);
+        let covered = &response[..response.len() - 8];
+        assert_eq!(
+            u32::from_be_bytes(value.try_into().unwrap()),
+            crc32fast::hash(covered) ^ FINGERPRINT_XOR
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::handler::Transform;
+use crate::network::SocketOptions;
 use crate::stream::StreamConfig;
 use std::time::Duration;
 
@@ -13,19 +15,24 @@ use std::time::Duration;
 ///
 /// let config = HttpConfig {
 ///     bind_addr: "127.0.0.1:8080".parse().unwrap(),
+///     additional_addrs: Vec::new(),
 ///     max_connections: 100,
 ///     buffer_size: 8192, // Larger buffer for HTTP
 ///     read_timeout: Duration::from_secs(30),
 ///     write_timeout: Duration::from_secs(30),
-///     server_name: Some("EchoServer/1.0".to_string()),
-///     echo_headers: true,
-///     default_content_type: Some("text/plain".to_string()),
+///     transform: None,
+///     workers: 1,
+///     steer_by_cpu: false,
+///     socket_options: Default::default(),
 /// };
 /// ```
 #[derive(Debug, Clone)]
 pub struct HttpConfig {
     /// Network address to bind to
     pub bind_addr: std::net::SocketAddr,
+    /// Further addresses served alongside `bind_addr`, sharing its
+    /// connection limit and statistics
+    pub additional_addrs: Vec<std::net::SocketAddr>,
     /// Maximum number of concurrent connections
     pub max_connections: usize,
     /// Buffer size for reading/writing data
@@ -34,25 +41,31 @@ pub struct HttpConfig {
     pub read_timeout: Duration,
     /// Write timeout for connections
     pub write_timeout: Duration,
-    /// Server name to include in responses (optional)
-    pub server_name: Option<String>,
-    /// Whether to echo back request headers in response
-    pub echo_headers: bool,
-    /// Default content type for responses
-    pub default_content_type: Option<String>,
+    /// Transform applied to echoed request bodies; plain echo if `None`
+    pub transform: Option<Transform>,
+    /// Accept loops, each on its own `SO_REUSEPORT` listener; 0 runs one per
+    /// runtime worker thread
+    pub workers: usize,
+    /// Hand each new connection to the accept loop of the CPU that received
+    /// it, rather than by the kernel's hash of its addresses (Linux only)
+    pub steer_by_cpu: bool,
+    /// Socket options of the listener and accepted streams
+    pub socket_options: SocketOptions,
 }
 
 impl Default for HttpConfig {
     fn default() -> Self {
         Self {
             bind_addr: "127.0.0.1:8080".parse().unwrap(),
+            additional_addrs: Vec::new(),
             max_connections: 100,
             buffer_size: 8192, // Larger buffer for HTTP requests
             read_timeout: Duration::from_secs(30),
             write_timeout: Duration::from_secs(30),
-            server_name: Some("EchoServer/1.0".to_string()),
-            echo_headers: true,
-            default_content_type: Some("text/plain".to_string()),
+            transform: None,
+            workers: 1,
+            steer_by_cpu: false,
+            socket_options: SocketOptions::default(),
         }
     }
 }
@@ -61,10 +74,17 @@ impl From<HttpConfig> for StreamConfig {
     fn from(config: HttpConfig) -> Self {
         Self {
             bind_addr: config.bind_addr,
+            additional_addrs: config.additional_addrs,
             max_connections: config.max_connections,
             buffer_size: config.buffer_size,
             read_timeout: config.read_timeout,
             write_timeout: config.write_timeout,
+            faults: None,
+            transform: config.transform,
+            framing: None,
+            workers: config.workers,
+            steer_by_cpu: config.steer_by_cpu,
+            socket_options: config.socket_options,
         }
     }
 }
@@ -1,6 +1,9 @@
+use crate::network::SocketOptions;
+use crate::performance::global_pool;
 use crate::stream::{StreamConfig, StreamProtocol};
 
 use async_trait::async_trait;
+use socket2::SockRef;
 use std::io;
 use std::net::SocketAddr;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -52,11 +55,20 @@ impl StreamProtocol for HttpProtocol {
     type Stream = HttpStream;
 
     async fn bind(config: &StreamConfig) -> std::result::Result<Self::Listener, Self::Error> {
-        TcpListener::bind(config.bind_addr)
-            .await
+        crate::tcp::stream_protocol::bind_listener(config.bind_addr, &config.socket_options, false)
             .map_err(HttpProtocolError::Io)
     }
 
+    async fn bind_workers(
+        config: &StreamConfig,
+        count: usize,
+    ) -> std::result::Result<Vec<Self::Listener>, Self::Error> {
+        if count <= 1 {
+            return Ok(vec![Self::bind(config).await?]);
+        }
+        crate::tcp::stream_protocol::bind_listeners(config, count).map_err(HttpProtocolError::Io)
+    }
+
     async fn accept(
         listener: &mut Self::Listener,
     ) -> std::result::Result<(Self::Stream, SocketAddr), Self::Error> {
@@ -64,6 +76,15 @@ impl StreamProtocol for HttpProtocol {
         Ok((HttpStream::new(stream), addr))
     }
 
+    fn configure_stream(
+        stream: &Self::Stream,
+        options: &SocketOptions,
+    ) -> std::result::Result<(), Self::Error> {
+        options
+            .apply_to_stream(&SockRef::from(&stream.inner))
+            .map_err(HttpProtocolError::Io)
+    }
+
     async fn connect(addr: SocketAddr) -> std::result::Result<Self::Stream, Self::Error> {
         let stream = TcpStream::connect(addr)
             .await
@@ -81,7 +102,8 @@ impl StreamProtocol for HttpProtocol {
         }
 
         // Read more data into our buffer
-        let mut temp_buffer = vec![0u8; 1024];
+        let mut temp_buffer = global_pool().get_with_capacity(1024);
+        temp_buffer.resize(1024, 0);
         let n = stream
             .inner
             .read(&mut temp_buffer)
@@ -188,6 +210,24 @@ impl StreamProtocol for HttpProtocol {
         stream.inner.flush().await.map_err(HttpProtocolError::Io)
     }
 
+    async fn shutdown_write(stream: &mut Self::Stream) -> std::result::Result<(), Self::Error> {
+        stream
+            .inner
+            .shutdown()
+            .await
+            .map_err(HttpProtocolError::Io)
+    }
+
+    async fn abort(stream: Self::Stream) -> std::result::Result<(), Self::Error> {
+        // A zero linger makes close() send RST instead of FIN
+        stream
+            .inner
+            .set_linger(Some(std::time::Duration::ZERO))
+            .map_err(HttpProtocolError::Io)?;
+        drop(stream);
+        Ok(())
+    }
+
     fn map_io_error(err: io::Error) -> Self::Error {
         HttpProtocolError::Io(err)
     }
@@ -8,10 +8,17 @@ use tokio::net::{TcpListener, TcpStream};
 async fn test_http_protocol_bind_and_accept() {
     let config = StreamConfig {
         bind_addr: "127.0.0.1:0".parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 8192,
         read_timeout: std::time::Duration::from_secs(5),
         write_timeout: std::time::Duration::from_secs(5),
+        faults: None,
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let listener = HttpProtocol::bind(&config).await.unwrap();
@@ -49,10 +56,17 @@ async fn test_http_protocol_connect() {
 async fn test_http_protocol_simple_post() {
     let config = StreamConfig {
         bind_addr: "127.0.0.1:0".parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 8192,
         read_timeout: std::time::Duration::from_secs(5),
         write_timeout: std::time::Duration::from_secs(5),
+        faults: None,
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let listener = HttpProtocol::bind(&config).await.unwrap();
@@ -93,10 +107,17 @@ async fn test_http_protocol_simple_post() {
 async fn test_http_protocol_method_not_allowed() {
     let config = StreamConfig {
         bind_addr: "127.0.0.1:0".parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 8192,
         read_timeout: std::time::Duration::from_secs(5),
         write_timeout: std::time::Duration::from_secs(5),
+        faults: None,
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let listener = HttpProtocol::bind(&config).await.unwrap();
@@ -140,10 +161,17 @@ async fn test_http_protocol_method_not_allowed() {
 async fn test_http_protocol_incomplete_request() {
     let config = StreamConfig {
         bind_addr: "127.0.0.1:0".parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 8192,
         read_timeout: std::time::Duration::from_secs(5),
         write_timeout: std::time::Duration::from_secs(5),
+        faults: None,
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let listener = HttpProtocol::bind(&config).await.unwrap();
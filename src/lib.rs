@@ -32,9 +32,17 @@ pub enum EchoError {
     #[error("UTF-8 error: {0}")]
     Utf8(#[from] std::string::FromUtf8Error),
 
+    /// Framing errors (oversized or malformed messages)
+    #[error("Framing error: {0}")]
+    Frame(String),
+
     /// Unsupported operation errors
     #[error("Unsupported operation: {0}")]
     Unsupported(String),
+
+    /// DTLS errors (setup, handshake, records)
+    #[error("DTLS error: {0}")]
+    Dtls(String),
 }
 
 impl From<HttpProtocolError> for EchoError {
@@ -50,25 +58,39 @@ impl From<HttpProtocolError> for EchoError {
     }
 }
 
+#[cfg(feature = "dtls")]
+impl From<openssl::error::ErrorStack> for EchoError {
+    fn from(err: openssl::error::ErrorStack) -> Self {
+        EchoError::Dtls(err.to_string())
+    }
+}
+
 /// Result type for the echosrv library
 pub type Result<T> = std::result::Result<T, EchoError>;
 
 pub mod common;
 pub mod datagram;
+pub mod dtls;
+pub mod fault;
+pub mod handler;
 pub mod http;
 pub mod network;
 pub mod performance;
+pub mod proxy;
 pub mod security;
 pub mod stream;
 pub mod tcp;
 pub mod udp;
 pub mod unix;
+pub mod uring;
 
 // Re-export main types for convenience
 pub use common::{EchoClient, EchoServerTrait};
 pub use datagram::{DatagramConfig, DatagramEchoClient, DatagramEchoServer};
+pub use handler::{DatagramHandler, EchoHandler};
 pub use http::{HttpConfig, HttpEchoClient, HttpEchoServer, HttpProtocol};
 pub use network::Address;
+pub use proxy::{ProxyConfig, ProxyServer, TcpProxyServer};
 pub use stream::{Client as StreamClient, StreamConfig, StreamEchoServer};
 pub use tcp::{TcpConfig, TcpEchoClient, TcpEchoServer};
 pub use udp::{UdpConfig, UdpEchoClient, UdpEchoServer};
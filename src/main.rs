@@ -1,175 +1,301 @@
-use color_eyre::eyre::{Result, WrapErr};
-use echosrv::http::{HttpConfig, HttpEchoServer};
-use echosrv::tcp::TcpConfig;
-use echosrv::udp::UdpConfig;
-use echosrv::unix::{UnixDatagramConfig, UnixStreamConfig};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use color_eyre::eyre::{Result, WrapErr, eyre};
+use echosrv::datagram::{DatagramConfig, DatagramEchoServer, DatagramProtocol, TwampReflector};
+#[cfg(feature = "dtls")]
+use echosrv::dtls::DtlsEchoServer;
+use echosrv::fault::{ChaosAdmin, ChaosController, FaultConfig, Faulty};
+use echosrv::http::{HttpEchoServer, HttpProtocol};
+use echosrv::proxy::{ProxyAdmin, ProxyServer, TcpProxyServer, Toxics};
+use echosrv::stream::{StreamConfig, StreamEchoServer, StreamProtocol};
+use echosrv::tcp::TcpProtocol;
+use echosrv::udp::UdpProtocol;
+use echosrv::unix::UnixStreamProtocol;
+use echosrv::uring::{self, Backend};
+#[cfg(target_os = "linux")]
+use echosrv::uring::{UringTcpEchoServer, UringUdpEchoServer};
 use echosrv::{
-    EchoServerTrait, TcpEchoServer, UdpEchoServer, UnixDatagramEchoServer, UnixStreamEchoServer,
+    EchoError, EchoServerTrait, TcpEchoServer, UdpEchoServer, UnixDatagramEchoServer,
+    UnixStreamEchoServer,
 };
-use std::time::Duration;
+use std::net::SocketAddr;
+use tokio::task::JoinSet;
 
-use tracing::info;
+use tracing::{info, warn};
+
+mod cli;
+
+use cli::{Cli, Command, ServiceArgs, UdpArgs, attach_chaos};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize error handling
     color_eyre::install()?;
 
+    // Parse command line arguments (falls back to ECHOSRV_* environment variables)
+    let cli = Cli::parse();
+
     // Initialize logging
     tracing_subscriber::fmt()
-        .with_env_filter("echosrv=info")
+        .with_env_filter(cli.log_filter.as_str())
         .init();
 
-    // Parse command line arguments
-    let args: Vec<String> = std::env::args().collect();
-
-    // Default to TCP if no protocol specified
-    let protocol = args
-        .get(1)
-        .map(|s| s.to_lowercase())
-        .unwrap_or_else(|| "tcp".to_string());
-
-    // For Unix domain sockets, the second argument is the socket path
-    // For TCP/UDP, it's the port
-    let socket_path_or_port = args.get(2);
-
-    match protocol.as_str() {
-        "http" => {
-            let port = socket_path_or_port
-                .and_then(|p| p.parse::<u16>().ok())
-                .unwrap_or(8080);
-
-            let config = HttpConfig {
-                bind_addr: format!("127.0.0.1:{port}").parse().unwrap(),
-                max_connections: 1000, // Higher limit for production use
-                buffer_size: 8192,     // Larger buffer for HTTP
-                read_timeout: Duration::from_secs(30),
-                write_timeout: Duration::from_secs(30),
-                server_name: Some("EchoServer/1.0".to_string()),
-                echo_headers: true,
-                default_content_type: Some("text/plain".to_string()),
-            };
-            let server = HttpEchoServer::new(config.clone().into());
-            info!(address = %config.bind_addr, max_connections = config.max_connections, "Starting HTTP echo server");
-            server
-                .run()
-                .await
-                .wrap_err("Failed to run HTTP echo server")?;
+    // Every listening endpoint runs as its own server task
+    let mut servers = JoinSet::new();
+
+    match cli.command.unwrap_or_default() {
+        Command::Http(args) => {
+            let chaos = args.chaos().unwrap_or_else(|e| usage_error(&e));
+            start_chaos(chaos.as_ref(), args.chaos.chaos_admin).await?;
+            for endpoint in args.configs().unwrap_or_else(|e| usage_error(&e)) {
+                let mut config = StreamConfig {
+                    faults: endpoint.faults,
+                    ..endpoint.config.into()
+                };
+                info!(addresses = ?config.addresses(), max_connections = config.max_connections, "Starting HTTP echo server");
+                attach_chaos(&mut config.faults, chaos.as_ref());
+                // Stream faults are applied by the server itself; only shaping needs the wrapper
+                if config
+                    .faults
+                    .as_ref()
+                    .is_some_and(FaultConfig::may_shape_traffic)
+                {
+                    let server = StreamEchoServer::<Faulty<HttpProtocol>>::new(config);
+                    spawn_server(&mut servers, server, "HTTP echo server");
+                } else {
+                    let server = HttpEchoServer::new(config);
+                    spawn_server(&mut servers, server, "HTTP echo server");
+                }
+            }
         }
-        "tcp" => {
-            let port = socket_path_or_port
-                .and_then(|p| p.parse::<u16>().ok())
-                .unwrap_or(8080);
-
-            let config = TcpConfig {
-                bind_addr: format!("127.0.0.1:{port}").parse().unwrap(),
-                max_connections: 1000, // Higher limit for production use
-                buffer_size: 1024,
-                read_timeout: Duration::from_secs(30),
-                write_timeout: Duration::from_secs(30),
-            };
-
-            info!(address = %config.bind_addr, max_connections = config.max_connections, "Starting TCP echo server");
-
-            let server = TcpEchoServer::new(config.into());
-            server
-                .run()
-                .await
-                .wrap_err("Failed to run TCP echo server")?;
+        Command::Tcp(args) => {
+            let chaos = args.chaos().unwrap_or_else(|e| usage_error(&e));
+            start_chaos(chaos.as_ref(), args.chaos.chaos_admin).await?;
+            let backend = resolve_backend(args.backend.backend);
+            for endpoint in args.configs().unwrap_or_else(|e| usage_error(&e)) {
+                let mut config = StreamConfig {
+                    faults: endpoint.faults,
+                    ..endpoint.config.into()
+                };
+                info!(addresses = ?config.addresses(), max_connections = config.max_connections, service = ?args.service.service, whoami = ?args.service.whoami, backend = backend.name(), "Starting TCP echo server");
+                attach_chaos(&mut config.faults, chaos.as_ref());
+                #[cfg(target_os = "linux")]
+                if backend == Backend::IoUring {
+                    if config.faults.is_some() || config.framing.is_some() {
+                        usage_error(
+                            "--backend io-uring supports neither fault injection nor framing",
+                        );
+                    }
+                    let server = UringTcpEchoServer::new(config);
+                    let server = match (args.service.service, args.service.whoami) {
+                        (Some(service), _) => server.with_handler(service),
+                        (None, Some(whoami)) => server.with_handler(whoami),
+                        (None, None) => server,
+                    };
+                    spawn_server(&mut servers, server, "TCP echo server");
+                    continue;
+                }
+                // Stream faults are applied by the server itself; only shaping needs the wrapper
+                if config
+                    .faults
+                    .as_ref()
+                    .is_some_and(FaultConfig::may_shape_traffic)
+                {
+                    let server = StreamEchoServer::<Faulty<TcpProtocol>>::new(config);
+                    let server = with_stream_service(server, &args.service);
+                    spawn_server(&mut servers, server, "TCP echo server");
+                } else {
+                    let server = with_stream_service(TcpEchoServer::new(config), &args.service);
+                    spawn_server(&mut servers, server, "TCP echo server");
+                }
+            }
         }
-        "udp" => {
-            let port = socket_path_or_port
-                .and_then(|p| p.parse::<u16>().ok())
-                .unwrap_or(8080);
-
-            let config = UdpConfig {
-                bind_addr: format!("127.0.0.1:{port}").parse().unwrap(),
-                buffer_size: 1024,
-                read_timeout: Duration::from_secs(30),
-                write_timeout: Duration::from_secs(30),
-            };
-
-            info!(address = %config.bind_addr, "Starting UDP echo server");
-
-            let server = UdpEchoServer::new(config.into());
-            server
-                .run()
-                .await
-                .wrap_err("Failed to run UDP echo server")?;
+        Command::Udp(args) => {
+            let chaos = args.chaos().unwrap_or_else(|e| usage_error(&e));
+            start_chaos(chaos.as_ref(), args.chaos.chaos_admin).await?;
+            let backend = resolve_backend(args.backend.backend);
+            for endpoint in args.configs().unwrap_or_else(|e| usage_error(&e)) {
+                let mut config = DatagramConfig {
+                    faults: endpoint.faults,
+                    ..endpoint.config.into()
+                };
+                info!(addresses = ?config.addresses(), service = ?args.service.service, whoami = ?args.service.whoami, twamp = args.twamp, backend = backend.name(), "Starting UDP echo server");
+                attach_chaos(&mut config.faults, chaos.as_ref());
+                #[cfg(target_os = "linux")]
+                if backend == Backend::IoUring {
+                    if config.faults.is_some() {
+                        usage_error("--backend io-uring does not support fault injection");
+                    }
+                    let server = UringUdpEchoServer::new(config);
+                    let server = match (args.service.service, args.service.whoami) {
+                        (Some(service), _) => server.with_handler(service),
+                        (None, Some(whoami)) => server.with_handler(whoami),
+                        (None, None) if args.twamp => server.with_handler(TwampReflector::new()),
+                        (None, None) => server,
+                    };
+                    spawn_server(&mut servers, server, "UDP echo server");
+                    continue;
+                }
+                // Impairments are applied by the server itself; only shaping needs the wrapper
+                if config
+                    .faults
+                    .as_ref()
+                    .is_some_and(FaultConfig::may_shape_traffic)
+                {
+                    let server = DatagramEchoServer::<Faulty<UdpProtocol>>::new(config);
+                    let server = with_udp_handler(server, &args);
+                    spawn_server(&mut servers, server, "UDP echo server");
+                } else {
+                    let server = with_udp_handler(UdpEchoServer::new(config), &args);
+                    spawn_server(&mut servers, server, "UDP echo server");
+                }
+            }
         }
-        "unix-stream" => {
-            let socket_path: std::path::PathBuf = socket_path_or_port
-                .map(|p| p.into())
-                .unwrap_or_else(|| "/tmp/echosrv_stream.sock".into());
-
-            let config = UnixStreamConfig::default()
-                .with_socket_path(socket_path.clone());
+        Command::UnixStream(args) => {
+            let chaos = args.chaos().unwrap_or_else(|e| usage_error(&e));
+            start_chaos(chaos.as_ref(), args.chaos.chaos_admin).await?;
+            for mut config in args.configs() {
+                attach_chaos(&mut config.faults, chaos.as_ref());
+                info!(strategy = ?config.bind_strategy, max_connections = config.max_connections, "Starting Unix domain stream echo server");
+                let server = UnixStreamEchoServer::new(config);
+                spawn_server(&mut servers, server, "Unix domain stream echo server");
+            }
+        }
+        Command::UnixDgram(args) => {
+            let chaos = args.chaos().unwrap_or_else(|e| usage_error(&e));
+            start_chaos(chaos.as_ref(), args.chaos.chaos_admin).await?;
+            for mut config in args.configs() {
+                attach_chaos(&mut config.faults, chaos.as_ref());
+                info!(strategy = ?config.bind_strategy, "Starting Unix domain datagram echo server");
+                let server = UnixDatagramEchoServer::new(config);
+                spawn_server(&mut servers, server, "Unix domain datagram echo server");
+            }
+        }
+        #[cfg(feature = "dtls")]
+        Command::Dtls(args) => {
+            let dtls = args.dtls().unwrap_or_else(|e| usage_error(&e));
+            for config in args.configs().unwrap_or_else(|e| usage_error(&e)) {
+                info!(addresses = ?config.addresses(), credentials = ?dtls.credentials, cookie_exchange = dtls.cookie_exchange, "Starting DTLS echo server");
+                let server = DtlsEchoServer::new(config, dtls.clone());
+                spawn_server(&mut servers, server, "DTLS echo server");
+            }
+        }
+        Command::Proxy(args) => {
+            let configs = args.configs().unwrap_or_else(|e| usage_error(&e));
+            if let (Some(addr), Some(config)) = (args.proxy_admin, configs.first()) {
+                start_proxy_admin(addr, config.toxics.clone()).await?;
+            }
+            for config in configs {
+                info!(address = %config.bind_addr, upstream = %config.upstream, "Starting TCP proxy");
+                if config.upstream.is_unix() {
+                    let server = ProxyServer::<TcpProtocol, UnixStreamProtocol>::new(config);
+                    spawn_server(&mut servers, server, "TCP proxy");
+                } else {
+                    let server = TcpProxyServer::new(config);
+                    spawn_server(&mut servers, server, "TCP proxy");
+                }
+            }
+        }
+    }
 
-            info!(socket_path = %socket_path.display(), max_connections = config.max_connections, "Starting Unix domain stream echo server");
+    // Stop at the first server that fails; the others shut down on Ctrl+C
+    while let Some(result) = servers.join_next().await {
+        result.map_err(|e| eyre!("Server task failed: {e}"))??;
+    }
 
-            let server = UnixStreamEchoServer::new(config);
-            server
-                .run()
-                .await
-                .wrap_err("Failed to run Unix domain stream echo server")?;
-        }
-        "unix-dgram" => {
-            let socket_path: std::path::PathBuf = socket_path_or_port
-                .map(|p| p.into())
-                .unwrap_or_else(|| "/tmp/echosrv_datagram.sock".into());
+    Ok(())
+}
 
-            let config = UnixDatagramConfig::default()
-                .with_socket_path(socket_path.clone());
+/// Reports an invalid flag combination the way clap does and exits
+fn usage_error(message: &str) -> ! {
+    Cli::command()
+        .error(ErrorKind::ArgumentConflict, message)
+        .exit()
+}
 
-            info!(socket_path = %socket_path.display(), "Starting Unix domain datagram echo server");
+/// Drives the chaos timeline and its admin endpoint in the background
+///
+/// Both stop with the process, so they are not part of the server join set.
+async fn start_chaos(chaos: Option<&ChaosController>, admin: Option<SocketAddr>) -> Result<()> {
+    let Some(chaos) = chaos else {
+        return Ok(());
+    };
+    if let Some(addr) = admin {
+        let admin = ChaosAdmin::bind(addr, chaos.clone())
+            .await
+            .wrap_err_with(|| format!("Failed to bind chaos admin endpoint on {addr}"))?;
+        tokio::spawn(async move {
+            if let Err(e) = admin.run().await {
+                tracing::error!(error = %e, "Chaos admin endpoint failed");
+            }
+        });
+    }
+    let chaos = chaos.clone();
+    tokio::spawn(async move { chaos.run().await });
+    Ok(())
+}
 
-            let server = UnixDatagramEchoServer::new(config);
-            server
-                .run()
-                .await
-                .wrap_err("Failed to run Unix domain datagram echo server")?;
+/// Serves the proxy's toxic admin endpoint in the background
+async fn start_proxy_admin(addr: SocketAddr, toxics: Toxics) -> Result<()> {
+    let admin = ProxyAdmin::bind(addr, toxics)
+        .await
+        .wrap_err_with(|| format!("Failed to bind proxy admin endpoint on {addr}"))?;
+    tokio::spawn(async move {
+        if let Err(e) = admin.run().await {
+            tracing::error!(error = %e, "Proxy admin endpoint failed");
         }
-        _ => {
-            eprintln!(
-                "Usage: {} [http|tcp|udp|unix-stream|unix-dgram] [port|socket_path]",
-                args[0]
-            );
-            eprintln!("  http|tcp|udp|unix-stream|unix-dgram: Protocol to use (default: tcp)");
-            eprintln!("  port:    Port to bind to for HTTP/TCP/UDP (default: 8080)");
-            eprintln!("  socket_path: Unix domain socket path (default: /tmp/echosrv_*.sock)");
-            eprintln!();
-            eprintln!("Examples:");
-            eprintln!(
-                "  {} http 8080                   # Start HTTP echo server on port 8080",
-                args[0]
-            );
-            eprintln!(
-                "  {} tcp 8080                    # Start TCP echo server on port 8080",
-                args[0]
-            );
-            eprintln!(
-                "  {} udp 9090                    # Start UDP echo server on port 9090",
-                args[0]
-            );
-            eprintln!(
-                "  {} unix-stream /tmp/echo.sock   # Start Unix stream server",
-                args[0]
-            );
-            eprintln!(
-                "  {} unix-dgram /tmp/echo.sock    # Start Unix datagram server",
-                args[0]
-            );
-            eprintln!(
-                "  {} http                        # Start HTTP echo server on default port 8080",
-                args[0]
-            );
-            eprintln!(
-                "  {} tcp                         # Start TCP echo server on default port 8080",
-                args[0]
-            );
-            std::process::exit(1);
+    });
+    Ok(())
+}
+
+/// Returns the selected backend, or Tokio if this kernel cannot run it
+fn resolve_backend(backend: Option<Backend>) -> Backend {
+    match backend.unwrap_or_default() {
+        Backend::IoUring if !uring::is_supported() => {
+            warn!("io_uring is not available on this kernel, falling back to the tokio backend");
+            Backend::Tokio
         }
+        backend => backend,
+    }
+}
+
+/// Replaces the echo with the service or address reply `args` select
+fn with_stream_service<P>(server: StreamEchoServer<P>, args: &ServiceArgs) -> StreamEchoServer<P>
+where
+    P: StreamProtocol,
+    P::Error: Into<EchoError> + std::fmt::Display,
+{
+    match (args.service, args.whoami) {
+        (Some(service), _) => server.with_handler(service),
+        (None, Some(whoami)) => server.with_handler(whoami),
+        (None, None) => server,
     }
+}
 
-    Ok(())
+/// Replaces the echo with the service, address reply or TWAMP reflector
+/// `args` select
+fn with_udp_handler<P>(server: DatagramEchoServer<P>, args: &UdpArgs) -> DatagramEchoServer<P>
+where
+    P: DatagramProtocol,
+    P::Error: Into<EchoError> + std::fmt::Display,
+{
+    match (args.service.service, args.service.whoami) {
+        (Some(service), _) => server.with_handler(service),
+        (None, Some(whoami)) => server.with_handler(whoami),
+        (None, None) if args.twamp => server.with_handler(TwampReflector::new()),
+        (None, None) => server,
+    }
+}
+
+/// Runs `server` on the given join set, wrapping errors with `name`
+fn spawn_server<S>(servers: &mut JoinSet<Result<()>>, server: S, name: &'static str)
+where
+    S: EchoServerTrait + Send + Sync + 'static,
+{
+    servers.spawn(async move {
+        server
+            .run()
+            .await
+            .wrap_err_with(|| format!("Failed to run {name}"))
+    });
 }
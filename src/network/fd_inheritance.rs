@@ -98,13 +98,13 @@ impl FdInheritanceConfig {
 
         // Validate that FDs are intended for this process
         // systemd sets LISTEN_PID to the target process PID
-        if let Ok(listen_pid) = std::env::var("LISTEN_PID") {
-            if let Ok(expected_pid) = listen_pid.parse::<u32>() {
-                let current_pid = std::process::id();
-                if current_pid != expected_pid {
-                    // FDs not intended for this process - ignore them
-                    return Ok(config);
-                }
+        if let Ok(listen_pid) = std::env::var("LISTEN_PID")
+            && let Ok(expected_pid) = listen_pid.parse::<u32>()
+        {
+            let current_pid = std::process::id();
+            if current_pid != expected_pid {
+                // FDs not intended for this process - ignore them
+                return Ok(config);
             }
         }
 
@@ -163,7 +163,6 @@ impl FdInheritanceConfig {
 /// when the inheritance setup is incorrect.
 pub mod validation {
     use super::*;
-    use std::os::unix::io::AsRawFd;
 
     /// Validate that a file descriptor is a socket of the expected type
     /// 
@@ -3,9 +3,13 @@
 pub mod address;
 pub mod config;
 pub mod fd_inheritance;
+pub mod multicast;
 pub mod socket_builder;
+pub mod socket_options;
 
 pub use address::Address;
 pub use config::{Config, StreamConfig};
 pub use fd_inheritance::{BindStrategy, BindTarget, FdInheritanceConfig};
+pub use multicast::{MulticastConfig, MulticastGroup};
 pub use socket_builder::{BuildSocket, SocketBuilder, SocketSource};
+pub use socket_options::{Keepalive, SocketOptions};
@@ -0,0 +1,241 @@
+use socket2::{Domain, InterfaceIndexOrAddress, Socket};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+/// A multicast group joined on one interface
+///
+/// Parses from `GROUP[%IFACE]`, where the interface is a name such as `lo`
+/// or an index; without one, the system picks the interface.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::network::MulticastGroup;
+///
+/// let group: MulticastGroup = "239.1.2.3%1".parse().unwrap();
+/// assert_eq!(group.addr, "239.1.2.3".parse::<std::net::IpAddr>().unwrap());
+/// assert_eq!(group.interface, Some(1));
+/// assert!("10.0.0.1".parse::<MulticastGroup>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MulticastGroup {
+    /// Address of the group
+    pub addr: IpAddr,
+    /// Index of the interface to join on; the system's choice if `None`
+    pub interface: Option<u32>,
+}
+
+impl MulticastGroup {
+    /// Joins `addr` on the interface the system picks
+    pub fn new(addr: IpAddr) -> Self {
+        Self {
+            addr,
+            interface: None,
+        }
+    }
+
+    /// Joins on the interface with index `interface`
+    pub fn on_interface(mut self, interface: u32) -> Self {
+        self.interface = Some(interface);
+        self
+    }
+
+    fn join(&self, socket: &Socket) -> io::Result<()> {
+        match self.addr {
+            IpAddr::V4(addr) => match self.interface {
+                Some(index) => {
+                    socket.join_multicast_v4_n(&addr, &InterfaceIndexOrAddress::Index(index))
+                }
+                None => socket.join_multicast_v4(&addr, &Ipv4Addr::UNSPECIFIED),
+            },
+            IpAddr::V6(addr) => socket.join_multicast_v6(&addr, self.interface.unwrap_or(0)),
+        }
+    }
+}
+
+impl FromStr for MulticastGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, interface) = match s.rsplit_once('%') {
+            Some((addr, interface)) => (addr, Some(interface_index(interface)?)),
+            None => (s, None),
+        };
+        let addr = addr
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map_err(|e| format!("invalid multicast group '{addr}': {e}"))?;
+        if !addr.is_multicast() {
+            return Err(format!("'{addr}' is not a multicast address"));
+        }
+        Ok(Self { addr, interface })
+    }
+}
+
+impl fmt::Display for MulticastGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.interface {
+            Some(interface) => write!(f, "{}%{interface}", self.addr),
+            None => write!(f, "{}", self.addr),
+        }
+    }
+}
+
+/// Resolves an interface name or index to its index
+fn interface_index(interface: &str) -> Result<u32, String> {
+    if let Ok(index) = interface.parse() {
+        return Ok(index);
+    }
+    let name = std::ffi::CString::new(interface)
+        .map_err(|_| format!("invalid interface name '{interface}'"))?;
+    // SAFETY: the name is a valid C string that outlives the call
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(format!("unknown interface '{interface}'")),
+        index => Ok(index),
+    }
+}
+
+/// Multicast groups a UDP server joins, and how it sends to groups
+///
+/// Group traffic is echoed back to its unicast sender like any other
+/// datagram. Sockets must be bound to a wildcard address (or the group's)
+/// on the port the group traffic is sent to.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::network::{MulticastConfig, MulticastGroup};
+/// use echosrv::udp::UdpConfig;
+///
+/// let config = UdpConfig {
+///     bind_addr: "0.0.0.0:5353".parse().unwrap(),
+///     multicast: Some(
+///         MulticastConfig::new(vec!["239.1.2.3%lo".parse().unwrap()])
+///             .with_ttl(1)
+///             .with_loopback(true),
+///     ),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MulticastConfig {
+    /// Groups to join
+    pub groups: Vec<MulticastGroup>,
+    /// Time to live of datagrams sent to groups (IP_MULTICAST_TTL, or
+    /// IPV6_MULTICAST_HOPS on IPv6); the system default if `None`
+    pub ttl: Option<u32>,
+    /// Whether datagrams sent to groups loop back to local members
+    /// (IP_MULTICAST_LOOP, or IPV6_MULTICAST_LOOP on IPv6); the system
+    /// default if `None`
+    pub loopback: Option<bool>,
+}
+
+impl MulticastConfig {
+    /// Joins `groups` with the system's TTL and loopback
+    pub fn new(groups: Vec<MulticastGroup>) -> Self {
+        Self {
+            groups,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the time to live of datagrams sent to groups
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Sets whether datagrams sent to groups loop back to local members
+    pub fn with_loopback(mut self, loopback: bool) -> Self {
+        self.loopback = Some(loopback);
+        self
+    }
+
+    /// Applies the TTL and loopback to `socket`, and joins the groups if
+    /// `join` is set
+    ///
+    /// On Linux, the socket only receives traffic of groups it joined
+    /// itself (IP_MULTICAST_ALL off), so of several sockets sharing a port
+    /// only those that join see group traffic.
+    pub(crate) fn apply(&self, socket: &Socket, join: bool) -> io::Result<()> {
+        let ipv6 = socket.domain()? == Domain::IPV6;
+        #[cfg(target_os = "linux")]
+        {
+            socket.set_multicast_all_v4(false)?;
+            if ipv6 {
+                socket.set_multicast_all_v6(false)?;
+            }
+        }
+        if let Some(ttl) = self.ttl {
+            if ipv6 {
+                socket.set_multicast_hops_v6(ttl)?;
+            } else {
+                socket.set_multicast_ttl_v4(ttl)?;
+            }
+        }
+        if let Some(loopback) = self.loopback {
+            if ipv6 {
+                socket.set_multicast_loop_v6(loopback)?;
+            } else {
+                socket.set_multicast_loop_v4(loopback)?;
+            }
+        }
+        if join {
+            for group in &self.groups {
+                group.join(socket).map_err(|e| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("cannot join multicast group {group}: {e}"),
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socket2::{Protocol, Type};
+
+    #[test]
+    fn test_parse_group() {
+        let group: MulticastGroup = "ff02::1234%lo".parse().unwrap();
+        assert_eq!(group.addr, "ff02::1234".parse::<IpAddr>().unwrap());
+        assert!(group.interface.is_some());
+        assert_eq!(
+            "[ff02::1]".parse::<MulticastGroup>().unwrap(),
+            MulticastGroup::new("ff02::1".parse().unwrap())
+        );
+        assert_eq!(
+            "239.1.2.3%7".parse::<MulticastGroup>().unwrap().to_string(),
+            "239.1.2.3%7"
+        );
+        assert!(
+            "239.1.2.3%no-such-interface"
+                .parse::<MulticastGroup>()
+                .is_err()
+        );
+        assert!("::1".parse::<MulticastGroup>().is_err());
+    }
+
+    #[test]
+    fn test_apply_multicast() {
+        let config = MulticastConfig::new(vec!["239.1.2.3%lo".parse().unwrap()])
+            .with_ttl(3)
+            .with_loopback(false);
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+        config.apply(&socket, true).unwrap();
+        assert_eq!(socket.multicast_ttl_v4().unwrap(), 3);
+        assert!(!socket.multicast_loop_v4().unwrap());
+
+        // A group of the other family cannot be joined
+        let config = MulticastConfig::new(vec!["ff02::1".parse().unwrap()]);
+        assert!(config.apply(&socket, true).is_err());
+        config.apply(&socket, false).unwrap();
+    }
+}
@@ -14,11 +14,12 @@
 // while maintaining type safety and clear error messages.
 
 use crate::network::fd_inheritance::{validation, FdInheritanceConfig, BindStrategy};
+use crate::network::socket_options::SocketOptions;
 
 // Re-export types that builders need
 pub use crate::network::fd_inheritance::BindTarget;
 use crate::{EchoError, Result};
-use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::io::RawFd;
 
 /// Generic socket builder that handles FD inheritance logic
 /// 
@@ -168,6 +169,40 @@ pub trait BuildSocket<T> {
         }
     }
     
+    /// Apply socket options to a bound or inherited socket
+    /// 
+    /// Protocols without configurable options keep the default, which leaves
+    /// the socket as it is.
+    /// 
+    /// # Arguments
+    /// * `socket` - Socket returned by from_fd() or bind_to()
+    /// * `options` - Options to set; unset options keep their current values
+    fn configure(_socket: &T, _options: &SocketOptions) -> Result<()> {
+        Ok(())
+    }
+    
+    /// Create a socket like build(), then apply socket options to it
+    /// 
+    /// Inherited sockets get the same options as freshly bound ones, so a
+    /// server behaves the same whether it was started by a process manager
+    /// or on its own.
+    /// 
+    /// # Arguments
+    /// * `strategy` - How to create the socket (bind, inherit, or fallback)
+    /// * `service_name` - Service name for FD lookup in inheritance config
+    /// * `fd_config` - Configuration with inherited FDs from parent process
+    /// * `options` - Socket options to apply to the created socket
+    fn build_with_options(
+        strategy: &BindStrategy,
+        service_name: &str,
+        fd_config: &FdInheritanceConfig,
+        options: &SocketOptions,
+    ) -> Result<T> {
+        let socket = Self::build(strategy, service_name, fd_config)?;
+        Self::configure(&socket, options)?;
+        Ok(socket)
+    }
+    
     /// Validate inherited file descriptor matches socket requirements
     /// 
     /// This method performs comprehensive validation of inherited FDs:
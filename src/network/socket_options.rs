@@ -0,0 +1,392 @@
+use socket2::{Domain, Socket, TcpKeepalive};
+use std::io;
+use std::time::Duration;
+
+/// Listen backlog used when [`SocketOptions::backlog`] is not set, the same
+/// as Tokio's `TcpListener::bind`
+pub const DEFAULT_BACKLOG: u32 = 1024;
+
+/// Keepalive probing of idle TCP connections
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::network::Keepalive;
+/// use std::time::Duration;
+///
+/// let keepalive = Keepalive::new(Duration::from_secs(60))
+///     .with_interval(Duration::from_secs(10))
+///     .with_retries(5);
+/// assert_eq!(keepalive.retries, Some(5));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// Idle time before the first probe
+    pub idle: Duration,
+    /// Time between unanswered probes; the system default if `None`
+    pub interval: Option<Duration>,
+    /// Unanswered probes before the connection is dropped; the system
+    /// default if `None`
+    pub retries: Option<u32>,
+}
+
+impl Keepalive {
+    /// Probes connections idle for `idle`, with the system's interval and
+    /// retry count
+    pub fn new(idle: Duration) -> Self {
+        Self {
+            idle,
+            interval: None,
+            retries: None,
+        }
+    }
+
+    /// Sets the time between unanswered probes
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Sets how many unanswered probes drop the connection
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+}
+
+/// Socket options applied to listeners, accepted streams and datagram sockets
+///
+/// Every option left at `None` keeps the system default. Stream servers set
+/// the buffer sizes, type of service, TTL, IPv6-only flag, Fast Open queue
+/// and backlog on their listeners when binding or adopting an inherited
+/// socket, and the remaining options on every accepted stream; accepted
+/// streams inherit the listener's buffer sizes, type of service and TTL.
+/// Datagram servers set the buffer sizes, type of service, TTL, IPv6-only
+/// flag and broadcast permission on their sockets.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::network::{Keepalive, SocketOptions};
+/// use echosrv::tcp::TcpConfig;
+/// use std::time::Duration;
+///
+/// let config = TcpConfig {
+///     socket_options: SocketOptions {
+///         nodelay: Some(true),
+///         keepalive: Some(Keepalive::new(Duration::from_secs(60))),
+///         recv_buffer_size: Some(256 * 1024),
+///         ..Default::default()
+///     }
+///     .with_dscp(46),
+///     ..Default::default()
+/// };
+/// assert_eq!(config.socket_options.tos, Some(184));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Send small writes without waiting to coalesce them (TCP_NODELAY)
+    pub nodelay: Option<bool>,
+    /// Probe idle connections (SO_KEEPALIVE and TCP_KEEPIDLE, TCP_KEEPINTVL
+    /// and TCP_KEEPCNT)
+    pub keepalive: Option<Keepalive>,
+    /// Kernel receive buffer size in bytes (SO_RCVBUF)
+    pub recv_buffer_size: Option<usize>,
+    /// Kernel send buffer size in bytes (SO_SNDBUF)
+    pub send_buffer_size: Option<usize>,
+    /// Type of service byte of sent packets (IP_TOS, or IPV6_TCLASS on IPv6)
+    pub tos: Option<u32>,
+    /// Time to live of sent packets (IP_TTL, or IPV6_UNICAST_HOPS on IPv6)
+    pub ttl: Option<u32>,
+    /// How long closing a stream waits to send queued data (SO_LINGER); zero
+    /// resets the connection instead
+    pub linger: Option<Duration>,
+    /// How long sent data may go unacknowledged before the connection is
+    /// dropped (TCP_USER_TIMEOUT, Linux only)
+    pub user_timeout: Option<Duration>,
+    /// Pending TCP Fast Open connections a listener queues (TCP_FASTOPEN,
+    /// Linux only)
+    pub fastopen: Option<u32>,
+    /// Connections a listener queues before they are accepted; see
+    /// [`DEFAULT_BACKLOG`]
+    pub backlog: Option<u32>,
+    /// Whether an IPv6 socket serves IPv6 only (IPV6_V6ONLY); `Some(false)`
+    /// lets a socket bound to `[::]` serve IPv4 clients too, as mapped
+    /// addresses. Ignored for IPv4 sockets
+    pub only_v6: Option<bool>,
+    /// Whether a datagram socket may send and receive broadcasts
+    /// (SO_BROADCAST). Some systems deliver broadcasts to sockets bound to
+    /// a wildcard address regardless, and every socket sharing a port gets
+    /// its own copy
+    pub broadcast: Option<bool>,
+    /// Whether a datagram socket reports when the kernel received each
+    /// datagram (SO_TIMESTAMPING with software receive timestamps, Linux
+    /// only). The kernel starts stamping shortly after the first socket
+    /// asks, so the first datagrams may arrive without a timestamp
+    pub timestamping: Option<bool>,
+    /// Whether a datagram socket reports the TTL or hop limit each datagram
+    /// arrived with (IP_RECVTTL, and IPV6_RECVHOPLIMIT on IPv6)
+    pub recv_ttl: Option<bool>,
+}
+
+impl SocketOptions {
+    /// Sets the type of service byte from a DSCP code point (0 to 63),
+    /// leaving the ECN bits clear
+    pub fn with_dscp(mut self, dscp: u8) -> Self {
+        self.tos = Some(u32::from(dscp & 0x3f) << 2);
+        self
+    }
+
+    /// Returns the listen backlog to use
+    pub fn backlog(&self) -> i32 {
+        self.backlog
+            .unwrap_or(DEFAULT_BACKLOG)
+            .try_into()
+            .unwrap_or(i32::MAX)
+    }
+
+    /// Applies the options of a listening socket: buffer sizes, type of
+    /// service, TTL, IPv6-only and the Fast Open queue
+    ///
+    /// The backlog takes effect when the caller calls `listen`.
+    pub(crate) fn apply_to_listener(&self, socket: &Socket) -> io::Result<()> {
+        self.apply_to_datagram(socket)?;
+        if let Some(queue) = self.fastopen {
+            set_fastopen(socket, queue)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the options of an accepted stream: no-delay, keepalive,
+    /// linger and the user timeout
+    pub(crate) fn apply_to_stream(&self, socket: &Socket) -> io::Result<()> {
+        if let Some(nodelay) = self.nodelay {
+            socket.set_nodelay(nodelay)?;
+        }
+        if let Some(keepalive) = self.keepalive {
+            let mut params = TcpKeepalive::new().with_time(keepalive.idle);
+            if let Some(interval) = keepalive.interval {
+                params = params.with_interval(interval);
+            }
+            if let Some(retries) = keepalive.retries {
+                params = params.with_retries(retries);
+            }
+            socket.set_tcp_keepalive(&params)?;
+        }
+        if let Some(linger) = self.linger {
+            socket.set_linger(Some(linger))?;
+        }
+        if let Some(timeout) = self.user_timeout {
+            set_user_timeout(socket, timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the options of a datagram socket: buffer sizes, type of
+    /// service, TTL, IPv6-only, broadcast and what is reported about
+    /// received datagrams
+    pub(crate) fn apply_to_datagram(&self, socket: &Socket) -> io::Result<()> {
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(broadcast) = self.broadcast {
+            socket.set_broadcast(broadcast)?;
+        }
+        if let Some(enable) = self.timestamping {
+            set_timestamping(socket, enable)?;
+        }
+        if self.tos.is_none()
+            && self.ttl.is_none()
+            && self.only_v6.is_none()
+            && self.recv_ttl.is_none()
+        {
+            return Ok(());
+        }
+        let ipv6 = socket.domain()? == Domain::IPV6;
+        // Bound sockets reject the option, so inherited ones are only
+        // changed if they differ
+        if let Some(only_v6) = self.only_v6
+            && ipv6
+            && socket.only_v6()? != only_v6
+        {
+            socket.set_only_v6(only_v6)?;
+        }
+        if let Some(tos) = self.tos {
+            if ipv6 {
+                socket.set_tclass_v6(tos)?;
+            } else {
+                socket.set_tos(tos)?;
+            }
+        }
+        if let Some(ttl) = self.ttl {
+            if ipv6 {
+                socket.set_unicast_hops_v6(ttl)?;
+            } else {
+                socket.set_ttl(ttl)?;
+            }
+        }
+        if let Some(enable) = self.recv_ttl {
+            // IPv4 datagrams reaching a dual-stack socket report their TTL
+            // through the IPv4 option
+            if ipv6 {
+                set_option(
+                    socket,
+                    libc::IPPROTO_IPV6,
+                    libc::IPV6_RECVHOPLIMIT,
+                    enable.into(),
+                )?;
+            }
+            if !ipv6 || !socket.only_v6()? {
+                set_option(socket, libc::IPPROTO_IP, libc::IP_RECVTTL, enable.into())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn set_option(
+    socket: &Socket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: the option value is a c_int that outlives the call
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            (&raw const value).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_timestamping(socket: &Socket, enable: bool) -> io::Result<()> {
+    let flags = if enable {
+        libc::SOF_TIMESTAMPING_RX_SOFTWARE | libc::SOF_TIMESTAMPING_SOFTWARE
+    } else {
+        0
+    };
+    set_option(
+        socket,
+        libc::SOL_SOCKET,
+        libc::SO_TIMESTAMPING,
+        flags as libc::c_int,
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_timestamping(_socket: &Socket, _enable: bool) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_TIMESTAMPING needs Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn set_user_timeout(socket: &Socket, timeout: Duration) -> io::Result<()> {
+    socket.set_tcp_user_timeout(Some(timeout))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_user_timeout(_socket: &Socket, _timeout: Duration) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "TCP_USER_TIMEOUT needs Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn set_fastopen(socket: &Socket, queue: u32) -> io::Result<()> {
+    let queue = libc::c_int::try_from(queue).unwrap_or(libc::c_int::MAX);
+    set_option(socket, libc::IPPROTO_TCP, libc::TCP_FASTOPEN, queue)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_fastopen(_socket: &Socket, _queue: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "TCP_FASTOPEN needs Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socket2::{Protocol, Type};
+
+    #[test]
+    fn test_dscp() {
+        assert_eq!(SocketOptions::default().with_dscp(46).tos, Some(0xb8));
+        assert_eq!(SocketOptions::default().with_dscp(0xff).tos, Some(0xfc));
+        assert_eq!(SocketOptions::default().backlog(), 1024);
+    }
+
+    #[test]
+    fn test_apply_options() {
+        let options = SocketOptions {
+            nodelay: Some(true),
+            keepalive: Some(
+                Keepalive::new(Duration::from_secs(30))
+                    .with_interval(Duration::from_secs(5))
+                    .with_retries(4),
+            ),
+            recv_buffer_size: Some(64 * 1024),
+            tos: Some(0x10),
+            ttl: Some(42),
+            linger: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)).unwrap();
+        options.apply_to_listener(&socket).unwrap();
+        options.apply_to_stream(&socket).unwrap();
+
+        assert!(socket.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(socket.tos().unwrap(), 0x10);
+        assert_eq!(socket.ttl().unwrap(), 42);
+        assert_eq!(socket.linger().unwrap(), Some(Duration::from_secs(1)));
+        // The kernel may round the buffer size, but never below the request
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+    }
+
+    #[test]
+    fn test_only_v6() {
+        let options = SocketOptions {
+            only_v6: Some(false),
+            ..Default::default()
+        };
+        let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+        socket.set_only_v6(true).unwrap();
+        options.apply_to_datagram(&socket).unwrap();
+        assert!(!socket.only_v6().unwrap());
+
+        // IPv4 sockets have no such option and are left alone
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+        options.apply_to_datagram(&socket).unwrap();
+    }
+
+    #[test]
+    fn test_arrival_reports() {
+        let options = SocketOptions {
+            timestamping: Some(true),
+            recv_ttl: Some(true),
+            ..Default::default()
+        };
+        for domain in [Domain::IPV4, Domain::IPV6] {
+            let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+            options.apply_to_datagram(&socket).unwrap();
+        }
+    }
+}
@@ -1,56 +1,191 @@
 use bytes::{Bytes, BytesMut};
-use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
+/// Size classes of the global pool, in addition to its 8 KiB default
+const GLOBAL_SIZE_CLASSES: [usize; 6] = [512, 1024, 2048, 4096, 16 * 1024, 64 * 1024];
+
 /// A pool of reusable buffers to reduce allocations
+///
+/// Buffers are grouped in size classes; a request is served from the
+/// smallest class that fits it. Each class keeps its free buffers in several
+/// shards, so threads returning and taking buffers rarely contend on the same
+/// lock. Requests larger than every class are allocated and never pooled.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::performance::BufferPool;
+///
+/// let pool = BufferPool::new(1024, 16).with_size_classes(&[4096, 65536]);
+/// let buffer = pool.get_with_capacity(3000);
+/// assert_eq!(buffer.capacity(), 4096);
+/// drop(buffer);
+///
+/// let _buffer = pool.get_with_capacity(3000);
+/// let stats = pool.stats();
+/// assert_eq!((stats.hits, stats.misses), (1, 1));
+/// ```
 #[derive(Debug)]
 pub struct BufferPool {
-    pool: Arc<Mutex<VecDeque<BytesMut>>>,
+    /// Size classes in ascending order
+    classes: Vec<Arc<SizeClass>>,
     buffer_size: usize,
     max_pool_size: usize,
+    oversized: AtomicU64,
 }
 
 impl BufferPool {
     /// Create a new buffer pool
+    ///
+    /// The pool starts with a single size class of `buffer_size` bytes, which
+    /// holds up to `max_pool_size` free buffers.
     pub fn new(buffer_size: usize, max_pool_size: usize) -> Self {
         Self {
-            pool: Arc::new(Mutex::new(VecDeque::with_capacity(max_pool_size))),
+            classes: vec![Arc::new(SizeClass::new(buffer_size, max_pool_size))],
             buffer_size,
             max_pool_size,
+            oversized: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds size classes, each holding up to the pool's maximum of free buffers
+    pub fn with_size_classes(mut self, sizes: &[usize]) -> Self {
+        for &size in sizes {
+            if self.classes.iter().all(|class| class.size != size) {
+                self.classes
+                    .push(Arc::new(SizeClass::new(size, self.max_pool_size)));
+            }
         }
+        self.classes.sort_by_key(|class| class.size);
+        self
     }
 
     /// Get a buffer from the pool or create a new one
+    ///
+    /// The buffer has at least the pool's default `buffer_size` capacity.
     pub fn get(&self) -> PooledBuffer {
-        let buffer = {
-            let mut pool = self.pool.lock().unwrap();
-            pool.pop_front()
-                .unwrap_or_else(|| BytesMut::with_capacity(self.buffer_size))
+        self.get_with_capacity(self.buffer_size)
+    }
+
+    /// Get an empty buffer with at least `capacity` bytes of capacity
+    pub fn get_with_capacity(&self, capacity: usize) -> PooledBuffer {
+        let Some(class) = self.classes.iter().find(|class| class.size >= capacity) else {
+            self.oversized.fetch_add(1, Ordering::Relaxed);
+            return PooledBuffer {
+                buffer: Some(BytesMut::with_capacity(capacity)),
+                class: None,
+            };
         };
 
         PooledBuffer {
-            buffer: Some(buffer),
-            pool: Arc::clone(&self.pool),
-            max_pool_size: self.max_pool_size,
+            buffer: Some(class.take()),
+            class: Some(Arc::clone(class)),
         }
     }
 
     /// Get pool statistics
     pub fn stats(&self) -> PoolStats {
-        let pool = self.pool.lock().unwrap();
+        let classes: Vec<SizeClassStats> = self.classes.iter().map(|c| c.stats()).collect();
         PoolStats {
-            available_buffers: pool.len(),
+            available_buffers: classes.iter().map(|c| c.available_buffers).sum(),
             buffer_size: self.buffer_size,
             max_pool_size: self.max_pool_size,
+            hits: classes.iter().map(|c| c.hits).sum(),
+            misses: classes.iter().map(|c| c.misses).sum(),
+            oversized: self.oversized.load(Ordering::Relaxed),
+            classes,
         }
     }
 }
 
+/// Free buffers of one size, spread across shards
+#[derive(Debug)]
+struct SizeClass {
+    size: usize,
+    max_buffers: usize,
+    shards: Box<[Mutex<Vec<BytesMut>>]>,
+    /// Free buffers across all shards, bounded by `max_buffers`
+    available: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl SizeClass {
+    fn new(size: usize, max_buffers: usize) -> Self {
+        let shards = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .clamp(1, 16);
+        Self {
+            size,
+            max_buffers,
+            shards: (0..shards).map(|_| Mutex::new(Vec::new())).collect(),
+            available: AtomicUsize::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Takes a free buffer, trying this thread's shard before the others
+    fn take(&self) -> BytesMut {
+        if self.available.load(Ordering::Acquire) > 0 {
+            let home = shard_index();
+            for i in 0..self.shards.len() {
+                let shard = &self.shards[(home + i) % self.shards.len()];
+                if let Some(buffer) = shard.lock().unwrap().pop() {
+                    self.available.fetch_sub(1, Ordering::AcqRel);
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return buffer;
+                }
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        BytesMut::with_capacity(self.size)
+    }
+
+    /// Returns a buffer to this thread's shard, unless the class is full
+    fn give(&self, mut buffer: BytesMut) {
+        // Buffers that were split or advanced may no longer fit the class
+        buffer.clear();
+        if buffer.capacity() < self.size {
+            return;
+        }
+        if self.available.fetch_add(1, Ordering::AcqRel) >= self.max_buffers {
+            self.available.fetch_sub(1, Ordering::AcqRel);
+            return;
+        }
+        self.shards[shard_index() % self.shards.len()]
+            .lock()
+            .unwrap()
+            .push(buffer);
+    }
+
+    fn stats(&self) -> SizeClassStats {
+        SizeClassStats {
+            buffer_size: self.size,
+            available_buffers: self.available.load(Ordering::Acquire),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Index of the shard the current thread prefers
+fn shard_index() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SHARD: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    SHARD.with(|shard| *shard)
+}
+
 /// A buffer that returns to the pool when dropped
+#[derive(Debug)]
 pub struct PooledBuffer {
     buffer: Option<BytesMut>,
-    pool: Arc<Mutex<VecDeque<BytesMut>>>,
-    max_pool_size: usize,
+    /// Size class to return the buffer to; `None` for oversized buffers
+    class: Option<Arc<SizeClass>>,
 }
 
 impl PooledBuffer {
@@ -91,19 +226,19 @@ impl PooledBuffer {
     pub fn capacity(&self) -> usize {
         self.buffer.as_ref().map_or(0, |b| b.capacity())
     }
+
+    /// Returns where `data` lies within the buffer, if it borrows from it
+    pub(crate) fn range_of(&self, data: &[u8]) -> Option<Range<usize>> {
+        let start = (data.as_ptr() as usize).checked_sub(self.as_ptr() as usize)?;
+        let end = start + data.len();
+        (end <= self.len()).then_some(start..end)
+    }
 }
 
 impl Drop for PooledBuffer {
     fn drop(&mut self) {
-        if let Some(mut buffer) = self.buffer.take() {
-            // Clear the buffer and return it to the pool if there's space
-            buffer.clear();
-
-            let mut pool = self.pool.lock().unwrap();
-            if pool.len() < self.max_pool_size {
-                pool.push_back(buffer);
-            }
-            // If pool is full, just drop the buffer
+        if let (Some(buffer), Some(class)) = (self.buffer.take(), &self.class) {
+            class.give(buffer);
         }
     }
 }
@@ -122,28 +257,78 @@ impl std::ops::DerefMut for PooledBuffer {
     }
 }
 
+/// Data queued for sending
+///
+/// A reply that borrows from a pooled read buffer keeps the buffer itself,
+/// which returns to the pool once the reply has been sent.
+#[derive(Debug)]
+pub(crate) enum Payload {
+    /// Part of a pooled buffer
+    Pooled(PooledBuffer, Range<usize>),
+    /// Bytes produced elsewhere, such as by a handler or codec
+    Shared(Bytes),
+}
+
+impl Payload {
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        match self {
+            Payload::Pooled(buffer, range) => &buffer[range.clone()],
+            Payload::Shared(data) => data,
+        }
+    }
+}
+
 /// Statistics about buffer pool usage
 #[derive(Debug, Clone)]
 pub struct PoolStats {
+    /// Free buffers across all size classes
     pub available_buffers: usize,
+    /// Capacity of buffers handed out by `get`
     pub buffer_size: usize,
+    /// Most free buffers kept per size class
     pub max_pool_size: usize,
+    /// Requests served with a pooled buffer
+    pub hits: u64,
+    /// Requests that allocated because their size class had no free buffer
+    pub misses: u64,
+    /// Requests larger than every size class, allocated and never pooled
+    pub oversized: u64,
+    /// Per size class breakdown, smallest first
+    pub classes: Vec<SizeClassStats>,
+}
+
+/// Statistics about one size class of a buffer pool
+#[derive(Debug, Clone)]
+pub struct SizeClassStats {
+    /// Capacity of the buffers in this class
+    pub buffer_size: usize,
+    /// Free buffers in this class
+    pub available_buffers: usize,
+    /// Requests served with a pooled buffer
+    pub hits: u64,
+    /// Requests that allocated because the class had no free buffer
+    pub misses: u64,
 }
 
 /// Global buffer pool for the application
 static GLOBAL_POOL: std::sync::OnceLock<BufferPool> = std::sync::OnceLock::new();
 
 /// Get the global buffer pool instance
+///
+/// Servers and clients draw their buffers from this pool. Unless initialized
+/// otherwise, `get` hands out 8 KiB buffers and requests are served from size
+/// classes between 512 bytes and 64 KiB, keeping up to 100 free buffers each.
 pub fn global_pool() -> &'static BufferPool {
-    GLOBAL_POOL.get_or_init(|| {
-        BufferPool::new(8192, 100) // 8KB buffers, up to 100 in pool
-    })
+    GLOBAL_POOL.get_or_init(|| BufferPool::new(8192, 100).with_size_classes(&GLOBAL_SIZE_CLASSES))
 }
 
 /// Initialize the global buffer pool with custom settings
+///
+/// `buffer_size` is added to the default size classes. Must be called before
+/// the pool is first used.
 pub fn init_global_pool(buffer_size: usize, max_pool_size: usize) -> Result<(), &'static str> {
     GLOBAL_POOL
-        .set(BufferPool::new(buffer_size, max_pool_size))
+        .set(BufferPool::new(buffer_size, max_pool_size).with_size_classes(&GLOBAL_SIZE_CLASSES))
         .map_err(|_| "Global pool already initialized")
 }
 
@@ -190,6 +375,7 @@ mod tests {
         let buffer = pool.get();
         assert!(buffer.is_empty());
         assert_eq!(buffer.capacity(), 1024);
+        assert_eq!((pool.stats().hits, pool.stats().misses), (1, 1));
     }
 
     #[test]
@@ -213,6 +399,64 @@ mod tests {
         assert_eq!(pool.stats().available_buffers, 2);
     }
 
+    #[test]
+    fn test_size_classes() {
+        let pool = BufferPool::new(1024, 4).with_size_classes(&[256, 4096, 1024]);
+        assert_eq!(pool.get_with_capacity(100).capacity(), 256);
+        assert_eq!(pool.get_with_capacity(1024).capacity(), 1024);
+        assert_eq!(pool.get_with_capacity(1025).capacity(), 4096);
+
+        // Oversized requests are allocated exactly and never pooled
+        drop(pool.get_with_capacity(10_000));
+        let stats = pool.stats();
+        assert_eq!(stats.oversized, 1);
+        assert_eq!(stats.available_buffers, 3);
+        let sizes: Vec<_> = stats.classes.iter().map(|c| c.buffer_size).collect();
+        assert_eq!(sizes, vec![256, 1024, 4096]);
+    }
+
+    #[test]
+    fn test_shrunken_buffers_are_not_pooled() {
+        let pool = BufferPool::new(1024, 4);
+        let mut buffer = pool.get();
+        buffer.resize(1024, 0);
+        let _head = buffer.split_to(512);
+        drop(buffer);
+        assert_eq!(pool.stats().available_buffers, 0);
+    }
+
+    #[test]
+    fn test_range_of() {
+        let pool = BufferPool::new(64, 1);
+        let mut buffer = pool.get();
+        buffer.extend_from_slice(b"hello world");
+        assert_eq!(buffer.range_of(&buffer[6..]), Some(6..11));
+        assert_eq!(buffer.range_of(b"hello"), None);
+    }
+
+    #[test]
+    fn test_concurrent_use() {
+        let pool = Arc::new(BufferPool::new(1024, 8));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = Arc::clone(&pool);
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        let mut buffer = pool.get();
+                        buffer.extend_from_slice(b"data");
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let stats = pool.stats();
+        assert_eq!(stats.hits + stats.misses, 4000);
+        assert!(stats.available_buffers <= 8);
+    }
+
     #[test]
     fn test_global_pool() {
         // This might interfere with other tests, so be careful
@@ -2,4 +2,6 @@
 
 pub mod buffer_pool;
 
-pub use buffer_pool::{BufferPool, PoolStats, PooledBuffer, global_pool, init_global_pool};
+pub use buffer_pool::{
+    BufferPool, PoolStats, PooledBuffer, SizeClassStats, global_pool, init_global_pool,
+};
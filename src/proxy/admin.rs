@@ -0,0 +1,104 @@
+use super::toxic::{Toxic, Toxics};
+use crate::Result;
+use crate::common::admin::{self, AdminRequest, AdminResponse};
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tracing::info;
+
+/// Plain HTTP endpoint for changing a proxy's toxics at runtime
+///
+/// Routes:
+///
+/// - `GET /toxics` returns one toxic spec per line
+/// - `POST /toxics` adds the toxic whose spec is the request body
+/// - `DELETE /toxics/NAME` removes a toxic
+///
+/// There is no authentication, so bind it to a loopback or otherwise
+/// trusted address.
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::proxy::{ProxyAdmin, Toxics};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let toxics = Toxics::default();
+///     let admin = ProxyAdmin::bind("127.0.0.1:9901".parse()?, toxics.clone()).await?;
+///     admin.run().await?;
+///     Ok(())
+/// }
+/// ```
+pub struct ProxyAdmin {
+    listener: TcpListener,
+    toxics: Toxics,
+}
+
+impl ProxyAdmin {
+    /// Binds the admin endpoint for `toxics` to `addr`
+    pub async fn bind(addr: SocketAddr, toxics: Toxics) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Self { listener, toxics })
+    }
+
+    /// Returns the address the endpoint is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serves admin requests until the task is dropped
+    pub async fn run(self) -> Result<()> {
+        info!(address = %self.local_addr()?, "Proxy admin endpoint listening");
+        let toxics = self.toxics;
+        admin::serve(self.listener, "proxy", move |request| {
+            route(&request, &toxics)
+        })
+        .await
+    }
+}
+
+fn route(request: &AdminRequest, toxics: &Toxics) -> AdminResponse {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/toxics") => AdminResponse::text(200, list_text(toxics)),
+        ("POST", "/toxics") => {
+            let toxic = match std::str::from_utf8(&request.body)
+                .map_err(|e| e.to_string())
+                .and_then(|spec| spec.trim().parse::<Toxic>().map_err(|e| e.to_string()))
+            {
+                Ok(toxic) => toxic,
+                Err(e) => return AdminResponse::text(400, format!("{e}\n")),
+            };
+            if toxics.get(&toxic.name).is_some() {
+                return AdminResponse::text(
+                    409,
+                    format!("Toxic '{}' already exists\n", toxic.name),
+                );
+            }
+            let spec = toxic.to_string();
+            match toxics.add(toxic) {
+                Ok(()) => {
+                    info!(toxic = %spec, "Toxic added through admin endpoint");
+                    AdminResponse::text(200, format!("{spec}\n"))
+                }
+                Err(e) => AdminResponse::text(409, format!("{e}\n")),
+            }
+        }
+        ("DELETE", path) if path.starts_with("/toxics/") => {
+            let name = &path["/toxics/".len()..];
+            match toxics.remove(name) {
+                Some(toxic) => {
+                    info!(toxic = %toxic, "Toxic removed through admin endpoint");
+                    AdminResponse::text(200, format!("{toxic}\n"))
+                }
+                None => AdminResponse::text(404, format!("No toxic named '{name}'\n")),
+            }
+        }
+        (_, "/toxics") => AdminResponse::method_not_allowed(),
+        (_, path) if path.starts_with("/toxics/") => AdminResponse::method_not_allowed(),
+        _ => AdminResponse::not_found(),
+    }
+}
+
+fn list_text(toxics: &Toxics) -> String {
+    toxics.list().iter().map(|t| format!("{t}\n")).collect()
+}
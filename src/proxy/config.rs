@@ -0,0 +1,82 @@
+use super::toxic::Toxics;
+use crate::network::Address;
+use crate::stream::StreamConfig;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Configuration for a fault-injecting proxy
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::proxy::{ProxyConfig, Toxics};
+/// use std::time::Duration;
+///
+/// let config = ProxyConfig {
+///     bind_addr: "127.0.0.1:9000".parse().unwrap(),
+///     upstream: "127.0.0.1:8080".parse().unwrap(),
+///     max_connections: 100,
+///     buffer_size: 16 * 1024,
+///     connect_timeout: Duration::from_secs(5),
+///     idle_timeout: Duration::from_secs(300),
+///     write_timeout: Duration::from_secs(30),
+///     seed: Some(7),
+///     toxics: Toxics::default(),
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    /// Address to accept client connections on
+    pub bind_addr: SocketAddr,
+    /// Server the proxy relays to
+    pub upstream: Address,
+    /// Maximum number of concurrent connections
+    pub max_connections: usize,
+    /// Size of each read, per direction
+    pub buffer_size: usize,
+    /// How long to wait for the upstream connection
+    pub connect_timeout: Duration,
+    /// Close a connection after this long without data in either direction
+    pub idle_timeout: Duration,
+    /// Write timeout for either side
+    pub write_timeout: Duration,
+    /// Seed for toxicity rolls and jitter; random if `None`
+    pub seed: Option<u64>,
+    /// Toxics applied to relayed data, changeable while the proxy runs
+    pub toxics: Toxics,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: "127.0.0.1:0".parse().unwrap(),
+            upstream: Address::Network("127.0.0.1:8080".parse().unwrap()),
+            max_connections: 100,
+            buffer_size: 16 * 1024,
+            connect_timeout: Duration::from_secs(5),
+            idle_timeout: Duration::from_secs(300),
+            write_timeout: Duration::from_secs(30),
+            seed: None,
+            toxics: Toxics::default(),
+        }
+    }
+}
+
+impl From<&ProxyConfig> for StreamConfig {
+    fn from(config: &ProxyConfig) -> Self {
+        StreamConfig {
+            bind_addr: config.bind_addr,
+            additional_addrs: Vec::new(),
+            max_connections: config.max_connections,
+            buffer_size: config.buffer_size,
+            read_timeout: config.idle_timeout,
+            write_timeout: config.write_timeout,
+            faults: None,
+            transform: None,
+            framing: None,
+            workers: 1,
+            steer_by_cpu: false,
+            socket_options: Default::default(),
+        }
+    }
+}
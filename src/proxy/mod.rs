@@ -0,0 +1,20 @@
+//! Fault-injecting proxy for real servers
+//!
+//! [`ProxyServer`] sits between clients and an upstream server and relays
+//! bytes in both directions, applying named [`Toxic`]s on the way: latency,
+//! bandwidth limits and connection resets. The toxic set is a live
+//! [`Toxics`] handle, so faults can be added and removed while connections
+//! are open, either from code or through a [`ProxyAdmin`] endpoint.
+
+pub mod admin;
+pub mod config;
+pub mod server;
+pub mod toxic;
+
+pub use admin::ProxyAdmin;
+pub use config::ProxyConfig;
+pub use server::{ProxyCounters, ProxyServer, ProxyStats};
+pub use toxic::{Toxic, ToxicDirection, ToxicKind, Toxics};
+
+/// Proxy that accepts TCP connections and relays them to a TCP upstream
+pub type TcpProxyServer = ProxyServer<crate::tcp::TcpProtocol>;
@@ -0,0 +1,579 @@
+use super::config::ProxyConfig;
+use super::toxic::{DirectionToxics, Toxic, ToxicDirection, Toxics};
+use crate::common::EchoServerTrait;
+use crate::fault::FaultRng;
+use crate::fault::impairment::sleep_until_release;
+use crate::performance::global_pool;
+use crate::stream::{StreamConfig, StreamProtocol, WriteHalf};
+use crate::{EchoError, Result};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::signal;
+use tokio::sync::{Notify, broadcast};
+use tokio::time::{Instant, timeout};
+use tracing::{Instrument, debug, error, info, warn};
+
+/// Reads each direction may hold back before it stops reading
+const QUEUE_CHUNKS: usize = 16;
+
+/// Fault-injecting proxy in front of a real server
+///
+/// Accepts connections with protocol `P`, connects to the configured
+/// upstream with protocol `U` and relays bytes both ways, applying the
+/// [`Toxics`](super::Toxics) of its configuration. Toxics can be added and
+/// removed while the proxy runs, and open connections follow the changes.
+/// Both protocols must be able to split their streams, so that each
+/// direction is written independently.
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::common::EchoServerTrait;
+/// use echosrv::proxy::{ProxyConfig, TcpProxyServer};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let config = ProxyConfig {
+///         bind_addr: "127.0.0.1:9000".parse()?,
+///         upstream: "127.0.0.1:8080".parse()?,
+///         ..Default::default()
+///     };
+///     let proxy = TcpProxyServer::new(config);
+///     proxy
+///         .toxics()
+///         .add("slow=latency?latency=250&stream=downstream".parse()?)?;
+///     proxy.run().await?;
+///     Ok(())
+/// }
+/// ```
+pub struct ProxyServer<P: StreamProtocol, U: StreamProtocol = P> {
+    config: ProxyConfig,
+    protocols: std::marker::PhantomData<(P, U)>,
+    shutdown_signal: Arc<broadcast::Sender<()>>,
+    counters: Arc<ProxyCounters>,
+}
+
+/// Live counters of a proxy, shared with its connections
+#[derive(Debug, Default)]
+pub struct ProxyCounters {
+    connections: AtomicU64,
+    upstream_failures: AtomicU64,
+    bytes_to_upstream: AtomicU64,
+    bytes_to_client: AtomicU64,
+    resets: AtomicU64,
+}
+
+impl ProxyCounters {
+    /// Takes a snapshot of the counters
+    pub fn stats(&self) -> ProxyStats {
+        ProxyStats {
+            connections: self.connections.load(Ordering::Relaxed),
+            upstream_failures: self.upstream_failures.load(Ordering::Relaxed),
+            bytes_to_upstream: self.bytes_to_upstream.load(Ordering::Relaxed),
+            bytes_to_client: self.bytes_to_client.load(Ordering::Relaxed),
+            resets: self.resets.load(Ordering::Relaxed),
+        }
+    }
+
+    fn bump(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Snapshot of proxy counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProxyStats {
+    /// Client connections accepted
+    pub connections: u64,
+    /// Connections dropped because the upstream could not be reached
+    pub upstream_failures: u64,
+    /// Bytes relayed from clients to the upstream
+    pub bytes_to_upstream: u64,
+    /// Bytes relayed from the upstream to clients
+    pub bytes_to_client: u64,
+    /// Connections reset by a toxic
+    pub resets: u64,
+}
+
+impl<P: StreamProtocol, U: StreamProtocol> ProxyServer<P, U>
+where
+    P::Error: Into<EchoError> + std::fmt::Display,
+    U::Error: Into<EchoError> + std::fmt::Display,
+{
+    /// Creates a proxy with the given configuration
+    pub fn new(config: ProxyConfig) -> Self {
+        let (shutdown_signal, _) = broadcast::channel(1);
+        Self {
+            config,
+            protocols: std::marker::PhantomData,
+            shutdown_signal: Arc::new(shutdown_signal),
+            counters: Arc::new(ProxyCounters::default()),
+        }
+    }
+
+    /// Returns the live toxic set; changes apply to open connections too
+    pub fn toxics(&self) -> &Toxics {
+        &self.config.toxics
+    }
+
+    /// Returns a snapshot of the proxy's counters
+    pub fn stats(&self) -> ProxyStats {
+        self.counters.stats()
+    }
+
+    /// Connects to the upstream and relays until both sides are done
+    async fn handle_connection(
+        client: P::Stream,
+        addr: SocketAddr,
+        config: ProxyConfig,
+        rng: FaultRng,
+        counters: Arc<ProxyCounters>,
+    ) -> Result<()>
+    where
+        P: Send,
+        U: Send,
+    {
+        let upstream =
+            match timeout(config.connect_timeout, U::connect_address(&config.upstream)).await {
+                Ok(Ok(upstream)) => upstream,
+                Ok(Err(e)) => {
+                    ProxyCounters::bump(&counters.upstream_failures);
+                    return Err(e.into());
+                }
+                Err(_) => {
+                    ProxyCounters::bump(&counters.upstream_failures);
+                    return Err(EchoError::Timeout(format!(
+                        "Connecting to upstream {}",
+                        config.upstream
+                    )));
+                }
+            };
+        info!(%addr, upstream = %config.upstream, "Connected to upstream");
+
+        Self::relay(client, upstream, addr, &config, rng, &counters).await
+    }
+
+    /// Relays between the client and the upstream until both sides are done
+    ///
+    /// Both streams are split, and each direction writes from its own
+    /// future, so a peer that stops reading only holds up the data queued
+    /// for it.
+    async fn relay(
+        client: P::Stream,
+        upstream: U::Stream,
+        addr: SocketAddr,
+        config: &ProxyConfig,
+        mut rng: FaultRng,
+        counters: &ProxyCounters,
+    ) -> Result<()>
+    where
+        P: Send,
+        U: Send,
+    {
+        let (Ok((mut client_read, mut client_write)), Ok((mut upstream_read, mut upstream_write))) =
+            (P::into_split(client), U::into_split(upstream))
+        else {
+            return Err(EchoError::Unsupported(
+                "the proxy only relays streams that can be split".to_string(),
+            ));
+        };
+
+        let mut changes = config.toxics.subscribe();
+        let to_upstream =
+            Direction::new(DirectionToxics::new(ToxicDirection::Upstream, rng.fork()));
+        let to_client =
+            Direction::new(DirectionToxics::new(ToxicDirection::Downstream, rng.fork()));
+        let toxics = changes.borrow_and_update().clone();
+        Self::apply_toxics(addr, &toxics, &to_upstream, &to_client);
+
+        let limit = config.buffer_size.saturating_mul(QUEUE_CHUNKS);
+        let mut client_buffer = global_pool().get_with_capacity(config.buffer_size);
+        let mut upstream_buffer = global_pool().get_with_capacity(config.buffer_size);
+        client_buffer.resize(config.buffer_size, 0);
+        upstream_buffer.resize(config.buffer_size, 0);
+        let progress = Notify::new();
+
+        let ending = {
+            let mut upstream_writer = Box::pin(to_upstream.drain::<U>(
+                &mut upstream_write,
+                &progress,
+                config.write_timeout,
+                &counters.bytes_to_upstream,
+            ));
+            let mut client_writer = Box::pin(to_client.drain::<P>(
+                &mut client_write,
+                &progress,
+                config.write_timeout,
+                &counters.bytes_to_client,
+            ));
+            let (mut upstream_done, mut client_done) = (false, false);
+            let mut idle_deadline = Instant::now() + config.idle_timeout;
+
+            loop {
+                let now = Instant::now();
+                if to_upstream.pipe().reset_due(now) {
+                    break Ending::ResetUpstream;
+                }
+                if to_client.pipe().reset_due(now) {
+                    break Ending::ResetClient;
+                }
+                if upstream_done && client_done {
+                    info!(%addr, "Both sides closed");
+                    break Ending::Closed;
+                }
+
+                let reset_deadline = [
+                    to_upstream.pipe().toxics.reset_deadline(),
+                    to_client.pipe().toxics.reset_deadline(),
+                ]
+                .into_iter()
+                .flatten()
+                .min();
+
+                tokio::select! {
+                    result = P::read_half(&mut client_read, &mut client_buffer), if to_upstream.pipe().accepts(limit) => {
+                        let n = result.map_err(|e| e.into())?;
+                        if n == 0 {
+                            info!(%addr, "Client closed its write half");
+                        }
+                        to_upstream.receive(&client_buffer[..n], Instant::now());
+                        idle_deadline = Instant::now() + config.idle_timeout;
+                    }
+                    result = U::read_half(&mut upstream_read, &mut upstream_buffer), if to_client.pipe().accepts(limit) => {
+                        let n = result.map_err(|e| e.into())?;
+                        if n == 0 {
+                            info!(%addr, "Upstream closed its write half");
+                        }
+                        to_client.receive(&upstream_buffer[..n], Instant::now());
+                        idle_deadline = Instant::now() + config.idle_timeout;
+                    }
+                    result = &mut upstream_writer, if !upstream_done => {
+                        result?;
+                        upstream_done = true;
+                    }
+                    result = &mut client_writer, if !client_done => {
+                        result?;
+                        client_done = true;
+                    }
+                    _ = progress.notified() => {
+                        idle_deadline = Instant::now() + config.idle_timeout;
+                    }
+                    _ = sleep_until_release(reset_deadline) => {}
+                    Ok(()) = changes.changed() => {
+                        let toxics = changes.borrow_and_update().clone();
+                        Self::apply_toxics(addr, &toxics, &to_upstream, &to_client);
+                    }
+                    _ = tokio::time::sleep_until(idle_deadline) => {
+                        warn!(%addr, "Idle timeout");
+                        break Ending::Closed;
+                    }
+                }
+            }
+        };
+
+        match ending {
+            Ending::ResetUpstream => {
+                ProxyCounters::bump(&counters.resets);
+                info!(%addr, "Toxic: resetting upstream connection");
+                drop((client_read, client_write));
+                U::abort_split(upstream_read, upstream_write)
+                    .await
+                    .map_err(|e| e.into())
+            }
+            Ending::ResetClient => {
+                ProxyCounters::bump(&counters.resets);
+                info!(%addr, "Toxic: resetting client connection");
+                drop((upstream_read, upstream_write));
+                P::abort_split(client_read, client_write)
+                    .await
+                    .map_err(|e| e.into())
+            }
+            Ending::Closed => Ok(()),
+        }
+    }
+
+    fn apply_toxics(
+        addr: SocketAddr,
+        toxics: &[Toxic],
+        to_upstream: &Direction,
+        to_client: &Direction,
+    ) {
+        let now = Instant::now();
+        let names = |direction: &Direction| -> Vec<String> {
+            let mut pipe = direction.pipe();
+            let received = pipe.received;
+            pipe.toxics.update(toxics, received, now);
+            pipe.toxics.enabled().map(|t| t.name.clone()).collect()
+        };
+        let (upstream, downstream) = (names(to_upstream), names(to_client));
+        if !upstream.is_empty() || !downstream.is_empty() {
+            info!(%addr, ?upstream, ?downstream, "Toxics applied to connection");
+        }
+    }
+}
+
+/// How a relayed connection ended
+enum Ending {
+    /// Both sides closed, or the connection went idle
+    Closed,
+    /// A toxic reset the upstream connection
+    ResetUpstream,
+    /// A toxic reset the client connection
+    ResetClient,
+}
+
+/// One direction of a proxied connection
+///
+/// Data is queued with the instant its toxics allow it to leave, so latency
+/// never blocks the other direction. Reads stop while the queue is full.
+#[derive(Debug)]
+struct Pipe {
+    toxics: DirectionToxics,
+    queue: VecDeque<(Instant, Vec<u8>)>,
+    /// Bytes taken in and not yet written, including those being written
+    queued: usize,
+    /// Bytes taken into the queue so far
+    received: u64,
+    last_release: Option<Instant>,
+    /// The sending side has closed its write half
+    eof: bool,
+}
+
+impl Pipe {
+    fn new(toxics: DirectionToxics) -> Self {
+        Self {
+            toxics,
+            queue: VecDeque::new(),
+            queued: 0,
+            received: 0,
+            last_release: None,
+            eof: false,
+        }
+    }
+
+    fn accepts(&self, limit: usize) -> bool {
+        !self.eof && self.queued < limit
+    }
+
+    /// Queues data read from the sending side; empty data marks its end
+    fn receive(&mut self, data: &[u8], now: Instant) {
+        if data.is_empty() {
+            self.eof = true;
+            return;
+        }
+
+        // Anything past a reset toxic's byte budget is never relayed
+        let data = match self.toxics.budget(self.received) {
+            Some(budget) => {
+                &data[..data
+                    .len()
+                    .min(usize::try_from(budget).unwrap_or(usize::MAX))]
+            }
+            None => data,
+        };
+        let chunk_size = self.toxics.chunk_size().unwrap_or(data.len()).max(1);
+        for chunk in data.chunks(chunk_size) {
+            let release = now + self.toxics.delay(chunk.len());
+            // Jitter must not reorder the byte stream
+            let release = self.last_release.map_or(release, |last| release.max(last));
+            self.last_release = Some(release);
+            self.queue.push_back((release, chunk.to_vec()));
+            self.queued += chunk.len();
+            self.received += chunk.len() as u64;
+        }
+    }
+
+    fn next_release(&self) -> Option<Instant> {
+        self.queue.front().map(|(release, _)| *release)
+    }
+
+    /// Takes every chunk whose release time has passed, joined into one write
+    fn take_due(&mut self, now: Instant) -> Vec<u8> {
+        let mut due = Vec::new();
+        while self
+            .queue
+            .front()
+            .is_some_and(|(release, _)| *release <= now)
+        {
+            let Some((_, chunk)) = self.queue.pop_front() else {
+                break;
+            };
+            if due.is_empty() {
+                due = chunk;
+            } else {
+                due.extend_from_slice(&chunk);
+            }
+        }
+        due
+    }
+
+    fn reset_due(&self, now: Instant) -> bool {
+        let budget_spent = self.toxics.budget(self.received) == Some(0) && self.queued == 0;
+        budget_spent || self.toxics.reset_deadline().is_some_and(|d| d <= now)
+    }
+}
+
+/// A [`Pipe`] shared by the relay loop, which fills it, and the direction's
+/// writer, which drains it
+struct Direction {
+    pipe: Mutex<Pipe>,
+    /// Wakes the writer when data or the end of the stream is queued
+    queued: Notify,
+}
+
+impl Direction {
+    fn new(toxics: DirectionToxics) -> Self {
+        Self {
+            pipe: Mutex::new(Pipe::new(toxics)),
+            queued: Notify::new(),
+        }
+    }
+
+    fn pipe(&self) -> MutexGuard<'_, Pipe> {
+        self.pipe.lock().unwrap()
+    }
+
+    /// Queues data read from the sending side and wakes the writer
+    fn receive(&self, data: &[u8], now: Instant) {
+        self.pipe().receive(data, now);
+        self.queued.notify_one();
+    }
+
+    /// Writes each chunk once its release time has passed, then propagates
+    /// the sending side's half-close
+    ///
+    /// Wakes `progress` after every write, so the relay loop can read again
+    /// and check for resets.
+    async fn drain<S: StreamProtocol + Send>(
+        &self,
+        half: &mut WriteHalf,
+        progress: &Notify,
+        write_timeout: Duration,
+        counter: &AtomicU64,
+    ) -> Result<()>
+    where
+        S::Error: Into<EchoError>,
+    {
+        loop {
+            let (release, eof) = {
+                let pipe = self.pipe();
+                (pipe.next_release(), pipe.eof)
+            };
+            match release {
+                Some(release) => tokio::time::sleep_until(release).await,
+                None if eof => break,
+                None => {
+                    self.queued.notified().await;
+                    continue;
+                }
+            }
+
+            let data = self.pipe().take_due(Instant::now());
+            if data.is_empty() {
+                continue;
+            }
+            timeout(write_timeout, S::write_half(half, &data))
+                .await
+                .map_err(|_| EchoError::Timeout("Proxy write".to_string()))?
+                .map_err(|e| e.into())?;
+            self.pipe().queued -= data.len();
+            counter.fetch_add(data.len() as u64, Ordering::Relaxed);
+            progress.notify_one();
+        }
+
+        if let Err(e) = S::shutdown_write_half(half).await {
+            let e: EchoError = e.into();
+            debug!(error = %e, "Could not propagate half-close");
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<P, U> EchoServerTrait for ProxyServer<P, U>
+where
+    P: StreamProtocol + Send + Sync,
+    U: StreamProtocol + Send + Sync,
+    P::Error: Into<EchoError> + std::fmt::Display,
+    U::Error: Into<EchoError> + std::fmt::Display,
+    P::Stream: 'static,
+    U::Stream: 'static,
+{
+    /// Starts accepting connections and relaying them to the upstream
+    async fn run(&self) -> Result<()> {
+        let mut listener = P::bind(&StreamConfig::from(&self.config))
+            .await
+            .map_err(|e| e.into())?;
+
+        info!(address = %self.config.bind_addr, upstream = %self.config.upstream, "Proxy listening");
+
+        let seed = self
+            .config
+            .seed
+            .unwrap_or_else(crate::fault::rng::entropy_seed);
+        info!(seed, toxics = ?self.config.toxics, "Proxy toxics seeded");
+        let mut rng = FaultRng::new(seed);
+
+        let connection_count = Arc::new(AtomicUsize::new(0));
+        let mut shutdown_rx = self.shutdown_signal.subscribe();
+
+        loop {
+            tokio::select! {
+                accept_result = P::accept(&mut listener) => {
+                    match accept_result {
+                        Ok((stream, addr)) => {
+                            let current_count = connection_count.load(Ordering::SeqCst);
+                            if current_count >= self.config.max_connections {
+                                warn!(%addr, current = current_count, limit = self.config.max_connections, "Connection rejected: limit reached");
+                                continue;
+                            }
+
+                            connection_count.fetch_add(1, Ordering::SeqCst);
+                            ProxyCounters::bump(&self.counters.connections);
+                            let new_count = connection_count.load(Ordering::SeqCst);
+                            info!(%addr, current = new_count, "Accepted connection");
+
+                            let config = self.config.clone();
+                            let counters = self.counters.clone();
+                            let connection_count = connection_count.clone();
+                            let rng = rng.fork();
+                            let span = tracing::info_span!("proxy_connection", %addr, current = new_count);
+
+                            tokio::spawn(async move {
+                                let result = Self::handle_connection(stream, addr, config, rng, counters).instrument(span).await;
+                                if let Err(e) = result {
+                                    error!(%addr, error = %e, "Error proxying connection");
+                                }
+                                let final_count = connection_count.fetch_sub(1, Ordering::SeqCst) - 1;
+                                info!(%addr, current = final_count, "Connection closed");
+                            });
+                        }
+                        Err(e) => {
+                            error!(error = %e, "Failed to accept connection");
+                        }
+                    }
+                }
+                _ = signal::ctrl_c() => {
+                    info!("Received shutdown signal, stopping proxy");
+                    break;
+                }
+                _ = shutdown_rx.recv() => {
+                    info!("Received internal shutdown signal, stopping proxy");
+                    break;
+                }
+            }
+        }
+
+        info!(stats = ?self.counters.stats(), "Proxy stopped");
+        Ok(())
+    }
+
+    /// Returns a shutdown signal sender that can be used to gracefully shutdown the proxy
+    fn shutdown_signal(&self) -> broadcast::Sender<()> {
+        self.shutdown_signal.as_ref().clone()
+    }
+}
@@ -0,0 +1,593 @@
+use crate::fault::{FaultConfig, FaultRng, LatencyConfig, TrafficShaper};
+use crate::{EchoError, Result};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// A named fault applied to one direction of proxied connections
+///
+/// Toxics are written as `NAME=TYPE[?ATTR=VALUE&...]`, in the spirit of
+/// toxiproxy. Every toxic accepts `stream=upstream|downstream` (default
+/// downstream) and `toxicity=P`, the fraction of connections it applies to
+/// (default 1). The types and their attributes are:
+///
+/// - `latency`: `latency=MS`, `jitter=MS`
+/// - `bandwidth`: `rate=KB_PER_SECOND`
+/// - `reset_peer`: `timeout=MS` and/or `bytes=N`; with neither the
+///   connection is reset as soon as the toxic applies
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::proxy::{Toxic, ToxicDirection, ToxicKind};
+/// use std::time::Duration;
+///
+/// let toxic: Toxic = "slow=latency?latency=200&jitter=20&stream=upstream".parse().unwrap();
+/// assert_eq!(toxic.direction, ToxicDirection::Upstream);
+/// assert_eq!(
+///     toxic.kind,
+///     ToxicKind::Latency {
+///         latency: Duration::from_millis(200),
+///         jitter: Duration::from_millis(20),
+///     }
+/// );
+/// assert_eq!(toxic.to_string().parse::<Toxic>().unwrap(), toxic);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Toxic {
+    /// Unique name used to remove the toxic again
+    pub name: String,
+    /// What the toxic does
+    pub kind: ToxicKind,
+    /// Which direction of the connection it affects
+    pub direction: ToxicDirection,
+    /// Fraction of connections affected, in `[0, 1]`
+    pub toxicity: f64,
+}
+
+/// What a toxic does to the data it sees
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToxicKind {
+    /// Delay every chunk by `latency`, plus or minus up to `jitter`
+    Latency {
+        /// Base delay
+        latency: Duration,
+        /// Spread of the uniform jitter
+        jitter: Duration,
+    },
+    /// Limit throughput to `rate` KB (1000 bytes) per second
+    Bandwidth {
+        /// Rate in KB per second
+        rate: u64,
+    },
+    /// Reset the connection once `timeout` has passed or `bytes` have been
+    /// relayed since the toxic applied, whichever comes first
+    ResetPeer {
+        /// Time before the reset
+        timeout: Option<Duration>,
+        /// Bytes relayed before the reset
+        bytes: Option<u64>,
+    },
+}
+
+/// Direction of a proxied connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToxicDirection {
+    /// Data from the client to the upstream server
+    Upstream,
+    /// Data from the upstream server to the client
+    #[default]
+    Downstream,
+}
+
+impl Toxic {
+    /// Creates a toxic affecting every downstream connection
+    pub fn new(name: impl Into<String>, kind: ToxicKind) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            direction: ToxicDirection::Downstream,
+            toxicity: 1.0,
+        }
+    }
+
+    /// Checks that the name and parameters are usable
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            return Err(EchoError::Config(format!(
+                "Invalid toxic name '{}', use letters, digits, '-', '_' or '.'",
+                self.name
+            )));
+        }
+        if !(0.0..=1.0).contains(&self.toxicity) {
+            return Err(EchoError::Config(format!(
+                "Toxicity of '{}' must be between 0 and 1",
+                self.name
+            )));
+        }
+        if let ToxicKind::Bandwidth { rate: 0 } = self.kind {
+            return Err(EchoError::Config(format!(
+                "Bandwidth toxic '{}' needs a rate above zero",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Toxic {
+    type Err = EchoError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, rest) = s.split_once('=').ok_or_else(|| {
+            EchoError::Config(format!("Invalid toxic '{s}', expected NAME=TYPE[?ATTRS]"))
+        })?;
+        let (kind, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let mut attributes = Attributes::parse(query)?;
+        let direction = attributes
+            .take("stream")
+            .map_or(Ok(ToxicDirection::default()), str::parse)?;
+        let toxicity = attributes
+            .take("toxicity")
+            .map_or(Ok(1.0), |v| parse_number::<f64>("toxicity", v))?;
+        let kind = match kind {
+            "latency" => ToxicKind::Latency {
+                latency: attributes.take_millis("latency")?.unwrap_or_default(),
+                jitter: attributes.take_millis("jitter")?.unwrap_or_default(),
+            },
+            "bandwidth" => ToxicKind::Bandwidth {
+                rate: attributes
+                    .take("rate")
+                    .map(|v| parse_number("rate", v))
+                    .transpose()?
+                    .ok_or_else(|| EchoError::Config("Bandwidth toxic needs a rate".to_string()))?,
+            },
+            "reset_peer" => {
+                let timeout = attributes.take_millis("timeout")?;
+                let bytes = attributes
+                    .take("bytes")
+                    .map(|v| parse_number("bytes", v))
+                    .transpose()?;
+                ToxicKind::ResetPeer {
+                    timeout: timeout.or(bytes.is_none().then_some(Duration::ZERO)),
+                    bytes,
+                }
+            }
+            other => {
+                return Err(EchoError::Config(format!(
+                    "Unknown toxic type '{other}', expected latency, bandwidth or reset_peer"
+                )));
+            }
+        };
+        attributes.finish(kind_name(&kind))?;
+
+        let toxic = Toxic {
+            name: name.to_string(),
+            kind,
+            direction,
+            toxicity,
+        };
+        toxic.validate()?;
+        Ok(toxic)
+    }
+}
+
+impl fmt::Display for Toxic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}?", self.name, kind_name(&self.kind))?;
+        match &self.kind {
+            ToxicKind::Latency { latency, jitter } => write!(
+                f,
+                "latency={}&jitter={}&",
+                latency.as_millis(),
+                jitter.as_millis()
+            )?,
+            ToxicKind::Bandwidth { rate } => write!(f, "rate={rate}&")?,
+            ToxicKind::ResetPeer { timeout, bytes } => {
+                if let Some(timeout) = timeout {
+                    write!(f, "timeout={}&", timeout.as_millis())?;
+                }
+                if let Some(bytes) = bytes {
+                    write!(f, "bytes={bytes}&")?;
+                }
+            }
+        }
+        write!(f, "stream={}&toxicity={}", self.direction, self.toxicity)
+    }
+}
+
+impl FromStr for ToxicDirection {
+    type Err = EchoError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "upstream" => Ok(Self::Upstream),
+            "downstream" => Ok(Self::Downstream),
+            other => Err(EchoError::Config(format!(
+                "Unknown toxic stream '{other}', expected upstream or downstream"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ToxicDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Upstream => "upstream",
+            Self::Downstream => "downstream",
+        })
+    }
+}
+
+fn kind_name(kind: &ToxicKind) -> &'static str {
+    match kind {
+        ToxicKind::Latency { .. } => "latency",
+        ToxicKind::Bandwidth { .. } => "bandwidth",
+        ToxicKind::ResetPeer { .. } => "reset_peer",
+    }
+}
+
+fn parse_number<T: FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| EchoError::Config(format!("Invalid value '{value}' for toxic {key}")))
+}
+
+/// `key=value` attributes of a toxic spec, consumed as they are recognised
+struct Attributes<'a>(Vec<(&'a str, &'a str)>);
+
+impl<'a> Attributes<'a> {
+    fn parse(query: &'a str) -> Result<Self> {
+        query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                pair.split_once('=').ok_or_else(|| {
+                    EchoError::Config(format!(
+                        "Invalid toxic attribute '{pair}', expected KEY=VALUE"
+                    ))
+                })
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    fn take(&mut self, key: &str) -> Option<&'a str> {
+        let index = self.0.iter().position(|(k, _)| *k == key)?;
+        Some(self.0.remove(index).1)
+    }
+
+    fn take_millis(&mut self, key: &str) -> Result<Option<Duration>> {
+        self.take(key)
+            .map(|v| parse_number(key, v).map(Duration::from_millis))
+            .transpose()
+    }
+
+    fn finish(self, kind: &str) -> Result<()> {
+        match self.0.first() {
+            Some((key, _)) => Err(EchoError::Config(format!(
+                "Unknown attribute '{key}' for {kind} toxic"
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Live set of toxics shared by a proxy and whoever steers it
+///
+/// Cloning the handle shares the set. Connections pick up additions and
+/// removals as they happen, including connections that are already open.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::proxy::{Toxic, ToxicKind, Toxics};
+///
+/// let toxics = Toxics::default();
+/// toxics.add(Toxic::new("cut", ToxicKind::ResetPeer { timeout: None, bytes: Some(1024) })).unwrap();
+/// assert!(toxics.add(Toxic::new("cut", ToxicKind::Bandwidth { rate: 10 })).is_err());
+/// assert_eq!(toxics.list().len(), 1);
+/// assert!(toxics.remove("cut").is_some());
+/// ```
+#[derive(Clone)]
+pub struct Toxics {
+    sender: Arc<watch::Sender<Arc<Vec<Toxic>>>>,
+}
+
+impl Default for Toxics {
+    fn default() -> Self {
+        let (sender, _) = watch::channel(Arc::new(Vec::new()));
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+}
+
+impl fmt::Debug for Toxics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.sender.borrow().iter()).finish()
+    }
+}
+
+impl Toxics {
+    /// Adds a toxic; fails if it is invalid or its name is taken
+    pub fn add(&self, toxic: Toxic) -> Result<()> {
+        toxic.validate()?;
+        let mut result = Ok(());
+        self.sender.send_if_modified(|toxics| {
+            if toxics.iter().any(|t| t.name == toxic.name) {
+                result = Err(EchoError::Config(format!(
+                    "Toxic '{}' already exists",
+                    toxic.name
+                )));
+                return false;
+            }
+            Arc::make_mut(toxics).push(toxic.clone());
+            true
+        });
+        result
+    }
+
+    /// Removes the toxic called `name`, returning it if it existed
+    pub fn remove(&self, name: &str) -> Option<Toxic> {
+        let mut removed = None;
+        self.sender
+            .send_if_modified(|toxics| match toxics.iter().position(|t| t.name == name) {
+                Some(index) => {
+                    removed = Some(Arc::make_mut(toxics).remove(index));
+                    true
+                }
+                None => false,
+            });
+        removed
+    }
+
+    /// Returns the toxic called `name`
+    pub fn get(&self, name: &str) -> Option<Toxic> {
+        self.sender
+            .borrow()
+            .iter()
+            .find(|t| t.name == name)
+            .cloned()
+    }
+
+    /// Returns the toxics in the order they were added
+    pub fn list(&self) -> Vec<Toxic> {
+        self.sender.borrow().as_ref().clone()
+    }
+
+    /// Subscribes to changes of the set
+    pub(crate) fn subscribe(&self) -> watch::Receiver<Arc<Vec<Toxic>>> {
+        self.sender.subscribe()
+    }
+}
+
+/// State of one toxic on one connection
+#[derive(Debug)]
+struct Applied {
+    toxic: Toxic,
+    /// Whether the toxicity roll selected this connection
+    enabled: bool,
+    since: Instant,
+    /// Bytes already relayed when the toxic started applying
+    bytes_at_start: u64,
+}
+
+/// Toxics acting on one direction of one connection
+///
+/// Toxicity is rolled once per connection and toxic, and a toxic keeps its
+/// roll, clock and byte count for as long as it stays in the set.
+#[derive(Debug)]
+pub(crate) struct DirectionToxics {
+    direction: ToxicDirection,
+    applied: Vec<Applied>,
+    shaper: Option<TrafficShaper>,
+    rng: FaultRng,
+}
+
+impl DirectionToxics {
+    pub(crate) fn new(direction: ToxicDirection, rng: FaultRng) -> Self {
+        Self {
+            direction,
+            applied: Vec::new(),
+            shaper: None,
+            rng,
+        }
+    }
+
+    /// Applies the current set; `bytes` is the direction's byte count so far
+    pub(crate) fn update(&mut self, toxics: &[Toxic], bytes: u64, now: Instant) {
+        let mut previous = std::mem::take(&mut self.applied);
+        for toxic in toxics.iter().filter(|t| t.direction == self.direction) {
+            let applied = match previous.iter().position(|a| a.toxic == *toxic) {
+                Some(index) => previous.swap_remove(index),
+                None => Applied {
+                    enabled: self.rng.chance(toxic.toxicity),
+                    toxic: toxic.clone(),
+                    since: now,
+                    bytes_at_start: bytes,
+                },
+            };
+            self.applied.push(applied);
+        }
+
+        // Stacked latencies add up and the tightest bandwidth limit wins
+        let mut config = FaultConfig::default();
+        for toxic in self.enabled() {
+            match toxic.kind {
+                ToxicKind::Latency { latency, jitter } => {
+                    let total = config
+                        .latency
+                        .get_or_insert_with(|| LatencyConfig::fixed(Duration::ZERO));
+                    total.base += latency;
+                    total.jitter += jitter;
+                }
+                ToxicKind::Bandwidth { rate } => {
+                    let rate = rate.saturating_mul(1000);
+                    config.bandwidth.write =
+                        Some(config.bandwidth.write.map_or(rate, |r| r.min(rate)));
+                }
+                ToxicKind::ResetPeer { .. } => {}
+            }
+        }
+        self.shaper = config
+            .shapes_traffic()
+            .then(|| TrafficShaper::new(&config, self.rng.fork()));
+    }
+
+    /// Toxics that apply to this connection
+    pub(crate) fn enabled(&self) -> impl Iterator<Item = &Toxic> {
+        self.applied.iter().filter(|a| a.enabled).map(|a| &a.toxic)
+    }
+
+    /// Largest chunk that keeps bandwidth pacing smooth, or `None` if unlimited
+    pub(crate) fn chunk_size(&self) -> Option<usize> {
+        self.shaper.as_ref().and_then(TrafficShaper::write_chunk)
+    }
+
+    /// Delay before `len` more bytes may be relayed
+    pub(crate) fn delay(&mut self, len: usize) -> Duration {
+        self.shaper
+            .as_mut()
+            .map_or(Duration::ZERO, |s| s.latency() + s.reserve_write(len))
+    }
+
+    /// Bytes that may still be relayed before a reset, given `bytes` so far
+    pub(crate) fn budget(&self, bytes: u64) -> Option<u64> {
+        self.applied
+            .iter()
+            .filter(|a| a.enabled)
+            .filter_map(|a| match a.toxic.kind {
+                ToxicKind::ResetPeer {
+                    bytes: Some(limit), ..
+                } => Some((a.bytes_at_start + limit).saturating_sub(bytes)),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// When a timed reset fires, if one applies
+    pub(crate) fn reset_deadline(&self) -> Option<Instant> {
+        self.applied
+            .iter()
+            .filter(|a| a.enabled)
+            .filter_map(|a| match a.toxic.kind {
+                ToxicKind::ResetPeer {
+                    timeout: Some(timeout),
+                    ..
+                } => Some(a.since + timeout),
+                _ => None,
+            })
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display_round_trip() {
+        for spec in [
+            "lag=latency?latency=100&jitter=10&stream=upstream&toxicity=0.5",
+            "thin=bandwidth?rate=64&stream=downstream&toxicity=1",
+            "cut=reset_peer?timeout=500&bytes=1024&stream=downstream&toxicity=1",
+        ] {
+            let toxic: Toxic = spec.parse().unwrap();
+            assert_eq!(toxic.to_string(), spec);
+        }
+
+        let toxic: Toxic = "now=reset_peer".parse().unwrap();
+        assert_eq!(
+            toxic.kind,
+            ToxicKind::ResetPeer {
+                timeout: Some(Duration::ZERO),
+                bytes: None
+            }
+        );
+        assert_eq!(toxic.direction, ToxicDirection::Downstream);
+        assert_eq!(toxic.toxicity, 1.0);
+    }
+
+    #[test]
+    fn test_invalid_specs() {
+        for spec in [
+            "latency",
+            "x=slow",
+            "x=bandwidth",
+            "x=bandwidth?rate=0",
+            "x=latency?latency=fast",
+            "x=latency?rate=10",
+            "x=latency?toxicity=2",
+            "x=latency?stream=sideways",
+            "a/b=latency",
+            "=latency",
+        ] {
+            assert!(spec.parse::<Toxic>().is_err(), "{spec} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_set_changes_are_published() {
+        let toxics = Toxics::default();
+        let mut changes = toxics.subscribe();
+        toxics
+            .add("lag=latency?latency=10".parse().unwrap())
+            .unwrap();
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(changes.borrow_and_update().len(), 1);
+
+        assert!(toxics.add("lag=bandwidth?rate=1".parse().unwrap()).is_err());
+        assert!(toxics.remove("missing").is_none());
+        assert!(!changes.has_changed().unwrap());
+
+        assert_eq!(toxics.remove("lag").unwrap().name, "lag");
+        assert!(toxics.list().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_direction_toxics_keep_state_across_updates() {
+        let reset: Toxic = "cut=reset_peer?bytes=100&timeout=1000".parse().unwrap();
+        let lag: Toxic = "lag=latency?latency=50".parse().unwrap();
+        let other_way: Toxic = "up=latency?latency=500&stream=upstream".parse().unwrap();
+
+        let mut toxics = DirectionToxics::new(ToxicDirection::Downstream, FaultRng::new(1));
+        let start = Instant::now();
+        toxics.update(std::slice::from_ref(&reset), 20, start);
+        assert_eq!(toxics.budget(20), Some(100));
+        assert_eq!(toxics.delay(10), Duration::ZERO);
+
+        // Adding a toxic later keeps the reset's clock and byte count
+        tokio::time::advance(Duration::from_millis(400)).await;
+        toxics.update(&[reset, lag, other_way], 70, Instant::now());
+        assert_eq!(toxics.budget(70), Some(50));
+        assert_eq!(
+            toxics.reset_deadline(),
+            Some(start + Duration::from_secs(1))
+        );
+        assert_eq!(toxics.delay(10), Duration::from_millis(50));
+        assert_eq!(toxics.enabled().count(), 2);
+
+        toxics.update(&[], 70, Instant::now());
+        assert_eq!(toxics.budget(70), None);
+        assert_eq!(toxics.reset_deadline(), None);
+    }
+
+    #[test]
+    fn test_toxicity_zero_never_applies() {
+        let toxic: Toxic = "lag=latency?latency=50&toxicity=0".parse().unwrap();
+        let mut toxics = DirectionToxics::new(ToxicDirection::Downstream, FaultRng::new(3));
+        toxics.update(&[toxic], 0, Instant::now());
+        assert_eq!(toxics.enabled().count(), 0);
+        assert_eq!(toxics.chunk_size(), None);
+    }
+}
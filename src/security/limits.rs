@@ -110,7 +110,7 @@ impl ConnectionTracker {
     }
 
     /// Attempt to acquire a connection slot
-    pub async fn acquire_connection(&self) -> Result<ConnectionGuard, ConnectionError> {
+    pub async fn acquire_connection(&self) -> Result<ConnectionGuard<'_>, ConnectionError> {
         // Try to acquire a permit for the connection
         let permit = timeout(Duration::from_secs(1), self.connection_semaphore.acquire())
             .await
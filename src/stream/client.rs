@@ -1,9 +1,10 @@
-use super::StreamProtocol;
+use super::framing::FrameBuffer;
+use super::{FrameCodec, StreamProtocol};
 use crate::common::EchoClient;
 use crate::network::Address;
+use crate::performance::global_pool;
 use crate::{EchoError, Result};
 use async_trait::async_trait;
-use bytes::BytesMut;
 use std::time::Duration;
 use tokio::time::{Instant, timeout};
 
@@ -20,6 +21,8 @@ pub struct ClientConfig {
     pub buffer_size: usize,
     /// Maximum response size to prevent memory exhaustion
     pub max_response_size: usize,
+    /// Framing of messages, matching the server's; raw bytes if `None`
+    pub framing: Option<FrameCodec>,
 }
 
 impl Default for ClientConfig {
@@ -30,6 +33,7 @@ impl Default for ClientConfig {
             connect_timeout: Duration::from_secs(10),
             buffer_size: 1024,
             max_response_size: 10 * 1024 * 1024, // 10MB
+            framing: None,
         }
     }
 }
@@ -37,10 +41,12 @@ impl Default for ClientConfig {
 /// Stream-based echo client with configurable timeouts and error handling
 ///
 /// This client provides configurable timeouts, better error handling,
-/// and protection against memory exhaustion.
+/// and protection against memory exhaustion. With framing configured, each
+/// echo sends one frame and returns the payload of the next frame received.
 pub struct Client<P: StreamProtocol> {
     stream: P::Stream,
     config: ClientConfig,
+    frames: Option<FrameBuffer>,
     last_activity: Instant,
 }
 
@@ -68,6 +74,7 @@ where
 
         Ok(Self {
             stream,
+            frames: config.framing.clone().map(FrameBuffer::new),
             config,
             last_activity: Instant::now(),
         })
@@ -88,24 +95,31 @@ where
         self.last_activity = Instant::now();
     }
 
-    /// Send data and receive response with proper timeout handling
-    async fn send_and_receive(&mut self, data: &[u8]) -> Result<Vec<u8>> {
-        self.update_activity();
-
-        // Write data with timeout
-        timeout(self.config.write_timeout, P::write(&mut self.stream, data))
+    /// Writes and flushes data with the write timeout
+    async fn send(stream: &mut P::Stream, config: &ClientConfig, data: &[u8]) -> Result<()> {
+        timeout(config.write_timeout, P::write(stream, data))
             .await
             .map_err(|_| EchoError::Timeout("Write timeout".to_string()))?
             .map_err(|e| e.into())?;
 
-        timeout(self.config.write_timeout, P::flush(&mut self.stream))
+        timeout(config.write_timeout, P::flush(stream))
             .await
             .map_err(|_| EchoError::Timeout("Flush timeout".to_string()))?
-            .map_err(|e| e.into())?;
+            .map_err(|e| e.into())
+    }
+
+    /// Send data and receive response with proper timeout handling
+    async fn send_and_receive(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.update_activity();
+
+        // Write data with timeout
+        Self::send(&mut self.stream, &self.config, data).await?;
 
         // Read response with timeout and size limits
-        let mut response = BytesMut::with_capacity(self.config.buffer_size);
-        let mut buffer = vec![0u8; self.config.buffer_size];
+        let pool = global_pool();
+        let mut response = pool.get_with_capacity(self.config.buffer_size);
+        let mut buffer = pool.get_with_capacity(self.config.buffer_size);
+        buffer.resize(self.config.buffer_size, 0);
 
         loop {
             let read_result = timeout(
@@ -159,13 +173,78 @@ where
         Ok(response.to_vec())
     }
 
+    /// Send data as one frame and receive the next frame in response
+    async fn send_and_receive_frame(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        self.update_activity();
+
+        let Self {
+            stream,
+            config,
+            frames: Some(frames),
+            last_activity,
+        } = self
+        else {
+            return Err(EchoError::Config(
+                "Client has no framing configured".to_string(),
+            ));
+        };
+        let frame = frames.encode(data)?;
+        Self::send(stream, config, &frame).await?;
+
+        let mut buffer = global_pool().get_with_capacity(config.buffer_size);
+        buffer.resize(config.buffer_size, 0);
+        loop {
+            if let Some(frame) = frames.next_frame()? {
+                *last_activity = Instant::now();
+                return Ok(frame.to_vec());
+            }
+
+            let read_result = timeout(config.read_timeout, P::read(stream, &mut buffer)).await;
+            match read_result {
+                Ok(Ok(0)) => {
+                    // Whatever is left must make up the last frame
+                    frames.close();
+                    let frame = frames.next_frame()?.ok_or_else(|| {
+                        EchoError::Frame("Connection closed before a response frame".to_string())
+                    })?;
+                    return Ok(frame.to_vec());
+                }
+                Ok(Ok(n)) => {
+                    // Check size limit before extending
+                    if frames.buffered() + n > config.max_response_size {
+                        return Err(EchoError::Config(format!(
+                            "Response too large: {} bytes, max allowed: {}",
+                            frames.buffered() + n,
+                            config.max_response_size
+                        )));
+                    }
+                    frames.extend(&buffer[..n]);
+                }
+                Ok(Err(e)) => {
+                    return Err(e.into());
+                }
+                Err(_) => {
+                    return Err(EchoError::Timeout(format!(
+                        "Read timeout: no complete frame after {} bytes",
+                        frames.buffered()
+                    )));
+                }
+            }
+        }
+    }
+
     /// Get client configuration
     pub fn config(&self) -> &ClientConfig {
         &self.config
     }
 
     /// Update client configuration
+    ///
+    /// Changing the framing discards any partly received frame.
     pub fn set_config(&mut self, config: ClientConfig) {
+        if config.framing != self.config.framing {
+            self.frames = config.framing.clone().map(FrameBuffer::new);
+        }
         self.config = config;
     }
 }
@@ -176,7 +255,8 @@ where
     P::Error: Into<EchoError> + std::fmt::Display,
 {
     async fn echo(&mut self, data: &[u8]) -> Result<Vec<u8>> {
-        if data.is_empty() {
+        // An empty frame is still a message worth answering
+        if data.is_empty() && self.frames.is_none() {
             return Ok(Vec::new());
         }
 
@@ -189,6 +269,9 @@ where
             )));
         }
 
+        if self.frames.is_some() {
+            return self.send_and_receive_frame(data).await;
+        }
         self.send_and_receive(data).await
     }
 }
@@ -230,6 +313,11 @@ impl ClientConfigBuilder {
         self
     }
 
+    pub fn framing(mut self, codec: FrameCodec) -> Self {
+        self.config.framing = Some(codec);
+        self
+    }
+
     pub fn build(self) -> ClientConfig {
         self.config
     }
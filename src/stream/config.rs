@@ -1,3 +1,7 @@
+use super::FrameCodec;
+use crate::fault::FaultConfig;
+use crate::handler::Transform;
+use crate::network::SocketOptions;
 use std::net::SocketAddr;
 use std::time::Duration;
 
@@ -14,16 +18,26 @@ use std::time::Duration;
 ///
 /// let config = StreamConfig {
 ///     bind_addr: "127.0.0.1:8080".parse().unwrap(),
+///     additional_addrs: Vec::new(),
 ///     max_connections: 100,
 ///     buffer_size: 1024,
 ///     read_timeout: Duration::from_secs(30),
 ///     write_timeout: Duration::from_secs(30),
+///     faults: None,
+///     transform: None,
+///     framing: None,
+///     workers: 1,
+///     steer_by_cpu: false,
+///     socket_options: Default::default(),
 /// };
 /// ```
 #[derive(Debug, Clone)]
 pub struct StreamConfig {
     /// Address to bind the server to
     pub bind_addr: SocketAddr,
+    /// Further addresses served alongside `bind_addr`, sharing its
+    /// connection limit and statistics
+    pub additional_addrs: Vec<SocketAddr>,
     /// Maximum number of concurrent connections
     pub max_connections: usize,
     /// Buffer size for reading/writing data
@@ -32,16 +46,46 @@ pub struct StreamConfig {
     pub read_timeout: Duration,
     /// Write timeout for connections
     pub write_timeout: Duration,
+    /// Fault injection settings, applied when served through `fault::Faulty`
+    pub faults: Option<FaultConfig>,
+    /// Transform applied to echoed data; plain echo if `None`
+    pub transform: Option<Transform>,
+    /// Framing of messages exchanged with clients; raw chunks if `None`
+    pub framing: Option<FrameCodec>,
+    /// Accept loops, each on its own `SO_REUSEPORT` listener; 0 runs one per
+    /// runtime worker thread
+    pub workers: usize,
+    /// Hand each new connection to the accept loop of the CPU that received
+    /// it, rather than by the kernel's hash of its addresses (Linux only)
+    pub steer_by_cpu: bool,
+    /// Socket options of the listener and accepted streams
+    pub socket_options: SocketOptions,
+}
+
+impl StreamConfig {
+    /// Returns every address the server binds, `bind_addr` first
+    pub fn addresses(&self) -> Vec<SocketAddr> {
+        std::iter::once(self.bind_addr)
+            .chain(self.additional_addrs.iter().copied())
+            .collect()
+    }
 }
 
 impl Default for StreamConfig {
     fn default() -> Self {
         Self {
             bind_addr: "127.0.0.1:0".parse().unwrap(),
+            additional_addrs: Vec::new(),
             max_connections: 100,
             buffer_size: 1024,
             read_timeout: Duration::from_secs(30),
             write_timeout: Duration::from_secs(30),
+            faults: None,
+            transform: None,
+            framing: None,
+            workers: 1,
+            steer_by_cpu: false,
+            socket_options: SocketOptions::default(),
         }
     }
 }
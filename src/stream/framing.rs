@@ -0,0 +1,467 @@
+use crate::{EchoError, Result};
+use bytes::{Buf, BufMut, BytesMut};
+use std::fmt;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Largest frame accepted by a [`FrameCodec`] unless configured otherwise
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// How messages are delimited on a stream connection
+///
+/// Without framing a stream server answers whatever each read returned, so
+/// message boundaries are lost. With framing both sides exchange whole
+/// messages.
+///
+/// # Examples
+///
+/// ```
+/// use echosrv::stream::Framing;
+///
+/// let framing: Framing = "netstring".parse().unwrap();
+/// assert_eq!(framing, Framing::Netstring);
+/// assert_eq!(Framing::LengthU16.to_string(), "u16");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Newline-terminated messages; a trailing `\r` is stripped as well
+    Lines,
+    /// Messages preceded by their length as a big-endian `u16`
+    LengthU16,
+    /// Messages preceded by their length as a big-endian `u32`
+    LengthU32,
+    /// Netstrings: `<decimal length>:<payload>,`
+    Netstring,
+}
+
+impl Framing {
+    /// Every framing, in the order they are listed in help texts
+    pub const ALL: [Framing; 4] = [
+        Framing::Lines,
+        Framing::LengthU16,
+        Framing::LengthU32,
+        Framing::Netstring,
+    ];
+
+    /// The name used to select the framing
+    pub fn name(self) -> &'static str {
+        match self {
+            Framing::Lines => "lines",
+            Framing::LengthU16 => "u16",
+            Framing::LengthU32 => "u32",
+            Framing::Netstring => "netstring",
+        }
+    }
+}
+
+impl std::str::FromStr for Framing {
+    type Err = EchoError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        let alias = match name.as_str() {
+            "line" | "newline" => "lines",
+            "length-u16" => "u16",
+            "length-u32" => "u32",
+            other => other,
+        };
+        Framing::ALL
+            .into_iter()
+            .find(|f| f.name() == alias)
+            .ok_or_else(|| {
+                let names: Vec<_> = Framing::ALL.iter().map(|f| f.name()).collect();
+                EchoError::Config(format!(
+                    "Unknown framing '{s}', expected one of {}",
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Codec that splits a byte stream into [`Framing`] messages and back
+///
+/// Frames larger than the maximum frame size are rejected in both
+/// directions with [`EchoError::Frame`], as is malformed input.
+///
+/// # Examples
+///
+/// ```
+/// use bytes::BytesMut;
+/// use echosrv::stream::{FrameCodec, Framing};
+/// use tokio_util::codec::{Decoder, Encoder};
+///
+/// let mut codec = FrameCodec::new(Framing::Netstring);
+/// let mut buffer = BytesMut::new();
+/// codec.encode(&b"hello"[..], &mut buffer).unwrap();
+/// assert_eq!(&buffer[..], b"5:hello,");
+/// assert_eq!(&codec.decode(&mut buffer).unwrap().unwrap()[..], b"hello");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameCodec {
+    framing: Framing,
+    max_frame_size: usize,
+    /// Where to resume looking for a newline in a partial line
+    next_index: usize,
+}
+
+impl FrameCodec {
+    /// Creates a codec for `framing` with the default maximum frame size
+    pub fn new(framing: Framing) -> Self {
+        Self {
+            framing,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            next_index: 0,
+        }
+    }
+
+    /// Sets the largest payload, in bytes, a frame may carry
+    pub fn with_max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = size;
+        self
+    }
+
+    /// The framing this codec implements
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// The largest payload, in bytes, a frame may carry
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    fn check_size(&self, len: usize) -> Result<()> {
+        if len > self.max_frame_size {
+            return Err(EchoError::Frame(format!(
+                "Frame too large: {len} bytes, max allowed: {}",
+                self.max_frame_size
+            )));
+        }
+        Ok(())
+    }
+
+    fn decode_line(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>> {
+        let start = self.next_index.min(src.len());
+        let Some(offset) = src[start..].iter().position(|&b| b == b'\n') else {
+            self.next_index = src.len();
+            // Anything beyond the limit (and a `\r`) can only end in an
+            // oversized line
+            if src.len() > self.max_frame_size.saturating_add(1) {
+                return self.check_size(src.len()).map(|_| None);
+            }
+            return Ok(None);
+        };
+
+        self.next_index = 0;
+        let mut line = src.split_to(start + offset + 1);
+        line.truncate(line.len() - 1);
+        if line.ends_with(b"\r") {
+            line.truncate(line.len() - 1);
+        }
+        self.check_size(line.len())?;
+        Ok(Some(line))
+    }
+
+    fn decode_prefixed(&mut self, src: &mut BytesMut, width: usize) -> Result<Option<BytesMut>> {
+        if src.len() < width {
+            return Ok(None);
+        }
+        let len = match width {
+            2 => u16::from_be_bytes([src[0], src[1]]) as usize,
+            _ => u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize,
+        };
+        self.check_size(len)?;
+        if src.len() < width + len {
+            src.reserve(width + len - src.len());
+            return Ok(None);
+        }
+        src.advance(width);
+        Ok(Some(src.split_to(len)))
+    }
+
+    fn decode_netstring(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>> {
+        // The length can never need more digits than the maximum frame size
+        let max_digits = self.max_frame_size.to_string().len();
+        let header = &src[..src.len().min(max_digits + 1)];
+        let colon = header.iter().position(|&b| b == b':');
+        let digits = &header[..colon.unwrap_or(header.len())];
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return Err(EchoError::Frame(
+                "Invalid netstring: length is not a decimal number".to_string(),
+            ));
+        }
+        let Some(colon) = colon else {
+            if digits.len() > max_digits {
+                return self.check_size(usize::MAX).map(|_| None);
+            }
+            return Ok(None);
+        };
+        if digits.is_empty() {
+            return Err(EchoError::Frame(
+                "Invalid netstring: missing length".to_string(),
+            ));
+        }
+
+        let len = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|d| d.parse::<usize>().ok())
+            .unwrap_or(usize::MAX);
+        self.check_size(len)?;
+        let total = (colon + 2).saturating_add(len);
+        if src.len() < total {
+            src.reserve(total - src.len());
+            return Ok(None);
+        }
+        if src[total - 1] != b',' {
+            return Err(EchoError::Frame(
+                "Invalid netstring: missing trailing comma".to_string(),
+            ));
+        }
+        src.advance(colon + 1);
+        let payload = src.split_to(len);
+        src.advance(1);
+        Ok(Some(payload))
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = BytesMut;
+    type Error = EchoError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>> {
+        match self.framing {
+            Framing::Lines => self.decode_line(src),
+            Framing::LengthU16 => self.decode_prefixed(src, 2),
+            Framing::LengthU32 => self.decode_prefixed(src, 4),
+            Framing::Netstring => self.decode_netstring(src),
+        }
+    }
+
+    /// Decodes what is left once the peer has closed
+    ///
+    /// An unterminated last line is still a line; any other leftover bytes
+    /// are an incomplete frame.
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>> {
+        if let Some(frame) = self.decode(src)? {
+            return Ok(Some(frame));
+        }
+        if src.is_empty() {
+            return Ok(None);
+        }
+        if self.framing == Framing::Lines {
+            self.next_index = 0;
+            let mut line = src.split();
+            if line.ends_with(b"\r") {
+                line.truncate(line.len() - 1);
+            }
+            return Ok(Some(line));
+        }
+        Err(EchoError::Frame(format!(
+            "Connection closed with an incomplete frame: {} bytes buffered",
+            src.len()
+        )))
+    }
+}
+
+impl Encoder<&[u8]> for FrameCodec {
+    type Error = EchoError;
+
+    /// Writes `item` as one frame
+    ///
+    /// With [`Framing::Lines`], a payload that already ends with a newline is
+    /// written as is rather than gaining a blank line.
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> Result<()> {
+        self.check_size(item.len())?;
+        match self.framing {
+            Framing::Lines => {
+                dst.reserve(item.len() + 1);
+                dst.put_slice(item);
+                if !item.ends_with(b"\n") {
+                    dst.put_u8(b'\n');
+                }
+            }
+            Framing::LengthU16 => {
+                let len = u16::try_from(item.len()).map_err(|_| {
+                    EchoError::Frame(format!(
+                        "Frame too large for a u16 length prefix: {} bytes",
+                        item.len()
+                    ))
+                })?;
+                dst.reserve(item.len() + 2);
+                dst.put_u16(len);
+                dst.put_slice(item);
+            }
+            Framing::LengthU32 => {
+                let len = u32::try_from(item.len()).map_err(|_| {
+                    EchoError::Frame(format!(
+                        "Frame too large for a u32 length prefix: {} bytes",
+                        item.len()
+                    ))
+                })?;
+                dst.reserve(item.len() + 4);
+                dst.put_u32(len);
+                dst.put_slice(item);
+            }
+            Framing::Netstring => {
+                let header = format!("{}:", item.len());
+                dst.reserve(header.len() + item.len() + 1);
+                dst.put_slice(header.as_bytes());
+                dst.put_slice(item);
+                dst.put_u8(b',');
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bytes read from a framed connection, handed out one frame at a time
+#[derive(Debug)]
+pub(crate) struct FrameBuffer {
+    codec: FrameCodec,
+    buffer: BytesMut,
+    closed: bool,
+}
+
+impl FrameBuffer {
+    pub(crate) fn new(codec: FrameCodec) -> Self {
+        Self {
+            codec,
+            buffer: BytesMut::new(),
+            closed: false,
+        }
+    }
+
+    /// Appends bytes read from the connection
+    pub(crate) fn extend(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Records that the peer closed, so leftover bytes must form a frame
+    pub(crate) fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// Number of bytes not yet returned as frames
+    pub(crate) fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the next complete frame, if one has arrived
+    pub(crate) fn next_frame(&mut self) -> Result<Option<BytesMut>> {
+        if self.closed {
+            self.codec.decode_eof(&mut self.buffer)
+        } else {
+            self.codec.decode(&mut self.buffer)
+        }
+    }
+
+    /// Encodes `data` as a frame ready to be written
+    pub(crate) fn encode(&mut self, data: &[u8]) -> Result<BytesMut> {
+        let mut frame = BytesMut::new();
+        self.codec.encode(data, &mut frame)?;
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(codec: &mut FrameCodec, input: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let mut buffer = BytesMut::from(input);
+        let mut frames = Vec::new();
+        while let Some(frame) = codec.decode_eof(&mut buffer)? {
+            frames.push(frame.to_vec());
+        }
+        Ok(frames)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for framing in Framing::ALL {
+            let mut codec = FrameCodec::new(framing);
+            let mut buffer = BytesMut::new();
+            for message in [&b"hello"[..], b"", b"with, colon: and bytes \x00\xff"] {
+                codec.encode(message, &mut buffer).unwrap();
+            }
+            let frames = decode_all(&mut codec, &buffer).unwrap();
+            assert_eq!(
+                frames,
+                vec![
+                    b"hello".to_vec(),
+                    Vec::new(),
+                    b"with, colon: and bytes \x00\xff".to_vec()
+                ],
+                "{framing}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_partial_frames_wait_for_more() {
+        let mut codec = FrameCodec::new(Framing::LengthU32);
+        let mut buffer = BytesMut::from(&b"\x00\x00\x00\x05hel"[..]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        buffer.extend_from_slice(b"lo\x00\x00");
+        assert_eq!(&codec.decode(&mut buffer).unwrap().unwrap()[..], b"hello");
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        assert!(codec.decode_eof(&mut buffer).is_err());
+
+        let mut codec = FrameCodec::new(Framing::Lines);
+        let mut buffer = BytesMut::from(&b"one\r\ntw"[..]);
+        assert_eq!(&codec.decode(&mut buffer).unwrap().unwrap()[..], b"one");
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        buffer.extend_from_slice(b"o\nthree");
+        assert_eq!(&codec.decode(&mut buffer).unwrap().unwrap()[..], b"two");
+        assert_eq!(
+            &codec.decode_eof(&mut buffer).unwrap().unwrap()[..],
+            b"three"
+        );
+    }
+
+    #[test]
+    fn test_max_frame_size() {
+        for framing in Framing::ALL {
+            let mut codec = FrameCodec::new(framing).with_max_frame_size(4);
+            let mut buffer = BytesMut::new();
+            assert!(matches!(
+                codec.encode(&b"hello"[..], &mut buffer),
+                Err(EchoError::Frame(_))
+            ));
+
+            let mut large = FrameCodec::new(framing);
+            large.encode(&b"hello"[..], &mut buffer).unwrap();
+            assert!(
+                matches!(codec.decode(&mut buffer), Err(EchoError::Frame(_))),
+                "{framing}"
+            );
+        }
+
+        // Oversized lines are rejected before their newline arrives
+        let mut codec = FrameCodec::new(Framing::Lines).with_max_frame_size(4);
+        assert!(codec.decode(&mut BytesMut::from(&b"hello world"[..])).is_err());
+    }
+
+    #[test]
+    fn test_malformed_netstrings() {
+        let mut codec = FrameCodec::new(Framing::Netstring);
+        assert!(codec.decode(&mut BytesMut::from(&b"x:abc,"[..])).is_err());
+        assert!(codec.decode(&mut BytesMut::from(&b":abc,"[..])).is_err());
+        assert!(codec.decode(&mut BytesMut::from(&b"3:abc;"[..])).is_err());
+        assert!(codec.decode(&mut BytesMut::from(&b"99999999"[..])).is_err());
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for framing in Framing::ALL {
+            assert_eq!(framing.to_string().parse::<Framing>().unwrap(), framing);
+        }
+        assert_eq!("Newline".parse::<Framing>().unwrap(), Framing::Lines);
+        assert!("json".parse::<Framing>().is_err());
+    }
+}
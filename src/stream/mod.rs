@@ -5,10 +5,12 @@
 
 pub mod client;
 pub mod config;
+pub mod framing;
 pub mod protocol;
 pub mod server;
 
 pub use client::{Client, ClientConfig, ClientConfigBuilder};
 pub use config::StreamConfig;
-pub use protocol::StreamProtocol;
+pub use framing::{FrameCodec, Framing};
+pub use protocol::{ReadHalf, SplitWrite, StreamProtocol, WriteHalf};
 pub use server::StreamEchoServer;
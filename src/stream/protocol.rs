@@ -1,7 +1,31 @@
 use super::config::StreamConfig;
+use crate::network::Address;
 use crate::network::fd_inheritance::FdInheritanceConfig;
+use crate::network::SocketOptions;
 use async_trait::async_trait;
+use bytes::{BufMut, BytesMut};
 use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Read half of a stream split for full-duplex I/O
+pub type ReadHalf = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Write half of a stream split for full-duplex I/O
+pub type WriteHalf = Box<dyn SplitWrite>;
+
+/// Writer that can be the write half of a split stream
+///
+/// Protocols implement it for the write halves their
+/// [`into_split`](StreamProtocol::into_split) returns.
+pub trait SplitWrite: AsyncWrite + Send + Unpin {
+    /// Makes the stream close abruptly once its read half is dropped too,
+    /// sending a reset where the protocol has one
+    ///
+    /// Default implementation simply drops the half.
+    fn abort(self: Box<Self>) -> std::io::Result<()> {
+        Ok(())
+    }
+}
 
 /// Trait for stream-based protocols (TCP, Unix streams, etc.)
 ///
@@ -42,14 +66,81 @@ pub trait StreamProtocol {
         Self::bind(config).await
     }
 
+    /// Binds `count` listeners that share the configured address
+    ///
+    /// The kernel spreads new connections across the listeners, so each can
+    /// be served by its own accept loop. The default implementation only
+    /// supports a single listener; protocols that can share an address (such
+    /// as TCP with `SO_REUSEPORT`) override it.
+    async fn bind_workers(
+        config: &StreamConfig,
+        count: usize,
+    ) -> std::result::Result<Vec<Self::Listener>, Self::Error> {
+        if count > 1 {
+            return Err(Self::map_io_error(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "this protocol cannot share an address between listeners",
+            )));
+        }
+        Ok(vec![Self::bind(config).await?])
+    }
+
+    /// Binds `count` listeners on each configured address, as
+    /// [`bind_workers`](Self::bind_workers) would for that address alone
+    ///
+    /// The listeners of every address are returned together, each paired
+    /// with its address and `bind_addr`'s first, so one server can accept
+    /// from them all.
+    async fn bind_all(
+        config: &StreamConfig,
+        count: usize,
+    ) -> std::result::Result<Vec<(SocketAddr, Self::Listener)>, Self::Error> {
+        let mut listeners = Vec::new();
+        for addr in config.addresses() {
+            let config = StreamConfig {
+                bind_addr: addr,
+                ..config.clone()
+            };
+            let bound = Self::bind_workers(&config, count).await?;
+            listeners.extend(bound.into_iter().map(|bound| (addr, bound)));
+        }
+        Ok(listeners)
+    }
+
     /// Accepts a new connection from the listener (server-side)
     async fn accept(
         listener: &mut Self::Listener,
     ) -> std::result::Result<(Self::Stream, SocketAddr), Self::Error>;
 
+    /// Applies the per-connection socket options to an accepted stream
+    ///
+    /// Default implementation leaves the stream as it is, for protocols
+    /// without such options.
+    fn configure_stream(
+        stream: &Self::Stream,
+        options: &SocketOptions,
+    ) -> std::result::Result<(), Self::Error> {
+        let _ = (stream, options);
+        Ok(())
+    }
+
     /// Connects to a server at the given address (client-side)
     async fn connect(addr: SocketAddr) -> std::result::Result<Self::Stream, Self::Error>;
 
+    /// Connects to a server at a network or Unix address (client-side)
+    ///
+    /// Default implementation connects to network addresses through
+    /// `connect` and reports Unix paths as unsupported.
+    async fn connect_address(addr: &Address) -> std::result::Result<Self::Stream, Self::Error> {
+        match addr {
+            Address::Network(addr) => Self::connect(*addr).await,
+            Address::Unix(path) => Err(Self::map_io_error(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("cannot connect to Unix socket {}", path.display()),
+            ))),
+        }
+    }
+
     /// Reads data from a stream
     async fn read(
         stream: &mut Self::Stream,
@@ -62,6 +153,92 @@ pub trait StreamProtocol {
     /// Flushes a stream
     async fn flush(stream: &mut Self::Stream) -> std::result::Result<(), Self::Error>;
 
+    /// Splits a stream into halves that can be read and written concurrently
+    ///
+    /// Protocols that cannot be split hand the stream back unchanged, and the
+    /// server falls back to reading and writing in turn. Default
+    /// implementation never splits.
+    fn into_split(stream: Self::Stream) -> std::result::Result<(ReadHalf, WriteHalf), Self::Stream> {
+        Err(stream)
+    }
+
+    /// Reads data from the read half of a split stream
+    async fn read_half(
+        half: &mut ReadHalf,
+        buffer: &mut [u8],
+    ) -> std::result::Result<usize, Self::Error> {
+        half.read(buffer).await.map_err(Self::map_io_error)
+    }
+
+    /// Reads at most `max` bytes from the read half of a split stream into
+    /// the spare capacity of `buffer`
+    async fn read_half_buf(
+        half: &mut ReadHalf,
+        buffer: &mut BytesMut,
+        max: usize,
+    ) -> std::result::Result<usize, Self::Error> {
+        half.read_buf(&mut buffer.limit(max))
+            .await
+            .map_err(Self::map_io_error)
+    }
+
+    /// Writes and flushes data to the write half of a split stream
+    async fn write_half(
+        half: &mut WriteHalf,
+        data: &[u8],
+    ) -> std::result::Result<(), Self::Error> {
+        half.write_all(data).await.map_err(Self::map_io_error)?;
+        half.flush().await.map_err(Self::map_io_error)
+    }
+
+    /// Echoes everything read from a stream straight back until the peer
+    /// closes it, without copying the data through userspace
+    ///
+    /// Returns the number of bytes echoed, or `None` if the protocol has no
+    /// zero-copy path and the server should echo through a buffer instead,
+    /// which is the default.
+    async fn splice_echo(
+        stream: &mut Self::Stream,
+        config: &StreamConfig,
+    ) -> Option<std::result::Result<u64, Self::Error>> {
+        let _ = (stream, config);
+        None
+    }
+
+    /// Shuts down the write half of a stream, leaving the read half open
+    ///
+    /// Default implementation reports the operation as unsupported.
+    async fn shutdown_write(stream: &mut Self::Stream) -> std::result::Result<(), Self::Error> {
+        let _ = stream;
+        Err(Self::map_io_error(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "half-close is not supported by this protocol",
+        )))
+    }
+
+    /// Closes a stream abruptly, sending a reset where the protocol has one
+    ///
+    /// Default implementation simply drops the stream.
+    async fn abort(stream: Self::Stream) -> std::result::Result<(), Self::Error> {
+        drop(stream);
+        Ok(())
+    }
+
+    /// Shuts down the write half of a split stream, leaving its read half open
+    async fn shutdown_write_half(half: &mut WriteHalf) -> std::result::Result<(), Self::Error> {
+        half.shutdown().await.map_err(Self::map_io_error)
+    }
+
+    /// Closes a split stream abruptly, like [`abort`](Self::abort)
+    ///
+    /// Default implementation aborts the write half through
+    /// [`SplitWrite::abort`] and drops both halves.
+    async fn abort_split(read: ReadHalf, write: WriteHalf) -> std::result::Result<(), Self::Error> {
+        let aborted = write.abort().map_err(Self::map_io_error);
+        drop(read);
+        aborted
+    }
+
     /// Maps a standard IO error to this protocol's error type
     fn map_io_error(err: std::io::Error) -> Self::Error;
 }
@@ -1,17 +1,55 @@
-use super::{StreamConfig, StreamProtocol};
+use super::framing::FrameBuffer;
+use super::{ReadHalf, StreamConfig, StreamProtocol, WriteHalf};
 use crate::common::EchoServerTrait;
+use crate::fault::chaos::FaultSource;
+use crate::fault::stream::ConnectionFaults;
+use crate::fault::{FaultRng, StreamEnding, StreamFaults};
+use crate::handler::{Echo, EchoHandler, Opening, StreamContext};
+use crate::performance::buffer_pool::Payload;
+use crate::performance::global_pool;
 use crate::{EchoError, Result};
 use async_trait::async_trait;
+use bytes::Bytes;
+use std::borrow::Cow;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinSet;
 use tokio::{signal, time::timeout};
-use tracing::{Instrument, error, info, warn};
+use tracing::{Instrument, debug, error, info, warn};
+
+/// Responses queued between the reading and writing halves of a connection
+///
+/// Once the queue is full the server stops reading, so a client that does
+/// not read its echoes is slowed down rather than buffered without limit.
+const DUPLEX_QUEUE_DEPTH: usize = 16;
 
 /// Generic stream-based echo server that works with any stream protocol
 ///
 /// This server can work with any protocol that implements `StreamProtocol`,
-/// such as TCP, Unix streams, etc.
+/// such as TCP, Unix streams, etc. Each chunk read from a connection is
+/// answered by its [`EchoHandler`]: the config's
+/// [`Transform`](crate::handler::Transform) if one is set, otherwise a plain
+/// echo. Either can be replaced with [`with_handler`](Self::with_handler).
+///
+/// When the config sets a [`FrameCodec`](super::FrameCodec), the handler is
+/// given whole messages instead of chunks, each response is sent back as one
+/// frame, and a malformed or oversized frame closes the connection.
+///
+/// Connections are full-duplex when the protocol can split its streams: one
+/// half keeps reading while the other writes earlier responses back, so
+/// pipelining clients are not held to one round-trip per chunk. Connections
+/// with stream faults, and protocols that cannot be split, read and write in
+/// turn.
+///
+/// A plain echo with no framing, no stream faults and its debug-level
+/// `Received data` previews filtered out of the logs, as they are by
+/// default, skips the buffer altogether on
+/// protocols with a zero-copy path, such as TCP on Linux, where
+/// [`splice_echo`](StreamProtocol::splice_echo) moves the data from the
+/// receive queue to the send queue inside the kernel. Setting a handler,
+/// even [`Echo`], keeps every connection on the buffered path.
 ///
 /// # Examples
 ///
@@ -27,10 +65,17 @@ use tracing::{Instrument, error, info, warn};
 /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
 ///     let config = StreamConfig {
 ///         bind_addr: "127.0.0.1:8080".parse()?,
+///         additional_addrs: Vec::new(),
 ///         max_connections: 100,
 ///         buffer_size: 1024,
 ///         read_timeout: Duration::from_secs(30),
 ///         write_timeout: Duration::from_secs(30),
+///         faults: None,
+///         transform: None,
+///         framing: None,
+///         workers: 1,
+///         steer_by_cpu: false,
+///         socket_options: Default::default(),
 ///     };
 ///
 ///     let server: StreamEchoServer<TcpProtocol> = StreamEchoServer::new(config);
@@ -42,6 +87,8 @@ pub struct StreamEchoServer<P: StreamProtocol> {
     config: StreamConfig,
     protocol: std::marker::PhantomData<P>,
     shutdown_signal: Arc<tokio::sync::broadcast::Sender<()>>,
+    handler: Arc<dyn EchoHandler>,
+    splice: bool,
 }
 
 impl<P: StreamProtocol> StreamEchoServer<P>
@@ -51,10 +98,36 @@ where
     /// Creates a new stream-based echo server with the given configuration
     pub fn new(config: StreamConfig) -> Self {
         let (shutdown_signal, _) = tokio::sync::broadcast::channel(1);
+        let handler: Arc<dyn EchoHandler> = match config.transform {
+            Some(transform) => Arc::new(transform),
+            None => Arc::new(Echo),
+        };
         Self {
+            splice: config.transform.is_none(),
             config,
             protocol: std::marker::PhantomData,
             shutdown_signal: Arc::new(shutdown_signal),
+            handler,
+        }
+    }
+
+    /// Replaces the echo with `handler` as the source of every response
+    pub fn with_handler(mut self, handler: impl EchoHandler + 'static) -> Self {
+        self.handler = Arc::new(handler);
+        self.splice = false;
+        self
+    }
+
+    /// Returns the server's configuration
+    pub fn config(&self) -> &StreamConfig {
+        &self.config
+    }
+
+    /// Returns how many accept loops to run
+    fn worker_count(&self) -> usize {
+        match self.config.workers {
+            0 => tokio::runtime::Handle::current().metrics().num_workers(),
+            workers => workers,
         }
     }
 
@@ -63,12 +136,79 @@ where
         mut stream: P::Stream,
         addr: SocketAddr,
         config: StreamConfig,
-    ) -> Result<()> {
-        let mut buffer = vec![0; config.buffer_size];
+        handler: Arc<dyn EchoHandler>,
+        mut faults: Option<ConnectionFaults>,
+        splice: bool,
+    ) -> Result<()>
+    where
+        P: Send,
+    {
+        // Data moved inside the kernel cannot be previewed, so the zero-copy
+        // path is only taken when the debug-level previews would not be logged
+        if splice
+            && faults.is_none()
+            && config.framing.is_none()
+            && !tracing::enabled!(tracing::Level::DEBUG)
+            && let Some(result) = P::splice_echo(&mut stream, &config).await
+        {
+            let n = result.map_err(Into::into)?;
+            info!(%addr, spliced = n, "Client closed connection");
+            return Ok(());
+        }
+
+        let mut buffer = global_pool().get_with_capacity(config.buffer_size);
+        buffer.resize(config.buffer_size, 0);
+        let mut context = StreamContext::new(addr, config.bind_addr);
+        let mut frames = config.framing.clone().map(FrameBuffer::new);
+
+        match handler.open(&context) {
+            Opening::Wait => {}
+            Opening::Reply(data) => {
+                if let Some(n) =
+                    Self::respond(&mut stream, addr, &config, &data, &mut faults).await?
+                {
+                    info!(%addr, size = n, "Sent reply, closing connection");
+                }
+                return Ok(());
+            }
+            Opening::Generate(chunks) => {
+                return Self::generate(stream, addr, &config, chunks, faults, &mut buffer).await;
+            }
+        }
+
+        // Stream faults act on reads and writes in turn, so only fault-free
+        // connections are split
+        if faults.is_none() {
+            match P::into_split(stream) {
+                Ok((reader, writer)) => {
+                    return Self::duplex(reader, writer, addr, &config, &*handler, context, frames)
+                        .await;
+                }
+                Err(whole) => stream = whole,
+            }
+        }
 
         loop {
-            // Read with timeout
-            let read_result = timeout(config.read_timeout, P::read(&mut stream, &mut buffer)).await;
+            let active = faults.as_ref().and_then(ConnectionFaults::active);
+            if let Some(ending) = active.and_then(StreamFaults::due) {
+                return Self::end_connection(stream, addr, &config, ending, &mut buffer).await;
+            }
+
+            // Read with timeout; a new chaos phase interrupts the wait so that
+            // its faults apply to idle connections too
+            let read_result = tokio::select! {
+                result = timeout(config.read_timeout, P::read(&mut stream, &mut buffer)) => result,
+                phase = async {
+                    match faults.as_mut() {
+                        Some(faults) => faults.next_phase().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    let active = faults.as_ref().and_then(ConnectionFaults::active);
+                    info!(%addr, ?phase, faults = ?active, "Chaos phase applied to connection");
+                    continue;
+                }
+            };
             let n = match read_result {
                 Ok(Ok(n)) => n,
                 Ok(Err(e)) => {
@@ -83,100 +223,689 @@ where
             if n == 0 {
                 // Connection closed by client
                 info!(%addr, "Client closed connection");
+                if let Some(frames) = frames.as_mut() {
+                    // An unterminated last line still gets its answer
+                    frames.close();
+                    Self::answer_frames(
+                        &mut stream,
+                        addr,
+                        &config,
+                        &*handler,
+                        &mut context,
+                        frames,
+                        &mut faults,
+                    )
+                    .await?;
+                }
                 break;
             }
 
-            let preview = String::from_utf8_lossy(&buffer[..n]);
-            info!(%addr, size = n, preview = %preview, "Received data");
+            debug!(%addr, size = n, preview = %String::from_utf8_lossy(&buffer[..n]), "Received data");
 
-            // Echo back the received data with timeout
-            let write_result =
-                timeout(config.write_timeout, P::write(&mut stream, &buffer[..n])).await;
-            match write_result {
-                Ok(Ok(())) => {
-                    P::flush(&mut stream).await.map_err(|e| e.into())?;
-                    info!(%addr, size = n, "Echoed data");
+            if let Some(frames) = frames.as_mut() {
+                frames.extend(&buffer[..n]);
+                let open = Self::answer_frames(
+                    &mut stream,
+                    addr,
+                    &config,
+                    &*handler,
+                    &mut context,
+                    frames,
+                    &mut faults,
+                )
+                .await?;
+                if open {
+                    continue;
                 }
-                Ok(Err(e)) => {
-                    return Err(e.into());
+                break;
+            }
+
+            let response = handler.handle(&buffer[..n], &context);
+            context.advance(n);
+            let Some(response) = response else {
+                debug!(%addr, size = n, "Handler sent no response");
+                continue;
+            };
+
+            match Self::respond(&mut stream, addr, &config, &response, &mut faults).await? {
+                Some(n) => info!(%addr, size = n, "Echoed data"),
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Echoes a split connection, reading and writing concurrently
+    ///
+    /// The reading half answers each chunk or frame and queues the response;
+    /// the writing half sends queued responses as the client accepts them.
+    async fn duplex(
+        mut reader: ReadHalf,
+        mut writer: WriteHalf,
+        addr: SocketAddr,
+        config: &StreamConfig,
+        handler: &dyn EchoHandler,
+        mut context: StreamContext,
+        mut frames: Option<FrameBuffer>,
+    ) -> Result<()>
+    where
+        P: Send,
+    {
+        let (responses, mut queue) = mpsc::channel::<Payload>(DUPLEX_QUEUE_DEPTH);
+
+        let read = async move {
+            let pool = global_pool();
+            let mut buffer = pool.get_with_capacity(config.buffer_size);
+            loop {
+                // Reading into spare capacity spares zero-filling the buffer
+                buffer.clear();
+                let n = match timeout(
+                    config.read_timeout,
+                    P::read_half_buf(&mut reader, &mut buffer, config.buffer_size),
+                )
+                .await
+                {
+                    Ok(Ok(n)) => n,
+                    Ok(Err(e)) => return Err(e.into()),
+                    Err(_) => {
+                        warn!(%addr, "Read timeout");
+                        return Ok(());
+                    }
+                };
+
+                if n == 0 {
+                    info!(%addr, "Client closed connection");
+                    if let Some(frames) = frames.as_mut() {
+                        // An unterminated last line still gets its answer
+                        frames.close();
+                        Self::queue_frames(addr, handler, &mut context, frames, &responses).await;
+                    }
+                    return Ok(());
                 }
-                Err(_) => {
-                    warn!(%addr, "Write timeout");
+
+                debug!(%addr, size = n, preview = %String::from_utf8_lossy(&buffer), "Received data");
+
+                if let Some(frames) = frames.as_mut() {
+                    frames.extend(&buffer);
+                    if !Self::queue_frames(addr, handler, &mut context, frames, &responses).await {
+                        return Ok(());
+                    }
+                    continue;
+                }
+
+                let response = handler.handle(&buffer, &context);
+                context.advance(n);
+                // A plain echo queues the pooled chunk itself rather than a
+                // copy, and reads on into a fresh buffer
+                let response = match response {
+                    Some(Cow::Borrowed(data)) => match buffer.range_of(data) {
+                        Some(range) => {
+                            let next = pool.get_with_capacity(config.buffer_size);
+                            Payload::Pooled(std::mem::replace(&mut buffer, next), range)
+                        }
+                        None => Payload::Shared(Bytes::copy_from_slice(data)),
+                    },
+                    Some(Cow::Owned(data)) => Payload::Shared(Bytes::from(data)),
+                    None => {
+                        debug!(%addr, size = n, "Handler sent no response");
+                        continue;
+                    }
+                };
+                if responses.send(response).await.is_err() {
+                    return Ok(());
+                }
+            }
+        };
+
+        let write = async move {
+            while let Some(response) = queue.recv().await {
+                let response = response.as_bytes();
+                match timeout(config.write_timeout, P::write_half(&mut writer, response)).await {
+                    Ok(Ok(())) => info!(%addr, size = response.len(), "Echoed data"),
+                    Ok(Err(e)) => return Err(e.into()),
+                    Err(_) => {
+                        warn!(%addr, "Write timeout");
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(())
+        };
+
+        tokio::pin!(write);
+        tokio::select! {
+            // Responses still queued are written once reading stops
+            result = read => {
+                result?;
+                write.await
+            }
+            // The connection is done once the client stops taking responses
+            result = &mut write => result,
+        }
+    }
+
+    /// Queues the responses to every complete frame of a split connection
+    ///
+    /// Returns false once the connection should close, because the writing
+    /// half has stopped or the client sent a frame that cannot be decoded.
+    async fn queue_frames(
+        addr: SocketAddr,
+        handler: &dyn EchoHandler,
+        context: &mut StreamContext,
+        frames: &mut FrameBuffer,
+        responses: &mpsc::Sender<Payload>,
+    ) -> bool {
+        loop {
+            let frame = match frames.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return true,
+                Err(e) => {
+                    warn!(%addr, error = %e, "Invalid frame, closing connection");
+                    return false;
+                }
+            };
+
+            let response = handler.handle(&frame, context);
+            context.advance(frame.len());
+            let Some(response) = response else {
+                debug!(%addr, size = frame.len(), "Handler sent no response");
+                continue;
+            };
+            let response = match frames.encode(&response) {
+                Ok(response) => Payload::Shared(response.freeze()),
+                Err(e) => {
+                    warn!(%addr, error = %e, "Cannot frame response, closing connection");
+                    return false;
+                }
+            };
+            if responses.send(response).await.is_err() {
+                return false;
+            }
+        }
+    }
+
+    /// Answers every complete frame buffered for a framed connection
+    ///
+    /// Returns false once the connection should close, because a write timed
+    /// out or the client sent a frame that cannot be decoded.
+    async fn answer_frames(
+        stream: &mut P::Stream,
+        addr: SocketAddr,
+        config: &StreamConfig,
+        handler: &dyn EchoHandler,
+        context: &mut StreamContext,
+        frames: &mut FrameBuffer,
+        faults: &mut Option<ConnectionFaults>,
+    ) -> Result<bool> {
+        loop {
+            let frame = match frames.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(true),
+                Err(e) => {
+                    warn!(%addr, error = %e, "Invalid frame, closing connection");
+                    return Ok(false);
+                }
+            };
+
+            let response = handler.handle(&frame, context);
+            context.advance(frame.len());
+            let Some(response) = response else {
+                debug!(%addr, size = frame.len(), "Handler sent no response");
+                continue;
+            };
+            let response = match frames.encode(&response) {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!(%addr, error = %e, "Cannot frame response, closing connection");
+                    return Ok(false);
+                }
+            };
+
+            match Self::respond(stream, addr, config, &response, faults).await? {
+                Some(n) => info!(%addr, size = n, frame = frame.len(), "Echoed frame"),
+                None => return Ok(false),
+            }
+        }
+    }
+
+    /// Writes chunks from a generating handler until the client goes away
+    ///
+    /// Anything the client sends in the meantime is read and dropped.
+    async fn generate(
+        mut stream: P::Stream,
+        addr: SocketAddr,
+        config: &StreamConfig,
+        chunks: Box<dyn Iterator<Item = Vec<u8>> + Send>,
+        mut faults: Option<ConnectionFaults>,
+        buffer: &mut [u8],
+    ) -> Result<()>
+    where
+        P: Send,
+    {
+        let mut sent = 0;
+        for chunk in chunks {
+            let active = faults.as_ref().and_then(ConnectionFaults::active);
+            if let Some(ending) = active.and_then(StreamFaults::due) {
+                return Self::end_connection(stream, addr, config, ending, buffer).await;
+            }
+
+            match Self::respond(&mut stream, addr, config, &chunk, &mut faults).await {
+                Ok(Some(n)) => sent += n,
+                Ok(None) => break,
+                // Generated data is usually unread when the client hangs up
+                Err(e) if is_disconnect(&e) => {
+                    info!(%addr, "Client closed connection");
                     break;
                 }
+                Err(e) => return Err(e),
             }
+
+            // Poll once for client data without waiting, to notice a close
+            tokio::select! {
+                biased;
+                result = P::read(&mut stream, buffer) => match result.map_err(Into::into) {
+                    Ok(0) => {
+                        info!(%addr, "Client closed connection");
+                        break;
+                    }
+                    Ok(n) => debug!(%addr, size = n, "Discarded data"),
+                    Err(e) if is_disconnect(&e) => {
+                        info!(%addr, "Client closed connection");
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                },
+                _ = std::future::ready(()) => {}
+            }
+        }
+
+        info!(%addr, size = sent, "Generated data");
+        Ok(())
+    }
+
+    /// Writes a response with the write timeout, applying stream faults
+    ///
+    /// Returns how many bytes were written, or `None` if the write timed out.
+    async fn respond(
+        stream: &mut P::Stream,
+        addr: SocketAddr,
+        config: &StreamConfig,
+        response: &[u8],
+        faults: &mut Option<ConnectionFaults>,
+    ) -> Result<Option<usize>> {
+        // Stream faults may cut the response short before ending the connection
+        let mut active = faults.as_mut().and_then(ConnectionFaults::active_mut);
+        let n = active
+            .as_ref()
+            .map_or(response.len(), |f| f.echo_len(response.len()));
+
+        let write_result = timeout(
+            config.write_timeout,
+            Self::write_echo(stream, &response[..n], active.as_deref_mut()),
+        )
+        .await;
+        match write_result {
+            Ok(Ok(())) => P::flush(stream).await.map_err(|e| e.into())?,
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => {
+                warn!(%addr, "Write timeout");
+                return Ok(None);
+            }
+        }
+
+        if let Some(faults) = active {
+            faults.record_echo(n);
+        }
+        Ok(Some(n))
+    }
+
+    /// Writes an echo, splitting it into partial writes if the faults ask for it
+    async fn write_echo(
+        stream: &mut P::Stream,
+        data: &[u8],
+        faults: Option<&mut StreamFaults>,
+    ) -> std::result::Result<(), P::Error> {
+        let Some(faults) = faults.filter(|f| f.splits_writes()) else {
+            return P::write(stream, data).await;
+        };
+
+        let mut rest = data;
+        while !rest.is_empty() {
+            let Some((size, delay)) = faults.next_chunk(rest.len()) else {
+                break;
+            };
+            let (chunk, tail) = rest.split_at(size);
+            P::write(stream, chunk).await?;
+            P::flush(stream).await?;
+            debug!(size, remaining = tail.len(), "Partial write");
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            rest = tail;
+        }
+        Ok(())
+    }
+
+    /// Ends a connection the way its stream faults dictate
+    async fn end_connection(
+        mut stream: P::Stream,
+        addr: SocketAddr,
+        config: &StreamConfig,
+        ending: StreamEnding,
+        buffer: &mut [u8],
+    ) -> Result<()>
+    where
+        P: Send,
+    {
+        match ending {
+            StreamEnding::Reset => {
+                info!(%addr, "Fault: resetting connection");
+                P::abort(stream).await.map_err(|e| e.into())
+            }
+            StreamEnding::HalfClose => {
+                info!(%addr, "Fault: closing write half");
+                P::shutdown_write(&mut stream).await.map_err(|e| e.into())?;
+                Self::discard_reads(&mut stream, addr, config, buffer).await
+            }
+            StreamEnding::StallReads => {
+                // Holding the connection for good would keep its slot taken
+                // after the client leaves, so it idles out like any other
+                info!(%addr, timeout = ?config.read_timeout, "Fault: stalling reads until the read timeout");
+                tokio::time::sleep(config.read_timeout).await;
+                warn!(%addr, "Read timeout");
+                Ok(())
+            }
+            StreamEnding::StallWrites => {
+                info!(%addr, "Fault: stalling writes indefinitely");
+                Self::discard_reads(&mut stream, addr, config, buffer).await
+            }
+        }
+    }
+
+    /// Reads and discards data until the client closes or goes idle
+    async fn discard_reads(
+        stream: &mut P::Stream,
+        addr: SocketAddr,
+        config: &StreamConfig,
+        buffer: &mut [u8],
+    ) -> Result<()> {
+        loop {
+            match timeout(config.read_timeout, P::read(stream, buffer)).await {
+                Ok(Ok(0)) => {
+                    info!(%addr, "Client closed connection");
+                    return Ok(());
+                }
+                Ok(Ok(n)) => debug!(%addr, size = n, "Discarded data"),
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => {
+                    warn!(%addr, "Read timeout");
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+impl<P> StreamEchoServer<P>
+where
+    P: StreamProtocol + Send + Sync + 'static,
+    P::Error: Into<EchoError> + std::fmt::Display,
+    P::Listener: 'static,
+    P::Stream: 'static,
+{
+    /// Accepts connections on `listeners`, each paired with the address it
+    /// is bound to, until shutdown
+    ///
+    /// Lets servers whose listeners are not bound from the configuration
+    /// (such as Unix sockets) bind them themselves.
+    pub(crate) async fn serve(&self, listeners: Vec<(SocketAddr, P::Listener)>) -> Result<()> {
+        let workers = self.worker_count();
+        for address in self.config.addresses() {
+            info!(%address, workers, "Stream echo server listening");
+        }
+
+        let connection_count = Arc::new(AtomicUsize::new(0));
+        let accepted = Arc::new(AcceptCount::default());
+        let faults = FaultSource::new(self.config.faults.as_ref());
+        let mut fault_rng = self
+            .config
+            .faults
+            .as_ref()
+            .filter(|f| f.stream.is_active() || f.chaos.is_some())
+            .map(|f| {
+                if f.chaos.is_some() {
+                    info!(phase = ?faults.phase(), "Stream faults follow chaos timeline");
+                } else {
+                    info!(faults = ?f.stream, "Stream faults configured");
+                }
+                FaultRng::new(f.resolve_seed("stream faults", &self.config.bind_addr))
+            });
+
+        let mut tasks = JoinSet::new();
+        for (id, (address, listener)) in listeners.into_iter().enumerate() {
+            // The first loop keeps the configured seed, so a single loop
+            // makes the same fault decisions regardless of the loop count
+            let fault_rng = fault_rng
+                .as_mut()
+                .map(|rng| if id == 0 { rng.clone() } else { rng.fork() });
+            let acceptor = Acceptor::<P> {
+                listener,
+                config: StreamConfig {
+                    bind_addr: address,
+                    ..self.config.clone()
+                },
+                handler: Arc::clone(&self.handler),
+                splice: self.splice,
+                connection_count: Arc::clone(&connection_count),
+                accepted: Arc::clone(&accepted),
+                faults: faults.clone(),
+                fault_rng,
+                shutdown_rx: self.shutdown_signal.subscribe(),
+            };
+            tasks.spawn(
+                acceptor
+                    .run()
+                    .instrument(tracing::info_span!("acceptor", id)),
+            );
         }
 
+        while let Some(result) = tasks.join_next().await {
+            if let Err(e) = result {
+                error!(error = %e, "Accept loop failed");
+            }
+        }
+
+        info!("Stream echo server stopped");
         Ok(())
     }
 }
 
 #[async_trait]
-impl<P: StreamProtocol + Sync> EchoServerTrait for StreamEchoServer<P>
+impl<P> EchoServerTrait for StreamEchoServer<P>
 where
+    P: StreamProtocol + Send + Sync + 'static,
     P::Error: Into<EchoError> + std::fmt::Display,
+    P::Listener: 'static,
     P::Stream: 'static,
 {
     /// Starts the stream-based echo server and listens for connections
     async fn run(&self) -> Result<()> {
-        let mut listener = P::bind(&self.config).await.map_err(|e| e.into())?;
+        let listeners = P::bind_all(&self.config, self.worker_count())
+            .await
+            .map_err(|e| e.into())?;
+        self.serve(listeners).await
+    }
 
-        info!(address = %self.config.bind_addr, "Stream echo server listening");
+    /// Returns a shutdown signal sender that can be used to gracefully shutdown the server
+    fn shutdown_signal(&self) -> tokio::sync::broadcast::Sender<()> {
+        self.shutdown_signal.as_ref().clone()
+    }
+}
 
-        let connection_count = Arc::new(AtomicUsize::new(0));
-        let mut shutdown_rx = self.shutdown_signal.subscribe();
+/// Connections accepted by all loops of a server in the current phase
+///
+/// Counts towards the `accept_limit` fault, starting over with each chaos
+/// phase.
+#[derive(Debug, Default)]
+struct AcceptCount {
+    /// Phase the count belongs to, and the count
+    state: Mutex<(usize, usize)>,
+}
+
+impl AcceptCount {
+    /// Claims one of the `limit` accepts of `phase`, returning how many are
+    /// claimed with it, or `None` if none are left
+    fn claim(&self, phase: usize, limit: usize) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.0 != phase {
+            *state = (phase, 0);
+        }
+        (state.1 < limit).then(|| {
+            state.1 += 1;
+            state.1
+        })
+    }
+
+    /// Returns a claim that did not lead to a connection
+    fn release(&self, phase: usize) {
+        let mut state = self.state.lock().unwrap();
+        if state.0 == phase {
+            state.1 -= 1;
+        }
+    }
+}
 
+/// One accept loop of a stream server, serving its own listener
+///
+/// Every loop of a server shares its connection count, so the connection
+/// limit holds across all of them, and its accept count, so the accept
+/// limit does too.
+struct Acceptor<P: StreamProtocol> {
+    listener: P::Listener,
+    config: StreamConfig,
+    handler: Arc<dyn EchoHandler>,
+    splice: bool,
+    connection_count: Arc<AtomicUsize>,
+    accepted: Arc<AcceptCount>,
+    faults: FaultSource,
+    fault_rng: Option<FaultRng>,
+    shutdown_rx: broadcast::Receiver<()>,
+}
+
+impl<P> Acceptor<P>
+where
+    P: StreamProtocol + Send + Sync + 'static,
+    P::Error: Into<EchoError> + std::fmt::Display,
+    P::Stream: 'static,
+{
+    /// Accepts connections until shutdown, serving each in its own task
+    async fn run(mut self) {
         loop {
+            // Each loop claims an accept before waiting for a connection, so
+            // together they never exceed the limit
+            let phase = self.faults.phase_id();
+            let limit = self
+                .faults
+                .current()
+                .stream
+                .accept_limit
+                .unwrap_or(usize::MAX);
+            let claimed = self.accepted.claim(phase, limit);
             tokio::select! {
-                accept_result = P::accept(&mut listener) => {
+                accept_result = P::accept(&mut self.listener), if claimed.is_some() => {
                     match accept_result {
                         Ok((stream, addr)) => {
-                            let current_count = connection_count.load(Ordering::SeqCst);
-                            if current_count >= self.config.max_connections {
-                                warn!(%addr, current = current_count, limit = self.config.max_connections, "Connection rejected: limit reached");
-                                continue;
+                            if claimed == Some(limit) {
+                                info!(limit, "Fault: accept limit reached, leaving new connections in the backlog");
                             }
-
-                            connection_count.fetch_add(1, Ordering::SeqCst);
-                            let new_count = connection_count.load(Ordering::SeqCst);
-                            info!(%addr, current = new_count, "Accepted connection");
-
-                            let config = self.config.clone();
-                            let connection_count = connection_count.clone();
-                            let span = tracing::info_span!("connection", %addr, current = new_count);
-
-                            // Handle connection in a separate task with proper Send bounds
-                            tokio::spawn(async move {
-                                let result = Self::handle_connection(stream, addr, config).instrument(span).await;
-                                if let Err(e) = result {
-                                    error!(%addr, error = %e, "Error handling connection");
-                                }
-                                let final_count = connection_count.fetch_sub(1, Ordering::SeqCst) - 1;
-                                info!(%addr, current = final_count, "Connection closed");
-                            });
+                            self.serve(stream, addr);
                         }
                         Err(e) => {
+                            self.accepted.release(phase);
                             error!(error = %e, "Failed to accept connection");
                         }
                     }
                 }
+                _ = self.faults.changed() => {
+                    // The accept limit counts connections from the start of each phase
+                    if claimed.is_some() {
+                        self.accepted.release(phase);
+                    }
+                    info!(address = %self.config.bind_addr, phase = ?self.faults.phase(), "Chaos phase applied to listener");
+                }
                 _ = signal::ctrl_c() => {
                     info!("Received shutdown signal, stopping server");
                     break;
                 }
-                _ = shutdown_rx.recv() => {
+                _ = self.shutdown_rx.recv() => {
                     info!("Received internal shutdown signal, stopping server");
                     break;
                 }
             }
         }
+    }
+
+    /// Spawns the task serving an accepted connection, unless the server is
+    /// already at its connection limit
+    fn serve(&mut self, stream: P::Stream, addr: SocketAddr) {
+        let limit = self.config.max_connections;
+        let admitted =
+            self.connection_count
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                    (count < limit).then_some(count + 1)
+                });
+        let new_count = match admitted {
+            Ok(count) => count + 1,
+            Err(current) => {
+                warn!(%addr, current, limit, "Connection rejected: limit reached");
+                return;
+            }
+        };
+        info!(%addr, current = new_count, "Accepted connection");
+        if let Err(e) = P::configure_stream(&stream, &self.config.socket_options) {
+            let e: EchoError = e.into();
+            warn!(%addr, error = %e, "Failed to set socket options");
+        }
 
-        info!("Stream echo server stopped");
-        Ok(())
+        let config = self.config.clone();
+        let handler = Arc::clone(&self.handler);
+        let splice = self.splice;
+        let connection_count = Arc::clone(&self.connection_count);
+        let span = tracing::info_span!("connection", %addr, current = new_count);
+        let faults = self
+            .fault_rng
+            .as_mut()
+            .map(|rng| ConnectionFaults::new(self.faults.clone(), rng.fork()));
+        if let Some(active) = faults.as_ref().and_then(ConnectionFaults::active) {
+            info!(%addr, faults = ?active, "Fault: misbehaving on this connection");
+        }
+
+        // Handle connection in a separate task with proper Send bounds
+        tokio::spawn(async move {
+            let result = StreamEchoServer::<P>::handle_connection(
+                stream, addr, config, handler, faults, splice,
+            )
+            .instrument(span)
+            .await;
+            if let Err(e) = result {
+                error!(%addr, error = %e, "Error handling connection");
+            }
+            let final_count = connection_count.fetch_sub(1, Ordering::SeqCst) - 1;
+            info!(%addr, current = final_count, "Connection closed");
+        });
     }
+}
 
-    /// Returns a shutdown signal sender that can be used to gracefully shutdown the server
-    fn shutdown_signal(&self) -> tokio::sync::broadcast::Sender<()> {
-        self.shutdown_signal.as_ref().clone()
+/// Returns true if `error` means the peer closed or reset the connection
+fn is_disconnect(error: &EchoError) -> bool {
+    match error {
+        EchoError::Tcp(e) | EchoError::Unix(e) => matches!(
+            e.kind(),
+            std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::BrokenPipe
+                | std::io::ErrorKind::ConnectionAborted
+        ),
+        _ => false,
     }
 }
@@ -1,4 +1,6 @@
-use crate::stream::StreamConfig;
+use crate::handler::Transform;
+use crate::network::SocketOptions;
+use crate::stream::{FrameCodec, StreamConfig};
 use std::net::SocketAddr;
 use std::time::Duration;
 
@@ -12,16 +14,25 @@ use std::time::Duration;
 ///
 /// let config = TcpConfig {
 ///     bind_addr: "127.0.0.1:8080".parse().unwrap(),
+///     additional_addrs: Vec::new(),
 ///     max_connections: 100,
 ///     buffer_size: 1024,
 ///     read_timeout: Duration::from_secs(30),
 ///     write_timeout: Duration::from_secs(30),
+///     transform: None,
+///     framing: None,
+///     workers: 1,
+///     steer_by_cpu: false,
+///     socket_options: Default::default(),
 /// };
 /// ```
 #[derive(Debug, Clone)]
 pub struct TcpConfig {
     /// Address to bind the server to
     pub bind_addr: SocketAddr,
+    /// Further addresses served alongside `bind_addr`, sharing its
+    /// connection limit and statistics
+    pub additional_addrs: Vec<SocketAddr>,
     /// Maximum number of concurrent connections
     pub max_connections: usize,
     /// Buffer size for reading/writing data
@@ -30,16 +41,34 @@ pub struct TcpConfig {
     pub read_timeout: Duration,
     /// Write timeout for connections
     pub write_timeout: Duration,
+    /// Transform applied to echoed data; plain echo if `None`
+    pub transform: Option<Transform>,
+    /// Framing of messages exchanged with clients; raw chunks if `None`
+    pub framing: Option<FrameCodec>,
+    /// Accept loops, each on its own `SO_REUSEPORT` listener; 0 runs one per
+    /// runtime worker thread
+    pub workers: usize,
+    /// Hand each new connection to the accept loop of the CPU that received
+    /// it, rather than by the kernel's hash of its addresses (Linux only)
+    pub steer_by_cpu: bool,
+    /// Socket options of the listener and accepted streams
+    pub socket_options: SocketOptions,
 }
 
 impl Default for TcpConfig {
     fn default() -> Self {
         Self {
             bind_addr: "127.0.0.1:0".parse().unwrap(),
+            additional_addrs: Vec::new(),
             max_connections: 100,
             buffer_size: 1024,
             read_timeout: Duration::from_secs(30),
             write_timeout: Duration::from_secs(30),
+            transform: None,
+            framing: None,
+            workers: 1,
+            steer_by_cpu: false,
+            socket_options: SocketOptions::default(),
         }
     }
 }
@@ -48,10 +77,17 @@ impl From<TcpConfig> for StreamConfig {
     fn from(config: TcpConfig) -> Self {
         Self {
             bind_addr: config.bind_addr,
+            additional_addrs: config.additional_addrs,
             max_connections: config.max_connections,
             buffer_size: config.buffer_size,
             read_timeout: config.read_timeout,
             write_timeout: config.write_timeout,
+            faults: None,
+            transform: config.transform,
+            framing: config.framing,
+            workers: config.workers,
+            steer_by_cpu: config.steer_by_cpu,
+            socket_options: config.socket_options,
         }
     }
 }
@@ -1,8 +1,12 @@
 pub mod config;
 pub mod server;
 pub mod socket_builder;
+#[cfg(target_os = "linux")]
+mod splice;
 pub mod stream_protocol;
-pub mod tests;
+
+#[cfg(test)]
+mod tests;
 
 pub use config::TcpConfig;
 pub use server::TcpEchoServer;
@@ -18,10 +18,16 @@ use crate::stream::StreamEchoServer;
 /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
 ///     let config = TcpConfig {
 ///         bind_addr: "127.0.0.1:8080".parse()?,
+///         additional_addrs: Vec::new(),
 ///         max_connections: 100,
 ///         buffer_size: 1024,
 ///         read_timeout: Duration::from_secs(30),
 ///         write_timeout: Duration::from_secs(30),
+///         transform: None,
+///         framing: None,
+///         workers: 1,
+///         steer_by_cpu: false,
+///         socket_options: Default::default(),
 ///     };
 ///
 ///     let server = TcpEchoServer::new(config.into());
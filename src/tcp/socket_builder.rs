@@ -12,7 +12,9 @@
 
 use crate::network::socket_builder::BuildSocket;
 use crate::network::fd_inheritance::BindTarget;
+use crate::network::socket_options::SocketOptions;
 use crate::{EchoError, Result};
+use socket2::SockRef;
 use std::os::unix::io::{FromRawFd, RawFd};
 use tokio::net::TcpListener;
 
@@ -54,12 +56,12 @@ impl BuildSocket<TcpListener> for TcpSocketBuilder {
         // Configure for async operation with Tokio
         // Tokio requires non-blocking sockets for proper async behavior
         std_listener.set_nonblocking(true)
-            .map_err(|e| EchoError::Tcp(e))?;
+            .map_err(EchoError::Tcp)?;
         
         // Convert std TcpListener to Tokio TcpListener
         // This registers the socket with Tokio's async runtime
         TcpListener::from_std(std_listener)
-            .map_err(|e| EchoError::Tcp(e))
+            .map_err(EchoError::Tcp)
     }
     
     /// Create TCP listener by binding to network address
@@ -76,15 +78,15 @@ impl BuildSocket<TcpListener> for TcpSocketBuilder {
             BindTarget::Network(addr) => {
                 // Create standard library TcpListener bound to address
                 let std_listener = std::net::TcpListener::bind(addr)
-                    .map_err(|e| EchoError::Tcp(e))?;
+                    .map_err(EchoError::Tcp)?;
                 
                 // Configure for async operation
                 std_listener.set_nonblocking(true)
-                    .map_err(|e| EchoError::Tcp(e))?;
+                    .map_err(EchoError::Tcp)?;
                 
                 // Convert to Tokio async TcpListener
                 TcpListener::from_std(std_listener)
-                    .map_err(|e| EchoError::Tcp(e))
+                    .map_err(EchoError::Tcp)
             }
             
             // TCP sockets cannot bind to Unix domain socket paths
@@ -95,4 +97,26 @@ impl BuildSocket<TcpListener> for TcpSocketBuilder {
             }
         }
     }
+    
+    /// Apply listener socket options to a TCP listener
+    /// 
+    /// Buffer sizes, type of service, TTL and the Fast Open queue are set on
+    /// the listener, and accepted connections inherit them. An explicit
+    /// backlog is applied by calling listen() again, which Linux and the BSDs
+    /// accept on a socket that is already listening.
+    /// 
+    /// # Arguments
+    /// * `listener` - Bound or inherited TCP listener
+    /// * `options` - Socket options to apply
+    fn configure(listener: &TcpListener, options: &SocketOptions) -> Result<()> {
+        let socket = SockRef::from(listener);
+        options.apply_to_listener(&socket)
+            .map_err(EchoError::Tcp)?;
+        
+        if options.backlog.is_some() {
+            socket.listen(options.backlog())
+                .map_err(EchoError::Tcp)?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,90 @@
+use crate::stream::StreamConfig;
+use std::io::{self, PipeReader};
+use std::os::fd::{AsRawFd, RawFd};
+use tokio::io::Interest;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tracing::warn;
+
+/// Most bytes moved by one `splice` call, the default capacity of a pipe
+const SPLICE_CHUNK: usize = 64 * 1024;
+
+/// Echoes `stream` back to itself through a pipe until the client closes it,
+/// returning the number of bytes echoed
+///
+/// Each chunk is spliced from the socket's receive queue into the pipe and
+/// from the pipe onto its send queue, so the data never leaves the kernel.
+/// The read and write timeouts apply to every wait, as they do for buffered
+/// echoes, and end the connection the same way.
+pub(crate) async fn echo(stream: &mut TcpStream, config: &StreamConfig) -> io::Result<u64> {
+    let addr = stream.peer_addr()?;
+    let (pipe_out, pipe_in) = io::pipe()?;
+    let socket = stream.as_raw_fd();
+    let mut total = 0;
+
+    loop {
+        let Ok(ready) = timeout(config.read_timeout, stream.readable()).await else {
+            warn!(%addr, "Read timeout");
+            return Ok(total);
+        };
+        ready?;
+        let n = match stream.try_io(Interest::READABLE, || {
+            splice(socket, pipe_in.as_raw_fd(), SPLICE_CHUNK)
+        }) {
+            Ok(0) => return Ok(total),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        };
+        if !drain(stream, &pipe_out, n, config).await? {
+            warn!(%addr, "Write timeout");
+            return Ok(total);
+        }
+        total += n as u64;
+    }
+}
+
+/// Splices the `len` bytes waiting in the pipe onto the socket, returning
+/// false if the client stopped taking them for the write timeout
+async fn drain(
+    stream: &TcpStream,
+    pipe_out: &PipeReader,
+    mut len: usize,
+    config: &StreamConfig,
+) -> io::Result<bool> {
+    let socket = stream.as_raw_fd();
+    while len > 0 {
+        let Ok(ready) = timeout(config.write_timeout, stream.writable()).await else {
+            return Ok(false);
+        };
+        ready?;
+        match stream.try_io(Interest::WRITABLE, || {
+            splice(pipe_out.as_raw_fd(), socket, len)
+        }) {
+            Ok(n) => len -= n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Moves up to `len` bytes from `from` to `to` without blocking on the pipe
+fn splice(from: RawFd, to: RawFd, len: usize) -> io::Result<usize> {
+    // SAFETY: both descriptors stay open for the call, and null offsets make
+    // the kernel use and advance the descriptors' own positions
+    let n = unsafe {
+        libc::splice(
+            from,
+            std::ptr::null_mut(),
+            to,
+            std::ptr::null_mut(),
+            len,
+            libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
@@ -1,8 +1,15 @@
+use super::socket_builder::TcpSocketBuilder;
 use crate::EchoError;
-use crate::stream::{StreamConfig, StreamProtocol};
+use crate::network::fd_inheritance::{BindStrategy, BindTarget, FdInheritanceConfig};
+use crate::network::{BuildSocket, SocketOptions};
+use crate::stream::{ReadHalf, SplitWrite, StreamConfig, StreamProtocol, WriteHalf};
 use async_trait::async_trait;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::io;
 use std::net::SocketAddr;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
 use tokio::net::{TcpListener, TcpStream};
 
 /// TCP protocol implementation
@@ -15,8 +22,34 @@ impl StreamProtocol for TcpProtocol {
     type Stream = TcpStream;
 
     async fn bind(config: &StreamConfig) -> std::result::Result<TcpListener, EchoError> {
-        TcpListener::bind(config.bind_addr)
-            .await
+        bind_listener(config.bind_addr, &config.socket_options, false)
+            .map_err(|e| EchoError::Config(format!("Failed to bind TCP listener: {e}")))
+    }
+
+    async fn bind_with_inheritance(
+        config: &StreamConfig,
+        fd_config: &FdInheritanceConfig,
+    ) -> std::result::Result<TcpListener, EchoError> {
+        let strategy = BindStrategy::InheritOrBind {
+            fd: None,
+            fallback_target: BindTarget::Network(config.bind_addr),
+        };
+        TcpSocketBuilder::build_with_options(
+            &strategy,
+            "tcp-echo",
+            fd_config,
+            &config.socket_options,
+        )
+    }
+
+    async fn bind_workers(
+        config: &StreamConfig,
+        count: usize,
+    ) -> std::result::Result<Vec<TcpListener>, EchoError> {
+        if count <= 1 {
+            return Ok(vec![Self::bind(config).await?]);
+        }
+        bind_listeners(config, count)
             .map_err(|e| EchoError::Config(format!("Failed to bind TCP listener: {e}")))
     }
 
@@ -26,6 +59,15 @@ impl StreamProtocol for TcpProtocol {
         listener.accept().await.map_err(EchoError::Tcp)
     }
 
+    fn configure_stream(
+        stream: &TcpStream,
+        options: &SocketOptions,
+    ) -> std::result::Result<(), EchoError> {
+        options
+            .apply_to_stream(&SockRef::from(stream))
+            .map_err(EchoError::Tcp)
+    }
+
     async fn connect(addr: SocketAddr) -> std::result::Result<TcpStream, EchoError> {
         TcpStream::connect(addr)
             .await
@@ -47,7 +89,143 @@ impl StreamProtocol for TcpProtocol {
         stream.flush().await.map_err(EchoError::Tcp)
     }
 
+    fn into_split(stream: TcpStream) -> std::result::Result<(ReadHalf, WriteHalf), TcpStream> {
+        let (read, write) = stream.into_split();
+        Ok((Box::new(read), Box::new(write)))
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn splice_echo(
+        stream: &mut TcpStream,
+        config: &StreamConfig,
+    ) -> Option<std::result::Result<u64, EchoError>> {
+        Some(
+            super::splice::echo(stream, config)
+                .await
+                .map_err(EchoError::Tcp),
+        )
+    }
+
+    async fn shutdown_write(stream: &mut TcpStream) -> std::result::Result<(), EchoError> {
+        stream.shutdown().await.map_err(EchoError::Tcp)
+    }
+
+    async fn abort(stream: TcpStream) -> std::result::Result<(), EchoError> {
+        // A zero linger makes close() send RST instead of FIN
+        stream
+            .set_linger(Some(Duration::ZERO))
+            .map_err(EchoError::Tcp)?;
+        drop(stream);
+        Ok(())
+    }
+
     fn map_io_error(err: std::io::Error) -> EchoError {
         EchoError::Tcp(err)
     }
 }
+
+impl SplitWrite for OwnedWriteHalf {
+    fn abort(self: Box<Self>) -> io::Result<()> {
+        // A zero linger makes close() send RST instead of FIN, and forgetting
+        // the half skips the FIN its drop would send
+        SockRef::from(AsRef::<TcpStream>::as_ref(&*self)).set_linger(Some(Duration::ZERO))?;
+        self.forget();
+        Ok(())
+    }
+}
+
+/// Binds `count` TCP listeners to the configured address with `SO_REUSEPORT`
+///
+/// The kernel hashes each new connection to one of the listeners, or hands
+/// it to the listener of the CPU that received it when `steer_by_cpu` is
+/// set. Used by TCP and HTTP alike.
+pub(crate) fn bind_listeners(config: &StreamConfig, count: usize) -> io::Result<Vec<TcpListener>> {
+    // Later listeners join the port the first one got, in case it was 0
+    let options = &config.socket_options;
+    let first = bind_listener(config.bind_addr, options, true)?;
+    let addr = first.local_addr()?;
+    let mut listeners = vec![first];
+    for _ in 1..count {
+        listeners.push(bind_listener(addr, options, true)?);
+    }
+    if config.steer_by_cpu {
+        steer_by_cpu(&listeners[0], count)?;
+    }
+    Ok(listeners)
+}
+
+/// Binds a TCP listener with the listener's socket options, which other
+/// listeners may bind to the same address if `reuse_port` is set
+///
+/// The options are set before binding, so the buffer sizes are in place
+/// when the window scale of the first connection is negotiated.
+pub(crate) fn bind_listener(
+    addr: SocketAddr,
+    options: &SocketOptions,
+    reuse_port: bool,
+) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    options.apply_to_listener(&socket)?;
+    socket.bind(&addr.into())?;
+    socket.listen(options.backlog())?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Attaches a classic BPF program to the listeners' group that picks the
+/// listener numbered after the CPU that received the connection
+///
+/// Listeners are numbered in the order they were bound, so with one accept
+/// loop per runtime thread each CPU feeds a loop of its own.
+#[cfg(target_os = "linux")]
+fn steer_by_cpu(listener: &TcpListener, count: usize) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let instruction = |code: u32, k: u32| libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let mut program = [
+        // A = the CPU handling the packet
+        instruction(
+            libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+            (libc::SKF_AD_OFF + libc::SKF_AD_CPU) as u32,
+        ),
+        // A %= count
+        instruction(libc::BPF_ALU | libc::BPF_MOD | libc::BPF_K, count as u32),
+        // The listener at index A takes the connection
+        instruction(libc::BPF_RET | libc::BPF_A, 0),
+    ];
+    let filter = libc::sock_fprog {
+        len: program.len() as u16,
+        filter: program.as_mut_ptr(),
+    };
+    // SAFETY: the program outlives the call, which copies it into the kernel
+    let result = unsafe {
+        libc::setsockopt(
+            listener.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_ATTACH_REUSEPORT_CBPF,
+            (&raw const filter).cast(),
+            std::mem::size_of::<libc::sock_fprog>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn steer_by_cpu(_listener: &TcpListener, _count: usize) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "steering connections by CPU needs Linux",
+    ))
+}
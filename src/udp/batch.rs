@@ -0,0 +1,435 @@
+//! Batched UDP I/O on Linux
+//!
+//! `recvmmsg` and `sendmmsg` move a whole batch of datagrams per system call.
+//! With offload, the kernel also coalesces datagrams received from one sender
+//! (UDP GRO) and splits a run of equal-sized datagrams to one peer into
+//! segments (UDP GSO). Each falls back to one datagram per call when the
+//! kernel does not support it. Local addresses travel with each datagram as
+//! they do for single ones (see [`pktinfo`](super::pktinfo)).
+
+use super::pktinfo::{self, CONTROL_LEN, Control};
+use crate::datagram::{BatchConfig, Received, RecvBatch};
+use socket2::SockAddr;
+use std::io;
+use std::mem;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::Range;
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::Interest;
+use tokio::net::UdpSocket;
+use tracing::{debug, info};
+
+/// Most segments the kernel accepts in one segmented send
+const MAX_SEGMENTS: usize = 64;
+
+/// Largest payload of one segmented send
+const MAX_SEGMENTED_PAYLOAD: usize = 65507;
+
+static RECVMMSG_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+static SENDMMSG_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+static GSO_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Asks the kernel to coalesce received datagrams, returning whether it will
+pub(crate) fn enable_gro(socket: &UdpSocket) -> bool {
+    let enable: libc::c_int = 1;
+    // SAFETY: the option value points to a live c_int of the given size
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_UDP,
+            libc::UDP_GRO,
+            (&enable as *const libc::c_int).cast(),
+            mem::size_of_val(&enable) as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        debug!(error = %io::Error::last_os_error(), "UDP receive offload unavailable");
+    }
+    result == 0
+}
+
+/// Receives a batch of datagrams, falling back to one per call
+pub(crate) async fn recv_batch(socket: &UdpSocket, batch: &mut RecvBatch) -> io::Result<usize> {
+    if !RECVMMSG_UNSUPPORTED.load(Ordering::Relaxed) {
+        match socket
+            .async_io(Interest::READABLE, || recvmmsg(socket, batch))
+            .await
+        {
+            Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+                info!("recvmmsg unavailable, receiving one datagram per call");
+                RECVMMSG_UNSUPPORTED.store(true, Ordering::Relaxed);
+            }
+            result => return result,
+        }
+    }
+
+    let received = pktinfo::recv_msg(socket, batch.slot_mut(0)).await?;
+    batch.push(0, 0, received);
+    Ok(1)
+}
+
+/// Sends datagrams from the front of `datagrams`, falling back to one per call
+pub(crate) async fn send_batch(
+    socket: &UdpSocket,
+    datagrams: &[(&[u8], SocketAddr, Option<IpAddr>)],
+    config: &BatchConfig,
+) -> io::Result<usize> {
+    let Some(&(data, addr, local)) = datagrams.first() else {
+        return Ok(0);
+    };
+
+    while !SENDMMSG_UNSUPPORTED.load(Ordering::Relaxed) {
+        let segment = config.offload && !GSO_UNSUPPORTED.load(Ordering::Relaxed);
+        let groups = group(datagrams, segment, config.size);
+        match socket
+            .async_io(Interest::WRITABLE, || sendmmsg(socket, datagrams, &groups))
+            .await
+        {
+            Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+                info!("sendmmsg unavailable, sending one datagram per call");
+                SENDMMSG_UNSUPPORTED.store(true, Ordering::Relaxed);
+            }
+            // Kernels or devices without GSO reject segmented sends
+            Err(e) if groups[0].len() > 1 && is_offload_error(&e) => {
+                info!(error = %e, "UDP segmentation offload unavailable, sending datagrams separately");
+                GSO_UNSUPPORTED.store(true, Ordering::Relaxed);
+            }
+            result => return result,
+        }
+    }
+
+    pktinfo::send_msg(socket, data, addr, local).await?;
+    Ok(1)
+}
+
+fn is_offload_error(error: &io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(libc::EIO | libc::EINVAL))
+}
+
+/// Splits `datagrams` into at most `limit` messages
+///
+/// With `segment`, a run of datagrams to the same peer from the same local
+/// address shares one message when all have the size of the first, except a
+/// shorter last one.
+fn group(
+    datagrams: &[(&[u8], SocketAddr, Option<IpAddr>)],
+    segment: bool,
+    limit: usize,
+) -> Vec<Range<usize>> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    while start < datagrams.len() && groups.len() < limit {
+        let (first, addr, local) = datagrams[start];
+        let mut end = start + 1;
+        if segment && !first.is_empty() {
+            let mut total = first.len();
+            while end < datagrams.len() && end - start < MAX_SEGMENTS {
+                let (data, peer, from) = datagrams[end];
+                if peer != addr
+                    || from != local
+                    || data.is_empty()
+                    || data.len() > first.len()
+                    || total + data.len() > MAX_SEGMENTED_PAYLOAD
+                {
+                    break;
+                }
+                total += data.len();
+                end += 1;
+                if data.len() < first.len() {
+                    break;
+                }
+            }
+        }
+        groups.push(start..end);
+        start = end;
+    }
+    groups
+}
+
+/// Receives into every slot of `batch` with one `recvmmsg` call
+fn recvmmsg(socket: &UdpSocket, batch: &mut RecvBatch) -> io::Result<usize> {
+    let slots = batch.slots();
+    // SAFETY: all-zero bytes are a valid sockaddr_storage
+    let mut names = vec![unsafe { mem::zeroed::<libc::sockaddr_storage>() }; slots];
+    let mut controls = vec![Control::new(); slots];
+    let mut iovecs: Vec<libc::iovec> = batch
+        .slots_mut()
+        .map(|slot| libc::iovec {
+            iov_base: slot.as_mut_ptr().cast(),
+            iov_len: slot.len(),
+        })
+        .collect();
+    let mut headers: Vec<libc::mmsghdr> = names
+        .iter_mut()
+        .zip(iovecs.iter_mut())
+        .zip(controls.iter_mut())
+        .map(|((name, iovec), control)| {
+            // SAFETY: all-zero bytes are a valid msghdr
+            let mut header: libc::msghdr = unsafe { mem::zeroed() };
+            header.msg_name = (name as *mut libc::sockaddr_storage).cast();
+            header.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            header.msg_iov = iovec;
+            header.msg_iovlen = 1;
+            header.msg_control = control.0.as_mut_ptr().cast();
+            header.msg_controllen = CONTROL_LEN as _;
+            libc::mmsghdr {
+                msg_hdr: header,
+                msg_len: 0,
+            }
+        })
+        .collect();
+
+    // SAFETY: every header points to buffers that outlive the call
+    let received = unsafe {
+        libc::recvmmsg(
+            socket.as_raw_fd(),
+            headers.as_mut_ptr(),
+            slots as libc::c_uint,
+            // With MSG_TRUNC, lengths are the full size of truncated datagrams
+            libc::MSG_DONTWAIT | libc::MSG_TRUNC,
+            std::ptr::null_mut(),
+        )
+    };
+    if received < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let slot_size = batch.slot_size();
+    let mut count = 0;
+    for (slot, header) in headers.iter().take(received as usize).enumerate() {
+        // SAFETY: the kernel filled in the name and its length
+        let name = unsafe { SockAddr::new(names[slot], header.msg_hdr.msg_namelen) };
+        let addr = name.as_socket().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "datagram from a non-IP sender")
+        })?;
+        let datagram = pktinfo::received(header.msg_len as usize, slot_size, addr, &header.msg_hdr);
+        let len = datagram.len;
+
+        // A coalesced datagram is split back into the datagrams it was built from
+        let segment = gro_segment(&header.msg_hdr)
+            .filter(|&size| size > 0)
+            .unwrap_or(len.max(1));
+        let mut offset = 0;
+        loop {
+            let end = (offset + segment).min(len);
+            // Only the last datagram of a slot can have been cut short
+            let part = Received {
+                len: end - offset,
+                truncated: datagram.truncated.filter(|_| end == len),
+                ..datagram
+            };
+            batch.push(slot, offset, part);
+            count += 1;
+            offset = end;
+            if offset >= len {
+                break;
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Returns the segment size of a datagram the kernel coalesced
+fn gro_segment(header: &libc::msghdr) -> Option<usize> {
+    // SAFETY: the control buffer was filled in by recvmmsg and is walked
+    // with the kernel's own macros
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(header);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_UDP && (*cmsg).cmsg_type == libc::UDP_GRO {
+                let size: libc::c_int = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast());
+                return usize::try_from(size).ok();
+            }
+            cmsg = libc::CMSG_NXTHDR(header, cmsg);
+        }
+    }
+    None
+}
+
+/// Sends each group of datagrams as one message with one `sendmmsg` call,
+/// returning how many datagrams went out
+fn sendmmsg(
+    socket: &UdpSocket,
+    datagrams: &[(&[u8], SocketAddr, Option<IpAddr>)],
+    groups: &[Range<usize>],
+) -> io::Result<usize> {
+    let mut iovecs: Vec<libc::iovec> = datagrams
+        .iter()
+        .map(|(data, _, _)| libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        })
+        .collect();
+    let iovecs = iovecs.as_mut_ptr();
+    let names: Vec<SockAddr> = groups
+        .iter()
+        .map(|group| SockAddr::from(datagrams[group.start].1))
+        .collect();
+    let mut controls = vec![Control::new(); groups.len()];
+    let mut headers: Vec<libc::mmsghdr> = groups
+        .iter()
+        .zip(&names)
+        .zip(controls.iter_mut())
+        .map(|((group, name), control)| {
+            // SAFETY: all-zero bytes are a valid msghdr
+            let mut header: libc::msghdr = unsafe { mem::zeroed() };
+            header.msg_name = name.as_ptr() as *mut libc::c_void;
+            header.msg_namelen = name.len();
+            // SAFETY: the group lies within the iovec array
+            header.msg_iov = unsafe { iovecs.add(group.start) };
+            header.msg_iovlen = group.len() as _;
+            let (first, _, local) = datagrams[group.start];
+            if group.len() > 1 {
+                set_segment_size(&mut header, control, first.len());
+            }
+            if let Some(local) = local {
+                pktinfo::set_source(&mut header, control, local);
+            }
+            libc::mmsghdr {
+                msg_hdr: header,
+                msg_len: 0,
+            }
+        })
+        .collect();
+
+    // SAFETY: every header points to buffers that outlive the call
+    let sent = unsafe {
+        libc::sendmmsg(
+            socket.as_raw_fd(),
+            headers.as_mut_ptr(),
+            headers.len() as libc::c_uint,
+            libc::MSG_DONTWAIT,
+        )
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(groups[..sent as usize].iter().map(Range::len).sum())
+}
+
+/// Asks the kernel to split the message of `header` into `size`-byte segments
+fn set_segment_size(header: &mut libc::msghdr, control: &mut Control, size: usize) {
+    let size = size as u16;
+    pktinfo::append(
+        header,
+        control,
+        libc::SOL_UDP,
+        libc::UDP_SEGMENT,
+        &size.to_ne_bytes(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datagrams<'a>(
+        sizes: &[(usize, u16)],
+        data: &'a [u8],
+    ) -> Vec<(&'a [u8], SocketAddr, Option<IpAddr>)> {
+        sizes
+            .iter()
+            .map(|&(size, port)| {
+                let addr = SocketAddr::from(([127, 0, 0, 1], port));
+                (&data[..size], addr, None)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_group_without_segmentation() {
+        let data = [0u8; 64];
+        let datagrams = datagrams(&[(10, 1), (10, 1), (10, 1)], &data);
+        assert_eq!(group(&datagrams, false, 32), vec![0..1, 1..2, 2..3]);
+        assert_eq!(group(&datagrams, false, 2), vec![0..1, 1..2]);
+    }
+
+    #[test]
+    fn test_group_runs() {
+        let data = [0u8; 64];
+        // Equal sizes to one peer share a message; a shorter datagram ends
+        // the run, and a new peer or larger datagram starts a new one
+        let datagrams = datagrams(
+            &[(10, 1), (10, 1), (4, 1), (10, 1), (10, 2), (20, 2), (0, 2)],
+            &data,
+        );
+        assert_eq!(
+            group(&datagrams, true, 32),
+            vec![0..3, 3..4, 4..5, 5..6, 6..7]
+        );
+
+        // Replies from another local address start a new message too
+        let mut datagrams = datagrams;
+        datagrams[1].2 = Some(IpAddr::from([127, 0, 0, 2]));
+        assert_eq!(group(&datagrams, true, 32)[..3], [0..1, 1..2, 2..3]);
+    }
+
+    #[test]
+    fn test_group_limits() {
+        let data = vec![0u8; 1500];
+        let many = datagrams(&[(1, 1); 100], &data);
+        assert_eq!(
+            group(&many, true, 32),
+            vec![0..MAX_SEGMENTS, MAX_SEGMENTS..100]
+        );
+
+        let large = datagrams(&[(1500, 1); 50], &data);
+        let groups = group(&large, true, 32);
+        assert_eq!(groups[0], 0..43);
+    }
+
+    #[tokio::test]
+    async fn test_batch_round_trip() -> io::Result<()> {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await?;
+        let sender = UdpSocket::bind("127.0.0.1:0").await?;
+        let addr = receiver.local_addr()?;
+        let offload = BatchConfig::new(8).with_offload(true);
+        enable_gro(&receiver);
+
+        let payloads: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 100]).collect();
+        let outgoing: Vec<(&[u8], SocketAddr, Option<IpAddr>)> = payloads
+            .iter()
+            .map(|p| (p.as_slice(), addr, None))
+            .collect();
+        let mut sent = 0;
+        while sent < outgoing.len() {
+            sent += send_batch(&sender, &outgoing[sent..], &offload).await?;
+        }
+
+        let mut batch = RecvBatch::new(8, offload.slot_size(1500));
+        let mut received = Vec::new();
+        while received.len() < payloads.len() {
+            batch.clear();
+            recv_batch(&receiver, &mut batch).await?;
+            received.extend(batch.iter().map(|(data, datagram)| {
+                assert_eq!(datagram.peer, sender.local_addr().unwrap());
+                data.to_vec()
+            }));
+        }
+        assert_eq!(received, payloads);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_batch_truncation() -> io::Result<()> {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await?;
+        let sender = UdpSocket::bind("127.0.0.1:0").await?;
+        sender.send_to(&[1; 8], receiver.local_addr()?).await?;
+        sender.send_to(&[2; 40], receiver.local_addr()?).await?;
+
+        let mut batch = RecvBatch::new(4, 16);
+        let mut truncated = Vec::new();
+        while truncated.len() < 2 {
+            batch.clear();
+            recv_batch(&receiver, &mut batch).await?;
+            truncated.extend(
+                batch
+                    .iter()
+                    .map(|(data, datagram)| (data.len(), datagram.truncated)),
+            );
+        }
+        assert_eq!(truncated, vec![(8, None), (16, Some(40))]);
+        Ok(())
+    }
+}
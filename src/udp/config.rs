@@ -1,4 +1,6 @@
-use crate::datagram::DatagramConfig;
+use crate::datagram::{BatchConfig, DatagramConfig, TruncationPolicy};
+use crate::handler::Transform;
+use crate::network::{MulticastConfig, SocketOptions};
 use std::net::SocketAddr;
 use std::time::Duration;
 
@@ -12,30 +14,62 @@ use std::time::Duration;
 ///
 /// let config = UdpConfig {
 ///     bind_addr: "127.0.0.1:8080".parse().unwrap(),
+///     additional_addrs: Vec::new(),
 ///     buffer_size: 1024,
 ///     read_timeout: Duration::from_secs(30),
 ///     write_timeout: Duration::from_secs(30),
+///     transform: None,
+///     workers: 1,
+///     batch: None,
+///     socket_options: Default::default(),
+///     multicast: None,
+///     truncation: Default::default(),
 /// };
 /// ```
 #[derive(Debug, Clone)]
 pub struct UdpConfig {
     /// Address to bind the server to
     pub bind_addr: SocketAddr,
+    /// Further addresses served alongside `bind_addr`, sharing its
+    /// connection limit and statistics
+    pub additional_addrs: Vec<SocketAddr>,
     /// Buffer size for reading/writing data
     pub buffer_size: usize,
     /// Read timeout for connections
     pub read_timeout: Duration,
     /// Write timeout for connections
     pub write_timeout: Duration,
+    /// Transform applied to echoed datagrams; plain echo if `None`
+    pub transform: Option<Transform>,
+    /// Receive loops, each on its own `SO_REUSEPORT` socket; 0 runs one per
+    /// runtime worker thread
+    pub workers: usize,
+    /// Receive and send several datagrams per system call where the
+    /// protocol supports it; one at a time if `None`
+    pub batch: Option<BatchConfig>,
+    /// Socket options of the server's sockets
+    pub socket_options: SocketOptions,
+    /// Multicast groups to join, for protocols that support them; none if
+    /// `None`
+    pub multicast: Option<MulticastConfig>,
+    /// What to do with datagrams larger than `buffer_size`
+    pub truncation: TruncationPolicy,
 }
 
 impl Default for UdpConfig {
     fn default() -> Self {
         Self {
             bind_addr: "127.0.0.1:0".parse().unwrap(),
+            additional_addrs: Vec::new(),
             buffer_size: 1024,
             read_timeout: Duration::from_secs(30),
             write_timeout: Duration::from_secs(30),
+            transform: None,
+            workers: 1,
+            batch: None,
+            socket_options: SocketOptions::default(),
+            multicast: None,
+            truncation: TruncationPolicy::Echo,
         }
     }
 }
@@ -44,9 +78,17 @@ impl From<UdpConfig> for DatagramConfig {
     fn from(config: UdpConfig) -> Self {
         Self {
             bind_addr: config.bind_addr,
+            additional_addrs: config.additional_addrs,
             buffer_size: config.buffer_size,
             read_timeout: config.read_timeout,
             write_timeout: config.write_timeout,
+            faults: None,
+            transform: config.transform,
+            workers: config.workers,
+            batch: config.batch,
+            socket_options: config.socket_options,
+            multicast: config.multicast,
+            truncation: config.truncation,
         }
     }
 }
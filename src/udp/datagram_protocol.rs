@@ -1,10 +1,25 @@
+use super::socket_builder::UdpSocketBuilder;
 use crate::EchoError;
+#[cfg(target_os = "linux")]
+use crate::datagram::{BatchConfig, Received, RecvBatch};
 use crate::datagram::{DatagramConfig, DatagramProtocol};
+use crate::network::fd_inheritance::{BindStrategy, BindTarget, FdInheritanceConfig};
+use crate::network::{BuildSocket, SocketOptions};
 use async_trait::async_trait;
-use std::net::SocketAddr;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::net::{IpAddr, SocketAddr};
 use tokio::net::UdpSocket;
+#[cfg(target_os = "linux")]
+use tracing::debug;
+use tracing::{info, warn};
 
 /// UDP protocol implementation
+///
+/// On Linux, sockets bound to a wildcard address report the local address
+/// each datagram was sent to (`IP_PKTINFO` and `IPV6_RECVPKTINFO`), and
+/// [`send_msg`](DatagramProtocol::send_msg) replies from it. Datagrams
+/// larger than the receive buffer are reported with their full size
+/// (`MSG_TRUNC`).
 pub struct UdpProtocol;
 
 #[async_trait]
@@ -13,9 +28,64 @@ impl DatagramProtocol for UdpProtocol {
     type Socket = UdpSocket;
 
     async fn bind(config: &DatagramConfig) -> std::result::Result<UdpSocket, EchoError> {
-        UdpSocket::bind(config.bind_addr)
-            .await
-            .map_err(|e| EchoError::Config(format!("Failed to bind UDP socket: {e}")))
+        let socket = bind_socket(config.bind_addr, &config.socket_options, false)?;
+        configure_offload(&socket, config);
+        configure_pktinfo(&socket);
+        configure_multicast(&socket, config, true)?;
+        Ok(socket)
+    }
+
+    async fn bind_with_inheritance(
+        config: &DatagramConfig,
+        fd_config: &FdInheritanceConfig,
+    ) -> std::result::Result<UdpSocket, EchoError> {
+        let strategy = BindStrategy::InheritOrBind {
+            fd: None,
+            fallback_target: BindTarget::Network(config.bind_addr),
+        };
+        let socket = UdpSocketBuilder::build_with_options(
+            &strategy,
+            "udp-echo",
+            fd_config,
+            &config.socket_options,
+        )?;
+        configure_offload(&socket, config);
+        configure_pktinfo(&socket);
+        configure_multicast(&socket, config, true)?;
+        Ok(socket)
+    }
+
+    async fn bind_workers(
+        config: &DatagramConfig,
+        count: usize,
+    ) -> std::result::Result<Vec<UdpSocket>, EchoError> {
+        if count <= 1 {
+            return Ok(vec![Self::bind(config).await?]);
+        }
+
+        if config.socket_options.broadcast == Some(true) {
+            warn!(
+                workers = count,
+                "Every worker socket receives its own copy of each broadcast"
+            );
+        }
+
+        // Later sockets join the port the first one got, in case it was 0
+        let options = &config.socket_options;
+        let first = bind_socket(config.bind_addr, options, true)?;
+        let addr = first.local_addr().map_err(EchoError::Udp)?;
+        let mut sockets = vec![first];
+        for _ in 1..count {
+            sockets.push(bind_socket(addr, options, true)?);
+        }
+        // Only the first socket joins the groups, so each group datagram is
+        // echoed once
+        for (id, socket) in sockets.iter().enumerate() {
+            configure_offload(socket, config);
+            configure_pktinfo(socket);
+            configure_multicast(socket, config, id == 0)?;
+        }
+        Ok(sockets)
     }
 
     async fn recv_from(
@@ -33,7 +103,120 @@ impl DatagramProtocol for UdpProtocol {
         socket.send_to(data, addr).await.map_err(EchoError::Udp)
     }
 
+    #[cfg(target_os = "linux")]
+    async fn recv_msg(
+        socket: &UdpSocket,
+        buffer: &mut [u8],
+    ) -> std::result::Result<Received, EchoError> {
+        super::pktinfo::recv_msg(socket, buffer)
+            .await
+            .map_err(EchoError::Udp)
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn send_msg(
+        socket: &UdpSocket,
+        data: &[u8],
+        addr: SocketAddr,
+        local: Option<IpAddr>,
+    ) -> std::result::Result<usize, EchoError> {
+        super::pktinfo::send_msg(socket, data, addr, local)
+            .await
+            .map_err(EchoError::Udp)
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn recv_batch(
+        socket: &UdpSocket,
+        batch: &mut RecvBatch,
+    ) -> std::result::Result<usize, EchoError> {
+        super::batch::recv_batch(socket, batch)
+            .await
+            .map_err(EchoError::Udp)
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn send_batch(
+        socket: &UdpSocket,
+        datagrams: &[(&[u8], SocketAddr, Option<IpAddr>)],
+        config: &BatchConfig,
+    ) -> std::result::Result<usize, EchoError> {
+        super::batch::send_batch(socket, datagrams, config)
+            .await
+            .map_err(EchoError::Udp)
+    }
+
     fn map_io_error(err: std::io::Error) -> EchoError {
         EchoError::Udp(err)
     }
 }
+
+/// Turns on receive offload when the configuration asks for it
+///
+/// Sockets that cannot coalesce keep receiving one datagram per slot.
+fn configure_offload(socket: &UdpSocket, config: &DatagramConfig) {
+    #[cfg(target_os = "linux")]
+    if config.batch.is_some_and(|batch| batch.offload) {
+        super::batch::enable_gro(socket);
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (socket, config);
+}
+
+/// Asks for the local address of each datagram if the socket is bound to a
+/// wildcard address, so replies can leave from the address a client targeted
+///
+/// Sockets that cannot report it reply from the address the system picks.
+fn configure_pktinfo(socket: &UdpSocket) {
+    #[cfg(target_os = "linux")]
+    if let Ok(addr) = socket.local_addr()
+        && addr.ip().is_unspecified()
+        && let Err(e) = super::pktinfo::enable(socket, addr)
+    {
+        debug!(error = %e, "Local addresses of datagrams unavailable");
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = socket;
+}
+
+/// Sets the multicast TTL and loopback of the configuration, joining its
+/// groups if `join` is set
+fn configure_multicast(
+    socket: &UdpSocket,
+    config: &DatagramConfig,
+    join: bool,
+) -> std::result::Result<(), EchoError> {
+    let Some(multicast) = &config.multicast else {
+        return Ok(());
+    };
+    multicast.apply(&SockRef::from(socket), join).map_err(|e| {
+        EchoError::Config(format!(
+            "Failed to configure multicast on {}: {e}",
+            config.bind_addr
+        ))
+    })?;
+    if join && !multicast.groups.is_empty() {
+        info!(address = %config.bind_addr, groups = ?multicast.groups, "Joined multicast groups");
+    }
+    Ok(())
+}
+
+/// Binds a UDP socket with the datagram socket options, which other sockets
+/// may bind to the same address if `reuse_port` is set
+fn bind_socket(
+    addr: SocketAddr,
+    options: &SocketOptions,
+    reuse_port: bool,
+) -> std::result::Result<UdpSocket, EchoError> {
+    let bind = || -> std::io::Result<UdpSocket> {
+        let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+        if reuse_port {
+            socket.set_reuse_port(true)?;
+        }
+        socket.set_nonblocking(true)?;
+        options.apply_to_datagram(&socket)?;
+        socket.bind(&addr.into())?;
+        UdpSocket::from_std(socket.into())
+    };
+    bind().map_err(|e| EchoError::Config(format!("Failed to bind UDP socket to {addr}: {e}")))
+}
@@ -1,8 +1,14 @@
+#[cfg(target_os = "linux")]
+mod batch;
 pub mod config;
 pub mod datagram_protocol;
+#[cfg(target_os = "linux")]
+pub(crate) mod pktinfo;
 pub mod server;
 pub mod socket_builder;
-pub mod tests;
+
+#[cfg(test)]
+mod tests;
 
 pub use config::UdpConfig;
 pub use datagram_protocol::UdpProtocol;
@@ -10,3 +16,4 @@ pub use server::UdpEchoServer;
 
 // Type alias for the generic datagram client with UDP protocol
 pub type UdpEchoClient = crate::datagram::DatagramEchoClient<UdpProtocol>;
+pub type UdpTwampClient = crate::datagram::TwampClient<UdpProtocol>;
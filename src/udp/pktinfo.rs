@@ -0,0 +1,412 @@
+//! Local addresses and other control messages of UDP datagrams on Linux
+//!
+//! A socket bound to a wildcard address can receive datagrams sent to any
+//! address of the host, but the kernel picks the source of a reply from the
+//! routing table, which may not be the address the client targeted. With
+//! `IP_PKTINFO` and `IPV6_RECVPKTINFO` the kernel reports the destination of
+//! each datagram, and a reply carrying it as its source leaves from that
+//! address. The same control messages carry receive timestamps and the
+//! TTL of datagrams when the socket asks for them.
+
+use crate::datagram::Received;
+use socket2::SockAddr;
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::fd::AsRawFd;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::Interest;
+use tokio::net::UdpSocket;
+
+/// Room for the control messages of one datagram: a segment size, a local
+/// address of either family, a receive timestamp and a TTL
+pub(crate) const CONTROL_LEN: usize = 256;
+
+/// Control message buffer, aligned for `cmsghdr`
+#[derive(Clone, Copy)]
+#[repr(C, align(8))]
+pub(crate) struct Control(pub(crate) [u8; CONTROL_LEN]);
+
+impl Control {
+    pub(crate) fn new() -> Self {
+        Self([0; CONTROL_LEN])
+    }
+}
+
+/// Asks the kernel to report the local address of each received datagram
+///
+/// IPv4 datagrams reaching a dual-stack IPv6 socket report theirs through
+/// `IP_PKTINFO`, so IPv6 sockets turn on both options.
+pub(crate) fn enable(socket: &impl AsRawFd, addr: SocketAddr) -> io::Result<()> {
+    if addr.is_ipv6() {
+        set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO)?;
+    }
+    set_option(socket, libc::IPPROTO_IP, libc::IP_PKTINFO)
+}
+
+fn set_option(socket: &impl AsRawFd, level: libc::c_int, name: libc::c_int) -> io::Result<()> {
+    let enable: libc::c_int = 1;
+    // SAFETY: the option value points to a live c_int of the given size
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            (&enable as *const libc::c_int).cast(),
+            mem::size_of_val(&enable) as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// What the kernel reported about a received datagram in its control
+/// messages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Ancillary {
+    /// Local address the datagram was sent to
+    ///
+    /// For IPv4 this is the address the kernel answers from
+    /// (`ipi_spec_dst`), which is the destination itself unless that is a
+    /// broadcast or multicast address, as those cannot be a source.
+    pub(crate) local: Option<IpAddr>,
+    /// Software receive timestamp (`SO_TIMESTAMPING`)
+    pub(crate) timestamp: Option<SystemTime>,
+    /// Time to live or hop limit the datagram arrived with (`IP_RECVTTL`,
+    /// `IPV6_RECVHOPLIMIT`)
+    pub(crate) ttl: Option<u8>,
+}
+
+/// Reads the control messages of a received datagram
+pub(crate) fn ancillary(header: &libc::msghdr) -> Ancillary {
+    let mut ancillary = Ancillary::default();
+    // SAFETY: the control buffer was filled in by the kernel and is walked
+    // with its own macros
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(header);
+        while !cmsg.is_null() {
+            let data = libc::CMSG_DATA(cmsg);
+            match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                    let info: libc::in_pktinfo = std::ptr::read_unaligned(data.cast());
+                    let addr = Ipv4Addr::from(u32::from_be(info.ipi_spec_dst.s_addr));
+                    ancillary.local = Some(addr.into());
+                }
+                (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                    let info: libc::in6_pktinfo = std::ptr::read_unaligned(data.cast());
+                    let addr = Ipv6Addr::from(info.ipi6_addr.s6_addr);
+                    ancillary.local = Some(addr.to_canonical());
+                }
+                (libc::SOL_SOCKET, libc::SCM_TIMESTAMPING) => {
+                    // The software timestamp comes first, then two legacy
+                    // and hardware ones
+                    let time: libc::timespec = std::ptr::read_unaligned(data.cast());
+                    ancillary.timestamp = system_time(time);
+                }
+                (libc::IPPROTO_IP, libc::IP_TTL) | (libc::IPPROTO_IPV6, libc::IPV6_HOPLIMIT) => {
+                    let ttl: libc::c_int = std::ptr::read_unaligned(data.cast());
+                    ancillary.ttl = u8::try_from(ttl).ok();
+                }
+                _ => {}
+            }
+            cmsg = libc::CMSG_NXTHDR(header, cmsg);
+        }
+    }
+    ancillary
+}
+
+/// Reads control messages the kernel wrote somewhere else than a message
+/// header's own buffer
+pub(crate) fn ancillary_in(data: &[u8]) -> Ancillary {
+    // Copied, as the control messages are read in place and must be aligned
+    let mut control = Control::new();
+    let len = data.len().min(CONTROL_LEN);
+    control.0[..len].copy_from_slice(&data[..len]);
+    // SAFETY: all-zero bytes are a valid msghdr
+    let mut header: libc::msghdr = unsafe { mem::zeroed() };
+    header.msg_control = control.0.as_mut_ptr().cast();
+    header.msg_controllen = len as _;
+    ancillary(&header)
+}
+
+/// Converts a kernel timestamp, of which an all-zero one means none
+fn system_time(time: libc::timespec) -> Option<SystemTime> {
+    let secs = u64::try_from(time.tv_sec).ok()?;
+    let nanos = u32::try_from(time.tv_nsec).ok()?;
+    if secs == 0 && nanos == 0 {
+        return None;
+    }
+    UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+}
+
+/// Appends a control message to the message of `header`, whose control
+/// messages so far are in `control`
+///
+/// # Panics
+///
+/// Panics if the message does not fit in the control buffer.
+pub(crate) fn append(
+    header: &mut libc::msghdr,
+    control: &mut Control,
+    level: libc::c_int,
+    kind: libc::c_int,
+    data: &[u8],
+) {
+    let offset: usize = if header.msg_control.is_null() {
+        0
+    } else {
+        header.msg_controllen as _
+    };
+    // SAFETY: the lengths come from the kernel's own macros, and the message
+    // is written within the buffer, at an offset those macros keep aligned
+    unsafe {
+        let space = libc::CMSG_SPACE(data.len() as u32) as usize;
+        assert!(offset + space <= CONTROL_LEN, "control message overflow");
+        header.msg_control = control.0.as_mut_ptr().cast();
+        header.msg_controllen = (offset + space) as _;
+        let cmsg = control.0.as_mut_ptr().add(offset).cast::<libc::cmsghdr>();
+        (*cmsg).cmsg_level = level;
+        (*cmsg).cmsg_type = kind;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(data.len() as u32) as _;
+        std::ptr::copy_nonoverlapping(data.as_ptr(), libc::CMSG_DATA(cmsg), data.len());
+    }
+}
+
+/// Makes `local` the source address of the message of `header`
+///
+/// A broadcast or multicast address cannot be a source, so replies to
+/// datagrams sent to one leave from the address the kernel picks.
+pub(crate) fn set_source(header: &mut libc::msghdr, control: &mut Control, local: IpAddr) {
+    match local {
+        IpAddr::V4(addr) if !addr.is_broadcast() && !addr.is_multicast() => {
+            // SAFETY: all-zero bytes are a valid in_pktinfo
+            let mut info: libc::in_pktinfo = unsafe { mem::zeroed() };
+            info.ipi_spec_dst.s_addr = u32::from(addr).to_be();
+            append(
+                header,
+                control,
+                libc::IPPROTO_IP,
+                libc::IP_PKTINFO,
+                as_bytes(&info),
+            );
+        }
+        IpAddr::V6(addr) if !addr.is_multicast() => {
+            // SAFETY: all-zero bytes are a valid in6_pktinfo
+            let mut info: libc::in6_pktinfo = unsafe { mem::zeroed() };
+            info.ipi6_addr.s6_addr = addr.octets();
+            append(
+                header,
+                control,
+                libc::IPPROTO_IPV6,
+                libc::IPV6_PKTINFO,
+                as_bytes(&info),
+            );
+        }
+        _ => {}
+    }
+}
+
+fn as_bytes<T>(value: &T) -> &[u8] {
+    // SAFETY: the value is plain old data, viewed for its own size
+    unsafe { std::slice::from_raw_parts((value as *const T).cast(), mem::size_of::<T>()) }
+}
+
+/// Receives one datagram, reporting its sender, local address and size if
+/// it was truncated
+pub(crate) async fn recv_msg(socket: &UdpSocket, buffer: &mut [u8]) -> io::Result<Received> {
+    socket
+        .async_io(Interest::READABLE, || recvmsg(socket, buffer))
+        .await
+}
+
+fn recvmsg(socket: &UdpSocket, buffer: &mut [u8]) -> io::Result<Received> {
+    // SAFETY: all-zero bytes are a valid sockaddr_storage
+    let mut name: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut control = Control::new();
+    let mut iovec = libc::iovec {
+        iov_base: buffer.as_mut_ptr().cast(),
+        iov_len: buffer.len(),
+    };
+    // SAFETY: all-zero bytes are a valid msghdr
+    let mut header: libc::msghdr = unsafe { mem::zeroed() };
+    header.msg_name = (&mut name as *mut libc::sockaddr_storage).cast();
+    header.msg_namelen = mem::size_of_val(&name) as libc::socklen_t;
+    header.msg_iov = &mut iovec;
+    header.msg_iovlen = 1;
+    header.msg_control = control.0.as_mut_ptr().cast();
+    header.msg_controllen = CONTROL_LEN as _;
+
+    // With MSG_TRUNC the full size of a truncated datagram is returned
+    // SAFETY: the header points to buffers that outlive the call
+    let size = unsafe {
+        libc::recvmsg(
+            socket.as_raw_fd(),
+            &mut header,
+            libc::MSG_DONTWAIT | libc::MSG_TRUNC,
+        )
+    };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: the kernel filled in the name and its length
+    let name = unsafe { SockAddr::new(name, header.msg_namelen) };
+    let addr = name.as_socket().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "datagram from a non-IP sender")
+    })?;
+    Ok(received(size as usize, buffer.len(), addr, &header))
+}
+
+/// Describes a datagram of `size` bytes received into a buffer of
+/// `capacity` with `header`
+pub(crate) fn received(
+    size: usize,
+    capacity: usize,
+    addr: SocketAddr,
+    header: &libc::msghdr,
+) -> Received {
+    let ancillary = ancillary(header);
+    let received = Received::new(size.min(capacity), addr)
+        .with_local(ancillary.local)
+        .with_timestamp(ancillary.timestamp)
+        .with_ttl(ancillary.ttl);
+    if header.msg_flags & libc::MSG_TRUNC != 0 {
+        received.with_truncated(size)
+    } else {
+        received
+    }
+}
+
+/// Sends one datagram to `addr`, from `local` if given
+pub(crate) async fn send_msg(
+    socket: &UdpSocket,
+    data: &[u8],
+    addr: SocketAddr,
+    local: Option<IpAddr>,
+) -> io::Result<usize> {
+    let Some(local) = local else {
+        return socket.send_to(data, addr).await;
+    };
+    socket
+        .async_io(Interest::WRITABLE, || sendmsg(socket, data, addr, local))
+        .await
+}
+
+fn sendmsg(socket: &UdpSocket, data: &[u8], addr: SocketAddr, local: IpAddr) -> io::Result<usize> {
+    let name = SockAddr::from(addr);
+    let mut control = Control::new();
+    let mut iovec = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    // SAFETY: all-zero bytes are a valid msghdr
+    let mut header: libc::msghdr = unsafe { mem::zeroed() };
+    header.msg_name = name.as_ptr() as *mut libc::c_void;
+    header.msg_namelen = name.len();
+    header.msg_iov = &mut iovec;
+    header.msg_iovlen = 1;
+    set_source(&mut header, &mut control, local);
+
+    // SAFETY: the header points to buffers that outlive the call
+    let sent = unsafe { libc::sendmsg(socket.as_raw_fd(), &header, libc::MSG_DONTWAIT) };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(sent as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_round_trip() {
+        let mut control = Control::new();
+        // SAFETY: all-zero bytes are a valid msghdr
+        let mut header: libc::msghdr = unsafe { mem::zeroed() };
+        append(
+            &mut header,
+            &mut control,
+            libc::SOL_UDP,
+            libc::UDP_SEGMENT,
+            &1200u16.to_ne_bytes(),
+        );
+        set_source(&mut header, &mut control, "::1".parse().unwrap());
+        assert!(header.msg_controllen as usize <= CONTROL_LEN);
+        // Sent and received packet information share a layout for IPv6
+        assert_eq!(ancillary(&header).local, Some("::1".parse().unwrap()));
+
+        // Multicast addresses are left for the kernel to replace
+        let mut control = Control::new();
+        // SAFETY: all-zero bytes are a valid msghdr
+        let mut header: libc::msghdr = unsafe { mem::zeroed() };
+        set_source(&mut header, &mut control, "224.0.0.1".parse().unwrap());
+        assert!(header.msg_control.is_null());
+    }
+
+    #[tokio::test]
+    async fn test_local_address() -> io::Result<()> {
+        let server = UdpSocket::bind("0.0.0.0:0").await?;
+        enable(&server, server.local_addr()?)?;
+        let port = server.local_addr()?.port();
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        client.send_to(b"ping", ("127.0.0.2", port)).await?;
+
+        let mut buffer = [0; 16];
+        let received = recv_msg(&server, &mut buffer).await?;
+        assert_eq!(&buffer[..received.len], b"ping");
+        assert_eq!(received.local, Some("127.0.0.2".parse().unwrap()));
+        assert_eq!(received.truncated, None);
+
+        // The reply leaves from the address the client targeted
+        send_msg(&server, b"pong", received.peer, received.local).await?;
+        let (n, from) = client.recv_from(&mut buffer).await?;
+        assert_eq!(&buffer[..n], b"pong");
+        assert_eq!(from, SocketAddr::from(([127, 0, 0, 2], port)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_truncated_datagram() -> io::Result<()> {
+        let server = UdpSocket::bind("127.0.0.1:0").await?;
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        client.send_to(&[7; 100], server.local_addr()?).await?;
+
+        let mut buffer = [0; 16];
+        let received = recv_msg(&server, &mut buffer).await?;
+        assert_eq!(received.len, 16);
+        assert_eq!(received.truncated, Some(100));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_and_ttl() -> io::Result<()> {
+        let server = UdpSocket::bind("127.0.0.1:0").await?;
+        let options = crate::network::SocketOptions {
+            timestamping: Some(true),
+            recv_ttl: Some(true),
+            ..Default::default()
+        };
+        options.apply_to_datagram(&socket2::SockRef::from(&server))?;
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        client.set_ttl(42)?;
+
+        // The kernel turns receive timestamps on in the background, so the
+        // first datagrams may arrive without one
+        let mut buffer = [0; 16];
+        for _ in 0..50 {
+            let before = SystemTime::now();
+            client.send_to(b"ping", server.local_addr()?).await?;
+            let received = recv_msg(&server, &mut buffer).await?;
+            assert_eq!(received.ttl, Some(42));
+            if let Some(timestamp) = received.timestamp {
+                assert!(timestamp >= before && timestamp <= SystemTime::now());
+                return Ok(());
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("no kernel receive timestamp");
+    }
+}
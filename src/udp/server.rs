@@ -18,9 +18,16 @@ use crate::datagram::DatagramEchoServer;
 /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
 ///     let config = UdpConfig {
 ///         bind_addr: "127.0.0.1:8080".parse()?,
+///         additional_addrs: Vec::new(),
 ///         buffer_size: 1024,
 ///         read_timeout: Duration::from_secs(30),
 ///         write_timeout: Duration::from_secs(30),
+///         transform: None,
+///         workers: 1,
+///         batch: None,
+///         socket_options: Default::default(),
+///         multicast: None,
+///         truncation: Default::default(),
 ///     };
 ///
 ///     let server = UdpEchoServer::new(config.into());
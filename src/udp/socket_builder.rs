@@ -12,7 +12,9 @@
 
 use crate::network::socket_builder::BuildSocket;
 use crate::network::fd_inheritance::BindTarget;
+use crate::network::socket_options::SocketOptions;
 use crate::{EchoError, Result};
+use socket2::SockRef;
 use std::os::unix::io::{FromRawFd, RawFd};
 use tokio::net::UdpSocket;
 
@@ -56,12 +58,12 @@ impl BuildSocket<UdpSocket> for UdpSocketBuilder {
         // Configure for async operation with Tokio
         // Tokio requires non-blocking sockets for proper async behavior
         std_socket.set_nonblocking(true)
-            .map_err(|e| EchoError::Udp(e))?;
+            .map_err(EchoError::Udp)?;
         
         // Convert std UdpSocket to Tokio UdpSocket
         // This registers the socket with Tokio's async runtime for efficient I/O
         UdpSocket::from_std(std_socket)
-            .map_err(|e| EchoError::Udp(e))
+            .map_err(EchoError::Udp)
     }
     
     /// Create UDP socket by binding to network address
@@ -78,15 +80,15 @@ impl BuildSocket<UdpSocket> for UdpSocketBuilder {
             BindTarget::Network(addr) => {
                 // Create standard library UdpSocket bound to address
                 let std_socket = std::net::UdpSocket::bind(addr)
-                    .map_err(|e| EchoError::Udp(e))?;
+                    .map_err(EchoError::Udp)?;
                 
                 // Configure for async operation
                 std_socket.set_nonblocking(true)
-                    .map_err(|e| EchoError::Udp(e))?;
+                    .map_err(EchoError::Udp)?;
                 
                 // Convert to Tokio async UdpSocket
                 UdpSocket::from_std(std_socket)
-                    .map_err(|e| EchoError::Udp(e))
+                    .map_err(EchoError::Udp)
             }
             
             // UDP sockets cannot bind to Unix domain socket paths
@@ -97,4 +99,17 @@ impl BuildSocket<UdpSocket> for UdpSocketBuilder {
             }
         }
     }
+    
+    /// Apply datagram socket options to a UDP socket
+    /// 
+    /// Buffer sizes, type of service and TTL apply to UDP; stream-only
+    /// options such as keepalive are ignored.
+    /// 
+    /// # Arguments
+    /// * `socket` - Bound or inherited UDP socket
+    /// * `options` - Socket options to apply
+    fn configure(socket: &UdpSocket, options: &SocketOptions) -> Result<()> {
+        options.apply_to_datagram(&SockRef::from(socket))
+            .map_err(EchoError::Udp)
+    }
 }
\ No newline at end of file
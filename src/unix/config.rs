@@ -1,5 +1,7 @@
-use crate::datagram::DatagramConfig;
-use crate::stream::StreamConfig;
+use crate::datagram::{DatagramConfig, TruncationPolicy};
+use crate::fault::FaultConfig;
+use crate::handler::Transform;
+use crate::stream::{FrameCodec, StreamConfig};
 use crate::network::fd_inheritance::{BindStrategy, BindTarget};
 use std::path::PathBuf;
 use std::time::Duration;
@@ -10,15 +12,9 @@ use std::time::Duration;
 ///
 /// ```
 /// use echosrv::unix::UnixStreamConfig;
-/// use std::time::Duration;
 ///
-/// let config = UnixStreamConfig {
-///     socket_path: "/tmp/echo.sock".into(),
-///     max_connections: 100,
-///     buffer_size: 1024,
-///     read_timeout: Duration::from_secs(30),
-///     write_timeout: Duration::from_secs(30),
-/// };
+/// let config = UnixStreamConfig::default()
+///     .with_socket_path("/tmp/echo.sock".into());
 /// ```
 #[derive(Debug, Clone)]
 pub struct UnixStreamConfig {
@@ -34,6 +30,12 @@ pub struct UnixStreamConfig {
     pub read_timeout: Duration,
     /// Write timeout for connections
     pub write_timeout: Duration,
+    /// Fault injection settings (stream faults, latency and bandwidth)
+    pub faults: Option<FaultConfig>,
+    /// Transform applied to echoed data; plain echo if `None`
+    pub transform: Option<Transform>,
+    /// Framing of messages exchanged with clients; raw chunks if `None`
+    pub framing: Option<FrameCodec>,
 }
 
 impl Default for UnixStreamConfig {
@@ -45,6 +47,9 @@ impl Default for UnixStreamConfig {
             buffer_size: 1024,
             read_timeout: Duration::from_secs(30),
             write_timeout: Duration::from_secs(30),
+            faults: None,
+            transform: None,
+            framing: None,
         }
     }
 }
@@ -56,6 +61,24 @@ impl UnixStreamConfig {
         self
     }
     
+    /// Enable fault injection on echoed connections
+    pub fn with_faults(mut self, faults: FaultConfig) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    /// Transform echoed data instead of returning it unchanged
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    /// Exchange whole messages framed by `framing` instead of raw chunks
+    pub fn with_framing(mut self, framing: FrameCodec) -> Self {
+        self.framing = Some(framing);
+        self
+    }
+
     /// Enable FD inheritance with fallback to socket path
     pub fn with_fd_inheritance(mut self, service_name: String, fallback_path: PathBuf) -> Self {
         self.bind_strategy = BindStrategy::InheritOrBind {
@@ -74,10 +97,17 @@ impl From<UnixStreamConfig> for StreamConfig {
                 std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED),
                 0,
             ),
+            additional_addrs: Vec::new(),
             max_connections: config.max_connections,
             buffer_size: config.buffer_size,
             read_timeout: config.read_timeout,
             write_timeout: config.write_timeout,
+            faults: config.faults,
+            transform: config.transform,
+            framing: config.framing,
+            workers: 1,
+            steer_by_cpu: false,
+            socket_options: Default::default(),
         }
     }
 }
@@ -88,14 +118,9 @@ impl From<UnixStreamConfig> for StreamConfig {
 ///
 /// ```
 /// use echosrv::unix::UnixDatagramConfig;
-/// use std::time::Duration;
 ///
-/// let config = UnixDatagramConfig {
-///     socket_path: "/tmp/echo_dgram.sock".into(),
-///     buffer_size: 1024,
-///     read_timeout: Duration::from_secs(30),
-///     write_timeout: Duration::from_secs(30),
-/// };
+/// let config = UnixDatagramConfig::default()
+///     .with_socket_path("/tmp/echo_dgram.sock".into());
 /// ```
 #[derive(Debug, Clone)]
 pub struct UnixDatagramConfig {
@@ -109,6 +134,12 @@ pub struct UnixDatagramConfig {
     pub read_timeout: Duration,
     /// Write timeout for connections
     pub write_timeout: Duration,
+    /// Fault injection settings (impairment, latency and bandwidth)
+    pub faults: Option<FaultConfig>,
+    /// What to do with datagrams larger than `buffer_size`
+    pub truncation: TruncationPolicy,
+    /// Transform applied to echoed datagrams; plain echo if `None`
+    pub transform: Option<Transform>,
 }
 
 impl Default for UnixDatagramConfig {
@@ -119,6 +150,9 @@ impl Default for UnixDatagramConfig {
             buffer_size: 1024,
             read_timeout: Duration::from_secs(30),
             write_timeout: Duration::from_secs(30),
+            faults: None,
+            truncation: TruncationPolicy::Echo,
+            transform: None,
         }
     }
 }
@@ -130,6 +164,18 @@ impl UnixDatagramConfig {
         self
     }
     
+    /// Enable fault injection on echoed datagrams
+    pub fn with_faults(mut self, faults: FaultConfig) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    /// Transform echoed datagrams instead of returning them unchanged
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
     /// Enable FD inheritance with fallback to socket path
     pub fn with_fd_inheritance(mut self, service_name: String, fallback_path: PathBuf) -> Self {
         self.bind_strategy = BindStrategy::InheritOrBind {
@@ -139,6 +185,12 @@ impl UnixDatagramConfig {
         self.service_name = service_name;
         self
     }
+
+    /// Set what happens to datagrams larger than the buffer
+    pub fn with_truncation(mut self, truncation: TruncationPolicy) -> Self {
+        self.truncation = truncation;
+        self
+    }
 }
 
 impl From<UnixDatagramConfig> for DatagramConfig {
@@ -148,9 +200,17 @@ impl From<UnixDatagramConfig> for DatagramConfig {
                 std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED),
                 0,
             ),
+            additional_addrs: Vec::new(),
             buffer_size: config.buffer_size,
             read_timeout: config.read_timeout,
             write_timeout: config.write_timeout,
+            faults: config.faults,
+            transform: config.transform,
+            workers: 1,
+            batch: None,
+            socket_options: Default::default(),
+            multicast: None,
+            truncation: config.truncation,
         }
     }
 }
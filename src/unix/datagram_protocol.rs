@@ -10,15 +10,30 @@
 // without additional setup. Client-side sockets typically don't need inheritance since
 // they're created per-connection or per-session.
 
-use crate::datagram::protocol::DatagramProtocol;
+use crate::datagram::protocol::{DatagramProtocol, Received};
 use crate::network::socket_builder::BuildSocket;
 use crate::network::fd_inheritance::BindTarget;
-use crate::network::fd_inheritance::{BindStrategy, FdInheritanceConfig};
+use crate::network::fd_inheritance::FdInheritanceConfig;
 use crate::{EchoError, Result};
 use async_trait::async_trait;
+use socket2::{MaybeUninitSlice, SockAddr, SockRef};
+use std::collections::{HashMap, VecDeque};
+use std::mem::MaybeUninit;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
 use std::os::unix::io::{FromRawFd, RawFd};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::Interest;
 use tokio::net::UnixDatagram;
+use tracing::{debug, warn};
+
+/// Receive flags that make the kernel return the full size of a truncated
+/// datagram rather than the bytes that fit
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const RECV_FLAGS: libc::c_int = libc::MSG_TRUNC;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const RECV_FLAGS: libc::c_int = 0;
 
 /// Unix domain datagram socket builder
 /// 
@@ -58,12 +73,12 @@ impl BuildSocket<UnixDatagram> for UnixDatagramSocketBuilder {
         // Configure for async operation with Tokio
         // Tokio requires non-blocking sockets for proper async behavior
         std_socket.set_nonblocking(true)
-            .map_err(|e| EchoError::Unix(e))?;
+            .map_err(EchoError::Unix)?;
         
         // Convert std UnixDatagram to Tokio UnixDatagram
         // This registers the socket with Tokio's async runtime for efficient I/O
         UnixDatagram::from_std(std_socket)
-            .map_err(|e| EchoError::Unix(e))
+            .map_err(EchoError::Unix)
     }
     
     /// Create Unix datagram socket by binding to socket path
@@ -84,25 +99,25 @@ impl BuildSocket<UnixDatagram> for UnixDatagramSocketBuilder {
             BindTarget::Unix(path) => {
                 // Create parent directory if it doesn't exist
                 // This is safe because we only create the directory, not the socket file
-                if let Some(parent) = path.parent() {
-                    if !parent.exists() {
-                        std::fs::create_dir_all(parent)
-                            .map_err(|e| EchoError::Unix(e))?;
-                    }
+                if let Some(parent) = path.parent()
+                    && !parent.exists()
+                {
+                    std::fs::create_dir_all(parent)
+                        .map_err(EchoError::Unix)?;
                 }
                 
                 // Bind to socket path - let OS handle "already exists" errors
                 // This is atomic and avoids race conditions from manual file removal
                 let std_socket = std::os::unix::net::UnixDatagram::bind(path)
-                    .map_err(|e| EchoError::Unix(e))?;
+                    .map_err(EchoError::Unix)?;
                 
                 // Configure for async operation
                 std_socket.set_nonblocking(true)
-                    .map_err(|e| EchoError::Unix(e))?;
+                    .map_err(EchoError::Unix)?;
                 
                 // Convert to Tokio async UnixDatagram
                 UnixDatagram::from_std(std_socket)
-                    .map_err(|e| EchoError::Unix(e))
+                    .map_err(EchoError::Unix)
             }
             
             // Unix domain sockets cannot bind to network addresses
@@ -123,23 +138,127 @@ impl BuildSocket<UnixDatagram> for UnixDatagramSocketBuilder {
 #[derive(Debug, Clone)]
 pub struct UnixDatagramProtocol;
 
+/// Number of peer paths a [`UnixDatagramSocket`] remembers
+const PEER_TABLE_SIZE: usize = 4096;
+
+/// Upper 64 bits of the stand-in addresses given to peer paths (`fd00::/64`)
+const PEER_PREFIX: u128 = 0xfd00 << 112;
+
+/// Unix datagram socket that the generic datagram server can answer from
+/// 
+/// The `DatagramProtocol` trait addresses peers by `SocketAddr`, so each peer
+/// path gets a stand-in address in `fd00::/64` when a datagram arrives from it,
+/// and replies to that address go back to the path. The most recent
+/// `PEER_TABLE_SIZE` peers are remembered; a reply to a forgotten peer fails.
+/// 
+/// Datagrams from unnamed sockets have no path to answer, so they are
+/// counted and skipped rather than passed on.
+pub struct UnixDatagramSocket {
+    socket: UnixDatagram,
+    peers: Mutex<PeerTable>,
+    unnamed: AtomicU64,
+}
+
+impl UnixDatagramSocket {
+    /// Wraps a bound Unix datagram socket
+    pub fn new(socket: UnixDatagram) -> Self {
+        Self {
+            socket,
+            peers: Mutex::new(PeerTable::default()),
+            unnamed: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the wrapped socket
+    pub fn get_ref(&self) -> &UnixDatagram {
+        &self.socket
+    }
+
+    /// Returns how many datagrams from unnamed sockets were skipped
+    pub fn unnamed_datagrams(&self) -> u64 {
+        self.unnamed.load(Ordering::Relaxed)
+    }
+
+    /// Counts a skipped datagram from an unnamed socket, warning only about
+    /// the first
+    fn skip_unnamed(&self) {
+        let skipped = self.unnamed.fetch_add(1, Ordering::Relaxed) + 1;
+        if skipped == 1 {
+            warn!("Skipping datagrams from unnamed sockets; clients must bind a path to get replies");
+        } else {
+            debug!(skipped, "Skipped datagram from an unnamed socket");
+        }
+    }
+
+    /// Returns the stand-in address of the peer at `path`
+    fn peer_addr(&self, path: &Path) -> SocketAddr {
+        let id = self.peers.lock().unwrap().id_of(path);
+        SocketAddr::new(Ipv6Addr::from(PEER_PREFIX | u128::from(id)).into(), 0)
+    }
+
+    /// Returns the path behind a stand-in address, if still remembered
+    fn peer_path(&self, addr: SocketAddr) -> Option<PathBuf> {
+        let IpAddr::V6(ip) = addr.ip() else {
+            return None;
+        };
+        let bits = u128::from(ip);
+        if bits & !u128::from(u64::MAX) != PEER_PREFIX {
+            return None;
+        }
+        self.peers.lock().unwrap().path_of(bits as u64).map(Path::to_path_buf)
+    }
+}
+
+/// Peer paths by id, oldest first, forgetting the oldest once full
+#[derive(Default)]
+struct PeerTable {
+    /// Id of the first path in `paths`
+    first: u64,
+    paths: VecDeque<PathBuf>,
+    ids: HashMap<PathBuf, u64>,
+}
+
+impl PeerTable {
+    fn id_of(&mut self, path: &Path) -> u64 {
+        if let Some(&id) = self.ids.get(path) {
+            return id;
+        }
+        if self.paths.len() == PEER_TABLE_SIZE
+            && let Some(oldest) = self.paths.pop_front()
+        {
+            self.ids.remove(&oldest);
+            self.first += 1;
+        }
+        let id = self.first + self.paths.len() as u64;
+        self.paths.push_back(path.to_path_buf());
+        self.ids.insert(path.to_path_buf(), id);
+        id
+    }
+
+    fn path_of(&self, id: u64) -> Option<&Path> {
+        let index = usize::try_from(id.checked_sub(self.first)?).ok()?;
+        self.paths.get(index).map(PathBuf::as_path)
+    }
+}
+
 #[async_trait]
 impl DatagramProtocol for UnixDatagramProtocol {
     type Error = crate::EchoError;
-    type Socket = UnixDatagram;
+    type Socket = UnixDatagramSocket;
 
     /// Bind Unix datagram socket with automatic FD inheritance detection
     /// 
     /// For Unix domain sockets, we adapt the DatagramConfig to work with our
     /// UnixDatagramConfig. This provides compatibility with the existing trait.
-    async fn bind(config: &crate::datagram::DatagramConfig) -> std::result::Result<Self::Socket, Self::Error> {
+    async fn bind(_config: &crate::datagram::DatagramConfig) -> std::result::Result<Self::Socket, Self::Error> {
         // Convert generic config to Unix-specific config
         // For now, use default Unix config since DatagramConfig doesn't have path info
         let unix_config = super::config::UnixDatagramConfig::default();
         
         // Detect FD inheritance from environment (systemd, etc.)
         let fd_config = FdInheritanceConfig::from_systemd_env()?;
-        Self::bind_unix_with_inheritance(&unix_config, &fd_config).await
+        let socket = Self::bind_unix_with_inheritance(&unix_config, &fd_config).await?;
+        Ok(UnixDatagramSocket::new(socket))
     }
 
     /// Bind Unix datagram socket with explicit FD inheritance configuration
@@ -152,49 +271,60 @@ impl DatagramProtocol for UnixDatagramProtocol {
     ) -> std::result::Result<Self::Socket, Self::Error> {
         // Use default Unix config since generic DatagramConfig doesn't have path info
         let unix_config = super::config::UnixDatagramConfig::default();
-        Self::bind_unix_with_inheritance(&unix_config, fd_config).await
+        let socket = Self::bind_unix_with_inheritance(&unix_config, fd_config).await?;
+        Ok(UnixDatagramSocket::new(socket))
     }
 
     /// Receive datagram message from Unix socket
     /// 
     /// Unix domain datagram sockets preserve message boundaries, so each
-    /// receive operation gets exactly one complete message. For compatibility
-    /// with the DatagramProtocol trait, we return a dummy SocketAddr.
+    /// receive operation gets exactly one complete message. The sender is
+    /// reported by its stand-in address.
     async fn recv_from(
         socket: &Self::Socket,
         buffer: &mut [u8],
-    ) -> std::result::Result<(usize, std::net::SocketAddr), Self::Error> {
-        // Receive message with sender information
-        let (len, _sender_addr) = socket.recv_from(buffer).await
-            .map_err(|e| EchoError::Unix(e))?;
-        
-        // Convert Unix socket address to dummy SocketAddr for trait compatibility
-        // The actual peer address is not used in echo servers
-        let dummy_addr = std::net::SocketAddr::new(
-            std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED), 
-            0
-        );
-        
-        Ok((len, dummy_addr))
+    ) -> std::result::Result<(usize, SocketAddr), Self::Error> {
+        let received = Self::recv_msg(socket, buffer).await?;
+        Ok((received.len, received.peer))
+    }
+
+    /// Receive datagram message from Unix socket, noting whether it was truncated
+    /// 
+    /// A datagram from an unnamed socket cannot be answered, so it is
+    /// counted and skipped, and the next one is awaited.
+    async fn recv_msg(
+        socket: &Self::Socket,
+        buffer: &mut [u8],
+    ) -> std::result::Result<Received, Self::Error> {
+        let capacity = buffer.len();
+        loop {
+            let (size, truncated, sender_addr) = Self::recv_datagram(&socket.socket, buffer).await
+                .map_err(EchoError::Unix)?;
+            let Some(path) = sender_addr.as_pathname() else {
+                socket.skip_unnamed();
+                continue;
+            };
+            let received = Received::new(size.min(capacity), socket.peer_addr(path));
+            return Ok(if truncated { received.with_truncated(size) } else { received });
+        }
     }
 
     /// Send datagram message to Unix socket
     /// 
-    /// For Unix domain datagram communication, we ignore the SocketAddr parameter
-    /// and send to a default target path. Real Unix datagram applications should
-    /// use the UnixDatagramExt trait for proper path-based addressing.
+    /// The address must be the stand-in of a peer the socket received from.
     async fn send_to(
         socket: &Self::Socket,
         data: &[u8],
-        _addr: std::net::SocketAddr,
+        addr: SocketAddr,
     ) -> std::result::Result<usize, Self::Error> {
-        // For Unix domain datagrams, we need to send to a specific path
-        // This is a limitation of the current trait design
-        // In practice, UnixDatagramEchoClient will handle this differently
-        let target_path = PathBuf::from("/tmp/echosrv_datagram.sock");
-        
-        socket.send_to(data, &target_path).await
-            .map_err(|e| EchoError::Unix(e))
+        let Some(path) = socket.peer_path(addr) else {
+            return Err(EchoError::Unix(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no Unix peer is known by {addr}"),
+            )));
+        };
+        socket.socket.send_to(data, &path).await
+            .map_err(EchoError::Unix)
     }
 
     /// Maps a standard IO error to this protocol's error type
@@ -207,6 +337,7 @@ impl DatagramProtocol for UnixDatagramProtocol {
 /// 
 /// This trait provides Unix-specific functionality for datagram sockets,
 /// including client socket creation and abstract socket support.
+#[allow(async_fn_in_trait)]
 pub trait UnixDatagramExt {
     /// Create unbound Unix datagram socket for client use
     /// 
@@ -222,7 +353,7 @@ pub trait UnixDatagramExt {
     /// 
     /// # Arguments
     /// * `path` - Filesystem path to Unix domain socket
-    async fn connect_unix(path: &PathBuf) -> Result<UnixDatagram>;
+    async fn connect_unix(path: &Path) -> Result<UnixDatagram>;
     
     /// Create abstract Unix datagram socket
     /// 
@@ -250,6 +381,27 @@ impl UnixDatagramProtocol {
             fd_config,
         )
     }
+
+    /// Receive one datagram, returning its size as sent, whether it was
+    /// truncated to fit `buffer`, and the sender's address
+    /// 
+    /// On Linux the size of a truncated datagram is its full size
+    /// (`MSG_TRUNC`); elsewhere only the bytes that fit are known, so the
+    /// size is the buffer's.
+    pub async fn recv_datagram(
+        socket: &UnixDatagram,
+        buffer: &mut [u8],
+    ) -> std::io::Result<(usize, bool, SockAddr)> {
+        // SAFETY: initialized bytes are valid MaybeUninit<u8>, and the
+        // kernel only writes initialized bytes into them
+        let buffer = unsafe { &mut *(buffer as *mut [u8] as *mut [MaybeUninit<u8>]) };
+        socket.async_io(Interest::READABLE, || {
+            let mut slices = [MaybeUninitSlice::new(&mut *buffer)];
+            let (size, flags, sender_addr) = SockRef::from(socket)
+                .recv_from_vectored_with_flags(&mut slices, RECV_FLAGS)?;
+            Ok((size, flags.is_truncated(), sender_addr))
+        }).await
+    }
 }
 
 impl UnixDatagramExt for UnixDatagramProtocol {
@@ -267,22 +419,22 @@ impl UnixDatagramExt for UnixDatagramProtocol {
         
         // Create client socket bound to temporary path
         let std_socket = std::os::unix::net::UnixDatagram::bind(&client_path)
-            .map_err(|e| EchoError::Unix(e))?;
+            .map_err(EchoError::Unix)?;
         
         std_socket.set_nonblocking(true)
-            .map_err(|e| EchoError::Unix(e))?;
+            .map_err(EchoError::Unix)?;
         
         UnixDatagram::from_std(std_socket)
-            .map_err(|e| EchoError::Unix(e))
+            .map_err(EchoError::Unix)
     }
     
-    async fn connect_unix(path: &PathBuf) -> Result<UnixDatagram> {
+    async fn connect_unix(path: &Path) -> Result<UnixDatagram> {
         // Create temporary client socket first
         let client_socket = Self::create_client_socket().await?;
         
         // Connect to target socket (non-async method)
         client_socket.connect(path)
-            .map_err(|e| EchoError::Unix(e))?;
+            .map_err(EchoError::Unix)?;
         
         Ok(client_socket)
     }
@@ -292,12 +444,12 @@ impl UnixDatagramExt for UnixDatagramProtocol {
         let abstract_name = format!("\0{}", name);
         
         let std_socket = std::os::unix::net::UnixDatagram::bind(abstract_name)
-            .map_err(|e| EchoError::Unix(e))?;
+            .map_err(EchoError::Unix)?;
         
         std_socket.set_nonblocking(true)
-            .map_err(|e| EchoError::Unix(e))?;
+            .map_err(EchoError::Unix)?;
         
         UnixDatagram::from_std(std_socket)
-            .map_err(|e| EchoError::Unix(e))
+            .map_err(EchoError::Unix)
     }
 }
\ No newline at end of file
@@ -11,17 +11,11 @@
 //! ```no_run
 //! use echosrv::unix::{UnixStreamConfig, UnixStreamEchoServer};
 //! use echosrv::common::EchoServerTrait;
-//! use std::time::Duration;
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
-//!     let config = UnixStreamConfig {
-//!         socket_path: "/tmp/echo.sock".into(),
-//!         max_connections: 100,
-//!         buffer_size: 1024,
-//!         read_timeout: Duration::from_secs(30),
-//!         write_timeout: Duration::from_secs(30),
-//!     };
+//!     let config = UnixStreamConfig::default()
+//!         .with_socket_path("/tmp/echo.sock".into());
 //!
 //!     let server = UnixStreamEchoServer::new(config.into());
 //!     server.run().await?;
@@ -34,16 +28,11 @@
 //! ```no_run
 //! use echosrv::unix::{UnixDatagramConfig, UnixDatagramEchoServer};
 //! use echosrv::common::EchoServerTrait;
-//! use std::time::Duration;
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
-//!     let config = UnixDatagramConfig {
-//!         socket_path: "/tmp/echo_dgram.sock".into(),
-//!         buffer_size: 1024,
-//!         read_timeout: Duration::from_secs(30),
-//!         write_timeout: Duration::from_secs(30),
-//!     };
+//!     let config = UnixDatagramConfig::default()
+//!         .with_socket_path("/tmp/echo_dgram.sock".into());
 //!
 //!     let server = UnixDatagramEchoServer::new(config.into());
 //!     server.run().await?;
@@ -68,5 +57,5 @@ pub use client::{UnixDatagramEchoClient, UnixStreamEchoClient};
 pub use server::{UnixDatagramEchoServer, UnixStreamEchoServer};
 
 // Re-export protocol implementations
-pub use datagram_protocol::{UnixDatagramProtocol, UnixDatagramExt};
+pub use datagram_protocol::{UnixDatagramProtocol, UnixDatagramSocket, UnixDatagramExt};
 pub use stream_protocol::{UnixStreamProtocol, UnixStreamExt};
@@ -1,14 +1,15 @@
 use crate::Result;
 use crate::common::EchoServerTrait;
+use crate::datagram::DatagramEchoServer;
+use crate::stream::StreamEchoServer;
 use crate::unix::config::{UnixDatagramConfig, UnixStreamConfig};
-use crate::unix::datagram_protocol::{UnixDatagramExt, UnixDatagramProtocol};
-use crate::unix::stream_protocol::{UnixStreamProtocol, UnixStreamExt};
-use crate::stream::protocol::StreamProtocol;
-use crate::datagram::protocol::DatagramProtocol;
+use crate::unix::datagram_protocol::{UnixDatagramProtocol, UnixDatagramSocket};
+use crate::unix::stream_protocol::UnixStreamProtocol;
+use crate::fault::protocol::{FaultyListener, FaultySocket};
+use crate::fault::{Faulty, ImpairmentStats};
+use crate::handler::{DatagramHandler, EchoHandler};
 use async_trait::async_trait;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::time::timeout;
-use tracing::{error, info};
+use tracing::info;
 
 /// Unix domain stream echo server
 ///
@@ -21,17 +22,11 @@ use tracing::{error, info};
 /// ```no_run
 /// use echosrv::unix::{UnixStreamConfig, UnixStreamEchoServer};
 /// use echosrv::common::EchoServerTrait;
-/// use std::time::Duration;
 ///
 /// #[tokio::main]
 /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
-///     let config = UnixStreamConfig {
-///         socket_path: "/tmp/echo.sock".into(),
-///         max_connections: 100,
-///         buffer_size: 1024,
-///         read_timeout: Duration::from_secs(30),
-///         write_timeout: Duration::from_secs(30),
-///     };
+///     let config = UnixStreamConfig::default()
+///         .with_socket_path("/tmp/echo.sock".into());
 ///
 ///     let server = UnixStreamEchoServer::new(config);
 ///     server.run().await?;
@@ -40,22 +35,30 @@ use tracing::{error, info};
 /// ```
 pub struct UnixStreamEchoServer {
     config: UnixStreamConfig,
-    shutdown_tx: tokio::sync::broadcast::Sender<()>,
+    server: StreamEchoServer<Faulty<UnixStreamProtocol>>,
 }
 
 impl UnixStreamEchoServer {
     /// Creates a new Unix domain stream echo server with the given configuration
     pub fn new(config: UnixStreamConfig) -> Self {
-        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
         Self {
+            server: StreamEchoServer::new(config.clone().into()),
             config,
-            shutdown_tx,
         }
     }
+
+    /// Replaces the echo with `handler` as the source of every response
+    pub fn with_handler(mut self, handler: impl EchoHandler + 'static) -> Self {
+        self.server = self.server.with_handler(handler);
+        self
+    }
 }
 
 #[async_trait]
 impl EchoServerTrait for UnixStreamEchoServer {
+    /// Binds the socket path and serves it like a TCP listener, so stream
+    /// faults, shaping and the connection limit behave the same on both
+    /// transports
     async fn run(&self) -> Result<()> {
         // Extract socket path from bind strategy for logging
         let socket_path = match &self.config.bind_strategy {
@@ -75,7 +78,7 @@ impl EchoServerTrait for UnixStreamEchoServer {
         );
 
         // Use the new protocol implementation with FD inheritance
-        let mut listener = UnixStreamProtocol::bind_unix_with_inheritance(
+        let listener = UnixStreamProtocol::bind_unix_with_inheritance(
             &self.config, 
             &crate::network::fd_inheritance::FdInheritanceConfig::from_systemd_env()?
         ).await?;
@@ -84,75 +87,18 @@ impl EchoServerTrait for UnixStreamEchoServer {
             socket_path.display()
         );
 
-        let mut shutdown_rx = self.shutdown_tx.subscribe();
-
-        loop {
-            tokio::select! {
-                accept_result = listener.accept() => {
-                    match accept_result {
-                        Ok((mut stream, _addr)) => {
-                            let buffer_size = self.config.buffer_size;
-                            let read_timeout = self.config.read_timeout;
-                            let write_timeout = self.config.write_timeout;
-
-                            // Spawn a task to handle this connection
-                            tokio::spawn(async move {
-                                let mut buffer = vec![0u8; buffer_size];
-
-                                loop {
-                                    // Read with timeout
-                                    let read_result = timeout(read_timeout, stream.read(&mut buffer)).await;
-                                    match read_result {
-                                        Ok(Ok(0)) => {
-                                            // Connection closed by client
-                                            break;
-                                        }
-                                        Ok(Ok(n)) => {
-                                            let data = &buffer[..n];
-
-                                            // Echo back with timeout
-                                            if let Err(e) = timeout(write_timeout, stream.write_all(data)).await {
-                                                error!("Write timeout or error: {}", e);
-                                                break;
-                                            }
-
-                                            if let Err(e) = timeout(write_timeout, stream.flush()).await {
-                                                error!("Flush timeout or error: {}", e);
-                                                break;
-                                            }
-                                        }
-                                        Ok(Err(e)) => {
-                                            error!("Read error: {}", e);
-                                            break;
-                                        }
-                                        Err(_) => {
-                                            error!("Read timeout");
-                                            break;
-                                        }
-                                    }
-                                }
-                            });
-                        }
-                        Err(e) => {
-                            error!("Accept error: {}", e);
-                        }
-                    }
-                }
-                _ = shutdown_rx.recv() => {
-                    info!("Shutdown signal received, stopping Unix domain stream server");
-                    break;
-                }
-            }
-        }
+        let config = self.server.config();
+        let listener = FaultyListener::new(listener, config.faults.as_ref(), &config.bind_addr);
+        let result = self.server.serve(vec![(config.bind_addr, listener)]).await;
 
         // Clean up socket file
         let _ = std::fs::remove_file(socket_path);
         info!("Unix domain stream server stopped");
-        Ok(())
+        result
     }
 
     fn shutdown_signal(&self) -> tokio::sync::broadcast::Sender<()> {
-        self.shutdown_tx.clone()
+        self.server.shutdown_signal()
     }
 }
 
@@ -160,23 +106,20 @@ impl EchoServerTrait for UnixStreamEchoServer {
 ///
 /// This server handles Unix domain datagram messages and echoes back
 /// all received data. It's optimized for connectionless inter-process
-/// communication on Unix-like systems.
+/// communication on Unix-like systems. Messages larger than the buffer are
+/// logged, counted in [`truncated_datagrams`](Self::truncated_datagrams),
+/// and answered as `config.truncation` says.
 ///
 /// # Examples
 ///
 /// ```no_run
 /// use echosrv::unix::{UnixDatagramConfig, UnixDatagramEchoServer};
 /// use echosrv::common::EchoServerTrait;
-/// use std::time::Duration;
 ///
 /// #[tokio::main]
 /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
-///     let config = UnixDatagramConfig {
-///         socket_path: "/tmp/echo_dgram.sock".into(),
-///         buffer_size: 1024,
-///         read_timeout: Duration::from_secs(30),
-///         write_timeout: Duration::from_secs(30),
-///     };
+///     let config = UnixDatagramConfig::default()
+///         .with_socket_path("/tmp/echo_dgram.sock".into());
 ///
 ///     let server = UnixDatagramEchoServer::new(config);
 ///     server.run().await?;
@@ -185,22 +128,44 @@ impl EchoServerTrait for UnixStreamEchoServer {
 /// ```
 pub struct UnixDatagramEchoServer {
     config: UnixDatagramConfig,
-    shutdown_tx: tokio::sync::broadcast::Sender<()>,
+    server: DatagramEchoServer<Faulty<UnixDatagramProtocol>>,
 }
 
 impl UnixDatagramEchoServer {
     /// Creates a new Unix domain datagram echo server with the given configuration
     pub fn new(config: UnixDatagramConfig) -> Self {
-        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
         Self {
+            server: DatagramEchoServer::new(config.clone().into()),
             config,
-            shutdown_tx,
         }
     }
+
+    /// Replaces the echo with `handler` as the source of every reply
+    /// 
+    /// Peers reach the handler under stand-in `fd00::/64` addresses (see
+    /// [`UnixDatagramSocket`](super::UnixDatagramSocket)), so handlers that
+    /// reply with the peer's address, such as [`Whoami`](crate::handler::Whoami),
+    /// have nothing meaningful to report here.
+    pub fn with_handler(mut self, handler: impl DatagramHandler + 'static) -> Self {
+        self.server = self.server.with_handler(handler);
+        self
+    }
+
+    /// Returns how many datagrams each impairment has affected so far
+    pub fn impairment_stats(&self) -> ImpairmentStats {
+        self.server.impairment_stats()
+    }
+
+    /// Returns how many received datagrams were larger than the buffer
+    pub fn truncated_datagrams(&self) -> u64 {
+        self.server.truncated_datagrams()
+    }
 }
 
 #[async_trait]
 impl EchoServerTrait for UnixDatagramEchoServer {
+    /// Binds the socket path and serves it like a UDP socket, so truncation,
+    /// impairment and shaping behave the same on both transports
     async fn run(&self) -> Result<()> {
         // Extract socket path from bind strategy for logging
         let socket_path = match &self.config.bind_strategy {
@@ -228,52 +193,22 @@ impl EchoServerTrait for UnixDatagramEchoServer {
             "Unix domain datagram server bound to {}",
             socket_path.display()
         );
-        info!("Server socket created successfully");
 
-        let mut shutdown_rx = self.shutdown_tx.subscribe();
-        let mut buffer = vec![0u8; self.config.buffer_size];
-
-        loop {
-            tokio::select! {
-                recv_result = socket.recv_from(&mut buffer) => {
-                    match recv_result {
-                        Ok((len, peer_addr)) => {
-                            let data = &buffer[..len];
-                            info!("Received {} bytes from peer", len);
-
-                            // Echo back to the same peer
-                            // For Unix datagrams, we need to convert the address to a path
-                            if let Some(path) = peer_addr.as_pathname() {
-                                if let Err(e) = socket.send_to(data, path).await {
-                                    error!("Failed to send response: {}", e);
-                                } else {
-                                    info!("Sent {} bytes back to peer", len);
-                                }
-                            } else {
-                                // For anonymous sockets, we can't reply because we don't have a path
-                                // The client should use a named socket if it wants to receive responses
-                                error!("Received message from unnamed socket, cannot reply. Client should use a named socket.");
-                            }
-                        }
-                        Err(e) => {
-                            error!("Receive error: {}", e);
-                        }
-                    }
-                }
-                _ = shutdown_rx.recv() => {
-                    info!("Shutdown signal received, stopping Unix domain datagram server");
-                    break;
-                }
-            }
-        }
+        let config = self.server.config();
+        let socket = FaultySocket::new(
+            UnixDatagramSocket::new(socket),
+            config.faults.as_ref(),
+            &config.bind_addr,
+        );
+        let result = self.server.serve(vec![(config.bind_addr, socket)]).await;
 
         // Clean up socket file
         let _ = std::fs::remove_file(socket_path);
         info!("Unix domain datagram server stopped");
-        Ok(())
+        result
     }
 
     fn shutdown_signal(&self) -> tokio::sync::broadcast::Sender<()> {
-        self.shutdown_tx.clone()
+        self.server.shutdown_signal()
     }
 }
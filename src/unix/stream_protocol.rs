@@ -14,13 +14,16 @@
 // - Client connections don't create separate socket files
 
 use crate::stream::protocol::StreamProtocol;
+use crate::network::Address;
 use crate::network::socket_builder::BuildSocket;
 use crate::network::fd_inheritance::BindTarget;
-use crate::network::fd_inheritance::{BindStrategy, FdInheritanceConfig};
+use crate::network::fd_inheritance::FdInheritanceConfig;
 use crate::{EchoError, Result};
 use async_trait::async_trait;
 use std::os::unix::io::{FromRawFd, RawFd};
-use std::path::PathBuf;
+use std::path::Path;
+use crate::stream::{ReadHalf, SplitWrite, WriteHalf};
+use tokio::net::unix::OwnedWriteHalf;
 use tokio::net::{UnixListener, UnixStream};
 
 /// Unix domain stream socket builder
@@ -61,12 +64,12 @@ impl BuildSocket<UnixListener> for UnixStreamSocketBuilder {
         // Configure for async operation with Tokio
         // Tokio requires non-blocking sockets for proper async behavior
         std_listener.set_nonblocking(true)
-            .map_err(|e| EchoError::Unix(e))?;
+            .map_err(EchoError::Unix)?;
         
         // Convert std UnixListener to Tokio UnixListener
         // This registers the socket with Tokio's async runtime
         UnixListener::from_std(std_listener)
-            .map_err(|e| EchoError::Unix(e))
+            .map_err(EchoError::Unix)
     }
     
     /// Create Unix listener by binding to socket path
@@ -88,25 +91,25 @@ impl BuildSocket<UnixListener> for UnixStreamSocketBuilder {
             BindTarget::Unix(path) => {
                 // Create parent directory if it doesn't exist
                 // This is safe because we only create the directory, not the socket file
-                if let Some(parent) = path.parent() {
-                    if !parent.exists() {
-                        std::fs::create_dir_all(parent)
-                            .map_err(|e| EchoError::Unix(e))?;
-                    }
+                if let Some(parent) = path.parent()
+                    && !parent.exists()
+                {
+                    std::fs::create_dir_all(parent)
+                        .map_err(EchoError::Unix)?;
                 }
                 
                 // Bind to socket path - let OS handle "already exists" errors
                 // This is atomic and avoids race conditions from manual file removal
                 let std_listener = std::os::unix::net::UnixListener::bind(path)
-                    .map_err(|e| EchoError::Unix(e))?;
+                    .map_err(EchoError::Unix)?;
                 
                 // Configure for async operation
                 std_listener.set_nonblocking(true)
-                    .map_err(|e| EchoError::Unix(e))?;
+                    .map_err(EchoError::Unix)?;
                 
                 // Convert to Tokio async UnixListener
                 UnixListener::from_std(std_listener)
-                    .map_err(|e| EchoError::Unix(e))
+                    .map_err(EchoError::Unix)
             }
             
             // Unix domain sockets cannot bind to network addresses
@@ -137,14 +140,14 @@ impl StreamProtocol for UnixStreamProtocol {
     /// 
     /// For Unix domain sockets, we adapt the StreamConfig to work with our
     /// UnixStreamConfig. This provides compatibility with the existing trait.
-    async fn bind(config: &crate::stream::StreamConfig) -> std::result::Result<Self::Listener, Self::Error> {
+    async fn bind(_config: &crate::stream::StreamConfig) -> std::result::Result<Self::Listener, Self::Error> {
         // Convert generic config to Unix-specific config
         // For now, use default Unix config since StreamConfig doesn't have path info
         let unix_config = super::config::UnixStreamConfig::default();
         
         // Detect FD inheritance from environment (systemd, etc.)
         let fd_config = FdInheritanceConfig::from_systemd_env()
-            .map_err(|e| EchoError::Unix(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+            .map_err(|e| EchoError::Unix(std::io::Error::other(e)))?;
         Self::bind_unix_with_inheritance(&unix_config, &fd_config).await
     }
 
@@ -169,7 +172,7 @@ impl StreamProtocol for UnixStreamProtocol {
         listener: &mut Self::Listener,
     ) -> std::result::Result<(Self::Stream, std::net::SocketAddr), Self::Error> {
         let (stream, _addr) = listener.accept().await
-            .map_err(|e| EchoError::Unix(e))?;
+            .map_err(EchoError::Unix)?;
         
         // Create dummy SocketAddr for trait compatibility
         let dummy_addr = std::net::SocketAddr::new(
@@ -190,6 +193,16 @@ impl StreamProtocol for UnixStreamProtocol {
         ))
     }
 
+    /// Connects to a Unix socket path; network addresses are unsupported
+    async fn connect_address(addr: &Address) -> std::result::Result<Self::Stream, Self::Error> {
+        match addr {
+            Address::Unix(path) => Self::connect_unix(path).await,
+            Address::Network(addr) => Err(EchoError::Unsupported(format!(
+                "Unix stream protocol cannot connect to network address {addr}"
+            ))),
+        }
+    }
+
     /// Reads data from a stream
     async fn read(
         stream: &mut Self::Stream,
@@ -211,23 +224,41 @@ impl StreamProtocol for UnixStreamProtocol {
         stream.flush().await.map_err(EchoError::Unix)
     }
 
+    /// Splits a stream into owned read and write halves
+    fn into_split(
+        stream: Self::Stream,
+    ) -> std::result::Result<(ReadHalf, WriteHalf), Self::Stream> {
+        let (read, write) = stream.into_split();
+        Ok((Box::new(read), Box::new(write)))
+    }
+
+    /// Shuts down the write half of a stream
+    async fn shutdown_write(stream: &mut Self::Stream) -> std::result::Result<(), Self::Error> {
+        use tokio::io::AsyncWriteExt;
+        stream.shutdown().await.map_err(EchoError::Unix)
+    }
+
     /// Maps a standard IO error to this protocol's error type
     fn map_io_error(err: std::io::Error) -> Self::Error {
         EchoError::Unix(err)
     }
 }
 
+/// Unix streams have no reset, so an aborted write half is simply dropped
+impl SplitWrite for OwnedWriteHalf {}
+
 /// Extension trait for Unix domain socket specific operations
 /// 
 /// This trait provides Unix-specific functionality that doesn't fit in the
 /// generic StreamProtocol interface, such as connecting to socket paths
 /// instead of network addresses.
+#[allow(async_fn_in_trait)]
 pub trait UnixStreamExt {
     /// Connect to Unix domain socket using filesystem path
     /// 
     /// # Arguments
     /// * `path` - Filesystem path to Unix domain socket
-    async fn connect_unix(path: &PathBuf) -> Result<UnixStream>;
+    async fn connect_unix(path: &Path) -> Result<UnixStream>;
     
     /// Connect to abstract Unix domain socket
     /// 
@@ -258,15 +289,15 @@ impl UnixStreamProtocol {
 }
 
 impl UnixStreamExt for UnixStreamProtocol {
-    async fn connect_unix(path: &PathBuf) -> Result<UnixStream> {
+    async fn connect_unix(path: &Path) -> Result<UnixStream> {
         UnixStream::connect(path).await
-            .map_err(|e| EchoError::Unix(e))
+            .map_err(EchoError::Unix)
     }
     
     async fn connect_abstract(name: &str) -> Result<UnixStream> {
         // Abstract socket names start with null byte
         let abstract_name = format!("\0{}", name);
         UnixStream::connect(abstract_name).await
-            .map_err(|e| EchoError::Unix(e))
+            .map_err(EchoError::Unix)
     }
 }
\ No newline at end of file
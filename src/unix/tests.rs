@@ -1,8 +1,12 @@
 use crate::common::{EchoClient, EchoServerTrait};
+use crate::datagram::TruncationPolicy;
+use crate::handler::{DatagramContext, StreamContext, Transform};
+use crate::stream::{FrameCodec, Framing};
 use crate::unix::{
     UnixDatagramConfig, UnixDatagramEchoClient, UnixDatagramEchoServer, UnixStreamConfig,
     UnixStreamEchoClient, UnixStreamEchoServer,
 };
+use std::sync::Arc;
 use std::time::Duration;
 use tempfile::tempdir;
 
@@ -82,6 +86,256 @@ async fn test_unix_datagram_echo() {
     client_result.unwrap();
 }
 
+#[tokio::test]
+async fn test_unix_datagram_skips_unnamed_senders() {
+    use crate::datagram::protocol::DatagramProtocol;
+    use crate::unix::{UnixDatagramProtocol, UnixDatagramSocket};
+
+    let temp_dir = tempdir().unwrap();
+    let socket_path = temp_dir.path().join("unnamed.sock");
+    let socket = UnixDatagramSocket::new(tokio::net::UnixDatagram::bind(&socket_path).unwrap());
+
+    let unnamed = tokio::net::UnixDatagram::unbound().unwrap();
+    unnamed.send_to(b"unnamed", &socket_path).await.unwrap();
+    let named = tokio::net::UnixDatagram::bind(temp_dir.path().join("named.sock")).unwrap();
+    named.send_to(b"named", &socket_path).await.unwrap();
+
+    let mut buffer = [0u8; 64];
+    let received = tokio::time::timeout(
+        Duration::from_secs(5),
+        UnixDatagramProtocol::recv_msg(&socket, &mut buffer),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(&buffer[..received.len], b"named");
+    assert_eq!(socket.unnamed_datagrams(), 1);
+
+    UnixDatagramProtocol::send_to(&socket, b"reply", received.peer).await.unwrap();
+    let len = named.recv(&mut buffer).await.unwrap();
+    assert_eq!(&buffer[..len], b"reply");
+}
+
+#[tokio::test]
+async fn test_unix_transforms() {
+    let temp_dir = tempdir().unwrap();
+    let stream_path = temp_dir.path().join("transform_stream.sock");
+    let datagram_path = temp_dir.path().join("transform_datagram.sock");
+
+    let stream_server = UnixStreamEchoServer::new(
+        UnixStreamConfig::default()
+            .with_socket_path(stream_path.clone())
+            .with_transform(Transform::Uppercase),
+    );
+    let datagram_server = UnixDatagramEchoServer::new(
+        UnixDatagramConfig::default()
+            .with_socket_path(datagram_path.clone())
+            .with_transform(Transform::Reverse),
+    );
+    let stream_shutdown = stream_server.shutdown_signal();
+    let datagram_shutdown = datagram_server.shutdown_signal();
+    let stream_handle = tokio::spawn(async move { stream_server.run().await });
+    let datagram_handle = tokio::spawn(async move { datagram_server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client_result = tokio::time::timeout(Duration::from_secs(10), async {
+        let mut client = UnixStreamEchoClient::connect(stream_path).await.unwrap();
+        assert_eq!(client.echo_string("hello").await.unwrap(), "HELLO");
+
+        let mut client = UnixDatagramEchoClient::connect(datagram_path).await.unwrap();
+        assert_eq!(client.echo(b"hello").await.unwrap(), b"olleh");
+    })
+    .await;
+
+    let _ = stream_shutdown.send(());
+    let _ = datagram_shutdown.send(());
+    stream_handle.await.unwrap().unwrap();
+    datagram_handle.await.unwrap().unwrap();
+    client_result.unwrap();
+}
+
+#[tokio::test]
+async fn test_unix_custom_handlers() {
+    let temp_dir = tempdir().unwrap();
+    let stream_path = temp_dir.path().join("handler_stream.sock");
+    let datagram_path = temp_dir.path().join("handler_datagram.sock");
+
+    let stream_server = UnixStreamEchoServer::new(
+        UnixStreamConfig::default().with_socket_path(stream_path.clone()),
+    )
+    .with_handler(|data: &[u8], _: &StreamContext| Some(data.to_ascii_uppercase()));
+    let datagram_server = UnixDatagramEchoServer::new(
+        UnixDatagramConfig::default().with_socket_path(datagram_path.clone()),
+    )
+    .with_handler(|data: &[u8], _: &DatagramContext| Some(data.iter().rev().copied().collect()));
+    let stream_shutdown = stream_server.shutdown_signal();
+    let datagram_shutdown = datagram_server.shutdown_signal();
+    let stream_handle = tokio::spawn(async move { stream_server.run().await });
+    let datagram_handle = tokio::spawn(async move { datagram_server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client_result = tokio::time::timeout(Duration::from_secs(10), async {
+        let mut client = UnixStreamEchoClient::connect(stream_path).await.unwrap();
+        assert_eq!(client.echo_string("hi").await.unwrap(), "HI");
+
+        let mut client = UnixDatagramEchoClient::connect(datagram_path).await.unwrap();
+        assert_eq!(client.echo(b"hi").await.unwrap(), b"ih");
+    })
+    .await;
+
+    let _ = stream_shutdown.send(());
+    let _ = datagram_shutdown.send(());
+    stream_handle.await.unwrap().unwrap();
+    datagram_handle.await.unwrap().unwrap();
+    client_result.unwrap();
+}
+
+#[tokio::test]
+async fn test_unix_stream_framing() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let temp_dir = tempdir().unwrap();
+    let socket_path = temp_dir.path().join("framed_stream.sock");
+
+    let config = UnixStreamConfig::default()
+        .with_socket_path(socket_path.clone())
+        .with_transform(Transform::Reverse)
+        .with_framing(FrameCodec::new(Framing::Lines));
+    let server = UnixStreamEchoServer::new(config);
+    let shutdown_signal = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Each line is answered on its own, however the writes split it
+    let client_result = tokio::time::timeout(Duration::from_secs(10), async {
+        let mut stream = tokio::net::UnixStream::connect(&socket_path).await.unwrap();
+        stream.write_all(b"abc\r\nde").await.unwrap();
+        stream.write_all(b"f\nghi").await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response, b"cba\nfed\nihg\n");
+    })
+    .await;
+
+    let _ = shutdown_signal.send(());
+    server_handle.await.unwrap().unwrap();
+    client_result.unwrap();
+}
+
+#[tokio::test]
+async fn test_unix_datagram_truncation() {
+    let temp_dir = tempdir().unwrap();
+    let socket_path = temp_dir.path().join("test_truncation.sock");
+
+    let config = UnixDatagramConfig {
+        buffer_size: 16,
+        ..UnixDatagramConfig::default().with_socket_path(socket_path.clone())
+    }
+    .with_truncation(TruncationPolicy::Error);
+
+    let server = Arc::new(UnixDatagramEchoServer::new(config));
+    let shutdown_signal = server.shutdown_signal();
+    let server_handle = tokio::spawn({
+        let server = Arc::clone(&server);
+        async move { server.run().await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client_result = tokio::time::timeout(Duration::from_secs(10), async {
+        let mut client = UnixDatagramEchoClient::connect(socket_path).await.unwrap();
+
+        // A datagram larger than the buffer is answered with the marker
+        let response = client.echo(&[b'x'; 40]).await.unwrap();
+        assert_eq!(response, TruncationPolicy::marker(40));
+
+        let response = client.echo(b"fits").await.unwrap();
+        assert_eq!(response, b"fits");
+    })
+    .await;
+
+    let _ = shutdown_signal.send(());
+    server_handle.await.unwrap().unwrap();
+    client_result.unwrap();
+    assert_eq!(server.truncated_datagrams(), 1);
+}
+
+#[tokio::test]
+async fn test_unix_datagram_truncation_drop() {
+    let temp_dir = tempdir().unwrap();
+    let socket_path = temp_dir.path().join("test_truncation_drop.sock");
+
+    let config = UnixDatagramConfig {
+        buffer_size: 16,
+        ..UnixDatagramConfig::default().with_socket_path(socket_path.clone())
+    }
+    .with_truncation(TruncationPolicy::Drop);
+
+    let server = Arc::new(UnixDatagramEchoServer::new(config));
+    let shutdown_signal = server.shutdown_signal();
+    let server_handle = tokio::spawn({
+        let server = Arc::clone(&server);
+        async move { server.run().await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client_result = tokio::time::timeout(Duration::from_secs(10), async {
+        let client = tokio::net::UnixDatagram::bind(temp_dir.path().join("client.sock")).unwrap();
+        client.connect(&socket_path).unwrap();
+
+        // The datagram larger than the buffer gets no reply, the next one does
+        client.send(&[b'x'; 40]).await.unwrap();
+        client.send(b"fits").await.unwrap();
+        let mut response = [0u8; 64];
+        let n = client.recv(&mut response).await.unwrap();
+        assert_eq!(&response[..n], b"fits");
+    })
+    .await;
+
+    let _ = shutdown_signal.send(());
+    server_handle.await.unwrap().unwrap();
+    client_result.unwrap();
+    assert_eq!(server.truncated_datagrams(), 1);
+}
+
+#[tokio::test]
+async fn test_unix_datagram_multiple_clients() {
+    let temp_dir = tempdir().unwrap();
+    let socket_path = temp_dir.path().join("test_datagram_clients.sock");
+
+    let config = UnixDatagramConfig::default()
+        .with_socket_path(socket_path.clone());
+
+    let server = UnixDatagramEchoServer::new(config);
+    let shutdown_signal = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Each client gets its own echoes back, not another client's
+    let client_result = tokio::time::timeout(Duration::from_secs(10), async {
+        let mut handles = Vec::new();
+        for i in 0..4 {
+            let socket_path = socket_path.clone();
+            handles.push(tokio::spawn(async move {
+                let mut client = UnixDatagramEchoClient::connect(socket_path).await.unwrap();
+                for j in 0..10 {
+                    let message = format!("client {i} message {j}");
+                    let response = client.echo_string(&message).await.unwrap();
+                    assert_eq!(response, message);
+                }
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    })
+    .await;
+
+    let _ = shutdown_signal.send(());
+    server_handle.await.unwrap().unwrap();
+    client_result.unwrap();
+}
+
 #[tokio::test]
 async fn test_unix_stream_multiple_clients() {
     let temp_dir = tempdir().unwrap();
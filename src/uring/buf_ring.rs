@@ -0,0 +1,123 @@
+use io_uring::Submitter;
+use io_uring::types::BufRingEntry;
+use std::alloc::{Layout, alloc_zeroed, dealloc};
+use std::cell::UnsafeCell;
+use std::io;
+use std::sync::atomic::{AtomicU16, Ordering};
+
+/// Alignment the kernel requires of a buffer ring
+const PAGE_SIZE: usize = 4096;
+
+/// Receive buffers registered with a ring, which the kernel picks from as
+/// data arrives
+///
+/// Multishot receives take a buffer per completion and report its id; the
+/// buffer belongs to the application until [`recycle`](Self::recycle) hands
+/// it back. A plain echo is sent straight from the buffer it arrived in.
+pub(crate) struct BufRing {
+    entries: *mut BufRingEntry,
+    layout: Layout,
+    mask: u16,
+    tail: u16,
+    /// Buffer memory, written by the kernel behind Rust's back
+    buffers: Box<[UnsafeCell<u8>]>,
+    buffer_size: usize,
+}
+
+impl BufRing {
+    /// Registers `count` buffers of `buffer_size` bytes as buffer group
+    /// `group`; `count` is rounded up to a power of two
+    pub(crate) fn new(
+        submitter: &Submitter<'_>,
+        group: u16,
+        count: u16,
+        buffer_size: usize,
+    ) -> io::Result<Self> {
+        let count = count
+            .max(1)
+            .checked_next_power_of_two()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "too many ring buffers"))?;
+        let layout = Layout::from_size_align(
+            usize::from(count) * std::mem::size_of::<BufRingEntry>(),
+            PAGE_SIZE,
+        )
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: the layout has a non-zero size
+        let entries = unsafe { alloc_zeroed(layout) }.cast::<BufRingEntry>();
+        if entries.is_null() {
+            return Err(io::ErrorKind::OutOfMemory.into());
+        }
+
+        let mut ring = Self {
+            entries,
+            layout,
+            mask: count - 1,
+            tail: 0,
+            buffers: (0..usize::from(count) * buffer_size)
+                .map(|_| UnsafeCell::new(0))
+                .collect(),
+            buffer_size,
+        };
+        // SAFETY: the entries stay allocated until the ring is dropped, which
+        // happens after the io_uring instance that uses them is closed
+        unsafe { submitter.register_buf_ring(entries as u64, count, group)? };
+        for id in 0..count {
+            ring.push(id);
+        }
+        ring.publish();
+        Ok(ring)
+    }
+
+    /// Returns the first `len` bytes of buffer `id`, which a completion
+    /// handed to the application
+    pub(crate) fn get(&self, id: u16, len: usize) -> &[u8] {
+        let start = usize::from(id) * self.buffer_size;
+        let len = len.min(self.buffer_size);
+        assert!(start + len <= self.buffers.len(), "buffer id out of range");
+        // SAFETY: the range lies within the allocation. The kernel only
+        // writes to buffers that sit in the ring; a completion takes `id` out
+        // of it, and it goes back only through `recycle`, whose `&mut self`
+        // ends every borrow returned here first
+        unsafe { std::slice::from_raw_parts(self.base().add(start), len) }
+    }
+
+    /// Hands buffer `id` back to the kernel
+    pub(crate) fn recycle(&mut self, id: u16) {
+        self.push(id);
+        self.publish();
+    }
+
+    fn push(&mut self, id: u16) {
+        let start = usize::from(id) * self.buffer_size;
+        // SAFETY: the index is masked to the ring's length
+        let entry = unsafe { &mut *self.entries.add(usize::from(self.tail & self.mask)) };
+        // The kernel writes through this pointer, so it comes from the
+        // `UnsafeCell`s rather than from a shared borrow of the bytes
+        entry.set_addr(self.base().wrapping_add(start) as u64);
+        entry.set_len(self.buffer_size as u32);
+        entry.set_bid(id);
+        self.tail = self.tail.wrapping_add(1);
+    }
+
+    /// Start of the buffer memory, valid for writes
+    fn base(&self) -> *mut u8 {
+        UnsafeCell::raw_get(self.buffers.as_ptr())
+    }
+
+    /// Makes pushed buffers visible to the kernel
+    fn publish(&self) {
+        // SAFETY: the tail field lies within the first entry, and is only
+        // read by the kernel, so an atomic store is the sole access to it
+        unsafe {
+            let tail = BufRingEntry::tail(self.entries).cast_mut();
+            AtomicU16::from_ptr(tail).store(self.tail, Ordering::Release);
+        }
+    }
+}
+
+impl Drop for BufRing {
+    fn drop(&mut self) {
+        // SAFETY: allocated in `new` with the same layout
+        unsafe { dealloc(self.entries.cast(), self.layout) };
+    }
+}
@@ -0,0 +1,470 @@
+use super::buf_ring::BufRing;
+use super::event_loop::{self, Completion, RING_BUFFERS, RING_ENTRIES, TICK};
+use crate::common::EchoServerTrait;
+use crate::datagram::{DatagramConfig, DatagramProtocol, TruncationPolicy};
+use crate::handler::{DatagramContext, DatagramHandler, Echo};
+use crate::udp::UdpProtocol;
+use crate::udp::pktinfo::{self, CONTROL_LEN, Control};
+use crate::{EchoError, Result};
+use async_trait::async_trait;
+use io_uring::{IoUring, cqueue, opcode, types};
+use socket2::SockAddr;
+use std::borrow::Cow;
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tracing::{Instrument, debug, error, info, warn};
+
+/// Replies each worker keeps in flight before dropping new ones
+const SEND_QUEUE_DEPTH: usize = 1024;
+
+/// Buffer group of the receive buffer ring
+const BUFFER_GROUP: u16 = 0;
+
+/// Size of the header the kernel writes ahead of each received datagram
+/// (`struct io_uring_recvmsg_out`)
+const RECVMSG_HEADER: usize = 16;
+
+const RECV: u64 = 1;
+const SEND: u64 = 2;
+const TICK_TIMER: u64 = 3;
+
+fn token(kind: u64, index: usize) -> u64 {
+    (kind << 32) | index as u64
+}
+
+/// UDP echo server running on io_uring
+///
+/// Serves the same [`DatagramConfig`] and [`DatagramHandler`]s as
+/// [`UdpEchoServer`](crate::udp::UdpEchoServer). With `config.workers`
+/// above 1, each `SO_REUSEPORT` socket gets its own ring and thread. Replies
+/// are sent as receives complete, so `config.batch` is not needed and is
+/// ignored. Truncated datagrams are handled as in the default backend. Fault
+/// injection is not supported, and
+/// [`run`](EchoServerTrait::run) fails with [`EchoError::Unsupported`] if it
+/// is configured or the kernel lacks io_uring support.
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::common::EchoServerTrait;
+/// use echosrv::udp::UdpConfig;
+/// use echosrv::uring::UringUdpEchoServer;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let config = UdpConfig {
+///         bind_addr: "127.0.0.1:8080".parse()?,
+///         ..Default::default()
+///     };
+///     UringUdpEchoServer::new(config.into()).run().await?;
+///     Ok(())
+/// }
+/// ```
+pub struct UringUdpEchoServer {
+    config: DatagramConfig,
+    shutdown_signal: Arc<broadcast::Sender<()>>,
+    truncated: Arc<AtomicU64>,
+    handler: Arc<dyn DatagramHandler>,
+}
+
+impl UringUdpEchoServer {
+    /// Creates a new io_uring UDP echo server with the given configuration
+    pub fn new(config: DatagramConfig) -> Self {
+        let (shutdown_signal, _) = broadcast::channel(1);
+        let handler: Arc<dyn DatagramHandler> = match config.transform {
+            Some(transform) => Arc::new(transform),
+            None => Arc::new(Echo),
+        };
+        Self {
+            config,
+            shutdown_signal: Arc::new(shutdown_signal),
+            truncated: Arc::new(AtomicU64::new(0)),
+            handler,
+        }
+    }
+
+    /// Replaces the echo with `handler` as the source of every reply
+    pub fn with_handler(mut self, handler: impl DatagramHandler + 'static) -> Self {
+        self.handler = Arc::new(handler);
+        self
+    }
+
+    /// Returns how many received datagrams were larger than the buffer
+    pub fn truncated_datagrams(&self) -> u64 {
+        self.truncated.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl EchoServerTrait for UringUdpEchoServer {
+    /// Starts a completion loop per worker socket and echoes until shutdown
+    async fn run(&self) -> Result<()> {
+        if self.config.faults.is_some() {
+            return Err(EchoError::Unsupported(
+                "the io_uring backend does not support fault injection".to_string(),
+            ));
+        }
+        if !super::is_supported() {
+            return Err(EchoError::Unsupported(
+                "io_uring is not available on this kernel".to_string(),
+            ));
+        }
+
+        let workers = match self.config.workers {
+            0 => tokio::runtime::Handle::current().metrics().num_workers(),
+            workers => workers,
+        };
+        // Coalesced receives would reach the handler as one datagram
+        let config = DatagramConfig {
+            batch: None,
+            ..self.config.clone()
+        };
+        let sockets = UdpProtocol::bind_all(&config, workers).await?;
+        for address in config.addresses() {
+            info!(%address, workers, backend = "io-uring", "Datagram echo server listening");
+        }
+
+        let mut tasks = JoinSet::new();
+        for (id, (address, socket)) in sockets.into_iter().enumerate() {
+            let socket = socket.into_std().map_err(EchoError::Udp)?;
+            let config = DatagramConfig {
+                bind_addr: address,
+                ..config.clone()
+            };
+            let handler = Arc::clone(&self.handler);
+            let truncated = Arc::clone(&self.truncated);
+            let shutdown = self.shutdown_signal.as_ref().clone();
+            let serve = move |stop| {
+                Reflector::new(socket, config, handler, truncated, stop)
+                    .and_then(Reflector::run)
+                    .map_err(EchoError::Udp)
+            };
+            tasks.spawn(
+                async move { event_loop::run_loop("echosrv-uring-udp", &shutdown, serve).await }
+                    .instrument(tracing::info_span!("worker", id)),
+            );
+        }
+
+        let mut result = Ok(());
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    error!(error = %e, "Datagram worker failed");
+                    result = Err(e);
+                }
+                Err(e) => error!(error = %e, "Datagram worker failed"),
+            }
+        }
+
+        let truncated = self.truncated_datagrams();
+        if truncated > 0 {
+            warn!(
+                count = truncated,
+                buffer_size = self.config.buffer_size,
+                "Datagrams were truncated"
+            );
+        }
+        info!("Datagram echo server stopped");
+        result
+    }
+
+    /// Returns a shutdown signal sender that can be used to gracefully shutdown the server
+    fn shutdown_signal(&self) -> broadcast::Sender<()> {
+        self.shutdown_signal.as_ref().clone()
+    }
+}
+
+/// A reply waiting for its send to complete
+///
+/// Boxed, so the message header can point at the other fields.
+struct Reply {
+    header: libc::msghdr,
+    iovec: libc::iovec,
+    name: SockAddr,
+    control: Control,
+    /// Receive buffer holding a plain echo, handed back once it is sent
+    buffer: Option<u16>,
+    data: Vec<u8>,
+    addr: SocketAddr,
+}
+
+/// Completion loop of one worker socket
+struct Reflector {
+    // Dropped first, so the kernel stops using the buffers before they go
+    ring: IoUring,
+    buffers: BufRing,
+    socket: UdpSocket,
+    config: DatagramConfig,
+    handler: Arc<dyn DatagramHandler>,
+    truncated: Arc<AtomicU64>,
+    /// Header of the multishot receive; only the name and control lengths
+    /// are read by the kernel
+    header: Box<libc::msghdr>,
+    replies: Vec<Option<Box<Reply>>>,
+    free: Vec<usize>,
+    in_flight: usize,
+    starved: bool,
+    tick: Box<types::Timespec>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Reflector {
+    fn new(
+        socket: UdpSocket,
+        config: DatagramConfig,
+        handler: Arc<dyn DatagramHandler>,
+        truncated: Arc<AtomicU64>,
+        stop: Arc<AtomicBool>,
+    ) -> io::Result<Self> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let name_len = std::mem::size_of::<libc::sockaddr_storage>();
+        let buffers = BufRing::new(
+            &ring.submitter(),
+            BUFFER_GROUP,
+            RING_BUFFERS,
+            RECVMSG_HEADER + name_len + CONTROL_LEN + config.buffer_size,
+        )?;
+        // SAFETY: all-zero bytes are a valid msghdr
+        let mut header: Box<libc::msghdr> = Box::new(unsafe { std::mem::zeroed() });
+        header.msg_namelen = name_len as libc::socklen_t;
+        // Room for the local address of each datagram
+        header.msg_controllen = CONTROL_LEN as _;
+        Ok(Self {
+            ring,
+            buffers,
+            socket,
+            config,
+            handler,
+            truncated,
+            header,
+            replies: Vec::new(),
+            free: Vec::new(),
+            in_flight: 0,
+            starved: false,
+            tick: Box::new(TICK.into()),
+            stop,
+        })
+    }
+
+    fn run(mut self) -> io::Result<()> {
+        self.arm_recv()?;
+        event_loop::arm_tick(&mut self.ring, &self.tick, token(TICK_TIMER, 0))?;
+
+        let mut completions = Vec::new();
+        loop {
+            event_loop::wait(&mut self.ring, &mut completions)?;
+            let mut stopped = false;
+            for completion in completions.drain(..) {
+                let index = (completion.user_data & u64::from(u32::MAX)) as usize;
+                let step = match completion.user_data >> 32 {
+                    RECV => self.on_recv(completion),
+                    SEND => self.on_send(index, completion),
+                    TICK_TIMER => {
+                        stopped = self.stop.load(Ordering::Relaxed);
+                        event_loop::arm_tick(&mut self.ring, &self.tick, token(TICK_TIMER, 0))
+                    }
+                    _ => Ok(()),
+                };
+                if let Err(e) = step {
+                    error!(error = %e, "io_uring submission failed");
+                }
+            }
+            if stopped {
+                break;
+            }
+        }
+
+        event_loop::cancel_all(&mut self.ring);
+        Ok(())
+    }
+
+    fn arm_recv(&mut self) -> io::Result<()> {
+        let entry = opcode::RecvMsgMulti::new(
+            types::Fd(self.socket.as_raw_fd()),
+            &*self.header,
+            BUFFER_GROUP,
+        )
+        // Truncated datagrams report their full size
+        .flags(libc::MSG_TRUNC as u32)
+        .build()
+        .user_data(token(RECV, 0));
+        event_loop::push(&mut self.ring, &entry)
+    }
+
+    fn on_recv(&mut self, completion: Completion) -> io::Result<()> {
+        let more = cqueue::more(completion.flags);
+        if completion.result < 0 {
+            match -completion.result {
+                // Re-armed once a reply hands a buffer back
+                libc::ENOBUFS => self.starved = true,
+                e => {
+                    let e = io::Error::from_raw_os_error(e);
+                    error!(error = %e, "Failed to receive datagram");
+                }
+            }
+        } else if let Some(id) = cqueue::buffer_select(completion.flags) {
+            let keep = self.answer(id, completion.result as usize)?;
+            if !keep {
+                self.buffers.recycle(id);
+            }
+        }
+
+        if !more && !self.starved {
+            self.arm_recv()?;
+        }
+        Ok(())
+    }
+
+    /// Answers the datagram in buffer `id`, returning true if the reply is
+    /// sent from that buffer
+    fn answer(&mut self, id: u16, len: usize) -> io::Result<bool> {
+        let buffer = self.buffers.get(id, len);
+        let Ok(message) = types::RecvMsgOut::parse(buffer, &self.header) else {
+            warn!(size = len, "Malformed receive result");
+            return Ok(false);
+        };
+        let Some(addr) = sender(&message) else {
+            warn!("Datagram from a non-IP sender");
+            return Ok(false);
+        };
+
+        let ancillary = pktinfo::ancillary_in(message.control_data());
+        let local = ancillary.local.map_or(self.config.bind_addr, |ip: IpAddr| {
+            SocketAddr::new(ip, self.config.bind_addr.port())
+        });
+        let data = message.payload_data();
+        let preview = String::from_utf8_lossy(data);
+        info!(%addr, %local, size = data.len(), preview = %preview, "Received datagram");
+
+        let truncated = message
+            .is_payload_truncated()
+            .then(|| message.incoming_payload_len() as usize);
+        let policy = self.config.truncation;
+        if let Some(size) = truncated {
+            self.truncated.fetch_add(1, Ordering::Relaxed);
+            warn!(%addr, size, received = data.len(), %policy, "Datagram truncated to fit the buffer");
+        }
+
+        let context =
+            DatagramContext::new(addr, local).with_arrival(ancillary.timestamp, ancillary.ttl);
+        let response = match (truncated, policy) {
+            (Some(_), TruncationPolicy::Drop) => return Ok(false),
+            (Some(size), TruncationPolicy::Error) => Some(TruncationPolicy::marker(size).into()),
+            _ => self.handler.handle(data, &context),
+        };
+        let Some(response) = response else {
+            debug!(%addr, size = data.len(), "Handler sent no reply");
+            return Ok(false);
+        };
+        if self.in_flight >= SEND_QUEUE_DEPTH {
+            warn!(%addr, size = response.len(), "Send queue full, dropping reply");
+            return Ok(false);
+        }
+
+        // A plain echo is sent from the receive buffer rather than a copy
+        let (buffer, data, iovec) = match response {
+            Cow::Borrowed(part) => (
+                Some(id),
+                Vec::new(),
+                libc::iovec {
+                    iov_base: part.as_ptr() as *mut libc::c_void,
+                    iov_len: part.len(),
+                },
+            ),
+            Cow::Owned(part) => (
+                None,
+                part,
+                libc::iovec {
+                    iov_base: std::ptr::null_mut(),
+                    iov_len: 0,
+                },
+            ),
+        };
+        let mut reply = Box::new(Reply {
+            // SAFETY: all-zero bytes are a valid msghdr
+            header: unsafe { std::mem::zeroed() },
+            iovec,
+            name: SockAddr::from(addr),
+            control: Control::new(),
+            buffer,
+            data,
+            addr,
+        });
+        if reply.buffer.is_none() {
+            reply.iovec.iov_base = reply.data.as_mut_ptr().cast();
+            reply.iovec.iov_len = reply.data.len();
+        }
+        reply.header.msg_name = reply.name.as_ptr() as *mut libc::c_void;
+        reply.header.msg_namelen = reply.name.len();
+        reply.header.msg_iov = &mut reply.iovec;
+        reply.header.msg_iovlen = 1;
+        if let Some(from) = ancillary.local {
+            let reply = &mut *reply;
+            pktinfo::set_source(&mut reply.header, &mut reply.control, from);
+        }
+
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.replies.push(None);
+                self.replies.len() - 1
+            }
+        };
+        let entry = opcode::SendMsg::new(types::Fd(self.socket.as_raw_fd()), &reply.header)
+            .build()
+            .user_data(token(SEND, index));
+        self.replies[index] = Some(reply);
+        self.in_flight += 1;
+        event_loop::push(&mut self.ring, &entry)?;
+        Ok(buffer.is_some())
+    }
+
+    fn on_send(&mut self, index: usize, completion: Completion) -> io::Result<()> {
+        let Some(reply) = self.replies.get_mut(index).and_then(Option::take) else {
+            return Ok(());
+        };
+        self.free.push(index);
+        self.in_flight -= 1;
+
+        let size = reply.iovec.iov_len;
+        if completion.result < 0 {
+            let e = io::Error::from_raw_os_error(-completion.result);
+            error!(addr = %reply.addr, error = %e, "Failed to send echo response");
+        } else {
+            info!(addr = %reply.addr, size, "Echoed datagram");
+        }
+
+        if let Some(id) = reply.buffer {
+            self.buffers.recycle(id);
+            if std::mem::take(&mut self.starved) {
+                self.arm_recv()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the address a received datagram came from
+fn sender(message: &types::RecvMsgOut<'_>) -> Option<SocketAddr> {
+    let name = message.name_data();
+    // SAFETY: all-zero bytes are a valid sockaddr_storage
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let len = name.len().min(std::mem::size_of_val(&storage));
+    // SAFETY: at most the storage's size is copied into it
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            name.as_ptr(),
+            (&mut storage as *mut libc::sockaddr_storage).cast::<u8>(),
+            len,
+        );
+    }
+    // SAFETY: the storage holds the name the kernel wrote, of this length
+    let address = unsafe { SockAddr::new(storage, message.incoming_name_len()) };
+    address.as_socket()
+}
@@ -0,0 +1,270 @@
+//! io_uring transport backend for TCP and UDP echo
+//!
+//! The default backend runs every connection and datagram as Tokio I/O.
+//! On Linux the [`Backend::IoUring`] backend instead drives each listener
+//! from a completion loop on its own thread: one multishot accept keeps
+//! accepting connections, multishot receives pick buffers from a ring
+//! registered with the kernel, and a plain echo is sent straight from the
+//! buffer it arrived in.
+//!
+//! [`UringTcpEchoServer`] and [`UringUdpEchoServer`] take the same
+//! [`StreamConfig`](crate::stream::StreamConfig) and
+//! [`DatagramConfig`](crate::datagram::DatagramConfig) as the Tokio servers,
+//! answer through the same handlers, keep the same connection limit and read
+//! timeout, and implement [`EchoServerTrait`](crate::EchoServerTrait). Fault
+//! injection and framing need the Tokio backend.
+//!
+//! Multishot receives need Linux 6.0 or later. [`is_supported`] probes the
+//! running kernel, so callers can fall back to the Tokio backend.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[cfg(target_os = "linux")]
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! use echosrv::common::EchoServerTrait;
+//! use echosrv::tcp::TcpConfig;
+//! use echosrv::uring::{self, UringTcpEchoServer};
+//!
+//! let config = TcpConfig {
+//!     bind_addr: "127.0.0.1:8080".parse()?,
+//!     ..Default::default()
+//! };
+//! if uring::is_supported() {
+//!     UringTcpEchoServer::new(config.into()).run().await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(target_os = "linux")]
+mod buf_ring;
+#[cfg(target_os = "linux")]
+mod datagram;
+#[cfg(target_os = "linux")]
+mod stream;
+
+#[cfg(target_os = "linux")]
+pub use datagram::UringUdpEchoServer;
+#[cfg(target_os = "linux")]
+pub use stream::UringTcpEchoServer;
+
+use crate::EchoError;
+
+/// Transport that runs the echo loops
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Tokio sockets, on every platform
+    #[default]
+    Tokio,
+    /// io_uring completion loops, on Linux 6.0 and later
+    IoUring,
+}
+
+impl Backend {
+    /// Returns the name accepted by [`FromStr`](std::str::FromStr)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Tokio => "tokio",
+            Backend::IoUring => "io-uring",
+        }
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = EchoError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tokio" => Ok(Backend::Tokio),
+            "io-uring" | "io_uring" | "uring" => Ok(Backend::IoUring),
+            _ => Err(EchoError::Config(format!(
+                "Unknown backend '{s}', expected tokio or io-uring"
+            ))),
+        }
+    }
+}
+
+/// Returns true if the running kernel can serve the io_uring backend
+///
+/// The probe sets up a small ring, checks the operations the backend uses
+/// and registers a buffer ring; the answer is computed once per process.
+pub fn is_supported() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        static SUPPORTED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+        *SUPPORTED.get_or_init(|| match probe() {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::debug!(error = %e, "io_uring backend unavailable");
+                false
+            }
+        })
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn probe() -> std::io::Result<()> {
+    use io_uring::{IoUring, Probe, opcode};
+
+    let ring = IoUring::new(8)?;
+    let mut probe = Probe::new();
+    ring.submitter().register_probe(&mut probe)?;
+    let needed = [
+        opcode::AcceptMulti::CODE,
+        opcode::RecvMulti::CODE,
+        opcode::RecvMsgMulti::CODE,
+        opcode::Send::CODE,
+        opcode::SendMsg::CODE,
+        opcode::Timeout::CODE,
+    ];
+    if let Some(code) = needed.into_iter().find(|&code| !probe.is_supported(code)) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("io_uring operation {code} unsupported"),
+        ));
+    }
+    let buffers = buf_ring::BufRing::new(&ring.submitter(), 0, 1, 64)?;
+    // The buffers must outlive the ring they are registered with
+    drop(ring);
+    drop(buffers);
+    Ok(())
+}
+
+/// Pieces shared by the TCP and UDP completion loops
+#[cfg(target_os = "linux")]
+mod event_loop {
+    use crate::{EchoError, Result};
+    use io_uring::{IoUring, squeue, types};
+    use std::io;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use tokio::signal;
+    use tokio::sync::{broadcast, oneshot};
+    use tracing::info;
+
+    /// Submission and completion queue entries per ring
+    pub(super) const RING_ENTRIES: u32 = 1024;
+
+    /// Receive buffers registered per ring
+    pub(super) const RING_BUFFERS: u16 = 1024;
+
+    /// How often a loop wakes to check for shutdown and read timeouts
+    pub(super) const TICK: Duration = Duration::from_millis(100);
+
+    /// One completion, copied out of the queue so handling it may submit more
+    #[derive(Debug, Clone, Copy)]
+    pub(super) struct Completion {
+        pub(super) user_data: u64,
+        pub(super) result: i32,
+        pub(super) flags: u32,
+    }
+
+    /// Queues `entry`, submitting what is queued first if the queue is full
+    pub(super) fn push(ring: &mut IoUring, entry: &squeue::Entry) -> io::Result<()> {
+        loop {
+            // SAFETY: every entry built by the loops points to memory they
+            // keep alive until its completion arrives
+            if unsafe { ring.submission().push(entry) }.is_ok() {
+                return Ok(());
+            }
+            ring.submit()?;
+        }
+    }
+
+    /// Submits queued entries and waits for at least one completion
+    pub(super) fn wait(ring: &mut IoUring, completions: &mut Vec<Completion>) -> io::Result<()> {
+        match ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+        completions.extend(ring.completion().map(|cqe| Completion {
+            user_data: cqe.user_data(),
+            result: cqe.result(),
+            flags: cqe.flags(),
+        }));
+        Ok(())
+    }
+
+    /// Arms the timer that wakes the loop every [`TICK`]
+    pub(super) fn arm_tick(
+        ring: &mut IoUring,
+        timespec: &types::Timespec,
+        user_data: u64,
+    ) -> io::Result<()> {
+        let entry = io_uring::opcode::Timeout::new(timespec)
+            .build()
+            .user_data(user_data);
+        push(ring, &entry)
+    }
+
+    /// Cancels every operation still in flight, so none outlives the memory
+    /// it points to
+    pub(super) fn cancel_all(ring: &mut IoUring) {
+        let _ = ring.submit();
+        let _ = ring
+            .submitter()
+            .register_sync_cancel(None, types::CancelBuilder::any());
+        ring.completion().for_each(drop);
+    }
+
+    /// Runs `serve` on its own thread until it fails or the server is asked
+    /// to stop, through `shutdown` or Ctrl+C
+    pub(super) async fn run_loop<F>(
+        name: &str,
+        shutdown: &broadcast::Sender<()>,
+        serve: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(Arc<AtomicBool>) -> Result<()> + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (done_tx, mut done) = oneshot::channel();
+        let mut shutdown_rx = shutdown.subscribe();
+        let loop_stop = Arc::clone(&stop);
+        std::thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let _ = done_tx.send(serve(loop_stop));
+            })
+            .map_err(|e| EchoError::Config(format!("Failed to start {name} thread: {e}")))?;
+
+        tokio::select! {
+            result = &mut done => return flatten(result),
+            _ = signal::ctrl_c() => {
+                info!("Received shutdown signal, stopping server");
+            }
+            _ = shutdown_rx.recv() => {
+                info!("Received internal shutdown signal, stopping server");
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+        flatten(done.await)
+    }
+
+    fn flatten(result: std::result::Result<Result<()>, oneshot::error::RecvError>) -> Result<()> {
+        result.unwrap_or_else(|_| Err(EchoError::Config("io_uring loop panicked".to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_names() {
+        for backend in [Backend::Tokio, Backend::IoUring] {
+            assert_eq!(backend.name().parse::<Backend>().unwrap(), backend);
+        }
+        assert_eq!("io_uring".parse::<Backend>().unwrap(), Backend::IoUring);
+        assert_eq!("TOKIO".parse::<Backend>().unwrap(), Backend::Tokio);
+        assert!("epoll".parse::<Backend>().is_err());
+        assert_eq!(Backend::default(), Backend::Tokio);
+    }
+}
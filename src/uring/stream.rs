@@ -0,0 +1,627 @@
+use super::buf_ring::BufRing;
+use super::event_loop::{self, Completion, RING_BUFFERS, RING_ENTRIES, TICK};
+use crate::common::EchoServerTrait;
+use crate::handler::{Echo, EchoHandler, Opening, StreamContext};
+use crate::stream::{StreamConfig, StreamProtocol};
+use crate::tcp::TcpProtocol;
+use crate::{EchoError, Result};
+use async_trait::async_trait;
+use io_uring::{IoUring, cqueue, opcode, types};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::ops::Range;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tracing::{Instrument, debug, error, info, warn};
+
+/// Responses queued per connection before it stops receiving
+///
+/// Matches the Tokio backend: a client that does not read its echoes is
+/// slowed down rather than buffered without limit.
+const QUEUE_DEPTH: usize = 16;
+
+/// Buffer group of the receive buffer ring
+const BUFFER_GROUP: u16 = 0;
+
+const ACCEPT: u64 = 1;
+const TICK_TIMER: u64 = 2;
+const RECV: u64 = 3;
+const SEND: u64 = 4;
+const CANCEL: u64 = 5;
+
+fn token(kind: u64, index: usize) -> u64 {
+    (kind << 32) | index as u64
+}
+
+/// TCP echo server running on io_uring
+///
+/// Serves the same [`StreamConfig`] and [`EchoHandler`]s as
+/// [`TcpEchoServer`](crate::tcp::TcpEchoServer), with the connection limit
+/// and read and write timeouts applied by a completion loop per listener.
+/// With `config.workers` above 1, each loop runs on its own `SO_REUSEPORT`
+/// listener and thread, sharing the connection limit. Framing and fault
+/// injection are not supported, and [`run`](EchoServerTrait::run)
+/// fails with [`EchoError::Unsupported`] if they are configured or the
+/// kernel lacks io_uring support.
+///
+/// # Examples
+///
+/// ```no_run
+/// use echosrv::common::EchoServerTrait;
+/// use echosrv::tcp::TcpConfig;
+/// use echosrv::uring::UringTcpEchoServer;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let config = TcpConfig {
+///         bind_addr: "127.0.0.1:8080".parse()?,
+///         ..Default::default()
+///     };
+///     UringTcpEchoServer::new(config.into()).run().await?;
+///     Ok(())
+/// }
+/// ```
+pub struct UringTcpEchoServer {
+    config: StreamConfig,
+    shutdown_signal: Arc<broadcast::Sender<()>>,
+    handler: Arc<dyn EchoHandler>,
+}
+
+impl UringTcpEchoServer {
+    /// Creates a new io_uring TCP echo server with the given configuration
+    pub fn new(config: StreamConfig) -> Self {
+        let (shutdown_signal, _) = broadcast::channel(1);
+        let handler: Arc<dyn EchoHandler> = match config.transform {
+            Some(transform) => Arc::new(transform),
+            None => Arc::new(Echo),
+        };
+        Self {
+            config,
+            shutdown_signal: Arc::new(shutdown_signal),
+            handler,
+        }
+    }
+
+    /// Replaces the echo with `handler` as the source of every response
+    pub fn with_handler(mut self, handler: impl EchoHandler + 'static) -> Self {
+        self.handler = Arc::new(handler);
+        self
+    }
+}
+
+#[async_trait]
+impl EchoServerTrait for UringTcpEchoServer {
+    /// Starts the completion loop and accepts connections until shutdown
+    async fn run(&self) -> Result<()> {
+        if self.config.faults.is_some() || self.config.framing.is_some() {
+            return Err(EchoError::Unsupported(
+                "the io_uring backend supports neither fault injection nor framing".to_string(),
+            ));
+        }
+        if !super::is_supported() {
+            return Err(EchoError::Unsupported(
+                "io_uring is not available on this kernel".to_string(),
+            ));
+        }
+
+        let workers = match self.config.workers {
+            0 => tokio::runtime::Handle::current().metrics().num_workers(),
+            workers => workers,
+        };
+        let listeners = TcpProtocol::bind_all(&self.config, workers).await?;
+        for address in self.config.addresses() {
+            info!(%address, workers, backend = "io-uring", "Stream echo server listening");
+        }
+
+        let connection_count = Arc::new(AtomicUsize::new(0));
+        let mut tasks = JoinSet::new();
+        for (id, (address, listener)) in listeners.into_iter().enumerate() {
+            let listener = listener.into_std().map_err(EchoError::Tcp)?;
+            let config = StreamConfig {
+                bind_addr: address,
+                ..self.config.clone()
+            };
+            let handler = Arc::clone(&self.handler);
+            let connection_count = Arc::clone(&connection_count);
+            let shutdown = self.shutdown_signal.as_ref().clone();
+            let serve = move |stop| {
+                Acceptor::new(listener, config, handler, connection_count, stop)
+                    .and_then(Acceptor::run)
+                    .map_err(EchoError::Tcp)
+            };
+            tasks.spawn(
+                async move { event_loop::run_loop("echosrv-uring-tcp", &shutdown, serve).await }
+                    .instrument(tracing::info_span!("acceptor", id)),
+            );
+        }
+
+        let mut result = Ok(());
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    error!(error = %e, "Accept loop failed");
+                    result = Err(e);
+                }
+                Err(e) => error!(error = %e, "Accept loop failed"),
+            }
+        }
+
+        info!("Stream echo server stopped");
+        result
+    }
+
+    /// Returns a shutdown signal sender that can be used to gracefully shutdown the server
+    fn shutdown_signal(&self) -> broadcast::Sender<()> {
+        self.shutdown_signal.as_ref().clone()
+    }
+}
+
+/// A response waiting to be sent
+enum Outgoing {
+    /// Part of a receive buffer, sent without copying
+    Buffer(u16, Range<usize>),
+    /// Data the handler produced
+    Owned(Vec<u8>),
+}
+
+/// Returns where `part` lies within `data`, or `None` if it lies elsewhere
+fn range_within(data: &[u8], part: &[u8]) -> Option<Range<usize>> {
+    let start = (part.as_ptr() as usize).checked_sub(data.as_ptr() as usize)?;
+    let end = start + part.len();
+    (end <= data.len()).then_some(start..end)
+}
+
+impl Outgoing {
+    fn bytes<'a>(&'a self, buffers: &'a BufRing) -> &'a [u8] {
+        match self {
+            Outgoing::Buffer(id, range) => &buffers.get(*id, range.end)[range.clone()],
+            Outgoing::Owned(data) => data,
+        }
+    }
+}
+
+struct Connection {
+    fd: OwnedFd,
+    addr: SocketAddr,
+    context: StreamContext,
+    /// Responses in order; the front one is partly sent after `sent` bytes
+    queue: VecDeque<Outgoing>,
+    sent: usize,
+    /// Chunks still to write for a generating service
+    generator: Option<Box<dyn Iterator<Item = Vec<u8>> + Send>>,
+    /// A multishot receive is armed
+    receiving: bool,
+    /// A send is in flight, since the given time
+    sending: Option<Instant>,
+    /// The receive was cancelled because the queue is full
+    paused: bool,
+    /// The receive stopped for want of buffers
+    starved: bool,
+    /// Close once the queue has drained
+    closing: bool,
+    /// The socket has been shut down to stop its receive
+    shut: bool,
+    last_active: Instant,
+}
+
+/// Completion loop of one listener and its connections
+struct Acceptor {
+    // Dropped first, so the kernel stops using the buffers before they go
+    ring: IoUring,
+    buffers: BufRing,
+    listener: TcpListener,
+    config: StreamConfig,
+    handler: Arc<dyn EchoHandler>,
+    connections: Vec<Option<Connection>>,
+    free: Vec<usize>,
+    /// Connections open on this loop
+    open: usize,
+    /// Connections open on every loop of the server
+    connection_count: Arc<AtomicUsize>,
+    starved: usize,
+    recycled: bool,
+    tick: Box<types::Timespec>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Acceptor {
+    fn new(
+        listener: TcpListener,
+        config: StreamConfig,
+        handler: Arc<dyn EchoHandler>,
+        connection_count: Arc<AtomicUsize>,
+        stop: Arc<AtomicBool>,
+    ) -> io::Result<Self> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let buffers = BufRing::new(
+            &ring.submitter(),
+            BUFFER_GROUP,
+            RING_BUFFERS,
+            config.buffer_size,
+        )?;
+        Ok(Self {
+            ring,
+            buffers,
+            listener,
+            config,
+            handler,
+            connections: Vec::new(),
+            free: Vec::new(),
+            open: 0,
+            connection_count,
+            starved: 0,
+            recycled: false,
+            tick: Box::new(TICK.into()),
+            stop,
+        })
+    }
+
+    fn run(mut self) -> io::Result<()> {
+        self.arm_accept()?;
+        event_loop::arm_tick(&mut self.ring, &self.tick, token(TICK_TIMER, 0))?;
+
+        let mut completions = Vec::new();
+        let result = loop {
+            if let Err(e) = event_loop::wait(&mut self.ring, &mut completions) {
+                break Err(e);
+            }
+            let mut stopped = false;
+            for completion in completions.drain(..) {
+                let index = (completion.user_data & u64::from(u32::MAX)) as usize;
+                let step = match completion.user_data >> 32 {
+                    ACCEPT => self.on_accept(completion),
+                    TICK_TIMER => {
+                        stopped = self.stop.load(Ordering::Relaxed);
+                        self.on_tick()
+                    }
+                    RECV => self.on_recv(index, completion),
+                    SEND => self.on_send(index, completion),
+                    _ => Ok(()),
+                };
+                if let Err(e) = step {
+                    error!(error = %e, "io_uring submission failed");
+                }
+            }
+            if stopped {
+                break Ok(());
+            }
+            if let Err(e) = self.wake_starved() {
+                break Err(e);
+            }
+        };
+
+        event_loop::cancel_all(&mut self.ring);
+        if self.open > 0 {
+            info!(connections = self.open, "Closing open connections");
+        }
+        result
+    }
+
+    fn arm_accept(&mut self) -> io::Result<()> {
+        let entry = opcode::AcceptMulti::new(types::Fd(self.listener.as_raw_fd()))
+            .flags(libc::SOCK_CLOEXEC)
+            .build()
+            .user_data(token(ACCEPT, 0));
+        event_loop::push(&mut self.ring, &entry)
+    }
+
+    fn on_accept(&mut self, completion: Completion) -> io::Result<()> {
+        if !cqueue::more(completion.flags) {
+            self.arm_accept()?;
+        }
+        if completion.result < 0 {
+            let e = io::Error::from_raw_os_error(-completion.result);
+            error!(error = %e, "Failed to accept connection");
+            return Ok(());
+        }
+
+        // SAFETY: the kernel returned a new descriptor that nothing else owns
+        let fd = unsafe { OwnedFd::from_raw_fd(completion.result) };
+        let addr = match socket2::SockRef::from(&fd).peer_addr() {
+            Ok(addr) => addr.as_socket(),
+            Err(e) => {
+                debug!(error = %e, "Connection closed before it was served");
+                return Ok(());
+            }
+        };
+        let Some(addr) = addr else {
+            return Ok(());
+        };
+
+        let limit = self.config.max_connections;
+        let admitted =
+            self.connection_count
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                    (count < limit).then_some(count + 1)
+                });
+        let current = match admitted {
+            Ok(count) => count + 1,
+            Err(current) => {
+                warn!(%addr, current, limit, "Connection rejected: limit reached");
+                return Ok(());
+            }
+        };
+        self.open += 1;
+        info!(%addr, current, "Accepted connection");
+        let socket = socket2::SockRef::from(&fd);
+        if let Err(e) = self.config.socket_options.apply_to_stream(&socket) {
+            warn!(%addr, error = %e, "Failed to set socket options");
+        }
+
+        let context = StreamContext::new(addr, self.config.bind_addr);
+        let mut connection = Connection {
+            fd,
+            addr,
+            context,
+            queue: VecDeque::new(),
+            sent: 0,
+            generator: None,
+            receiving: false,
+            sending: None,
+            paused: false,
+            starved: false,
+            closing: false,
+            shut: false,
+            last_active: Instant::now(),
+        };
+        match self.handler.open(&context) {
+            Opening::Wait => {}
+            Opening::Reply(data) => {
+                connection.queue.push_back(Outgoing::Owned(data));
+                connection.closing = true;
+            }
+            Opening::Generate(chunks) => connection.generator = Some(chunks),
+        }
+
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.connections[index] = Some(connection);
+                index
+            }
+            None => {
+                self.connections.push(Some(connection));
+                self.connections.len() - 1
+            }
+        };
+        self.settle(index)
+    }
+
+    fn on_recv(&mut self, index: usize, completion: Completion) -> io::Result<()> {
+        let Some(connection) = self.connections.get_mut(index).and_then(Option::as_mut) else {
+            return Ok(());
+        };
+        if !cqueue::more(completion.flags) {
+            connection.receiving = false;
+        }
+        let addr = connection.addr;
+
+        match completion.result {
+            n if n > 0 => {
+                let n = n as usize;
+                let id = cqueue::buffer_select(completion.flags)
+                    .expect("multishot receive completes with a buffer");
+                connection.last_active = Instant::now();
+                // Whatever a client sends to a generating service is discarded
+                if connection.closing || connection.generator.is_some() {
+                    self.buffers.recycle(id);
+                    self.recycled = true;
+                    return self.settle(index);
+                }
+
+                let data = self.buffers.get(id, n);
+                debug!(%addr, size = n, preview = %String::from_utf8_lossy(data), "Received data");
+
+                let response = self.handler.handle(data, &connection.context);
+                connection.context.advance(n);
+                // Only a reply inside the receive buffer can be sent from it;
+                // anything else the handler borrowed is copied
+                let response = match response {
+                    Some(Cow::Borrowed(part)) => match range_within(data, part) {
+                        Some(range) => Some(Outgoing::Buffer(id, range)),
+                        None => Some(Outgoing::Owned(part.to_vec())),
+                    },
+                    Some(Cow::Owned(part)) => Some(Outgoing::Owned(part)),
+                    None => {
+                        debug!(%addr, size = n, "Handler sent no response");
+                        None
+                    }
+                };
+                if !matches!(response, Some(Outgoing::Buffer(..))) {
+                    self.buffers.recycle(id);
+                    self.recycled = true;
+                }
+                connection.queue.extend(response);
+            }
+            0 => {
+                if !connection.shut {
+                    info!(%addr, "Client closed connection");
+                }
+                connection.closing = true;
+            }
+            e if -e == libc::ENOBUFS => {
+                // Re-armed once another connection hands a buffer back
+                connection.starved = true;
+                self.starved += 1;
+            }
+            e if -e == libc::ECANCELED => {}
+            e => {
+                if !connection.shut {
+                    let e = io::Error::from_raw_os_error(-e);
+                    error!(%addr, error = %e, "Error handling connection");
+                }
+                connection.closing = true;
+            }
+        }
+        self.settle(index)
+    }
+
+    fn on_send(&mut self, index: usize, completion: Completion) -> io::Result<()> {
+        let Some(connection) = self.connections.get_mut(index).and_then(Option::as_mut) else {
+            return Ok(());
+        };
+        connection.sending = None;
+        let addr = connection.addr;
+
+        if completion.result <= 0 {
+            if !connection.shut {
+                let e = match completion.result {
+                    0 => io::ErrorKind::WriteZero.into(),
+                    e => io::Error::from_raw_os_error(-e),
+                };
+                error!(%addr, error = %e, "Error handling connection");
+            }
+            for outgoing in connection.queue.drain(..) {
+                if let Outgoing::Buffer(id, _) = outgoing {
+                    self.buffers.recycle(id);
+                    self.recycled = true;
+                }
+            }
+            connection.generator = None;
+            connection.closing = true;
+            return self.settle(index);
+        }
+
+        connection.sent += completion.result as usize;
+        let front = connection
+            .queue
+            .front()
+            .expect("a send completes for a queued response");
+        let len = front.bytes(&self.buffers).len();
+        if connection.sent >= len {
+            info!(%addr, size = len, "Echoed data");
+            connection.sent = 0;
+            if let Some(Outgoing::Buffer(id, _)) = connection.queue.pop_front() {
+                self.buffers.recycle(id);
+                self.recycled = true;
+            }
+        }
+        self.settle(index)
+    }
+
+    /// Closes connections idle past the read timeout, and shuts down those
+    /// whose send has waited past the write timeout
+    fn on_tick(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        for index in 0..self.connections.len() {
+            let Some(connection) = self.connections[index].as_mut() else {
+                continue;
+            };
+            let stalled = connection
+                .sending
+                .is_some_and(|since| now - since > self.config.write_timeout);
+            if stalled && !connection.shut {
+                warn!(addr = %connection.addr, "Write timeout");
+                connection.closing = true;
+                shutdown(connection);
+            } else if !connection.closing
+                && connection.generator.is_none()
+                && now - connection.last_active > self.config.read_timeout
+            {
+                warn!(addr = %connection.addr, "Read timeout");
+                connection.closing = true;
+            }
+            self.settle(index)?;
+        }
+        event_loop::arm_tick(&mut self.ring, &self.tick, token(TICK_TIMER, 0))
+    }
+
+    /// Re-arms receives that ran out of buffers once some have come back
+    fn wake_starved(&mut self) -> io::Result<()> {
+        if !std::mem::take(&mut self.recycled) || self.starved == 0 {
+            return Ok(());
+        }
+        self.starved = 0;
+        for index in 0..self.connections.len() {
+            if let Some(connection) = self.connections[index].as_mut()
+                && std::mem::take(&mut connection.starved)
+            {
+                self.settle(index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Submits whatever a connection needs next, and closes it once done
+    fn settle(&mut self, index: usize) -> io::Result<()> {
+        let Some(connection) = self.connections[index].as_mut() else {
+            return Ok(());
+        };
+        let fd = types::Fd(connection.fd.as_raw_fd());
+
+        if connection.queue.is_empty()
+            && !connection.closing
+            && let Some(generator) = connection.generator.as_mut()
+        {
+            match generator.next() {
+                Some(chunk) => connection.queue.push_back(Outgoing::Owned(chunk)),
+                None => connection.closing = true,
+            }
+        }
+
+        if connection.sending.is_none()
+            && let Some(front) = connection.queue.front()
+        {
+            let data = &front.bytes(&self.buffers)[connection.sent..];
+            let entry = opcode::Send::new(fd, data.as_ptr(), data.len() as u32)
+                .flags(libc::MSG_NOSIGNAL)
+                .build()
+                .user_data(token(SEND, index));
+            connection.sending = Some(Instant::now());
+            event_loop::push(&mut self.ring, &entry)?;
+        }
+
+        let full = connection.queue.len() >= QUEUE_DEPTH;
+        if connection.receiving && full && !connection.paused {
+            connection.paused = true;
+            let entry = opcode::AsyncCancel::new(token(RECV, index))
+                .build()
+                .user_data(token(CANCEL, index));
+            event_loop::push(&mut self.ring, &entry)?;
+        } else if !connection.receiving && !full && !connection.closing && !connection.starved {
+            // A reply-only service never reads from its client
+            connection.paused = false;
+            let entry = opcode::RecvMulti::new(fd, BUFFER_GROUP)
+                .build()
+                .user_data(token(RECV, index));
+            connection.receiving = true;
+            event_loop::push(&mut self.ring, &entry)?;
+        }
+
+        if connection.closing && connection.sending.is_none() && connection.queue.is_empty() {
+            if connection.receiving {
+                // The receive ends once the socket is shut down
+                shutdown(connection);
+            } else {
+                self.close(index);
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self, index: usize) {
+        let Some(connection) = self.connections[index].take() else {
+            return;
+        };
+        self.free.push(index);
+        self.open -= 1;
+        if connection.starved {
+            self.starved -= 1;
+        }
+        let current = self.connection_count.fetch_sub(1, Ordering::SeqCst) - 1;
+        info!(addr = %connection.addr, current, "Connection closed");
+    }
+}
+
+fn shutdown(connection: &mut Connection) {
+    if !std::mem::replace(&mut connection.shut, true) {
+        // SAFETY: the descriptor is open for as long as the connection
+        unsafe { libc::shutdown(connection.fd.as_raw_fd(), libc::SHUT_RDWR) };
+    }
+}
@@ -125,10 +125,16 @@ async fn test_end_to_end_improvements() -> Result<(), Box<dyn std::error::Error>
     // Create server with configuration
     let config = TcpConfig {
         bind_addr: addr,
+        additional_addrs: Vec::new(),
         max_connections: 50,
         buffer_size: 2048,
         read_timeout: Duration::from_secs(30),
         write_timeout: Duration::from_secs(30),
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let server = TcpEchoServer::new(config.clone().into());
@@ -140,7 +146,7 @@ async fn test_end_to_end_improvements() -> Result<(), Box<dyn std::error::Error>
     tokio::time::sleep(Duration::from_millis(100)).await;
 
     // Test with multiple clients using different data sizes
-    let test_cases = vec![
+    let test_cases = [
         b"small".to_vec(),
         vec![b'x'; 1024],                                 // 1KB
         vec![b'y'; 4096],                                 // 4KB
@@ -156,7 +162,6 @@ async fn test_end_to_end_improvements() -> Result<(), Box<dyn std::error::Error>
     // Test concurrent access
     let mut handles = Vec::new();
     for i in 0..10 {
-        let addr = addr;
         let handle = tokio::spawn(async move {
             let mut client = TcpEchoClient::connect(addr).await?;
             let message = format!("Concurrent test message {i}");
@@ -206,13 +211,13 @@ async fn test_error_handling() {
 #[tokio::test]
 #[cfg(unix)]
 async fn test_unix_socket_improvements() {
-    use echosrv::unix::{Protocol, StreamExt};
+    use echosrv::unix::{UnixStreamExt, UnixStreamProtocol};
 
     let temp_dir = tempdir().unwrap();
     let socket_path = temp_dir.path().join("test.sock");
 
     // Test connection using extension trait
-    let connect_result = Protocol::connect_unix(&socket_path).await;
+    let connect_result = UnixStreamProtocol::connect_unix(&socket_path).await;
 
     // Connection should fail since no server is listening
     assert!(connect_result.is_err());
@@ -234,10 +239,16 @@ async fn test_performance_characteristics() -> Result<(), Box<dyn std::error::Er
 
     let config = TcpConfig {
         bind_addr: addr,
+        additional_addrs: Vec::new(),
         max_connections: 100,
         buffer_size: 8192,
         read_timeout: Duration::from_secs(30),
         write_timeout: Duration::from_secs(30),
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let server = TcpEchoServer::new(config.clone().into());
@@ -0,0 +1,940 @@
+use echosrv::datagram::{DatagramConfig, DatagramEchoServer};
+use echosrv::fault::{
+    BandwidthLimit, ChaosAdmin, ChaosController, ChaosPhase, FaultConfig, Faulty, HalfCloseFault,
+    ImpairmentConfig, LatencyConfig, PartialWriteFault, ResetFault, StallDirection, StallFault,
+    StreamFaultConfig,
+};
+use echosrv::proxy::{ProxyAdmin, ProxyConfig, TcpProxyServer, ToxicDirection, Toxics};
+use echosrv::stream::{StreamConfig, StreamEchoServer};
+use echosrv::tcp::TcpProtocol;
+use echosrv::udp::UdpProtocol;
+use echosrv::unix::{
+    UnixDatagramConfig, UnixDatagramEchoServer, UnixStreamConfig, UnixStreamEchoServer,
+};
+use echosrv::{
+    EchoClient, EchoError, EchoServerTrait, Result, TcpEchoClient, TcpEchoServer, UdpEchoClient,
+    UdpEchoServer,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket, UnixDatagram, UnixStream};
+
+async fn free_tcp_addr() -> Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    Ok(listener.local_addr()?)
+}
+
+async fn free_udp_addr() -> Result<SocketAddr> {
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    socket.local_addr().map_err(EchoError::Udp)
+}
+
+#[tokio::test]
+async fn test_tcp_fixed_latency() -> Result<()> {
+    let addr = free_tcp_addr().await?;
+    let config = StreamConfig {
+        bind_addr: addr,
+        faults: Some(FaultConfig {
+            seed: Some(1),
+            latency: Some(LatencyConfig::fixed(Duration::from_millis(150))),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let server: StreamEchoServer<Faulty<TcpProtocol>> = StreamEchoServer::new(config);
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TcpEchoClient::connect(addr).await?;
+    let start = Instant::now();
+    let response = client.echo_string("slow").await?;
+    let elapsed = start.elapsed();
+
+    assert_eq!(response, "slow");
+    assert!(
+        elapsed >= Duration::from_millis(150),
+        "echo took {elapsed:?}"
+    );
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_write_bandwidth_limit() -> Result<()> {
+    let addr = free_tcp_addr().await?;
+    let config = StreamConfig {
+        bind_addr: addr,
+        buffer_size: 8192,
+        faults: Some(FaultConfig {
+            bandwidth: BandwidthLimit {
+                write: Some(16 * 1024),
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let server: StreamEchoServer<Faulty<TcpProtocol>> = StreamEchoServer::new(config);
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // 8 KiB at 16 KiB/s needs at least ~0.5s once the first chunk is sent
+    let payload = vec![0x5a; 8192];
+    let mut client = TcpEchoClient::connect(addr).await?;
+    let start = Instant::now();
+    let response = client.echo(&payload).await?;
+    let elapsed = start.elapsed();
+
+    assert_eq!(response, payload);
+    assert!(
+        elapsed >= Duration::from_millis(400),
+        "echo took {elapsed:?}"
+    );
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_fixed_latency() -> Result<()> {
+    let addr = free_udp_addr().await?;
+    let config = DatagramConfig {
+        bind_addr: addr,
+        faults: Some(FaultConfig {
+            latency: Some(LatencyConfig::fixed(Duration::from_millis(150))),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let server: DatagramEchoServer<Faulty<UdpProtocol>> = DatagramEchoServer::new(config);
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = UdpEchoClient::connect(addr).await?;
+    let start = Instant::now();
+    let response = client.echo_string("slow").await?;
+    let elapsed = start.elapsed();
+
+    assert_eq!(response, "slow");
+    assert!(
+        elapsed >= Duration::from_millis(150),
+        "echo took {elapsed:?}"
+    );
+
+    server_handle.abort();
+    Ok(())
+}
+
+/// Sends `count` datagrams at once and returns when the last echo arrived
+async fn burst_echo_time(socket: &UdpSocket, count: usize) -> Result<Duration> {
+    let start = Instant::now();
+    for i in 0..count {
+        socket
+            .send(format!("burst {i}").as_bytes())
+            .await
+            .map_err(EchoError::Udp)?;
+    }
+    let mut buf = [0u8; 64];
+    for _ in 0..count {
+        tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buf))
+            .await
+            .map_err(|_| EchoError::Timeout("burst echo".to_string()))?
+            .map_err(EchoError::Udp)?;
+    }
+    Ok(start.elapsed())
+}
+
+#[tokio::test]
+async fn test_udp_latency_overlaps_between_datagrams() -> Result<()> {
+    let addr = free_udp_addr().await?;
+    let config = DatagramConfig {
+        bind_addr: addr,
+        faults: Some(FaultConfig {
+            latency: Some(LatencyConfig::fixed(Duration::from_millis(200))),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let server: DatagramEchoServer<Faulty<UdpProtocol>> = DatagramEchoServer::new(config);
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    socket.connect(addr).await.map_err(EchoError::Udp)?;
+
+    // Five datagrams delayed one after another would take a full second
+    let elapsed = burst_echo_time(&socket, 5).await?;
+    assert!(
+        elapsed >= Duration::from_millis(200) && elapsed < Duration::from_millis(500),
+        "burst took {elapsed:?}"
+    );
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unix_datagram_latency_overlaps_between_datagrams() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let socket_path = temp_dir.path().join("latency.sock");
+    let config = UnixDatagramConfig {
+        faults: Some(FaultConfig {
+            latency: Some(LatencyConfig::fixed(Duration::from_millis(200))),
+            ..Default::default()
+        }),
+        ..UnixDatagramConfig::default().with_socket_path(socket_path.clone())
+    };
+
+    let server = UnixDatagramEchoServer::new(config);
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client =
+        UnixDatagram::bind(temp_dir.path().join("client.sock")).map_err(EchoError::Unix)?;
+    client.connect(&socket_path).map_err(EchoError::Unix)?;
+    let start = Instant::now();
+    for i in 0..5 {
+        client
+            .send(format!("burst {i}").as_bytes())
+            .await
+            .map_err(EchoError::Unix)?;
+    }
+    let mut buf = [0u8; 64];
+    for _ in 0..5 {
+        tokio::time::timeout(Duration::from_secs(5), client.recv(&mut buf))
+            .await
+            .map_err(|_| EchoError::Timeout("burst echo".to_string()))?
+            .map_err(EchoError::Unix)?;
+    }
+    let elapsed = start.elapsed();
+    assert!(
+        elapsed >= Duration::from_millis(200) && elapsed < Duration::from_millis(500),
+        "burst took {elapsed:?}"
+    );
+
+    let _ = shutdown.send(());
+    server_handle.await.unwrap()?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_impairment_counters() -> Result<()> {
+    let addr = free_udp_addr().await?;
+    let config = DatagramConfig {
+        bind_addr: addr,
+        faults: Some(FaultConfig {
+            seed: Some(11),
+            impairment: ImpairmentConfig {
+                duplicate: 1.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let server = Arc::new(UdpEchoServer::new(config));
+    let server_handle = tokio::spawn({
+        let server = Arc::clone(&server);
+        async move { server.run().await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    socket
+        .send_to(b"twice", addr)
+        .await
+        .map_err(EchoError::Udp)?;
+
+    let mut buffer = [0u8; 64];
+    for _ in 0..2 {
+        let (n, _) = tokio::time::timeout(Duration::from_secs(1), socket.recv_from(&mut buffer))
+            .await
+            .map_err(|_| EchoError::Timeout("missing duplicate".to_string()))?
+            .map_err(EchoError::Udp)?;
+        assert_eq!(&buffer[..n], b"twice");
+    }
+
+    let stats = server.impairment_stats();
+    assert_eq!(stats.received, 1);
+    assert_eq!(stats.duplicated, 1);
+    assert_eq!(stats.dropped, 0);
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_total_loss() -> Result<()> {
+    let addr = free_udp_addr().await?;
+    let config = DatagramConfig {
+        bind_addr: addr,
+        faults: Some(FaultConfig {
+            impairment: ImpairmentConfig {
+                loss: 1.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let server = Arc::new(UdpEchoServer::new(config));
+    let server_handle = tokio::spawn({
+        let server = Arc::clone(&server);
+        async move { server.run().await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = UdpEchoClient::connect(addr).await?;
+    let result = tokio::time::timeout(Duration::from_millis(300), client.echo_string("lost")).await;
+    assert!(result.is_err(), "datagram should have been dropped");
+    assert_eq!(server.impairment_stats().dropped, 1);
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_read_bandwidth_with_reordering_loses_nothing() -> Result<()> {
+    let addr = free_udp_addr().await?;
+    let config = DatagramConfig {
+        bind_addr: addr,
+        faults: Some(FaultConfig {
+            seed: Some(13),
+            // Each 100-byte datagram costs 50ms, longer than a reorder hold
+            bandwidth: BandwidthLimit {
+                read: Some(2000),
+                ..Default::default()
+            },
+            impairment: ImpairmentConfig {
+                reorder: 1.0,
+                reorder_timeout: Duration::from_millis(10),
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let server: Arc<DatagramEchoServer<Faulty<UdpProtocol>>> =
+        Arc::new(DatagramEchoServer::new(config));
+    let server_handle = tokio::spawn({
+        let server = Arc::clone(&server);
+        async move { server.run().await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    for i in 0..10u8 {
+        socket
+            .send_to(&[i; 100], addr)
+            .await
+            .map_err(EchoError::Udp)?;
+    }
+
+    let mut echoed = Vec::new();
+    let mut buffer = [0u8; 256];
+    while echoed.len() < 10 {
+        let (n, _) = tokio::time::timeout(Duration::from_secs(2), socket.recv_from(&mut buffer))
+            .await
+            .map_err(|_| EchoError::Timeout(format!("only {} echoes", echoed.len())))?
+            .map_err(EchoError::Udp)?;
+        assert_eq!(n, 100);
+        echoed.push(buffer[0]);
+    }
+    echoed.sort_unstable();
+    assert_eq!(echoed, (0..10).collect::<Vec<u8>>());
+    assert_eq!(server.impairment_stats().received, 10);
+
+    server_handle.abort();
+    Ok(())
+}
+
+/// Starts a plain TCP echo server with the given stream faults
+async fn start_faulty_tcp(stream: StreamFaultConfig) -> Result<SocketAddr> {
+    let addr = free_tcp_addr().await?;
+    let config = StreamConfig {
+        bind_addr: addr,
+        faults: Some(FaultConfig {
+            seed: Some(5),
+            stream,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let server = TcpEchoServer::new(config);
+    tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    Ok(addr)
+}
+
+/// Reads until EOF or an error, returning what arrived and how it ended
+async fn read_to_end(stream: &mut TcpStream) -> (Vec<u8>, std::io::Result<()>) {
+    let mut data = Vec::new();
+    let mut buffer = [0u8; 1024];
+    loop {
+        match tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buffer)).await {
+            Ok(Ok(0)) => return (data, Ok(())),
+            Ok(Ok(n)) => data.extend_from_slice(&buffer[..n]),
+            Ok(Err(e)) => return (data, Err(e)),
+            Err(_) => panic!("connection neither closed nor reset"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_tcp_reset_after_bytes() -> Result<()> {
+    let addr = start_faulty_tcp(StreamFaultConfig {
+        reset: Some(ResetFault {
+            after_bytes: 4,
+            probability: 1.0,
+        }),
+        ..Default::default()
+    })
+    .await?;
+
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"abcdefgh").await?;
+    let (echoed, end) = read_to_end(&mut stream).await;
+
+    assert!(b"abcd".starts_with(&echoed), "echoed {echoed:?}");
+    assert_eq!(
+        end.expect_err("connection should be reset").kind(),
+        std::io::ErrorKind::ConnectionReset
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_half_close_after_bytes() -> Result<()> {
+    let addr = start_faulty_tcp(StreamFaultConfig {
+        half_close: Some(HalfCloseFault {
+            after_bytes: 5,
+            probability: 1.0,
+        }),
+        ..Default::default()
+    })
+    .await?;
+
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"hello world").await?;
+    let (echoed, end) = read_to_end(&mut stream).await;
+    assert_eq!(echoed, b"hello");
+    assert!(end.is_ok());
+
+    // The server still reads after closing its write half
+    stream.write_all(b"still there").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unix_stream_half_close_after_bytes() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let socket_path = temp_dir.path().join("half_close.sock");
+    let config = UnixStreamConfig::default()
+        .with_socket_path(socket_path.clone())
+        .with_faults(FaultConfig {
+            seed: Some(5),
+            stream: StreamFaultConfig {
+                half_close: Some(HalfCloseFault {
+                    after_bytes: 5,
+                    probability: 1.0,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+    let server = UnixStreamEchoServer::new(config);
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut stream = UnixStream::connect(&socket_path)
+        .await
+        .map_err(EchoError::Unix)?;
+    stream
+        .write_all(b"hello world")
+        .await
+        .map_err(EchoError::Unix)?;
+    let mut echoed = Vec::new();
+    tokio::time::timeout(Duration::from_secs(2), stream.read_to_end(&mut echoed))
+        .await
+        .map_err(|_| EchoError::Timeout("half-close".to_string()))?
+        .map_err(EchoError::Unix)?;
+    assert_eq!(echoed, b"hello");
+
+    let _ = shutdown.send(());
+    server_handle.await.unwrap()?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_partial_writes() -> Result<()> {
+    let addr = start_faulty_tcp(StreamFaultConfig {
+        partial_writes: Some(PartialWriteFault {
+            max_chunk: 4,
+            delay: Duration::from_millis(5),
+            probability: 1.0,
+        }),
+        ..Default::default()
+    })
+    .await?;
+
+    let payload = b"split into many small writes";
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(payload).await?;
+
+    let mut echoed = Vec::new();
+    let mut reads = 0;
+    let mut buffer = [0u8; 1024];
+    while echoed.len() < payload.len() {
+        let n = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buffer))
+            .await
+            .map_err(|_| EchoError::Timeout("partial echo".to_string()))??;
+        assert!(n > 0, "connection closed early");
+        echoed.extend_from_slice(&buffer[..n]);
+        reads += 1;
+    }
+
+    assert_eq!(echoed, payload);
+    assert!(reads > 1, "echo arrived in a single read");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_accept_limit() -> Result<()> {
+    let addr = start_faulty_tcp(StreamFaultConfig {
+        accept_limit: Some(1),
+        ..Default::default()
+    })
+    .await?;
+
+    let mut first = TcpEchoClient::connect(addr).await?;
+    assert_eq!(first.echo_string("served").await?, "served");
+
+    // The kernel completes the handshake, but the server never accepts
+    let mut second = TcpEchoClient::connect(addr).await?;
+    let result =
+        tokio::time::timeout(Duration::from_millis(300), second.echo_string("queued")).await;
+    assert!(result.is_err(), "second connection should not be served");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_accept_limit_spans_accept_loops() -> Result<()> {
+    let addr = free_tcp_addr().await?;
+    let config = StreamConfig {
+        bind_addr: addr,
+        workers: 4,
+        faults: Some(FaultConfig {
+            stream: StreamFaultConfig {
+                accept_limit: Some(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let server = TcpEchoServer::new(config);
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Whichever loops the kernel hands connections to, at most two are
+    // served; a loop left without a claim leaves its share in the backlog
+    let mut served = 0;
+    let mut clients = Vec::new();
+    for _ in 0..12 {
+        let mut client = TcpEchoClient::connect(addr).await?;
+        let result =
+            tokio::time::timeout(Duration::from_millis(200), client.echo_string("hi")).await;
+        if matches!(result, Ok(Ok(_))) {
+            served += 1;
+        }
+        clients.push(client);
+    }
+    assert!((1..=2).contains(&served), "{served} connections served");
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_stalled_reads_release_connection_slot() -> Result<()> {
+    let addr = free_tcp_addr().await?;
+    let config = StreamConfig {
+        bind_addr: addr,
+        max_connections: 1,
+        read_timeout: Duration::from_millis(200),
+        faults: Some(FaultConfig {
+            seed: Some(5),
+            stream: StreamFaultConfig {
+                stall: Some(StallFault {
+                    direction: StallDirection::Read,
+                    after_bytes: 0,
+                    probability: 1.0,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let server = TcpEchoServer::new(config);
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The stalled connection is closed once it idles out (reset, as its data
+    // was never read), freeing the only slot: the next client is stalled in
+    // turn rather than rejected at once
+    for _ in 0..2 {
+        let mut stream = TcpStream::connect(addr).await?;
+        let start = Instant::now();
+        stream.write_all(b"stalled").await?;
+        let (echoed, _) = read_to_end(&mut stream).await;
+        assert!(echoed.is_empty());
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(150),
+            "closed after {elapsed:?}"
+        );
+    }
+
+    server_handle.abort();
+    Ok(())
+}
+
+/// A two-phase timeline: healthy, then `faults`, each lasting a minute
+fn chaos_timeline(faults: FaultConfig) -> ChaosController {
+    ChaosController::new(vec![
+        ChaosPhase::new("healthy", Duration::from_secs(60), FaultConfig::default()),
+        ChaosPhase::new("chaos", Duration::from_secs(60), faults),
+    ])
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_chaos_phase_resets_open_connections() -> Result<()> {
+    let chaos = chaos_timeline(FaultConfig {
+        stream: StreamFaultConfig {
+            reset: Some(ResetFault {
+                after_bytes: 0,
+                probability: 1.0,
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let addr = free_tcp_addr().await?;
+    let config = StreamConfig {
+        bind_addr: addr,
+        faults: Some(FaultConfig {
+            chaos: Some(chaos.clone()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let server = TcpEchoServer::new(config);
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"healthy").await?;
+    let mut buffer = [0u8; 16];
+    let n = stream.read(&mut buffer).await?;
+    assert_eq!(&buffer[..n], b"healthy");
+
+    // The idle connection is reset as soon as the phase starts
+    assert_eq!(chaos.advance().name, "chaos");
+    let (echoed, end) = read_to_end(&mut stream).await;
+    assert!(echoed.is_empty());
+    assert_eq!(
+        end.expect_err("connection should be reset").kind(),
+        std::io::ErrorKind::ConnectionReset
+    );
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chaos_phase_switches_datagram_loss() -> Result<()> {
+    let chaos = chaos_timeline(FaultConfig {
+        impairment: ImpairmentConfig {
+            loss: 1.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let addr = free_udp_addr().await?;
+    let config = DatagramConfig {
+        bind_addr: addr,
+        faults: Some(FaultConfig {
+            chaos: Some(chaos.clone()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let server = Arc::new(UdpEchoServer::new(config));
+    let server_handle = tokio::spawn({
+        let server = Arc::clone(&server);
+        async move { server.run().await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = UdpEchoClient::connect(addr).await?;
+    assert_eq!(client.echo_string("before").await?, "before");
+
+    chaos.advance();
+    let result = tokio::time::timeout(Duration::from_millis(300), client.echo_string("lost")).await;
+    assert!(result.is_err(), "datagram should have been dropped");
+
+    chaos.advance();
+    assert_eq!(client.echo_string("after").await?, "after");
+    assert_eq!(server.impairment_stats().dropped, 1);
+
+    server_handle.abort();
+    Ok(())
+}
+
+/// Sends one request to the admin endpoint and returns the response
+async fn admin_request(addr: SocketAddr, method: &str, path: &str, body: &str) -> Result<String> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+#[tokio::test]
+async fn test_chaos_admin_endpoint() -> Result<()> {
+    let chaos = chaos_timeline(FaultConfig::default());
+    let admin = ChaosAdmin::bind("127.0.0.1:0".parse().unwrap(), chaos.clone()).await?;
+    let addr = admin.local_addr()?;
+    let admin_handle = tokio::spawn(admin.run());
+
+    let status = admin_request(addr, "GET", "/chaos", "").await?;
+    assert!(status.starts_with("HTTP/1.1 200"), "{status}");
+    assert!(status.contains("phase=healthy"), "{status}");
+
+    let advanced = admin_request(addr, "POST", "/chaos/advance", "").await?;
+    assert!(advanced.contains("phase=chaos"), "{advanced}");
+    assert!(advanced.contains("manual_advances=1"), "{advanced}");
+    assert_eq!(chaos.current().name, "chaos");
+
+    let metrics = admin_request(addr, "GET", "/metrics", "").await?;
+    assert!(
+        metrics.contains("echosrv_chaos_phase{index=\"1\",name=\"chaos\"} 1"),
+        "{metrics}"
+    );
+    assert!(
+        metrics.contains("echosrv_chaos_transitions_total 1"),
+        "{metrics}"
+    );
+
+    assert!(
+        admin_request(addr, "GET", "/chaos/advance", "")
+            .await?
+            .starts_with("HTTP/1.1 405")
+    );
+    assert!(
+        admin_request(addr, "GET", "/nope", "")
+            .await?
+            .starts_with("HTTP/1.1 404")
+    );
+
+    admin_handle.abort();
+    Ok(())
+}
+
+/// Starts a TCP echo server and a proxy in front of it
+async fn start_proxy(toxics: &[&str]) -> Result<(SocketAddr, Arc<TcpProxyServer>)> {
+    let upstream = free_tcp_addr().await?;
+    let server = TcpEchoServer::new(StreamConfig {
+        bind_addr: upstream,
+        ..Default::default()
+    });
+    tokio::spawn(async move { server.run().await });
+
+    let addr = free_tcp_addr().await?;
+    let proxy = Arc::new(TcpProxyServer::new(ProxyConfig {
+        bind_addr: addr,
+        upstream: upstream.into(),
+        seed: Some(9),
+        ..Default::default()
+    }));
+    for toxic in toxics {
+        proxy.toxics().add(toxic.parse()?)?;
+    }
+    let running = proxy.clone();
+    tokio::spawn(async move { running.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    Ok((addr, proxy))
+}
+
+#[tokio::test]
+async fn test_proxy_applies_toxics_at_runtime() -> Result<()> {
+    let (addr, proxy) = start_proxy(&[]).await?;
+    let mut client = TcpEchoClient::connect(addr).await?;
+    assert_eq!(client.echo_string("direct").await?, "direct");
+
+    // Open connections pick up new toxics
+    proxy
+        .toxics()
+        .add("slow=latency?latency=200&stream=upstream".parse()?)?;
+    let start = Instant::now();
+    assert_eq!(client.echo_string("delayed").await?, "delayed");
+    let elapsed = start.elapsed();
+    assert!(
+        elapsed >= Duration::from_millis(200),
+        "echo took {elapsed:?}"
+    );
+
+    assert!(proxy.toxics().remove("slow").is_some());
+    assert_eq!(client.echo_string("again").await?, "again");
+
+    let stats = proxy.stats();
+    assert_eq!(stats.connections, 1);
+    assert_eq!(stats.bytes_to_upstream, 18);
+    assert_eq!(stats.bytes_to_client, 18);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_proxy_directions_are_independent() -> Result<()> {
+    // An upstream that never reads, but keeps talking to the client
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let upstream = listener.local_addr()?;
+    tokio::spawn(async move {
+        let Ok((mut stream, _)) = listener.accept().await else {
+            return;
+        };
+        loop {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            if stream.write_all(b"ping").await.is_err() {
+                return;
+            }
+        }
+    });
+
+    let addr = free_tcp_addr().await?;
+    let proxy = TcpProxyServer::new(ProxyConfig {
+        bind_addr: addr,
+        upstream: upstream.into(),
+        ..Default::default()
+    });
+    tokio::spawn(async move { proxy.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Fill every buffer on the way to the stalled upstream
+    let (mut reader, mut writer) = TcpStream::connect(addr).await?.into_split();
+    tokio::spawn(async move {
+        let chunk = vec![0u8; 64 * 1024];
+        while writer.write_all(&chunk).await.is_ok() {}
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut buf = [0u8; 4];
+    for _ in 0..3 {
+        tokio::time::timeout(Duration::from_secs(2), reader.read_exact(&mut buf))
+            .await
+            .map_err(|_| EchoError::Timeout("upstream data stalled".to_string()))??;
+        assert_eq!(&buf, b"ping");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_proxy_reset_peer_after_bytes() -> Result<()> {
+    let (addr, proxy) = start_proxy(&["cut=reset_peer?bytes=4"]).await?;
+
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"abcdefgh").await?;
+    let (echoed, end) = read_to_end(&mut stream).await;
+
+    assert!(b"abcd".starts_with(&echoed), "echoed {echoed:?}");
+    assert_eq!(
+        end.expect_err("connection should be reset").kind(),
+        std::io::ErrorKind::ConnectionReset
+    );
+    assert_eq!(proxy.stats().resets, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_proxy_unreachable_upstream() -> Result<()> {
+    let addr = free_tcp_addr().await?;
+    let proxy = Arc::new(TcpProxyServer::new(ProxyConfig {
+        bind_addr: addr,
+        upstream: free_tcp_addr().await?.into(),
+        ..Default::default()
+    }));
+    let running = proxy.clone();
+    tokio::spawn(async move { running.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut stream = TcpStream::connect(addr).await?;
+    let (echoed, _) = read_to_end(&mut stream).await;
+    assert!(echoed.is_empty());
+    assert_eq!(proxy.stats().upstream_failures, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_proxy_admin_endpoint() -> Result<()> {
+    let toxics = Toxics::default();
+    let admin = ProxyAdmin::bind("127.0.0.1:0".parse().unwrap(), toxics.clone()).await?;
+    let addr = admin.local_addr()?;
+    let admin_handle = tokio::spawn(admin.run());
+
+    let added = admin_request(addr, "POST", "/toxics", "slow=latency?latency=100\n").await?;
+    assert!(added.starts_with("HTTP/1.1 200"), "{added}");
+    assert_eq!(
+        toxics.get("slow").unwrap().direction,
+        ToxicDirection::Downstream
+    );
+
+    let listed = admin_request(addr, "GET", "/toxics", "").await?;
+    assert!(
+        listed.ends_with("slow=latency?latency=100&jitter=0&stream=downstream&toxicity=1\n"),
+        "{listed}"
+    );
+
+    let duplicate = admin_request(addr, "POST", "/toxics", "slow=bandwidth?rate=1").await?;
+    assert!(duplicate.starts_with("HTTP/1.1 409"), "{duplicate}");
+    let invalid = admin_request(addr, "POST", "/toxics", "slow=fast").await?;
+    assert!(invalid.starts_with("HTTP/1.1 400"), "{invalid}");
+
+    let removed = admin_request(addr, "DELETE", "/toxics/slow", "").await?;
+    assert!(removed.starts_with("HTTP/1.1 200"), "{removed}");
+    assert!(toxics.list().is_empty());
+    assert!(
+        admin_request(addr, "DELETE", "/toxics/slow", "")
+            .await?
+            .starts_with("HTTP/1.1 404")
+    );
+
+    admin_handle.abort();
+    Ok(())
+}
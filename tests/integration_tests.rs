@@ -1,11 +1,25 @@
+use async_trait::async_trait;
+use bytes::BytesMut;
 use echosrv::common::create_controlled_test_server_with_limit;
+use echosrv::datagram::BatchConfig;
+use echosrv::handler::{DatagramContext, Service, StreamContext, Transform};
 use echosrv::http::{HttpConfig, HttpEchoServer};
+use echosrv::stream::{
+    ClientConfigBuilder, FrameCodec, Framing, ReadHalf, StreamConfig, StreamEchoServer,
+    StreamProtocol, WriteHalf,
+};
 use echosrv::{Address, EchoClient, EchoServerTrait, TcpEchoServer, UdpEchoServer};
 use echosrv::{EchoError, Result};
 use echosrv::{TcpConfig, TcpEchoClient};
 use echosrv::{UdpConfig, UdpEchoClient};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
 use std::time::Duration;
-use tokio::net::{TcpListener, UdpSocket};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ReadBuf};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio_util::codec::{Decoder, Encoder};
 use tracing::{error, info};
 
 #[tokio::test]
@@ -19,7 +33,6 @@ async fn test_multiple_concurrent_tcp_clients() -> Result<()> {
     let mut handles = Vec::new();
 
     for i in 0..client_count {
-        let addr = addr;
         let handle = tokio::spawn(async move {
             let mut client = TcpEchoClient::connect(addr).await?;
             let message = format!("Message from TCP client {i}");
@@ -86,7 +99,6 @@ async fn test_multiple_concurrent_udp_clients() -> Result<()> {
     let mut handles = Vec::new();
 
     for i in 0..client_count {
-        let addr = addr;
         let handle = tokio::spawn(async move {
             let mut client = UdpEchoClient::connect(addr).await?;
             let message = format!("Message from UDP client {i}");
@@ -112,10 +124,16 @@ async fn test_multiple_concurrent_udp_clients() -> Result<()> {
 async fn test_tcp_connection_limit() -> Result<()> {
     let config = TcpConfig {
         bind_addr: "127.0.0.1:0".parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 2, // Very low limit for testing
         buffer_size: 1024,
         read_timeout: Duration::from_secs(30),
         write_timeout: Duration::from_secs(30),
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let listener = TcpListener::bind(config.bind_addr)
@@ -126,10 +144,16 @@ async fn test_tcp_connection_limit() -> Result<()> {
 
     let config = TcpConfig {
         bind_addr: addr,
+        additional_addrs: Vec::new(),
         max_connections: 2,
         buffer_size: 1024,
         read_timeout: Duration::from_secs(30),
         write_timeout: Duration::from_secs(30),
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let server = TcpEchoServer::new(config.into());
@@ -145,7 +169,6 @@ async fn test_tcp_connection_limit() -> Result<()> {
     let mut handles = Vec::new();
 
     for i in 0..5 {
-        let addr = addr;
         let handle = tokio::spawn(async move {
             match TcpEchoClient::connect(addr).await {
                 Ok(mut client) => {
@@ -302,10 +325,16 @@ async fn test_tcp_timeout_configuration() -> Result<()> {
     // Test server with very short timeouts
     let config = TcpConfig {
         bind_addr: "127.0.0.1:0".parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 1024,
         read_timeout: Duration::from_millis(100), // Very short timeout
         write_timeout: Duration::from_millis(100),
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let listener = TcpListener::bind(config.bind_addr).await?;
@@ -314,10 +343,16 @@ async fn test_tcp_timeout_configuration() -> Result<()> {
 
     let config = TcpConfig {
         bind_addr: addr,
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 1024,
         read_timeout: Duration::from_millis(100),
         write_timeout: Duration::from_millis(100),
+        transform: None,
+        framing: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
 
     let server = TcpEchoServer::new(config.into());
@@ -343,9 +378,16 @@ async fn test_udp_timeout_configuration() -> Result<()> {
     // Test server with very short timeouts
     let config = UdpConfig {
         bind_addr: "127.0.0.1:0".parse().unwrap(),
+        additional_addrs: Vec::new(),
         buffer_size: 1024,
         read_timeout: Duration::from_millis(100), // Very short timeout
         write_timeout: Duration::from_millis(100),
+        transform: None,
+        workers: 1,
+        batch: None,
+        socket_options: Default::default(),
+        multicast: None,
+        truncation: Default::default(),
     };
 
     let server = UdpEchoServer::new(config.into());
@@ -381,7 +423,6 @@ async fn test_tcp_stress_test() -> Result<()> {
     let mut failed_connections = 0;
 
     for i in 0..100 {
-        let addr = addr;
         let handle = tokio::spawn(async move {
             match TcpEchoClient::connect(addr).await {
                 Ok(mut client) => {
@@ -483,7 +524,6 @@ async fn test_udp_stress_test() -> Result<()> {
     let mut failed_connections = 0;
 
     for i in 0..100 {
-        let addr = addr;
         let handle = tokio::spawn(async move {
             match UdpEchoClient::connect(addr).await {
                 Ok(mut client) => {
@@ -545,13 +585,15 @@ async fn test_http_echo_post() -> Result<()> {
     let test_addr = "127.0.0.1:8081";
     let config = HttpConfig {
         bind_addr: test_addr.parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 8192,
         read_timeout: Duration::from_secs(5),
         write_timeout: Duration::from_secs(5),
-        server_name: Some("TestHTTP/1.0".to_string()),
-        echo_headers: true,
-        default_content_type: Some("text/plain".to_string()),
+        transform: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
     let server = HttpEchoServer::new(config.into());
     let server_handle = tokio::spawn(async move { server.run().await });
@@ -584,13 +626,15 @@ async fn test_http_method_not_allowed() -> Result<()> {
     let test_addr = "127.0.0.1:8082";
     let config = HttpConfig {
         bind_addr: test_addr.parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 8192,
         read_timeout: Duration::from_secs(5),
         write_timeout: Duration::from_secs(5),
-        server_name: Some("TestHTTP/1.0".to_string()),
-        echo_headers: true,
-        default_content_type: Some("text/plain".to_string()),
+        transform: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
     let server = HttpEchoServer::new(config.into());
     let server_handle = tokio::spawn(async move { server.run().await });
@@ -619,13 +663,15 @@ async fn test_http_large_payload() -> Result<()> {
     let test_addr = "127.0.0.1:8083";
     let config = HttpConfig {
         bind_addr: test_addr.parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 16384, // Larger buffer for big payloads
         read_timeout: Duration::from_secs(10),
         write_timeout: Duration::from_secs(10),
-        server_name: Some("TestHTTP/1.0".to_string()),
-        echo_headers: true,
-        default_content_type: Some("text/plain".to_string()),
+        transform: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
     let server = HttpEchoServer::new(config.into());
     let server_handle = tokio::spawn(async move { server.run().await });
@@ -663,13 +709,15 @@ async fn test_http_concurrent_clients() -> Result<()> {
     let test_addr = "127.0.0.1:8084";
     let config = HttpConfig {
         bind_addr: test_addr.parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 20,
         buffer_size: 8192,
         read_timeout: Duration::from_secs(5),
         write_timeout: Duration::from_secs(5),
-        server_name: Some("TestHTTP/1.0".to_string()),
-        echo_headers: true,
-        default_content_type: Some("text/plain".to_string()),
+        transform: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
     let server = HttpEchoServer::new(config.into());
     let server_handle = tokio::spawn(async move { server.run().await });
@@ -725,13 +773,15 @@ async fn test_http_client_usage() -> Result<()> {
     let test_addr = "127.0.0.1:8085";
     let config = HttpConfig {
         bind_addr: test_addr.parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 8192,
         read_timeout: Duration::from_secs(5),
         write_timeout: Duration::from_secs(5),
-        server_name: Some("TestHTTP/1.0".to_string()),
-        echo_headers: true,
-        default_content_type: Some("text/plain".to_string()),
+        transform: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
     let server = HttpEchoServer::new(config.into());
     let server_handle = tokio::spawn(async move { server.run().await });
@@ -769,13 +819,15 @@ async fn test_http_malformed_requests() -> Result<()> {
     let test_addr = "127.0.0.1:8086";
     let config = HttpConfig {
         bind_addr: test_addr.parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 8192,
         read_timeout: Duration::from_secs(5),
         write_timeout: Duration::from_secs(5),
-        server_name: Some("TestHTTP/1.0".to_string()),
-        echo_headers: true,
-        default_content_type: Some("text/plain".to_string()),
+        transform: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
     let server = HttpEchoServer::new(config.into());
     let server_handle = tokio::spawn(async move { server.run().await });
@@ -810,13 +862,15 @@ async fn test_http_different_methods() -> Result<()> {
     let test_addr = "127.0.0.1:8087";
     let config = HttpConfig {
         bind_addr: test_addr.parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 8192,
         read_timeout: Duration::from_secs(5),
         write_timeout: Duration::from_secs(5),
-        server_name: Some("TestHTTP/1.0".to_string()),
-        echo_headers: true,
-        default_content_type: Some("text/plain".to_string()),
+        transform: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
     let server = HttpEchoServer::new(config.into());
     let server_handle = tokio::spawn(async move { server.run().await });
@@ -853,13 +907,15 @@ async fn test_http_headers_preservation() -> Result<()> {
     let test_addr = "127.0.0.1:8088";
     let config = HttpConfig {
         bind_addr: test_addr.parse().unwrap(),
+        additional_addrs: Vec::new(),
         max_connections: 10,
         buffer_size: 8192,
         read_timeout: Duration::from_secs(5),
         write_timeout: Duration::from_secs(5),
-        server_name: Some("TestHTTP/1.0".to_string()),
-        echo_headers: true,
-        default_content_type: Some("text/plain".to_string()),
+        transform: None,
+        workers: 1,
+        steer_by_cpu: false,
+        socket_options: Default::default(),
     };
     let server = HttpEchoServer::new(config.into());
     let server_handle = tokio::spawn(async move { server.run().await });
@@ -889,3 +945,1659 @@ async fn test_http_headers_preservation() -> Result<()> {
     server_handle.abort();
     Ok(())
 }
+
+#[tokio::test]
+async fn test_tcp_custom_handler() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    drop(listener);
+
+    let config = TcpConfig {
+        bind_addr: addr,
+        ..Default::default()
+    };
+    let server =
+        TcpEchoServer::new(config.into()).with_handler(|data: &[u8], context: &StreamContext| {
+            (data != b"skip").then(|| {
+                let mut response = format!("{}:", context.chunks).into_bytes();
+                response.extend(data.to_ascii_uppercase());
+                response
+            })
+        });
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TcpEchoClient::connect(addr).await?;
+    assert_eq!(client.echo_string("hello").await?, "0:HELLO");
+
+    // A chunk without a response leaves the connection open
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"skip").await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    stream.write_all(b"next").await?;
+    let mut buffer = [0u8; 16];
+    let n = stream.read(&mut buffer).await?;
+    assert_eq!(&buffer[..n], b"1:NEXT");
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_custom_handler() -> Result<()> {
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    let addr = socket.local_addr().map_err(EchoError::Udp)?;
+    drop(socket);
+
+    let config = UdpConfig {
+        bind_addr: addr,
+        ..Default::default()
+    };
+    let server =
+        UdpEchoServer::new(config.into()).with_handler(|data: &[u8], context: &DatagramContext| {
+            (data != b"drop").then(|| format!("{} from {}", data.len(), context.peer).into_bytes())
+        });
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let client = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    let client_addr = client.local_addr().map_err(EchoError::Udp)?;
+    let mut buffer = [0u8; 64];
+
+    client
+        .send_to(b"drop", addr)
+        .await
+        .map_err(EchoError::Udp)?;
+    let reply = tokio::time::timeout(Duration::from_millis(200), client.recv(&mut buffer)).await;
+    assert!(reply.is_err(), "dropped datagram should get no reply");
+
+    client
+        .send_to(b"hello", addr)
+        .await
+        .map_err(EchoError::Udp)?;
+    let n = client.recv(&mut buffer).await.map_err(EchoError::Udp)?;
+    assert_eq!(&buffer[..n], format!("5 from {client_addr}").as_bytes());
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_udp_workers() -> Result<()> {
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    let addr = socket.local_addr().map_err(EchoError::Udp)?;
+    drop(socket);
+
+    let config = UdpConfig {
+        bind_addr: addr,
+        workers: 0,
+        ..Default::default()
+    };
+    let server = UdpEchoServer::new(config.into());
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Each client has its own source port, so the kernel spreads them
+    // across the worker sockets
+    let mut handles = Vec::new();
+    for i in 0..32 {
+        handles.push(tokio::spawn(async move {
+            let mut client = UdpEchoClient::connect(addr).await?;
+            for j in 0..10 {
+                let message = format!("client {i} datagram {j}");
+                assert_eq!(client.echo_string(&message).await?, message);
+            }
+            Ok::<(), EchoError>(())
+        }));
+    }
+    for handle in handles {
+        handle
+            .await
+            .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+    }
+
+    // Every worker stops on shutdown
+    let _ = shutdown.send(());
+    tokio::time::timeout(Duration::from_secs(2), server_handle)
+        .await
+        .map_err(|_| EchoError::Timeout("server did not stop".to_string()))?
+        .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_tcp_accept_workers() -> Result<()> {
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let config = TcpConfig {
+        bind_addr: addr,
+        max_connections: 8,
+        workers: 4,
+        steer_by_cpu: cfg!(target_os = "linux"),
+        ..Default::default()
+    };
+    let server = TcpEchoServer::new(config.into());
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Each client has its own source port, so the kernel spreads them
+    // across the listeners, which share one connection limit
+    let mut clients = Vec::new();
+    for i in 0..8 {
+        let mut client = TcpEchoClient::connect(addr).await?;
+        let message = format!("client {i}");
+        assert_eq!(client.echo_string(&message).await?, message);
+        clients.push(client);
+    }
+    let mut rejected = TcpStream::connect(addr).await?;
+    let _ = rejected.write_all(b"over the limit").await;
+    let mut buffer = [0u8; 16];
+    let n = tokio::time::timeout(Duration::from_secs(2), rejected.read(&mut buffer))
+        .await
+        .map_err(|_| EchoError::Timeout("rejected connection stayed open".to_string()))?
+        .unwrap_or(0);
+    assert_eq!(n, 0, "the ninth connection must be rejected");
+
+    // Closing one makes room on whichever listener takes the next
+    drop(clients.pop());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut client = TcpEchoClient::connect(addr).await?;
+    assert_eq!(client.echo_string("room again").await?, "room again");
+
+    // Every accept loop stops on shutdown
+    let _ = shutdown.send(());
+    tokio::time::timeout(Duration::from_secs(2), server_handle)
+        .await
+        .map_err(|_| EchoError::Timeout("server did not stop".to_string()))?
+        .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_http_accept_workers() -> Result<()> {
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let config = HttpConfig {
+        bind_addr: addr,
+        workers: 2,
+        ..Default::default()
+    };
+    let server = HttpEchoServer::new(config.into());
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for i in 0..8 {
+        let mut stream = TcpStream::connect(addr).await?;
+        let body = format!("request {i}");
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = vec![0u8; 4096];
+        let n = stream.read(&mut response).await?;
+        assert_eq!(String::from_utf8_lossy(&response[..n]).trim(), body);
+    }
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_batch_offload() -> Result<()> {
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    let addr = socket.local_addr().map_err(EchoError::Udp)?;
+    drop(socket);
+
+    let config = UdpConfig {
+        bind_addr: addr,
+        batch: Some(BatchConfig::new(8).with_offload(true)),
+        ..Default::default()
+    };
+    let server = UdpEchoServer::new(config.into());
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // A burst of equal datagrams may be coalesced on receive and segmented
+    // on send, yet each comes back on its own and in order
+    let client = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    client.connect(addr).await.map_err(EchoError::Udp)?;
+    let datagrams: Vec<Vec<u8>> = (0..20u8).map(|i| vec![b'a' + i; 200]).collect();
+    for datagram in &datagrams {
+        client.send(datagram).await.map_err(EchoError::Udp)?;
+    }
+
+    let mut buffer = [0u8; 1024];
+    for datagram in &datagrams {
+        let n = tokio::time::timeout(Duration::from_secs(2), client.recv(&mut buffer))
+            .await
+            .map_err(|_| EchoError::Timeout("no echo".to_string()))?
+            .map_err(EchoError::Udp)?;
+        assert_eq!(&buffer[..n], &datagram[..]);
+    }
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_transform_modes() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let tcp_addr = listener.local_addr()?;
+    let http_addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    drop(listener);
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    let udp_addr = socket.local_addr().map_err(EchoError::Udp)?;
+    drop(socket);
+
+    let tcp = TcpEchoServer::new(
+        TcpConfig {
+            bind_addr: tcp_addr,
+            transform: Some(Transform::Uppercase),
+            framing: None,
+            ..Default::default()
+        }
+        .into(),
+    );
+    let udp = UdpEchoServer::new(
+        UdpConfig {
+            bind_addr: udp_addr,
+            transform: Some(Transform::Sha256),
+            ..Default::default()
+        }
+        .into(),
+    );
+    let http = HttpEchoServer::new(
+        HttpConfig {
+            bind_addr: http_addr,
+            transform: Some(Transform::Base64Encode),
+            ..Default::default()
+        }
+        .into(),
+    );
+    let tcp_handle = tokio::spawn(async move { tcp.run().await });
+    let udp_handle = tokio::spawn(async move { udp.run().await });
+    let http_handle = tokio::spawn(async move { http.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TcpEchoClient::connect(tcp_addr).await?;
+    assert_eq!(client.echo_string("hello").await?, "HELLO");
+
+    let mut client = UdpEchoClient::connect(udp_addr).await?;
+    assert_eq!(
+        client.echo(b"hello\n").await?,
+        b"5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03\n"
+    );
+
+    let mut stream = TcpStream::connect(http_addr).await?;
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello")
+        .await?;
+    let mut response = vec![0u8; 256];
+    let n = stream.read(&mut response).await?;
+    assert_eq!(&response[..n], b"aGVsbG8=\n");
+
+    tcp_handle.abort();
+    udp_handle.abort();
+    http_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_simple_services() -> Result<()> {
+    let mut tcp_addrs = Vec::new();
+    let mut handles = Vec::new();
+    for service in [Service::Discard, Service::Chargen, Service::Daytime] {
+        let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+        let config = TcpConfig {
+            bind_addr: addr,
+            ..Default::default()
+        };
+        let server = TcpEchoServer::new(config.into()).with_handler(service);
+        handles.push(tokio::spawn(async move { server.run().await }));
+        tcp_addrs.push(addr);
+    }
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    let udp_addr = socket.local_addr().map_err(EchoError::Udp)?;
+    drop(socket);
+    let config = UdpConfig {
+        bind_addr: udp_addr,
+        ..Default::default()
+    };
+    let server = UdpEchoServer::new(config.into()).with_handler(Service::Time);
+    handles.push(tokio::spawn(async move { server.run().await }));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Discard swallows data and keeps the connection open
+    let mut stream = TcpStream::connect(tcp_addrs[0]).await?;
+    stream.write_all(b"into the void").await?;
+    let mut buffer = [0u8; 16];
+    let read = tokio::time::timeout(Duration::from_millis(200), stream.read(&mut buffer)).await;
+    assert!(read.is_err(), "discard should never answer");
+
+    // Chargen starts writing without being asked
+    let mut stream = TcpStream::connect(tcp_addrs[1]).await?;
+    let mut pattern = vec![0u8; 148];
+    stream.read_exact(&mut pattern).await?;
+    assert!(pattern.starts_with(b" !\"#$%&'()*+,-./0123456789"));
+    assert!(pattern[74..].starts_with(b"!\"#$%&'()*+,-./0123456789"));
+
+    // Daytime sends one line and closes
+    let mut stream = TcpStream::connect(tcp_addrs[2]).await?;
+    let mut daytime = String::new();
+    stream.read_to_string(&mut daytime).await?;
+    assert!(
+        daytime.ends_with("-UTC\r\n"),
+        "unexpected daytime {daytime:?}"
+    );
+
+    // Time answers any datagram with seconds since 1900
+    let client = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    client
+        .send_to(b"", udp_addr)
+        .await
+        .map_err(EchoError::Udp)?;
+    let n = client.recv(&mut buffer).await.map_err(EchoError::Udp)?;
+    assert_eq!(n, 4);
+    let seconds = u32::from_be_bytes(buffer[..4].try_into().unwrap());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert!((seconds as u64 - 2_208_988_800).abs_diff(now) <= 5);
+
+    for handle in handles {
+        handle.abort();
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_framed_tcp_echo() -> Result<()> {
+    for framing in Framing::ALL {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        drop(listener);
+
+        let codec = FrameCodec::new(framing).with_max_frame_size(64);
+        let config = TcpConfig {
+            bind_addr: addr,
+            framing: Some(codec.clone()),
+            ..Default::default()
+        };
+        let server = TcpEchoServer::new(config.into());
+        let server_handle = tokio::spawn(async move { server.run().await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client_config = ClientConfigBuilder::new().framing(codec.clone()).build();
+        let mut client = TcpEchoClient::connect_with_config(addr, client_config).await?;
+        assert_eq!(client.echo_string("hello").await?, "hello");
+        assert_eq!(client.echo(b"").await?, b"");
+
+        // Messages split across writes and sent back to back stay whole
+        let mut encoded = BytesMut::new();
+        let mut encoder = codec.clone();
+        encoder.encode(&b"first message"[..], &mut encoded)?;
+        encoder.encode(&b"second"[..], &mut encoded)?;
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(&encoded[..3]).await?;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stream.write_all(&encoded[3..]).await?;
+        let mut received = BytesMut::new();
+        let mut decoder = codec.clone();
+        let mut frames = Vec::new();
+        while frames.len() < 2 {
+            let mut buffer = [0u8; 64];
+            let n = stream.read(&mut buffer).await?;
+            assert_ne!(n, 0, "{framing}: server closed early");
+            received.extend_from_slice(&buffer[..n]);
+            while let Some(frame) = decoder.decode(&mut received)? {
+                frames.push(frame.to_vec());
+            }
+        }
+        assert_eq!(frames, vec![b"first message".to_vec(), b"second".to_vec()]);
+
+        // An oversized frame closes the connection without a reply
+        let mut oversized = BytesMut::new();
+        FrameCodec::new(framing).encode(&[b'x'; 100][..], &mut oversized)?;
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(&oversized).await?;
+        let mut buffer = [0u8; 128];
+        assert_eq!(stream.read(&mut buffer).await?, 0, "{framing}");
+
+        server_handle.abort();
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_framed_transform_answers_each_line() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    drop(listener);
+
+    let config = TcpConfig {
+        bind_addr: addr,
+        transform: Some(Transform::Reverse),
+        framing: Some(FrameCodec::new(Framing::Lines)),
+        ..Default::default()
+    };
+    let server = TcpEchoServer::new(config.into());
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"abc\r\ndef\nghi").await?;
+    stream.shutdown().await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    assert_eq!(response, b"cba\nfed\nihg\n");
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_pipelined_echo() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    drop(listener);
+
+    let config = TcpConfig {
+        bind_addr: addr,
+        ..Default::default()
+    };
+    let server = TcpEchoServer::new(config.into());
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Far more than the socket buffers hold, sent while echoes come back
+    let payload: Vec<u8> = (0..8 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let (mut reader, mut writer) = TcpStream::connect(addr).await?.into_split();
+    let sent = payload.clone();
+    let sender = tokio::spawn(async move {
+        for chunk in sent.chunks(64 * 1024) {
+            writer.write_all(chunk).await?;
+        }
+        Ok::<_, std::io::Error>(writer)
+    });
+
+    let mut echoed = vec![0u8; payload.len()];
+    tokio::time::timeout(Duration::from_secs(10), reader.read_exact(&mut echoed))
+        .await
+        .map_err(|_| EchoError::Timeout("pipelined echo stalled".to_string()))??;
+    assert!(echoed == payload, "echo must preserve order and content");
+    sender
+        .await
+        .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+
+    server_handle.abort();
+    Ok(())
+}
+
+/// TCP as a downstream protocol might write it, with only the required
+/// methods, so its streams are never split
+struct SequentialTcp;
+
+#[async_trait]
+impl StreamProtocol for SequentialTcp {
+    type Error = EchoError;
+    type Listener = TcpListener;
+    type Stream = TcpStream;
+
+    async fn bind(config: &StreamConfig) -> Result<TcpListener> {
+        TcpListener::bind(config.bind_addr)
+            .await
+            .map_err(EchoError::Tcp)
+    }
+
+    async fn accept(listener: &mut TcpListener) -> Result<(TcpStream, SocketAddr)> {
+        listener.accept().await.map_err(EchoError::Tcp)
+    }
+
+    async fn connect(addr: SocketAddr) -> Result<TcpStream> {
+        TcpStream::connect(addr).await.map_err(EchoError::Tcp)
+    }
+
+    async fn read(stream: &mut TcpStream, buffer: &mut [u8]) -> Result<usize> {
+        stream.read(buffer).await.map_err(EchoError::Tcp)
+    }
+
+    async fn write(stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+        stream.write_all(data).await.map_err(EchoError::Tcp)
+    }
+
+    async fn flush(stream: &mut TcpStream) -> Result<()> {
+        stream.flush().await.map_err(EchoError::Tcp)
+    }
+
+    fn map_io_error(err: std::io::Error) -> EchoError {
+        EchoError::Tcp(err)
+    }
+}
+
+/// Bytes the read half of a `FailingTcp` stream reads before it fails
+const FAILING_AFTER: usize = 256 * 1024;
+
+/// TCP whose streams split into a read half that fails mid-stream
+struct FailingTcp;
+
+/// Read half that fails once it has read `remaining` more bytes
+struct FailingRead {
+    inner: OwnedReadHalf,
+    remaining: usize,
+}
+
+impl AsyncRead for FailingRead {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.remaining == 0 {
+            return Poll::Ready(Err(std::io::Error::other("injected read failure")));
+        }
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let n = buf.filled().len() - filled;
+        self.remaining = self.remaining.saturating_sub(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[async_trait]
+impl StreamProtocol for FailingTcp {
+    type Error = EchoError;
+    type Listener = TcpListener;
+    type Stream = TcpStream;
+
+    async fn bind(config: &StreamConfig) -> Result<TcpListener> {
+        SequentialTcp::bind(config).await
+    }
+
+    async fn accept(listener: &mut TcpListener) -> Result<(TcpStream, SocketAddr)> {
+        SequentialTcp::accept(listener).await
+    }
+
+    async fn connect(addr: SocketAddr) -> Result<TcpStream> {
+        SequentialTcp::connect(addr).await
+    }
+
+    async fn read(stream: &mut TcpStream, buffer: &mut [u8]) -> Result<usize> {
+        SequentialTcp::read(stream, buffer).await
+    }
+
+    async fn write(stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+        SequentialTcp::write(stream, data).await
+    }
+
+    async fn flush(stream: &mut TcpStream) -> Result<()> {
+        SequentialTcp::flush(stream).await
+    }
+
+    fn into_split(stream: TcpStream) -> std::result::Result<(ReadHalf, WriteHalf), TcpStream> {
+        let (read, write) = stream.into_split();
+        let read = FailingRead {
+            inner: read,
+            remaining: FAILING_AFTER,
+        };
+        Ok((Box::new(read), Box::new(write)))
+    }
+
+    fn map_io_error(err: std::io::Error) -> EchoError {
+        EchoError::Tcp(err)
+    }
+}
+
+/// Starts a stream echo server for protocol `P` on a free port
+async fn start_stream_server<P>() -> Result<(SocketAddr, tokio::task::JoinHandle<Result<()>>)>
+where
+    P: StreamProtocol<Error = EchoError> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    drop(listener);
+
+    let config = TcpConfig {
+        bind_addr: addr,
+        ..Default::default()
+    };
+    let server = StreamEchoServer::<P>::new(config.into());
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    Ok((addr, server_handle))
+}
+
+/// Sends `payload` to `addr` while reading the echo, returning what was
+/// echoed before the server closed or the payload was complete, and how
+/// sending ended
+async fn pipeline(addr: SocketAddr, payload: Vec<u8>) -> Result<(Vec<u8>, std::io::Result<()>)> {
+    let len = payload.len();
+    let (mut reader, mut writer) = TcpStream::connect(addr).await?.into_split();
+    let sender = tokio::spawn(async move {
+        for chunk in payload.chunks(64 * 1024) {
+            writer.write_all(chunk).await?;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let mut echoed = Vec::with_capacity(len);
+    let mut buffer = vec![0u8; 64 * 1024];
+    let receive = async {
+        while echoed.len() < len {
+            match reader.read(&mut buffer).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => echoed.extend_from_slice(&buffer[..n]),
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), receive)
+        .await
+        .map_err(|_| EchoError::Timeout("pipelined echo stalled".to_string()))?;
+    let sent = tokio::time::timeout(Duration::from_secs(10), sender)
+        .await
+        .map_err(|_| EchoError::Timeout("pipelined send stalled".to_string()))?
+        .map_err(|e| EchoError::Config(format!("Task join error: {e}")))?;
+    Ok((echoed, sent))
+}
+
+#[tokio::test]
+async fn test_unsplit_protocol_pipelined_echo() -> Result<()> {
+    // Protocols without into_split are echoed by reading and writing in turn
+    let (addr, server_handle) = start_stream_server::<SequentialTcp>().await?;
+
+    let payload: Vec<u8> = (0..2 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let (echoed, sent) = pipeline(addr, payload.clone()).await?;
+    sent?;
+    assert!(echoed == payload, "echo must preserve order and content");
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_split_read_error_closes_both_directions() -> Result<()> {
+    let (addr, server_handle) = start_stream_server::<FailingTcp>().await?;
+
+    // Far more than the read half takes before failing, or than the socket
+    // buffers hold
+    let payload: Vec<u8> = (0..32 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let (echoed, sent) = pipeline(addr, payload.clone()).await?;
+
+    // Echoes stop once reading fails, and the client can no longer send
+    assert!(
+        echoed.len() < payload.len(),
+        "echo must stop at the read error"
+    );
+    assert!(
+        echoed[..] == payload[..echoed.len()],
+        "echo must preserve order and content"
+    );
+    assert!(sent.is_err(), "the server must stop taking data");
+
+    // Other connections are unaffected
+    let mut client = TcpEchoClient::connect(addr).await?;
+    assert_eq!(client.echo_string("hello").await?, "hello");
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_uring_tcp_echo() -> Result<()> {
+    use echosrv::uring::{self, UringTcpEchoServer};
+
+    if !uring::is_supported() {
+        info!("io_uring unavailable, skipping");
+        return Ok(());
+    }
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let server = UringTcpEchoServer::new(
+        TcpConfig {
+            bind_addr: addr,
+            workers: 2,
+            ..Default::default()
+        }
+        .into(),
+    );
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    let chargen_addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let chargen = UringTcpEchoServer::new(
+        TcpConfig {
+            bind_addr: chargen_addr,
+            ..Default::default()
+        }
+        .into(),
+    )
+    .with_handler(Service::Chargen);
+    let chargen_handle = tokio::spawn(async move { chargen.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut handles = Vec::new();
+    for i in 0..10 {
+        handles.push(tokio::spawn(async move {
+            let mut client = TcpEchoClient::connect(addr).await?;
+            for j in 0..10 {
+                let message = format!("client {i} message {j}");
+                assert_eq!(client.echo_string(&message).await?, message);
+            }
+            Ok::<(), EchoError>(())
+        }));
+    }
+    for handle in handles {
+        handle
+            .await
+            .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+    }
+
+    // More than the buffer ring holds, so receives pause and partial sends
+    // resume while echoes come back
+    let payload: Vec<u8> = (0..8 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let (mut reader, mut writer) = TcpStream::connect(addr).await?.into_split();
+    let sent = payload.clone();
+    let sender = tokio::spawn(async move {
+        for chunk in sent.chunks(64 * 1024) {
+            writer.write_all(chunk).await?;
+        }
+        Ok::<_, std::io::Error>(writer)
+    });
+    let mut echoed = vec![0u8; payload.len()];
+    tokio::time::timeout(Duration::from_secs(10), reader.read_exact(&mut echoed))
+        .await
+        .map_err(|_| EchoError::Timeout("pipelined echo stalled".to_string()))??;
+    assert!(echoed == payload, "echo must preserve order and content");
+    sender
+        .await
+        .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+
+    // Services that write first run on the completion loop too
+    let mut stream = TcpStream::connect(chargen_addr).await?;
+    let mut pattern = vec![0u8; 148];
+    stream.read_exact(&mut pattern).await?;
+    assert!(pattern.starts_with(b" !\"#$%&'()*+,-./0123456789"));
+
+    let _ = shutdown.send(());
+    tokio::time::timeout(Duration::from_secs(2), server_handle)
+        .await
+        .map_err(|_| EchoError::Timeout("server did not stop".to_string()))?
+        .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+    chargen_handle.abort();
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_uring_tcp_static_reply() -> Result<()> {
+    use echosrv::handler::EchoHandler;
+    use echosrv::uring::{self, UringTcpEchoServer};
+    use std::borrow::Cow;
+
+    /// Answers everything with a reply that lives outside the receive buffer
+    struct Pong;
+
+    impl EchoHandler for Pong {
+        fn handle<'a>(&self, _data: &'a [u8], _context: &StreamContext) -> Option<Cow<'a, [u8]>> {
+            Some(Cow::Borrowed(b"pong"))
+        }
+    }
+
+    if !uring::is_supported() {
+        info!("io_uring unavailable, skipping");
+        return Ok(());
+    }
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let server = UringTcpEchoServer::new(
+        TcpConfig {
+            bind_addr: addr,
+            ..Default::default()
+        }
+        .into(),
+    )
+    .with_handler(Pong);
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = TcpEchoClient::connect(addr).await?;
+    assert_eq!(client.echo_string("ping").await?, "pong");
+    assert_eq!(client.echo_string("PING").await?, "pong");
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_uring_udp_echo() -> Result<()> {
+    use echosrv::uring::{self, UringUdpEchoServer};
+
+    if !uring::is_supported() {
+        info!("io_uring unavailable, skipping");
+        return Ok(());
+    }
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    let addr = socket.local_addr().map_err(EchoError::Udp)?;
+    drop(socket);
+
+    let server = UringUdpEchoServer::new(
+        UdpConfig {
+            bind_addr: addr,
+            workers: 2,
+            transform: Some(Transform::Uppercase),
+            ..Default::default()
+        }
+        .into(),
+    );
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut handles = Vec::new();
+    for i in 0..16 {
+        handles.push(tokio::spawn(async move {
+            let mut client = UdpEchoClient::connect(addr).await?;
+            for j in 0..10 {
+                let message = format!("client {i} datagram {j}");
+                assert_eq!(client.echo_string(&message).await?, message.to_uppercase());
+            }
+            Ok::<(), EchoError>(())
+        }));
+    }
+    for handle in handles {
+        handle
+            .await
+            .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+    }
+
+    let _ = shutdown.send(());
+    tokio::time::timeout(Duration::from_secs(2), server_handle)
+        .await
+        .map_err(|_| EchoError::Timeout("server did not stop".to_string()))?
+        .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_uring_udp_truncation() -> Result<()> {
+    use echosrv::datagram::TruncationPolicy;
+    use echosrv::uring::{self, UringUdpEchoServer};
+    use std::sync::Arc;
+
+    if !uring::is_supported() {
+        info!("io_uring unavailable, skipping");
+        return Ok(());
+    }
+    let socket = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    let addr = socket.local_addr().map_err(EchoError::Udp)?;
+    drop(socket);
+
+    let server = Arc::new(UringUdpEchoServer::new(
+        UdpConfig {
+            bind_addr: addr,
+            buffer_size: 16,
+            truncation: TruncationPolicy::Error,
+            ..Default::default()
+        }
+        .into(),
+    ));
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn({
+        let server = Arc::clone(&server);
+        async move { server.run().await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut client = UdpEchoClient::connect(addr).await?;
+    assert_eq!(client.echo(&[b'x'; 40]).await?, b"ERR TRUNCATED 40");
+    assert_eq!(client.echo(b"fits").await?, b"fits");
+    assert_eq!(server.truncated_datagrams(), 1);
+
+    let _ = shutdown.send(());
+    let _ = server_handle.await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_splice_echo() -> Result<()> {
+    // No subscriber logs the previews here, so a plain echo takes the
+    // zero-copy path where the platform has one
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let server = TcpEchoServer::new(
+        TcpConfig {
+            bind_addr: addr,
+            read_timeout: Duration::from_millis(300),
+            ..Default::default()
+        }
+        .into(),
+    );
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // More than a pipe holds, written while the echoes are read back
+    let payload: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let (mut reader, mut writer) = TcpStream::connect(addr).await?.into_split();
+    let sent = payload.clone();
+    let sender = tokio::spawn(async move {
+        for chunk in sent.chunks(64 * 1024) {
+            writer.write_all(chunk).await?;
+        }
+        // Half-closing still gets every echo back before the server closes
+        writer.shutdown().await?;
+        Ok::<_, std::io::Error>(())
+    });
+    let mut echoed = Vec::with_capacity(payload.len());
+    tokio::time::timeout(Duration::from_secs(10), reader.read_to_end(&mut echoed))
+        .await
+        .map_err(|_| EchoError::Timeout("spliced echo stalled".to_string()))??;
+    assert!(echoed == payload, "echo must preserve order and content");
+    sender
+        .await
+        .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+
+    // An idle connection is closed after the read timeout
+    let mut idle = TcpStream::connect(addr).await?;
+    let mut buffer = [0u8; 16];
+    let n = tokio::time::timeout(Duration::from_secs(2), idle.read(&mut buffer))
+        .await
+        .map_err(|_| EchoError::Timeout("idle connection stayed open".to_string()))??;
+    assert_eq!(n, 0);
+
+    let _ = shutdown.send(());
+    let _ = server_handle.await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_socket_options() -> Result<()> {
+    use echosrv::network::{FdInheritanceConfig, Keepalive, SocketOptions};
+    use echosrv::stream::StreamProtocol;
+    use echosrv::tcp::TcpProtocol;
+    use socket2::SockRef;
+    use std::os::fd::IntoRawFd;
+
+    let options = SocketOptions {
+        nodelay: Some(true),
+        keepalive: Some(Keepalive::new(Duration::from_secs(60)).with_retries(3)),
+        recv_buffer_size: Some(128 * 1024),
+        ttl: Some(32),
+        linger: Some(Duration::from_secs(1)),
+        user_timeout: cfg!(target_os = "linux").then_some(Duration::from_secs(10)),
+        fastopen: cfg!(target_os = "linux").then_some(16),
+        backlog: Some(64),
+        ..Default::default()
+    }
+    .with_dscp(10);
+
+    // Accepted streams get the per-connection options and still echo
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let config = TcpConfig {
+        bind_addr: addr,
+        socket_options: options.clone(),
+        ..Default::default()
+    };
+    let server = TcpEchoServer::new(config.clone().into());
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut client = TcpEchoClient::connect(addr).await?;
+    assert_eq!(client.echo_string("tuned").await?, "tuned");
+    let _ = shutdown.send(());
+    let _ = server_handle.await;
+
+    // Bound listeners get the listener options
+    let listener = TcpProtocol::bind(&config.clone().into()).await?;
+    let socket = SockRef::from(&listener);
+    assert_eq!(socket.tos()?, 40);
+    assert_eq!(socket.ttl()?, 32);
+    assert!(socket.recv_buffer_size()? >= 128 * 1024);
+    drop(listener);
+
+    // So do listeners inherited from a parent process
+    let inherited = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let inherited_addr = inherited.local_addr()?;
+    let fd_config = FdInheritanceConfig {
+        inherited_fds: [("tcp-echo".to_string(), inherited.into_raw_fd())].into(),
+        enable_inheritance: true,
+    };
+    let listener = TcpProtocol::bind_with_inheritance(&config.into(), &fd_config).await?;
+    assert_eq!(listener.local_addr()?, inherited_addr);
+    let socket = SockRef::from(&listener);
+    assert_eq!(socket.tos()?, 40);
+    assert_eq!(socket.ttl()?, 32);
+
+    // Datagram sockets take the options that apply to them
+    let addr = UdpSocket::bind("127.0.0.1:0").await?.local_addr()?;
+    let config = UdpConfig {
+        bind_addr: addr,
+        socket_options: options,
+        ..Default::default()
+    };
+    let server = UdpEchoServer::new(config.into());
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut client = UdpEchoClient::connect(addr).await?;
+    assert_eq!(client.echo_string("tuned").await?, "tuned");
+    let _ = shutdown.send(());
+    let _ = server_handle.await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_multi_address() -> Result<()> {
+    // Both loopbacks on one port, feeding one connection limit
+    let port = TcpListener::bind("127.0.0.1:0").await?.local_addr()?.port();
+    let v4: std::net::SocketAddr = ([127, 0, 0, 1], port).into();
+    let v6: std::net::SocketAddr = (std::net::Ipv6Addr::LOCALHOST, port).into();
+    let config = TcpConfig {
+        bind_addr: v4,
+        additional_addrs: vec![v6],
+        max_connections: 2,
+        ..Default::default()
+    };
+    let server =
+        TcpEchoServer::new(config.into()).with_handler(|_: &[u8], context: &StreamContext| {
+            Some(context.local.to_string().into_bytes())
+        });
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Handlers see the address each connection came in on
+    let mut first = TcpEchoClient::connect(v4).await?;
+    assert_eq!(first.echo_string("where").await?, v4.to_string());
+    let mut second = TcpEchoClient::connect(v6).await?;
+    assert_eq!(second.echo_string("where").await?, v6.to_string());
+
+    let mut rejected = TcpStream::connect(v6).await?;
+    let _ = rejected.write_all(b"over the limit").await;
+    let mut buffer = [0u8; 16];
+    let n = tokio::time::timeout(Duration::from_secs(2), rejected.read(&mut buffer))
+        .await
+        .map_err(|_| EchoError::Timeout("rejected connection stayed open".to_string()))?
+        .unwrap_or(0);
+    assert_eq!(n, 0, "the third connection must be rejected");
+
+    let _ = shutdown.send(());
+    let _ = server_handle.await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_dual_stack() -> Result<()> {
+    use echosrv::network::SocketOptions;
+
+    let port = UdpSocket::bind("[::]:0")
+        .await
+        .map_err(EchoError::Udp)?
+        .local_addr()
+        .map_err(EchoError::Udp)?
+        .port();
+    let config = UdpConfig {
+        bind_addr: (std::net::Ipv6Addr::UNSPECIFIED, port).into(),
+        socket_options: SocketOptions {
+            only_v6: Some(false),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let server = UdpEchoServer::new(config.into());
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // One IPv6 socket answers IPv4 and IPv6 clients alike
+    for addr in [
+        std::net::SocketAddr::from(([127, 0, 0, 1], port)),
+        std::net::SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, port)),
+    ] {
+        let mut client = UdpEchoClient::connect(addr).await?;
+        assert_eq!(client.echo_string("both").await?, "both");
+    }
+
+    let _ = shutdown.send(());
+    let _ = server_handle.await;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_udp_reply_source() -> Result<()> {
+    for batch in [None, Some(BatchConfig::new(8))] {
+        let port = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .map_err(EchoError::Udp)?
+            .local_addr()
+            .map_err(EchoError::Udp)?
+            .port();
+        let config = UdpConfig {
+            bind_addr: ([0, 0, 0, 0], port).into(),
+            batch,
+            ..Default::default()
+        };
+        let server = UdpEchoServer::new(config.into()).with_handler(
+            |_: &[u8], context: &DatagramContext| Some(context.local.to_string().into_bytes()),
+        );
+        let shutdown = server.shutdown_signal();
+        let server_handle = tokio::spawn(async move { server.run().await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // A wildcard server answers from the address the client targeted,
+        // not the one the routing table would pick
+        let target = std::net::SocketAddr::from(([127, 0, 0, 2], port));
+        let client = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .map_err(EchoError::Udp)?;
+        client
+            .send_to(b"where", target)
+            .await
+            .map_err(EchoError::Udp)?;
+        let mut buffer = [0; 64];
+        let (n, from) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buffer))
+            .await
+            .map_err(|_| EchoError::Timeout("no reply".to_string()))?
+            .map_err(EchoError::Udp)?;
+        assert_eq!(from, target);
+        assert_eq!(&buffer[..n], target.to_string().as_bytes());
+
+        let _ = shutdown.send(());
+        let _ = server_handle.await;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_udp_truncation() -> Result<()> {
+    use echosrv::datagram::TruncationPolicy;
+    use std::sync::Arc;
+
+    let large = [b'x'; 40];
+    let cases = [
+        (TruncationPolicy::Echo, None, Some(&large[..16])),
+        (TruncationPolicy::Drop, Some(BatchConfig::new(8)), None),
+        (
+            TruncationPolicy::Error,
+            None,
+            Some(&b"ERR TRUNCATED 40"[..]),
+        ),
+        (
+            TruncationPolicy::Error,
+            Some(BatchConfig::new(8)),
+            Some(&b"ERR TRUNCATED 40"[..]),
+        ),
+    ];
+    for (truncation, batch, expected) in cases {
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .map_err(EchoError::Udp)?
+            .local_addr()
+            .map_err(EchoError::Udp)?;
+        let config = UdpConfig {
+            bind_addr: addr,
+            buffer_size: 16,
+            batch,
+            truncation,
+            ..Default::default()
+        };
+        let server = Arc::new(UdpEchoServer::new(config.into()));
+        let server_handle = tokio::spawn({
+            let server = Arc::clone(&server);
+            async move { server.run().await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .map_err(EchoError::Udp)?;
+        client.connect(addr).await.map_err(EchoError::Udp)?;
+        client.send(&large).await.map_err(EchoError::Udp)?;
+        client.send(b"fits").await.map_err(EchoError::Udp)?;
+
+        // A dropped datagram leaves the short one's echo as the first reply
+        let mut replies = Vec::new();
+        for _ in 0..1 + usize::from(expected.is_some()) {
+            let mut buffer = [0; 64];
+            let n = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buffer))
+                .await
+                .map_err(|_| EchoError::Timeout(format!("no reply under {truncation}")))?
+                .map_err(EchoError::Udp)?;
+            replies.push(buffer[..n].to_vec());
+        }
+        let mut wanted: Vec<Vec<u8>> = expected.into_iter().map(<[u8]>::to_vec).collect();
+        wanted.push(b"fits".to_vec());
+        assert_eq!(replies, wanted, "{truncation}");
+        assert_eq!(server.truncated_datagrams(), 1);
+
+        server_handle.abort();
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_udp_multicast_and_broadcast() -> Result<()> {
+    use echosrv::network::{MulticastConfig, SocketOptions};
+
+    let group: std::net::Ipv4Addr = "239.255.77.1".parse().unwrap();
+    let multicast = UdpConfig {
+        // Only one of the workers joins, so the group is echoed once
+        workers: 2,
+        multicast: Some(
+            MulticastConfig::new(vec![format!("{group}%lo").parse().unwrap()]).with_ttl(1),
+        ),
+        ..Default::default()
+    };
+    let broadcast = UdpConfig {
+        socket_options: SocketOptions {
+            broadcast: Some(true),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    for (config, target) in [
+        (multicast, std::net::IpAddr::from(group)),
+        (broadcast, [127, 255, 255, 255].into()),
+    ] {
+        let port = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .map_err(EchoError::Udp)?
+            .local_addr()
+            .map_err(EchoError::Udp)?
+            .port();
+        let config = UdpConfig {
+            bind_addr: ([0, 0, 0, 0], port).into(),
+            ..config
+        };
+        let server = UdpEchoServer::new(config.into());
+        let shutdown = server.shutdown_signal();
+        let server_handle = tokio::spawn(async move { server.run().await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let client = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .map_err(EchoError::Udp)?;
+        socket2::SockRef::from(&client)
+            .set_multicast_if_v4(&std::net::Ipv4Addr::LOCALHOST)
+            .map_err(EchoError::Udp)?;
+        client.set_broadcast(true).map_err(EchoError::Udp)?;
+
+        // Group and broadcast traffic is echoed once, back to the unicast
+        // sender
+        client
+            .send_to(b"anyone", (target, port))
+            .await
+            .map_err(EchoError::Udp)?;
+        let mut buffer = [0; 64];
+        let (n, _) = tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buffer))
+            .await
+            .map_err(|_| EchoError::Timeout(format!("no echo from {target}")))?
+            .map_err(EchoError::Udp)?;
+        assert_eq!(&buffer[..n], b"anyone");
+        let again =
+            tokio::time::timeout(Duration::from_millis(200), client.recv_from(&mut buffer)).await;
+        assert!(again.is_err(), "{target} was echoed twice");
+
+        let _ = shutdown.send(());
+        let _ = server_handle.await;
+    }
+    Ok(())
+}
+
+/// Runs a TWAMP-light session against a reflector at `addr` and checks the
+/// answers carry the sequence numbers, timestamps and TTL
+#[cfg(target_os = "linux")]
+async fn check_twamp_session(addr: std::net::SocketAddr) -> Result<()> {
+    use echosrv::datagram::twamp::REFLECTOR_PACKET_LEN;
+    use echosrv::udp::UdpTwampClient;
+
+    let mut client = UdpTwampClient::connect(addr)
+        .await?
+        .with_packet_size(REFLECTOR_PACKET_LEN)
+        .with_timeout(Duration::from_millis(500));
+    let stats = client.run(10, Duration::from_millis(5)).await?;
+    assert_eq!(stats.sent, 10);
+    assert_eq!(stats.received, 10);
+    assert_eq!(stats.lost(), 0);
+    assert_eq!(stats.duplicates, 0);
+    let sequences: Vec<u32> = stats.samples.iter().map(|s| s.sequence).collect();
+    assert_eq!(sequences, (0..10).collect::<Vec<_>>());
+    let reflected: Vec<u32> = stats.samples.iter().map(|s| s.reflector_sequence).collect();
+    assert_eq!(reflected, sequences);
+    // Both ends share a clock, so one-way delays are known
+    assert!(
+        stats
+            .samples
+            .iter()
+            .all(|s| s.forward.is_some() && s.reverse.is_some())
+    );
+    assert!(stats.samples.iter().all(|s| s.sender_ttl == 64));
+    assert!(stats.rtt_min <= stats.rtt_avg && stats.rtt_avg <= stats.rtt_max);
+    assert!(stats.rtt_max < Some(Duration::from_secs(1)));
+
+    // A second run carries on numbering
+    let stats = client.run(2, Duration::from_millis(5)).await?;
+    assert_eq!(stats.received, 2);
+    assert_eq!(stats.samples[0].sequence, 10);
+    assert_eq!(stats.samples[0].reflector_sequence, 10);
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_udp_twamp() -> Result<()> {
+    use echosrv::datagram::TwampReflector;
+    use echosrv::network::SocketOptions;
+
+    for batch in [None, Some(BatchConfig::new(8))] {
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .map_err(EchoError::Udp)?
+            .local_addr()
+            .map_err(EchoError::Udp)?;
+        let config = UdpConfig {
+            bind_addr: addr,
+            batch,
+            socket_options: SocketOptions {
+                timestamping: Some(true),
+                recv_ttl: Some(true),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let server = UdpEchoServer::new(config.into()).with_handler(TwampReflector::new());
+        let shutdown = server.shutdown_signal();
+        let server_handle = tokio::spawn(async move { server.run().await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        check_twamp_session(addr).await?;
+
+        // Datagrams too short to be test packets get no answer
+        let client = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .map_err(EchoError::Udp)?;
+        client
+            .send_to(b"ping", addr)
+            .await
+            .map_err(EchoError::Udp)?;
+        let mut buffer = [0; 64];
+        let reply =
+            tokio::time::timeout(Duration::from_millis(200), client.recv_from(&mut buffer)).await;
+        assert!(reply.is_err(), "short datagram was answered");
+
+        let _ = shutdown.send(());
+        let _ = server_handle.await;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_uring_udp_twamp() -> Result<()> {
+    use echosrv::datagram::TwampReflector;
+    use echosrv::network::SocketOptions;
+    use echosrv::uring::{self, UringUdpEchoServer};
+
+    if !uring::is_supported() {
+        info!("io_uring unavailable, skipping");
+        return Ok(());
+    }
+    let addr = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?
+        .local_addr()
+        .map_err(EchoError::Udp)?;
+    let config = UdpConfig {
+        bind_addr: addr,
+        socket_options: SocketOptions {
+            timestamping: Some(true),
+            recv_ttl: Some(true),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let server = UringUdpEchoServer::new(config.into()).with_handler(TwampReflector::new());
+    let shutdown = server.shutdown_signal();
+    let server_handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    check_twamp_session(addr).await?;
+
+    let _ = shutdown.send(());
+    let _ = server_handle.await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_whoami() -> Result<()> {
+    use echosrv::handler::Whoami;
+    use echosrv::network::SocketOptions;
+
+    let mut tcp_addrs = Vec::new();
+    let mut handles = Vec::new();
+    for whoami in Whoami::ALL {
+        let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+        let config = TcpConfig {
+            bind_addr: addr,
+            ..Default::default()
+        };
+        let server = TcpEchoServer::new(config.into()).with_handler(whoami);
+        handles.push(tokio::spawn(async move { server.run().await }));
+        tcp_addrs.push(addr);
+    }
+    // A dual-stack socket reports its IPv4 clients as IPv4
+    let socket = UdpSocket::bind("[::]:0").await.map_err(EchoError::Udp)?;
+    let udp_port = socket.local_addr().map_err(EchoError::Udp)?.port();
+    drop(socket);
+    let config = UdpConfig {
+        bind_addr: ([0u16; 8], udp_port).into(),
+        socket_options: SocketOptions {
+            only_v6: Some(false),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let server = UdpEchoServer::new(config.into()).with_handler(Whoami::Stun);
+    handles.push(tokio::spawn(async move { server.run().await }));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Text mode sends the address the connection came from, then closes
+    let mut stream = TcpStream::connect(tcp_addrs[0]).await?;
+    let mut text = String::new();
+    stream.read_to_string(&mut text).await?;
+    assert_eq!(text, format!("{}\n", stream.local_addr()?));
+
+    // STUN mode answers Binding requests on the connection
+    let mut stream = TcpStream::connect(tcp_addrs[1]).await?;
+    stream
+        .write_all(&Whoami::binding_request(*b"tcp-request1"))
+        .await?;
+    let mut buffer = [0u8; 512];
+    let n = stream.read(&mut buffer).await?;
+    assert_eq!(&buffer[8..20], b"tcp-request1");
+    assert_eq!(
+        Whoami::mapped_address(&buffer[..n]),
+        Some(stream.local_addr()?)
+    );
+
+    let client = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    client
+        .send_to(b"not stun at all", ("127.0.0.1", udp_port))
+        .await
+        .map_err(EchoError::Udp)?;
+    client
+        .send_to(
+            &Whoami::binding_request(*b"udp-request1"),
+            ("127.0.0.1", udp_port),
+        )
+        .await
+        .map_err(EchoError::Udp)?;
+    let n = tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buffer))
+        .await
+        .map_err(|_| EchoError::Timeout("no STUN response".to_string()))?
+        .map_err(EchoError::Udp)?;
+    assert_eq!(&buffer[8..20], b"udp-request1");
+    assert_eq!(
+        Whoami::mapped_address(&buffer[..n]),
+        Some(client.local_addr().map_err(EchoError::Udp)?)
+    );
+
+    for handle in handles {
+        handle.abort();
+    }
+    Ok(())
+}
+
+#[cfg(feature = "dtls")]
+async fn start_dtls_server(
+    dtls: echosrv::dtls::DtlsConfig,
+) -> Result<(std::net::SocketAddr, tokio::task::JoinHandle<Result<()>>)> {
+    use echosrv::datagram::DatagramConfig;
+    use echosrv::dtls::DtlsEchoServer;
+
+    let addr = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?
+        .local_addr()
+        .map_err(EchoError::Udp)?;
+    let config = DatagramConfig {
+        bind_addr: addr,
+        buffer_size: 8192,
+        ..Default::default()
+    };
+    let server = DtlsEchoServer::new(config, dtls);
+    let handle = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    Ok((addr, handle))
+}
+
+#[cfg(feature = "dtls")]
+#[tokio::test]
+async fn test_dtls_psk_echo() -> Result<()> {
+    use echosrv::dtls::{DtlsClientConfig, DtlsConfig, DtlsEchoClient, DtlsTrust, Psk};
+
+    let psk = Psk::new("client", b"correct horse battery".to_vec());
+    let (addr, server_handle) = start_dtls_server(DtlsConfig::psk(psk.clone())).await?;
+
+    // Concurrent peers each get their own session
+    let mut handles = Vec::new();
+    for i in 0..3 {
+        let config = DtlsClientConfig::new(DtlsTrust::Psk(psk.clone()));
+        handles.push(tokio::spawn(async move {
+            let mut client = DtlsEchoClient::connect(addr, config).await?;
+            for j in 0..5 {
+                let message = format!("record {j} from DTLS client {i}");
+                assert_eq!(client.echo_string(&message).await?, message);
+            }
+            // Records larger than the MTU still arrive whole
+            let large = vec![b'x'; 4000];
+            assert!(
+                client.echo(&large).await? == large,
+                "large record was not echoed whole"
+            );
+            client.close().await
+        }));
+    }
+    for handle in handles {
+        handle
+            .await
+            .map_err(|e| EchoError::Config(format!("Task join error: {e}")))??;
+    }
+
+    // The wrong key fails the handshake; DTLS silently drops records that
+    // fail to authenticate, so this usually surfaces as a timeout
+    let wrong = Psk::new("client", b"incorrect".to_vec());
+    let config =
+        DtlsClientConfig::new(DtlsTrust::Psk(wrong)).with_handshake_timeout(Duration::from_secs(2));
+    let result = DtlsEchoClient::connect(addr, config).await;
+    assert!(
+        matches!(result, Err(EchoError::Dtls(_) | EchoError::Timeout(_))),
+        "wrong PSK was accepted: {:?}",
+        result.err()
+    );
+
+    // Plaintext datagrams get no answer
+    let client = UdpSocket::bind("127.0.0.1:0")
+        .await
+        .map_err(EchoError::Udp)?;
+    client
+        .send_to(b"plaintext", addr)
+        .await
+        .map_err(EchoError::Udp)?;
+    let mut buffer = [0; 64];
+    let reply =
+        tokio::time::timeout(Duration::from_millis(200), client.recv_from(&mut buffer)).await;
+    assert!(reply.is_err(), "plaintext datagram was answered");
+
+    server_handle.abort();
+    Ok(())
+}
+
+#[cfg(feature = "dtls")]
+#[tokio::test]
+async fn test_dtls_certificate_echo() -> Result<()> {
+    use echosrv::dtls::{DtlsClientConfig, DtlsConfig, DtlsCredentials, DtlsEchoClient, DtlsTrust};
+
+    let DtlsCredentials::Certificate { chain, key } = DtlsCredentials::generate("localhost")?
+    else {
+        unreachable!("generate returns a certificate");
+    };
+    for cookie_exchange in [true, false] {
+        let config = DtlsConfig::certificate(chain.clone(), key.clone())
+            .with_cookie_exchange(cookie_exchange);
+        let (addr, server_handle) = start_dtls_server(config).await?;
+
+        let trust = DtlsTrust::Certificate {
+            ca: chain.clone(),
+            server_name: "localhost".to_string(),
+        };
+        let mut client = DtlsEchoClient::connect(addr, DtlsClientConfig::new(trust)).await?;
+        assert_eq!(client.echo_string("Hello, DTLS!").await?, "Hello, DTLS!");
+        client.close().await?;
+
+        // The certificate must name the server
+        let trust = DtlsTrust::Certificate {
+            ca: chain.clone(),
+            server_name: "example.com".to_string(),
+        };
+        let result = DtlsEchoClient::connect(addr, DtlsClientConfig::new(trust)).await;
+        assert!(matches!(result, Err(EchoError::Dtls(_))));
+
+        server_handle.abort();
+    }
+
+    // Without credentials the server generates its own certificate
+    let (addr, server_handle) = start_dtls_server(DtlsConfig::default()).await?;
+    let config = DtlsClientConfig::new(DtlsTrust::Insecure);
+    let mut client = DtlsEchoClient::connect(addr, config).await?;
+    assert_eq!(client.echo(b"self-signed").await?, b"self-signed");
+    server_handle.abort();
+    Ok(())
+}
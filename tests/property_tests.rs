@@ -82,7 +82,6 @@ proptest! {
                     continue; // Skip empty messages
                 }
 
-                let addr = addr;
                 let message = message.clone();
                 let handle = tokio::spawn(async move {
                     let mut client = TcpEchoClient::connect(addr).await?;
@@ -131,10 +130,16 @@ proptest! {
             // Create server with custom buffer size
             let config = TcpConfig {
                 bind_addr: addr,
+                additional_addrs: Vec::new(),
                 max_connections: 10,
                 buffer_size,
                 read_timeout: Duration::from_secs(30),
                 write_timeout: Duration::from_secs(30),
+                transform: None,
+                framing: None,
+                workers: 1,
+                steer_by_cpu: false,
+                socket_options: Default::default(),
             };
 
             let server = TcpEchoServer::new(config.clone().into());
@@ -170,7 +175,6 @@ async fn stress_test_many_connections() {
     // Create 50 concurrent connections
     let mut handles = Vec::new();
     for i in 0..50 {
-        let addr = addr;
         let handle = tokio::spawn(async move {
             let mut client = TcpEchoClient::connect(addr).await?;
             let message = format!("Stress test message from client {i}");